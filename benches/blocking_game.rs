@@ -0,0 +1,104 @@
+use std::any::Any;
+use std::time::Duration;
+
+use code_challenge_game_types::gametraits::{
+    GameTrait, Paint, PlayerMove, PlayerMoveResult, PlayerTurn, TurnToken, User,
+};
+use coding_challenge::async_game_trait::{AsyncGame, AsyncGameTrait, BlockingAsyncGame};
+use coding_challenge::games::dumb;
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::future::join_all;
+
+// What a game that thinks hard about every move costs
+const MOVE_COST: Duration = Duration::from_millis(10);
+// Games played side by side, like a server hosting several at once
+const GAMES: usize = 4;
+const MOVES_PER_GAME: usize = 5;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SlowGame(dumb::Game);
+
+impl GameTrait for SlowGame {
+    fn player_moves(&mut self, token: TurnToken, player_move: PlayerMove) -> PlayerMoveResult {
+        std::thread::sleep(MOVE_COST);
+        self.0.player_moves(token, player_move)
+    }
+    fn player_connected(&mut self, user: User) {
+        self.0.player_connected(user)
+    }
+    fn player_disconnected(&mut self, user: &str) {
+        self.0.player_disconnected(user)
+    }
+    fn current_player_disconnected(&mut self, turn_token: TurnToken) -> Option<PlayerTurn> {
+        self.0.current_player_disconnected(turn_token)
+    }
+    fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        self.0.try_start_game()
+    }
+    fn reset(&mut self, users: Vec<User>) {
+        self.0.reset(users)
+    }
+}
+
+impl Paint for SlowGame {
+    fn paint(&self, ctx: &mut druid::PaintCtx) {
+        self.0.paint(ctx)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn eq(&self, other: &dyn Paint) -> bool {
+        self == other.as_any().downcast_ref::<SlowGame>().unwrap()
+    }
+}
+
+async fn play(mut game: Box<dyn AsyncGameTrait>) {
+    game.player_connected(User {
+        name: "p1".to_string(),
+        color: druid::Color::rgb8(200, 0, 0),
+    })
+    .await;
+    let mut turn = game.try_start_game().await.unwrap();
+    for _ in 0..MOVES_PER_GAME {
+        let player_move = PlayerMove {
+            serialized: r#"{"move":{"add":1}}"#.to_string(),
+        };
+        turn = match game.player_moves(turn.token, player_move).await {
+            PlayerMoveResult::Ok(next_turn) => next_turn,
+            _ => unreachable!("The slow game takes every move"),
+        };
+    }
+}
+
+// One thread for the async tasks, like a busy server. AsyncGame blocks it for every move,
+// BlockingAsyncGame lets the games think in parallel
+fn concurrent_games(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("concurrent games");
+    group.sample_size(10);
+    group.bench_function("AsyncGame", |b| {
+        b.iter(|| {
+            rt.block_on(join_all((0..GAMES).map(|_| {
+                play(AsyncGame::make_ptr_from_game(SlowGame(
+                    dumb::Game::new_with_target(1000),
+                )))
+            })))
+        })
+    });
+    group.bench_function("BlockingAsyncGame", |b| {
+        b.iter(|| {
+            rt.block_on(join_all((0..GAMES).map(|_| {
+                play(BlockingAsyncGame::make_ptr_from_game(SlowGame(
+                    dumb::Game::new_with_target(1000),
+                )))
+            })))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, concurrent_games);
+criterion_main!(benches);
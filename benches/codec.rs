@@ -0,0 +1,29 @@
+use coding_challenge::network_wrap::Codec;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// Roughly what a gomoku client gets every turn, with 20 cells taken
+fn board_state() -> serde_json::Value {
+    let cells: Vec<serde_json::Value> = (0..20)
+        .map(|i| serde_json::json!({"x": i % 20, "y": i / 3, "player": format!("player{}", i % 2)}))
+        .collect();
+    serde_json::json!({"your-turn": {"width": 20, "height": 20, "board": cells}})
+}
+
+fn round_trip(c: &mut Criterion) {
+    let state = board_state();
+    for (name, codec) in [("json", Codec::Json), ("msgpack", Codec::MsgPack)] {
+        c.bench_function(&format!("{name} round trip"), |b| {
+            b.iter(|| {
+                let bytes = codec.encode(&state).unwrap();
+                let body = match codec {
+                    Codec::Json => &bytes[..bytes.len() - 1],
+                    Codec::MsgPack => &bytes[4..],
+                };
+                codec.decode(body).unwrap()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, round_trip);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+// The auth line, the first thing anyone connecting gets to send.
+// Run with `cargo +nightly fuzz run authorize`, see parse_from_client.rs for more.
+#![no_main]
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use coding_challenge::user_connection::{authorize, BcryptHasher};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = std::str::from_utf8(data) {
+        // Lowest cost bcrypt allows, or the fuzzer spends all its time hashing
+        let hasher = BcryptHasher::new(4);
+        let mut db = Arc::new(Mutex::new(HashMap::new()));
+        // Once to register, once more to log in against the stored hash
+        let _ = authorize(line, &mut db, &hasher);
+        let _ = authorize(line, &mut db, &hasher);
+    }
+});
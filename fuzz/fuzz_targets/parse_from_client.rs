@@ -0,0 +1,17 @@
+// Everything a client sends first goes through here, it must never panic.
+//
+// Needs nightly and cargo-fuzz (`cargo install cargo-fuzz`), then from the repo root:
+//   cargo +nightly fuzz run parse_from_client
+// Add `-- -max_total_time=60` to stop after a minute, like CI does. Inputs that crash end up in
+// fuzz/artifacts/parse_from_client/, rerun one with
+//   cargo +nightly fuzz run parse_from_client fuzz/artifacts/parse_from_client/<file>
+#![no_main]
+
+use code_challenge_game_types::messages;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = std::str::from_utf8(data) {
+        let _ = serde_json::from_str::<messages::FromClient>(line);
+    }
+});
@@ -0,0 +1,12 @@
+// Moves are parsed as JSON before the game gets to look at them.
+// Run with `cargo +nightly fuzz run parse_player_move`, see parse_from_client.rs for more.
+#![no_main]
+
+use code_challenge_game_types::messages;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = std::str::from_utf8(data) {
+        let _ = serde_json::from_str::<messages::Move<serde_json::Value>>(line);
+    }
+});
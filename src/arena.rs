@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
+
+use crate::{
+    async_game_trait::AsyncGameTrait,
+    controller::{ControllerInfo, ControllerMsg, UiSender},
+};
+
+/// Everything one controller loop needs
+pub struct SlotSetup {
+    pub channel: (mpsc::Sender<ControllerMsg>, mpsc::Receiver<ControllerMsg>),
+    pub game: Box<dyn AsyncGameTrait>,
+    pub ui_sender: UiSender,
+    pub status_tx: watch::Sender<ControllerInfo>,
+}
+
+pub struct GameSlot {
+    pub tx: mpsc::Sender<ControllerMsg>,
+}
+
+/// Several games side by side, each run by its own controller loop. A new player joins the game
+/// with the fewest players
+#[derive(Clone)]
+pub struct ArenaController {
+    slots: Arc<Vec<GameSlot>>,
+    // Held while a player is routed, so two players joining at once see each other in the counts
+    joining: Arc<Mutex<()>>,
+}
+
+impl ArenaController {
+    pub fn new(slots: Vec<GameSlot>) -> Self {
+        Self {
+            slots: Arc::new(slots),
+            joining: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Sends `connected_msg` to the least loaded game, the connection talks to the returned
+    /// sender from then on. `None` if every controller is gone
+    pub async fn join(&self, connected_msg: ControllerMsg) -> Option<mpsc::Sender<ControllerMsg>> {
+        let _joining = self.joining.lock().await;
+        let slot = self.least_loaded().await?;
+        slot.tx.send(connected_msg).await.ok()?;
+        Some(slot.tx.clone())
+    }
+
+    // The first one wins a tie
+    async fn least_loaded(&self) -> Option<&GameSlot> {
+        let mut least: Option<(usize, &GameSlot)> = None;
+        for slot in self.slots.iter() {
+            let (count_tx, count_rx) = oneshot::channel();
+            if slot
+                .tx
+                .send(ControllerMsg::GetPlayerCount(count_tx))
+                .await
+                .is_err()
+            {
+                continue;
+            }
+            let Ok(count) = count_rx.await else {
+                continue;
+            };
+            if !matches!(least, Some((least_count, _)) if least_count <= count) {
+                least = Some((count, slot));
+            }
+        }
+        least.map(|(_, slot)| slot)
+    }
+}
@@ -1,7 +1,12 @@
 use async_trait::async_trait;
 use code_challenge_game_types::gametraits::*;
 use dyn_clone;
-use std::{fmt::Debug};
+use log::warn;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[async_trait]
 pub trait AsyncGameTrait: dyn_clone::DynClone + Send + Debug {
@@ -19,12 +24,96 @@ pub trait AsyncGameTrait: dyn_clone::DynClone + Send + Debug {
 
     async fn reset(&mut self, users: Vec<User>);
 
+    // Takes back the last move, the turn is whoever made it. None if the game can't or there's
+    // nothing to take back
+    async fn undo_last_move(&mut self) -> Option<PlayerTurn>;
+
     fn get_paint(&self) -> Box<dyn Paint>;
+
+    fn describe(&self) -> String;
+
+    // The lobby holds players back until there's this many
+    fn min_players(&self) -> usize;
+
+    // Anyone past this many is turned away
+    fn max_players(&self) -> usize;
+
+    // Who won a game that just ended in `PlayerMoveResult::Draw`, for games where the last move
+    // can lose. None if it really was a draw
+    fn winner(&self) -> Option<String>;
+}
+
+dyn_clone::clone_trait_object!(AsyncGameTrait);
+
+// For games that weren't given a description
+const UNKNOWN_GAME: &str = "Unknown game";
+
+// So the wrappers below can go around whatever game was picked at startup
+#[async_trait]
+impl AsyncGameTrait for Box<dyn AsyncGameTrait> {
+    async fn player_moves(
+        &mut self,
+        turn_token: TurnToken,
+        player_move: PlayerMove,
+    ) -> PlayerMoveResult {
+        (**self).player_moves(turn_token, player_move).await
+    }
+    async fn current_player_disconnected(&mut self, turn_token: TurnToken) -> Option<PlayerTurn> {
+        (**self).current_player_disconnected(turn_token).await
+    }
+
+    async fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        (**self).try_start_game().await
+    }
+
+    async fn player_connected(&mut self, user: User) {
+        (**self).player_connected(user).await
+    }
+    async fn player_disconnected(&mut self, user: &str) {
+        (**self).player_disconnected(user).await
+    }
+
+    async fn reset(&mut self, users: Vec<User>) {
+        (**self).reset(users).await
+    }
+
+    async fn undo_last_move(&mut self) -> Option<PlayerTurn> {
+        (**self).undo_last_move().await
+    }
+
+    fn get_paint(&self) -> Box<dyn Paint> {
+        (**self).get_paint()
+    }
+
+    fn describe(&self) -> String {
+        (**self).describe()
+    }
+
+    fn min_players(&self) -> usize {
+        (**self).min_players()
+    }
+
+    fn max_players(&self) -> usize {
+        (**self).max_players()
+    }
+
+    fn winner(&self) -> Option<String> {
+        (**self).winner()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct AsyncGame<T> {
     pub game: T,
+    // TODO: Take from GameTrait::describe once game-types has it
+    description: String,
+    // TODO: Take from GameTrait::min_players/max_players once game-types has them
+    min_players: usize,
+    max_players: usize,
+    // TODO: Drop once PlayerMoveResult can say who won
+    winner: fn(&T) -> Option<String>,
+    // TODO: Drop once game-types has GameTrait::undo_last_move
+    undo: fn(&mut T) -> Option<PlayerTurn>,
 }
 
 impl<T> AsyncGame<T>
@@ -32,12 +121,50 @@ where
     T: 'static + GameTrait + Clone + Paint,
 {
     pub fn new(game: T) -> Self {
-        Self { game }
+        Self {
+            game,
+            description: UNKNOWN_GAME.to_string(),
+            min_players: 1,
+            max_players: usize::MAX,
+            winner: |_| None,
+            undo: |_| None,
+        }
+    }
+
+    pub fn with_description(mut self, description: String) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn with_player_limits(mut self, min_players: usize, max_players: usize) -> Self {
+        self.min_players = min_players;
+        self.max_players = max_players;
+        self
+    }
+
+    pub fn with_winner(mut self, winner: fn(&T) -> Option<String>) -> Self {
+        self.winner = winner;
+        self
+    }
+
+    pub fn with_undo(mut self, undo: fn(&mut T) -> Option<PlayerTurn>) -> Self {
+        self.undo = undo;
+        self
     }
 
     pub fn make_ptr_from_game(game: T) -> Box<dyn AsyncGameTrait> {
         Box::new(AsyncGame::new(game))
     }
+
+    /// Gives up on any call to the game that takes longer than `timeout`, see `TimeoutGame`. The
+    /// game runs on the blocking thread pool like with `BlockingAsyncGame`, a call that never
+    /// returns couldn't be given up on otherwise
+    pub fn make_ptr_with_timeout(game: T, timeout: Duration) -> Box<dyn AsyncGameTrait>
+    where
+        T: Send,
+    {
+        Box::new(TimeoutGame::new(BlockingAsyncGame::new(game), timeout))
+    }
 }
 
 #[async_trait]
@@ -71,8 +198,784 @@ where
         self.game.reset(users)
     }
 
+    async fn undo_last_move(&mut self) -> Option<PlayerTurn> {
+        (self.undo)(&mut self.game)
+    }
+
     fn get_paint(&self) -> Box<dyn Paint> {
-        
         dyn_clone::clone_box(&*Box::new(self.game.clone())) as _
     }
+
+    fn describe(&self) -> String {
+        self.description.clone()
+    }
+
+    fn min_players(&self) -> usize {
+        self.min_players
+    }
+
+    fn max_players(&self) -> usize {
+        self.max_players
+    }
+
+    fn winner(&self) -> Option<String> {
+        (self.winner)(&self.game)
+    }
+}
+
+/// Like `AsyncGame`, but the calls that change the game run on tokio's blocking thread pool, so a
+/// slow game doesn't hold up the connections. Each call works on a copy of the game that only
+/// takes its place once the call is done, a call that's given up on (see `TimeoutGame`) leaves the
+/// game as it was
+#[derive(Debug, Clone)]
+pub struct BlockingAsyncGame<T> {
+    game: T,
+    // TODO: Take from GameTrait::describe once game-types has it
+    description: String,
+    // TODO: Take from GameTrait::min_players/max_players once game-types has them
+    min_players: usize,
+    max_players: usize,
+    // TODO: Drop once PlayerMoveResult can say who won
+    winner: fn(&T) -> Option<String>,
+    // TODO: Drop once game-types has GameTrait::undo_last_move
+    undo: fn(&mut T) -> Option<PlayerTurn>,
+}
+
+impl<T> BlockingAsyncGame<T>
+where
+    T: 'static + GameTrait + Clone + Paint + Send,
+{
+    pub fn new(game: T) -> Self {
+        Self {
+            game,
+            description: UNKNOWN_GAME.to_string(),
+            min_players: 1,
+            max_players: usize::MAX,
+            winner: |_| None,
+            undo: |_| None,
+        }
+    }
+
+    pub fn with_description(mut self, description: String) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn with_player_limits(mut self, min_players: usize, max_players: usize) -> Self {
+        self.min_players = min_players;
+        self.max_players = max_players;
+        self
+    }
+
+    pub fn with_winner(mut self, winner: fn(&T) -> Option<String>) -> Self {
+        self.winner = winner;
+        self
+    }
+
+    pub fn with_undo(mut self, undo: fn(&mut T) -> Option<PlayerTurn>) -> Self {
+        self.undo = undo;
+        self
+    }
+
+    pub fn make_ptr_from_game(game: T) -> Box<dyn AsyncGameTrait> {
+        Box::new(BlockingAsyncGame::new(game))
+    }
+
+    async fn on_blocking_thread<R, F>(&mut self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut T) -> R + Send + 'static,
+    {
+        let mut game = self.game.clone();
+        let (game, result) = tokio::task::spawn_blocking(move || {
+            let result = f(&mut game);
+            (game, result)
+        })
+        .await
+        .unwrap();
+        // Not reached if this call was dropped, whatever the abandoned call did goes with it
+        self.game = game;
+        result
+    }
+}
+
+#[async_trait]
+impl<T> AsyncGameTrait for BlockingAsyncGame<T>
+where
+    T: 'static + GameTrait + Clone + Paint + Send,
+{
+    async fn player_moves(
+        &mut self,
+        turn_token: TurnToken,
+        player_move: PlayerMove,
+    ) -> PlayerMoveResult {
+        self.on_blocking_thread(move |game| game.player_moves(turn_token, player_move))
+            .await
+    }
+    async fn current_player_disconnected(&mut self, turn_token: TurnToken) -> Option<PlayerTurn> {
+        self.on_blocking_thread(move |game| game.current_player_disconnected(turn_token))
+            .await
+    }
+
+    async fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        self.on_blocking_thread(|game| game.try_start_game()).await
+    }
+
+    async fn player_connected(&mut self, user: User) {
+        self.on_blocking_thread(move |game| game.player_connected(user))
+            .await
+    }
+    async fn player_disconnected(&mut self, user: &str) {
+        let user = user.to_string();
+        self.on_blocking_thread(move |game| game.player_disconnected(&user))
+            .await
+    }
+
+    async fn reset(&mut self, users: Vec<User>) {
+        self.on_blocking_thread(move |game| game.reset(users)).await
+    }
+
+    async fn undo_last_move(&mut self) -> Option<PlayerTurn> {
+        let undo = self.undo;
+        self.on_blocking_thread(move |game| undo(game)).await
+    }
+
+    fn get_paint(&self) -> Box<dyn Paint> {
+        Box::new(self.game.clone())
+    }
+
+    fn describe(&self) -> String {
+        self.description.clone()
+    }
+
+    fn min_players(&self) -> usize {
+        self.min_players
+    }
+
+    fn max_players(&self) -> usize {
+        self.max_players
+    }
+
+    fn winner(&self) -> Option<String> {
+        (self.winner)(&self.game)
+    }
+}
+
+/// Wraps another game so that a call that hangs can't freeze the controller. A call that takes
+/// longer than `timeout` is given up on: `player_moves` is an invalid move, `try_start_game` and
+/// `current_player_disconnected` have nobody to go next, and the rest are logged and skipped
+#[derive(Debug, Clone)]
+pub struct TimeoutGame<T> {
+    inner: T,
+    timeout: Duration,
+}
+
+impl<T: AsyncGameTrait> TimeoutGame<T> {
+    pub fn new(inner: T, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+}
+
+#[async_trait]
+impl<T> AsyncGameTrait for TimeoutGame<T>
+where
+    T: AsyncGameTrait + Clone + 'static,
+{
+    async fn player_moves(
+        &mut self,
+        turn_token: TurnToken,
+        player_move: PlayerMove,
+    ) -> PlayerMoveResult {
+        let timeout = self.timeout;
+        tokio::time::timeout(timeout, self.inner.player_moves(turn_token, player_move))
+            .await
+            .unwrap_or_else(|_| {
+                warn!("Game took longer than {timeout:?} to make a move, calling it invalid");
+                PlayerMoveResult::InvalidMove(None)
+            })
+    }
+    async fn current_player_disconnected(&mut self, turn_token: TurnToken) -> Option<PlayerTurn> {
+        let timeout = self.timeout;
+        tokio::time::timeout(timeout, self.inner.current_player_disconnected(turn_token))
+            .await
+            .unwrap_or_else(|_| {
+                warn!("Game took longer than {timeout:?} to pick the next player");
+                None
+            })
+    }
+
+    async fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        let timeout = self.timeout;
+        tokio::time::timeout(timeout, self.inner.try_start_game())
+            .await
+            .unwrap_or_else(|_| {
+                warn!("Game took longer than {timeout:?} to start");
+                None
+            })
+    }
+
+    async fn player_connected(&mut self, user: User) {
+        if tokio::time::timeout(self.timeout, self.inner.player_connected(user))
+            .await
+            .is_err()
+        {
+            warn!("Game took longer than {:?} to add a player", self.timeout);
+        }
+    }
+    async fn player_disconnected(&mut self, user: &str) {
+        if tokio::time::timeout(self.timeout, self.inner.player_disconnected(user))
+            .await
+            .is_err()
+        {
+            warn!("Game took longer than {:?} to remove {user}", self.timeout);
+        }
+    }
+
+    async fn reset(&mut self, users: Vec<User>) {
+        if tokio::time::timeout(self.timeout, self.inner.reset(users))
+            .await
+            .is_err()
+        {
+            warn!("Game took longer than {:?} to reset", self.timeout);
+        }
+    }
+
+    async fn undo_last_move(&mut self) -> Option<PlayerTurn> {
+        let timeout = self.timeout;
+        tokio::time::timeout(timeout, self.inner.undo_last_move())
+            .await
+            .unwrap_or_else(|_| {
+                warn!("Game took longer than {timeout:?} to undo a move");
+                None
+            })
+    }
+
+    fn get_paint(&self) -> Box<dyn Paint> {
+        self.inner.get_paint()
+    }
+
+    fn describe(&self) -> String {
+        self.inner.describe()
+    }
+
+    fn min_players(&self) -> usize {
+        self.inner.min_players()
+    }
+
+    fn max_players(&self) -> usize {
+        self.inner.max_players()
+    }
+
+    fn winner(&self) -> Option<String> {
+        self.inner.winner()
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MethodMetrics {
+    pub calls: AtomicU64,
+    pub total_ns: AtomicU64,
+}
+
+impl MethodMetrics {
+    fn record(&self, started: Instant) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        let ns = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.total_ns.fetch_add(ns, Ordering::Relaxed);
+    }
+}
+
+/// How often each game method was called and how long it took in total
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GameMetrics {
+    pub player_moves: MethodMetrics,
+    pub current_player_disconnected: MethodMetrics,
+    pub try_start_game: MethodMetrics,
+    pub player_connected: MethodMetrics,
+    pub player_disconnected: MethodMetrics,
+    pub reset: MethodMetrics,
+    pub undo_last_move: MethodMetrics,
+    pub get_paint: MethodMetrics,
+}
+
+impl GameMetrics {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Counts the calls to another game and times them, to find out which method is slow without
+/// setting up Prometheus. Clones count into the same `GameMetrics`
+#[derive(Debug, Clone)]
+pub struct MeteredGame<T> {
+    inner: T,
+    metrics: Arc<GameMetrics>,
+}
+
+impl<T: AsyncGameTrait> MeteredGame<T> {
+    pub fn new(inner: T) -> Self {
+        Self::with_metrics(inner, Arc::default())
+    }
+
+    /// Several games counting into the same `metrics`
+    pub fn with_metrics(inner: T, metrics: Arc<GameMetrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    pub fn metrics(&self) -> &GameMetrics {
+        &self.metrics
+    }
+}
+
+#[async_trait]
+impl<T> AsyncGameTrait for MeteredGame<T>
+where
+    T: AsyncGameTrait + Clone + 'static,
+{
+    async fn player_moves(
+        &mut self,
+        turn_token: TurnToken,
+        player_move: PlayerMove,
+    ) -> PlayerMoveResult {
+        let started = Instant::now();
+        let result = self.inner.player_moves(turn_token, player_move).await;
+        self.metrics.player_moves.record(started);
+        result
+    }
+    async fn current_player_disconnected(&mut self, turn_token: TurnToken) -> Option<PlayerTurn> {
+        let started = Instant::now();
+        let next_turn = self.inner.current_player_disconnected(turn_token).await;
+        self.metrics.current_player_disconnected.record(started);
+        next_turn
+    }
+
+    async fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        let started = Instant::now();
+        let first_turn = self.inner.try_start_game().await;
+        self.metrics.try_start_game.record(started);
+        first_turn
+    }
+
+    async fn player_connected(&mut self, user: User) {
+        let started = Instant::now();
+        self.inner.player_connected(user).await;
+        self.metrics.player_connected.record(started);
+    }
+    async fn player_disconnected(&mut self, user: &str) {
+        let started = Instant::now();
+        self.inner.player_disconnected(user).await;
+        self.metrics.player_disconnected.record(started);
+    }
+
+    async fn reset(&mut self, users: Vec<User>) {
+        let started = Instant::now();
+        self.inner.reset(users).await;
+        self.metrics.reset.record(started);
+    }
+
+    async fn undo_last_move(&mut self) -> Option<PlayerTurn> {
+        let started = Instant::now();
+        let turn = self.inner.undo_last_move().await;
+        self.metrics.undo_last_move.record(started);
+        turn
+    }
+
+    fn get_paint(&self) -> Box<dyn Paint> {
+        let started = Instant::now();
+        let paint = self.inner.get_paint();
+        self.metrics.get_paint.record(started);
+        paint
+    }
+
+    fn describe(&self) -> String {
+        self.inner.describe()
+    }
+
+    fn min_players(&self) -> usize {
+        self.inner.min_players()
+    }
+
+    fn max_players(&self) -> usize {
+        self.inner.max_players()
+    }
+
+    fn winner(&self) -> Option<String> {
+        self.inner.winner()
+    }
+}
+
+// What LoggedGame logs to, turn it on with e.g. RUST_LOG=game=debug
+pub const GAME_LOG_TARGET: &str = "game";
+
+/// Logs every call to another game before passing it on, the same way for every game
+#[derive(Debug, Clone)]
+pub struct LoggedGame<T> {
+    inner: T,
+    // Asked for on every call, so it's whatever logger main set up by then
+    logger: fn() -> &'static dyn log::Log,
+}
+
+impl<T: AsyncGameTrait> LoggedGame<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            logger: log::logger,
+        }
+    }
+
+    /// Logs somewhere else than the global logger, for tests that want to see the calls
+    #[allow(dead_code)]
+    pub fn with_logger(mut self, logger: fn() -> &'static dyn log::Log) -> Self {
+        self.logger = logger;
+        self
+    }
+}
+
+impl<T> LoggedGame<T> {
+    // Like debug!(target: GAME_LOG_TARGET, ..), to `self.logger`
+    fn log_call(&self, args: std::fmt::Arguments) {
+        let logger = (self.logger)();
+        let metadata = log::Metadata::builder()
+            .level(log::Level::Debug)
+            .target(GAME_LOG_TARGET)
+            .build();
+        if logger.enabled(&metadata) {
+            logger.log(&log::Record::builder().metadata(metadata).args(args).build());
+        }
+    }
+}
+
+#[async_trait]
+impl<T> AsyncGameTrait for LoggedGame<T>
+where
+    T: AsyncGameTrait + Clone + 'static,
+{
+    async fn player_moves(
+        &mut self,
+        turn_token: TurnToken,
+        player_move: PlayerMove,
+    ) -> PlayerMoveResult {
+        self.log_call(format_args!(
+            "player_moves player={} move={}",
+            turn_token.user.name, player_move.serialized
+        ));
+        self.inner.player_moves(turn_token, player_move).await
+    }
+    async fn current_player_disconnected(&mut self, turn_token: TurnToken) -> Option<PlayerTurn> {
+        self.log_call(format_args!(
+            "current_player_disconnected player={}",
+            turn_token.user.name
+        ));
+        self.inner.current_player_disconnected(turn_token).await
+    }
+
+    async fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        self.log_call(format_args!("try_start_game"));
+        self.inner.try_start_game().await
+    }
+
+    async fn player_connected(&mut self, user: User) {
+        self.log_call(format_args!("player_connected player={}", user.name));
+        self.inner.player_connected(user).await
+    }
+    async fn player_disconnected(&mut self, user: &str) {
+        self.log_call(format_args!("player_disconnected player={user}"));
+        self.inner.player_disconnected(user).await
+    }
+
+    async fn reset(&mut self, users: Vec<User>) {
+        let names: Vec<&str> = users.iter().map(|user| user.name.as_str()).collect();
+        self.log_call(format_args!("reset players={names:?}"));
+        self.inner.reset(users).await
+    }
+
+    async fn undo_last_move(&mut self) -> Option<PlayerTurn> {
+        self.log_call(format_args!("undo_last_move"));
+        self.inner.undo_last_move().await
+    }
+
+    fn get_paint(&self) -> Box<dyn Paint> {
+        self.inner.get_paint()
+    }
+
+    fn describe(&self) -> String {
+        self.inner.describe()
+    }
+
+    fn min_players(&self) -> usize {
+        self.inner.min_players()
+    }
+
+    fn max_players(&self) -> usize {
+        self.inner.max_players()
+    }
+
+    fn winner(&self) -> Option<String> {
+        self.inner.winner()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Takes `delay` to answer anything
+    #[derive(Debug, Clone)]
+    struct SlowGame {
+        delay: Duration,
+        resets: usize,
+    }
+
+    #[async_trait]
+    impl AsyncGameTrait for SlowGame {
+        async fn player_moves(
+            &mut self,
+            _turn_token: TurnToken,
+            _player_move: PlayerMove,
+        ) -> PlayerMoveResult {
+            tokio::time::sleep(self.delay).await;
+            PlayerMoveResult::Win
+        }
+        async fn current_player_disconnected(
+            &mut self,
+            turn_token: TurnToken,
+        ) -> Option<PlayerTurn> {
+            tokio::time::sleep(self.delay).await;
+            Some(PlayerTurn {
+                token: turn_token,
+                state: PlayerGameState {
+                    serialized: String::new(),
+                },
+            })
+        }
+
+        async fn try_start_game(&mut self) -> Option<PlayerTurn> {
+            tokio::time::sleep(self.delay).await;
+            Some(PlayerTurn {
+                token: TurnToken { user: user() },
+                state: PlayerGameState {
+                    serialized: String::new(),
+                },
+            })
+        }
+
+        async fn player_connected(&mut self, _user: User) {
+            tokio::time::sleep(self.delay).await;
+        }
+        async fn player_disconnected(&mut self, _user: &str) {
+            tokio::time::sleep(self.delay).await;
+        }
+
+        async fn reset(&mut self, _users: Vec<User>) {
+            tokio::time::sleep(self.delay).await;
+            self.resets += 1;
+        }
+
+        async fn undo_last_move(&mut self) -> Option<PlayerTurn> {
+            tokio::time::sleep(self.delay).await;
+            None
+        }
+
+        fn get_paint(&self) -> Box<dyn Paint> {
+            panic!("not painted in tests")
+        }
+
+        fn describe(&self) -> String {
+            "Slow game".to_string()
+        }
+
+        fn min_players(&self) -> usize {
+            1
+        }
+
+        fn max_players(&self) -> usize {
+            usize::MAX
+        }
+
+        fn winner(&self) -> Option<String> {
+            None
+        }
+    }
+
+    fn user() -> User {
+        User {
+            name: "p1".to_string(),
+            color: druid::Color::rgb8(0, 0, 0),
+        }
+    }
+
+    fn timeout_game(delay: Duration) -> TimeoutGame<SlowGame> {
+        TimeoutGame::new(SlowGame { delay, resets: 0 }, Duration::from_millis(20))
+    }
+
+    #[test]
+    fn player_limits_are_what_the_game_was_given() {
+        let game = AsyncGame::new(crate::games::gomoku::Game::new(5, 5, 3, Vec::new()));
+        assert_eq!((game.min_players(), game.max_players()), (1, usize::MAX));
+        let game = game.with_player_limits(2, 4);
+        assert_eq!((game.min_players(), game.max_players()), (2, 4));
+        let game = BlockingAsyncGame::new(crate::games::gomoku::Game::new(5, 5, 3, Vec::new()))
+            .with_player_limits(2, 2);
+        assert_eq!((game.clone().min_players(), game.max_players()), (2, 2));
+    }
+
+    #[tokio::test]
+    async fn slow_move_is_invalid() {
+        let mut game = timeout_game(Duration::from_secs(60));
+        let result = game
+            .player_moves(
+                TurnToken { user: user() },
+                PlayerMove {
+                    serialized: "{}".to_string(),
+                },
+            )
+            .await;
+        assert!(matches!(result, PlayerMoveResult::InvalidMove(None)));
+    }
+
+    #[tokio::test]
+    async fn slow_start_starts_nothing() {
+        let mut game = timeout_game(Duration::from_secs(60));
+        assert!(game.try_start_game().await.is_none());
+        assert!(game
+            .current_player_disconnected(TurnToken { user: user() })
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn slow_reset_is_skipped() {
+        let mut game = timeout_game(Duration::from_secs(60));
+        game.reset(vec![user()]).await;
+        assert_eq!(game.inner.resets, 0);
+    }
+
+    #[tokio::test]
+    async fn fast_game_is_left_alone() {
+        let mut game = timeout_game(Duration::ZERO);
+        let result = game
+            .player_moves(
+                TurnToken { user: user() },
+                PlayerMove {
+                    serialized: "{}".to_string(),
+                },
+            )
+            .await;
+        assert!(matches!(result, PlayerMoveResult::Win));
+        assert!(game.try_start_game().await.is_some());
+        game.reset(Vec::new()).await;
+        assert_eq!(game.inner.resets, 1);
+        assert_eq!(game.describe(), "Slow game");
+    }
+
+    fn calls(metrics: &MethodMetrics) -> u64 {
+        metrics.calls.load(Ordering::Relaxed)
+    }
+
+    #[tokio::test]
+    async fn calls_are_counted() {
+        let mut game = MeteredGame::new(SlowGame {
+            delay: Duration::from_millis(1),
+            resets: 0,
+        });
+        game.player_connected(user()).await;
+        game.try_start_game().await;
+        for _ in 0..3 {
+            game.player_moves(
+                TurnToken { user: user() },
+                PlayerMove {
+                    serialized: "{}".to_string(),
+                },
+            )
+            .await;
+        }
+        game.reset(vec![user()]).await;
+
+        let metrics = game.metrics();
+        assert_eq!(calls(&metrics.player_connected), 1);
+        assert_eq!(calls(&metrics.try_start_game), 1);
+        assert_eq!(calls(&metrics.player_moves), 3);
+        assert_eq!(calls(&metrics.reset), 1);
+        assert_eq!(calls(&metrics.player_disconnected), 0);
+        // Slept at least a millisecond each
+        assert!(metrics.player_moves.total_ns.load(Ordering::Relaxed) >= 3_000_000);
+    }
+
+    #[tokio::test]
+    async fn clones_count_together() {
+        let mut game = MeteredGame::new(SlowGame {
+            delay: Duration::ZERO,
+            resets: 0,
+        });
+        let mut clone = game.clone();
+        game.reset(Vec::new()).await;
+        clone.reset(Vec::new()).await;
+        assert_eq!(calls(&game.metrics().reset), 2);
+    }
+
+    #[test]
+    fn metrics_as_json() {
+        let metrics = GameMetrics::default();
+        metrics.reset.calls.store(2, Ordering::Relaxed);
+        metrics.reset.total_ns.store(1500, Ordering::Relaxed);
+
+        let json: serde_json::Value = serde_json::from_str(&metrics.to_json()).unwrap();
+        assert_eq!(json["reset"]["calls"], 2);
+        assert_eq!(json["reset"]["total-ns"], 1500);
+        assert_eq!(json["player-moves"]["calls"], 0);
+    }
+
+    // Keeps what's logged to GAME_LOG_TARGET
+    struct CapturingLogger {
+        lines: Mutex<Vec<String>>,
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == GAME_LOG_TARGET
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.lines.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger {
+        lines: Mutex::new(Vec::new()),
+    };
+
+    // Not the global logger, the flow tests set that up too
+    fn capturing_logger() -> &'static dyn log::Log {
+        &LOGGER
+    }
+
+    #[tokio::test]
+    async fn moves_are_logged_with_the_player() {
+        let mut game = LoggedGame::new(SlowGame {
+            delay: Duration::ZERO,
+            resets: 0,
+        })
+        .with_logger(capturing_logger);
+        let result = game
+            .player_moves(
+                TurnToken { user: user() },
+                PlayerMove {
+                    serialized: r#"{"x":1}"#.to_string(),
+                },
+            )
+            .await;
+        assert!(matches!(result, PlayerMoveResult::Win));
+
+        let lines = LOGGER.lines.lock().unwrap();
+        assert!(lines.iter().any(|line| line.contains("player_moves")
+            && line.contains("player=p1")
+            && line.contains(r#"{"x":1}"#)));
+    }
 }
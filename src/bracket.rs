@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BracketSlot {
+    Player(String),
+    // Nobody, whoever is drawn against it goes through without playing
+    Bye,
+    // Waiting for the match before it
+    Undecided,
+}
+
+/// Single elimination, the winner of every match goes on to the next round until one is left
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bracket {
+    // The first round has everyone, each round after is half as long, the last one is the champion.
+    // The winner of slots 2i and 2i+1 ends up in slot i of the round after
+    rounds: Vec<Vec<BracketSlot>>,
+}
+
+impl Bracket {
+    /// `seeded` is best first, the best are kept apart until the late rounds
+    pub fn new(seeded: Vec<String>) -> Self {
+        let size = seeded.len().next_power_of_two();
+        let first_round = seed_order(size)
+            .into_iter()
+            .map(|seed| match seeded.get(seed) {
+                Some(name) => BracketSlot::Player(name.clone()),
+                None => BracketSlot::Bye,
+            })
+            .collect::<Vec<_>>();
+        let mut rounds = vec![first_round];
+        while rounds.last().unwrap().len() > 1 {
+            let len = rounds.last().unwrap().len() / 2;
+            rounds.push(vec![BracketSlot::Undecided; len]);
+        }
+        let mut bracket = Self { rounds };
+        bracket.advance_byes();
+        bracket
+    }
+
+    /// The match to play now, the same one until `report_winner` is told how it went
+    pub fn next_match(&self) -> Option<(String, String)> {
+        let (round, i) = self.next_match_index()?;
+        match (&self.rounds[round][2 * i], &self.rounds[round][2 * i + 1]) {
+            (BracketSlot::Player(a), BracketSlot::Player(b)) => Some((a.clone(), b.clone())),
+            _ => None,
+        }
+    }
+
+    /// Moves `winner` on from the next match, returns who they knocked out. `None` if `winner`
+    /// isn't in it
+    pub fn report_winner(&mut self, winner: &str) -> Option<String> {
+        let (a, b) = self.next_match()?;
+        let loser = if a == winner {
+            b
+        } else if b == winner {
+            a
+        } else {
+            return None;
+        };
+        let (round, i) = self.next_match_index()?;
+        self.rounds[round + 1][i] = BracketSlot::Player(winner.to_string());
+        self.advance_byes();
+        Some(loser)
+    }
+
+    pub fn champion(&self) -> Option<&str> {
+        match self.rounds.last()?.first()? {
+            BracketSlot::Player(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn rounds(&self) -> &[Vec<BracketSlot>] {
+        &self.rounds
+    }
+
+    fn next_match_index(&self) -> Option<(usize, usize)> {
+        for round in 0..self.rounds.len() - 1 {
+            for i in 0..self.rounds[round + 1].len() {
+                let ready = matches!(
+                    (&self.rounds[round][2 * i], &self.rounds[round][2 * i + 1]),
+                    (BracketSlot::Player(_), BracketSlot::Player(_))
+                );
+                if ready && self.rounds[round + 1][i] == BracketSlot::Undecided {
+                    return Some((round, i));
+                }
+            }
+        }
+        None
+    }
+
+    fn advance_byes(&mut self) {
+        for round in 0..self.rounds.len() - 1 {
+            for i in 0..self.rounds[round + 1].len() {
+                if self.rounds[round + 1][i] != BracketSlot::Undecided {
+                    continue;
+                }
+                self.rounds[round + 1][i] =
+                    match (&self.rounds[round][2 * i], &self.rounds[round][2 * i + 1]) {
+                        (BracketSlot::Player(name), BracketSlot::Bye)
+                        | (BracketSlot::Bye, BracketSlot::Player(name)) => {
+                            BracketSlot::Player(name.clone())
+                        }
+                        (BracketSlot::Bye, BracketSlot::Bye) => BracketSlot::Bye,
+                        _ => BracketSlot::Undecided,
+                    };
+            }
+        }
+    }
+}
+
+// Zero based seeds in first round order, 1 meets the last seed, 2 the second last and so on
+fn seed_order(size: usize) -> Vec<usize> {
+    let mut order = vec![0];
+    while order.len() < size {
+        let len = order.len() * 2;
+        order = order.into_iter().flat_map(|s| [s, len - 1 - s]).collect();
+    }
+    order
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn names(n: usize) -> Vec<String> {
+        (1..=n).map(|i| format!("p{i}")).collect()
+    }
+
+    fn pair(a: &str, b: &str) -> Option<(String, String)> {
+        Some((a.to_string(), b.to_string()))
+    }
+
+    #[test]
+    fn four_players_play_three_matches() {
+        let mut bracket = Bracket::new(names(4));
+
+        assert_eq!(bracket.next_match(), pair("p1", "p4"));
+        assert_eq!(bracket.report_winner("p1"), Some("p4".to_string()));
+        assert_eq!(bracket.next_match(), pair("p2", "p3"));
+        assert_eq!(bracket.report_winner("p3"), Some("p2".to_string()));
+        assert_eq!(bracket.next_match(), pair("p1", "p3"));
+        assert_eq!(bracket.champion(), None);
+        assert_eq!(bracket.report_winner("p3"), Some("p1".to_string()));
+
+        assert_eq!(bracket.next_match(), None);
+        assert_eq!(bracket.champion(), Some("p3"));
+    }
+
+    #[test]
+    fn top_seeds_get_the_byes() {
+        let mut bracket = Bracket::new(names(5));
+
+        // p1, p2 and p3 go through to the second round without playing
+        assert_eq!(bracket.next_match(), pair("p4", "p5"));
+        bracket.report_winner("p5");
+        assert_eq!(bracket.next_match(), pair("p1", "p5"));
+        bracket.report_winner("p1");
+        assert_eq!(bracket.next_match(), pair("p2", "p3"));
+        bracket.report_winner("p2");
+        assert_eq!(bracket.next_match(), pair("p1", "p2"));
+        bracket.report_winner("p2");
+        assert_eq!(bracket.champion(), Some("p2"));
+    }
+
+    #[test]
+    fn only_someone_in_the_match_can_win_it() {
+        let mut bracket = Bracket::new(names(4));
+        assert_eq!(bracket.report_winner("p2"), None);
+        assert_eq!(bracket.report_winner("nobody"), None);
+        assert_eq!(bracket.next_match(), pair("p1", "p4"));
+    }
+
+    #[test]
+    fn seeds_are_spread_out() {
+        assert_eq!(seed_order(2), vec![0, 1]);
+        assert_eq!(seed_order(4), vec![0, 3, 1, 2]);
+        assert_eq!(seed_order(8), vec![0, 7, 3, 4, 1, 6, 2, 5]);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{path::Path, time::Duration};
+
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum GameKind {
+    Gomoku,
+    Tictactoe,
+    Reversi,
+    Rps,
+    NumberGuess,
+    Wordle,
+    Nim,
+}
+
+/// Server settings from `--config <path>`, see config.example.toml. Anything left out of the
+/// file gets the built-in default
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen_addr: String,
+    pub channel_buffer: usize,
+    pub default_turn_delay_ms: u64,
+    pub default_win_delay_ms: u64,
+    pub game_type: GameKind,
+    // Only used by gomoku
+    pub board_width: usize,
+    pub board_height: usize,
+    pub max_connections: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen_addr: "127.0.0.1:7654".to_string(),
+            channel_buffer: 1024,
+            default_turn_delay_ms: 200,
+            default_win_delay_ms: 500,
+            game_type: GameKind::Gomoku,
+            board_width: 20,
+            board_height: 20,
+            max_connections: 256,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(std::io::Error),
+    Parse(toml::de::Error),
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Read)?;
+        toml::from_str(&text).map_err(ConfigError::Parse)
+    }
+
+    pub fn turn_delay(&self) -> Duration {
+        Duration::from_millis(self.default_turn_delay_ms)
+    }
+
+    pub fn win_delay(&self) -> Duration {
+        Duration::from_millis(self.default_win_delay_ms)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn missing_settings_get_defaults() {
+        let config: ServerConfig = toml::from_str(
+            r#"
+            game_type = "number-guess"
+            default_win_delay_ms = 1000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.game_type, GameKind::NumberGuess);
+        assert_eq!(config.win_delay(), Duration::from_secs(1));
+        assert_eq!(config.listen_addr, ServerConfig::default().listen_addr);
+        assert_eq!(config.turn_delay(), ServerConfig::default().turn_delay());
+    }
+
+    #[test]
+    fn example_config_is_the_defaults() {
+        let example: ServerConfig = toml::from_str(include_str!("../config.example.toml")).unwrap();
+        assert_eq!(example, ServerConfig::default());
+    }
+
+    #[test]
+    fn typos_are_errors() {
+        assert!(toml::from_str::<ServerConfig>("max_conections = 2").is_err());
+    }
+}
@@ -1,4 +1,13 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
 use crate::async_game_trait::AsyncGameTrait;
 use code_challenge_game_types::{
@@ -7,12 +16,17 @@ use code_challenge_game_types::{
 };
 use druid::ExtEventSink;
 use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
 use tokio::{
     select,
-    sync::{mpsc, oneshot},
+    sync::{mpsc, oneshot, watch},
 };
 
 use crate::{
+    bracket::Bracket,
+    config::ServerConfig,
+    event_log::EventLogger,
+    metrics::Metrics,
     player_table::{PlayerInfo, PlayerTable},
     ui,
 };
@@ -26,16 +40,65 @@ pub type ErrorSender = oneshot::Sender<ToClient>;
 #[derive(Debug)]
 pub enum ControllerMsg {
     ImConnected(ImConnectedMsg),
+    ImSpectating(SpectatorMsg),
     ImDisconnected(String),
+    Resign(String),
+    Kick(String),
+    PauseGame,
+    ResumeGame,
     GoToMode(GameMode),
     ResetGame,
     SetTurnDelay(Duration),
     SetWinDelay(Duration),
+    // None lets players think as long as they like
+    SetTurnTimeLimit(Option<Duration>),
+    // A game still going after this long is a draw, None lets games go on forever
+    SetMaxGameDuration(Option<Duration>),
+    // The game is reset once nobody has been around for this long, None keeps it as it is
+    SetIdleTimeout(Option<Duration>),
+    // Instead of what the game says, from the next game on
+    SetMinPlayers(usize),
+    // Instead of what the game says, only turns away players who connect after it
+    SetMaxPlayers(usize),
+    // Runs after the ones added before it, for every move from then on
+    AddValidator(Arc<dyn MoveValidator>),
+    // An announcement from the host to everyone connected right now
+    Broadcast(String),
+    Shutdown,
+    GetHistory(oneshot::Sender<Vec<MoveRecord>>),
+    // A snapshot, the UI gets the same thing pushed to it after every event
+    GetStatus(oneshot::Sender<ControllerInfo>),
+    Chat { from: String, message: String },
+    OfferDraw(String),
+    AcceptDraw(String),
+    DeclineDraw(String),
+    // Take back the last move of the running game
+    RequestUndo { player: String },
+    SetDrawOfferTimeout(Duration),
+    // None turns the event log off
+    SetEventLog(Option<PathBuf>),
+    // Players, not spectators
+    GetPlayerCount(oneshot::Sender<usize>),
+    // Send the UI everything again, it just switched to this game
+    RefreshUi,
+    // Seeds everyone by ELO into a bracket, goes to `GameMode::Tournament`
+    StartTournament,
+    // Undo zooming and panning of the board in the UI
+    ResetView,
+    // Lets the host play by clicking the board in the UI
+    EnableManualInput,
+    DisableManualInput,
+    // Played as if `player` sent {"x":x,"y":y}, an empty `player` is whoever's turn it is
+    ManualMove { player: String, x: usize, y: usize },
+    // Played as if whoever's turn it is sent it, how a game moves on in `GameMode::Spectate`
+    InjectMove(String),
 }
 
 pub struct ImConnectedMsg {
     pub player_name: String,
     pub controller_to_player_sender: mpsc::Sender<ControllerToPlayerMsg>,
+    // The same one as last time takes over the player's seat instead of being turned away
+    pub session_token: Option<String>,
 }
 
 impl std::fmt::Debug for ImConnectedMsg {
@@ -44,34 +107,149 @@ impl std::fmt::Debug for ImConnectedMsg {
     }
 }
 
+pub struct SpectatorMsg {
+    pub name: String,
+    pub tx: mpsc::Sender<ControllerToPlayerMsg>,
+}
+
+impl std::fmt::Debug for SpectatorMsg {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "'{}'", self.name)
+    }
+}
+
 #[derive(Clone)]
 pub struct ControllerSender {
     pub rt_handle: tokio::runtime::Handle,
-    pub tx: mpsc::Sender<ControllerMsg>,
+    // One per game, messages go to the game the UI is showing
+    pub txs: Vec<mpsc::Sender<ControllerMsg>>,
+    pub shown: Arc<AtomicUsize>,
 }
 
-#[derive(Clone)]
+// Durations are in milliseconds when serialized
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct ControllerInfo {
+    #[serde(with = "users_serde")]
     pub connected_users: Vec<User>,
     pub game_mode: GameMode,
     pub score: HashMap<String, u64>,
+    // Only means something to this server process, kept up to date with connected_users
+    #[serde(skip)]
+    pub player_stats: HashMap<String, PlayerStats>,
+    // Since the server started, an undone move stops counting
+    pub total_moves: u32,
+    // Moves made in the game being played, 0 between games
+    pub turn_number: u32,
+    // Only means something to this server process, sent as how long ago it was
+    #[serde(skip)]
+    pub game_start_time: Option<Instant>,
+    // Whose turn it is, None between games
+    pub current_player: Option<String>,
+    pub elo_ratings: HashMap<String, f64>,
+    #[serde(with = "duration_millis")]
     pub turndelay: Duration,
+    #[serde(with = "duration_millis")]
     pub windelay: Duration,
+    #[serde(with = "option_duration_millis")]
+    pub turn_time_limit: Option<Duration>,
+    #[serde(with = "option_duration_millis")]
+    pub max_game_duration: Option<Duration>,
+    #[serde(with = "option_duration_millis")]
+    pub idle_timeout: Option<Duration>,
+    // Set by the operator, the game's own limits are used when None
+    pub min_players_override: Option<usize>,
+    pub max_players_override: Option<usize>,
+    pub paused: bool,
+    // Whether moves clicked in the UI are played
+    pub manual_input: bool,
+    // Only means something to this server process
+    #[serde(skip)]
+    pub draw_offer: Option<DrawOffer>,
+    // How long the other players have to accept a draw offer
+    #[serde(with = "duration_millis")]
+    pub draw_offer_timeout: Duration,
+    // Only in tournament mode
+    pub bracket: Option<Bracket>,
+    // From reading a move to being done with it, the latest MOVE_LATENCIES moves
+    #[serde(skip)]
+    pub move_latencies: VecDeque<Duration>,
+}
+
+/// How a connected player has been doing since they connected
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerStats {
+    pub move_count: u64,
+    pub connection_time: Instant,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrawOffer {
+    pub from: String,
+    pub offered_at: Instant,
+    pub accepted: HashSet<String>,
 }
 
 impl Default for ControllerInfo {
     fn default() -> Self {
+        Self::from(&ServerConfig::default())
+    }
+}
+
+impl From<&ServerConfig> for ControllerInfo {
+    fn from(config: &ServerConfig) -> Self {
         Self {
             connected_users: Default::default(),
             game_mode: GameMode::Practice,
             score: HashMap::default(),
-            turndelay: Duration::from_millis(200),
-            windelay: Duration::from_millis(500),
+            player_stats: HashMap::default(),
+            total_moves: 0,
+            turn_number: 0,
+            game_start_time: None,
+            current_player: None,
+            elo_ratings: HashMap::default(),
+            turndelay: config.turn_delay(),
+            windelay: config.win_delay(),
+            turn_time_limit: None,
+            max_game_duration: None,
+            idle_timeout: None,
+            min_players_override: None,
+            max_players_override: None,
+            paused: false,
+            manual_input: false,
+            draw_offer: None,
+            draw_offer_timeout: Duration::from_secs(30),
+            bracket: None,
+            move_latencies: VecDeque::new(),
         }
     }
 }
 
 impl ControllerInfo {
+    /// Most wins first, a tie goes by name
+    pub fn standings(&self) -> Vec<PlayerScore> {
+        let mut standings: Vec<PlayerScore> = self
+            .score
+            .iter()
+            .map(|(player, wins)| PlayerScore {
+                player: player.clone(),
+                wins: *wins,
+            })
+            .collect();
+        standings.sort_by(|a, b| b.wins.cmp(&a.wins).then_with(|| a.player.cmp(&b.player)));
+        standings
+    }
+
+    pub fn min_players(&self, game: &dyn AsyncGameTrait) -> usize {
+        self.min_players_override
+            .unwrap_or_else(|| game.min_players())
+    }
+
+    pub fn max_players(&self, game: &dyn AsyncGameTrait) -> usize {
+        self.max_players_override
+            .unwrap_or_else(|| game.max_players())
+    }
+
     fn add_player_win(&mut self, name: &String) {
         // Hehe
         match self.score.get_mut(name) {
@@ -84,16 +262,187 @@ impl ControllerInfo {
         }
     }
 
+    // Ratings outlive the scores, a new competition doesn't make anyone better or worse
+    fn rate_win(&mut self, winner: &str, players: &PlayerTable) {
+        for loser in self.in_game(players).iter().filter(|name| *name != winner) {
+            update_elo(winner, loser, ELO_K, &mut self.elo_ratings);
+        }
+    }
+
+    fn rate_draw(&mut self, players: &PlayerTable) {
+        let names = self.in_game(players);
+        for (i, a) in names.iter().enumerate() {
+            for b in &names[i + 1..] {
+                update_elo_draw(a, b, ELO_K, &mut self.elo_ratings);
+            }
+        }
+    }
+
+    // Everyone, or just the two in the current match of a tournament
+    fn in_game(&self, players: &PlayerTable) -> Vec<String> {
+        match (&self.game_mode, &self.bracket) {
+            (GameMode::Tournament, Some(bracket)) => match bracket.next_match() {
+                Some((a, b)) => vec![a, b],
+                None => Vec::new(),
+            },
+            _ => players.iter().map(|p| p.name.clone()).collect(),
+        }
+    }
+
+    // Who `winner` knocked out of the tournament, if there is one
+    fn report_tournament_winner(&mut self, winner: &str) -> Option<String> {
+        if self.game_mode != GameMode::Tournament {
+            return None;
+        }
+        let bracket = self.bracket.as_mut()?;
+        let loser = bracket.report_winner(winner)?;
+        if let Some(champion) = bracket.champion() {
+            info!("{champion} won the tournament");
+        }
+        Some(loser)
+    }
+
     fn reset_scores(&mut self) {
         self.score = HashMap::new();
     }
+
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_value(self).unwrap();
+        json["latency-p99-ms"] = self
+            .p99_latency()
+            .map(|latency| latency.as_millis() as u64)
+            .into();
+        json["game-start-time-secs-ago"] = self
+            .game_start_time
+            .map(|start| start.elapsed().as_secs())
+            .into();
+        json.to_string()
+    }
+
+    fn record_latency(&mut self, latency: Duration) {
+        if self.move_latencies.len() == MOVE_LATENCIES {
+            self.move_latencies.pop_front();
+        }
+        self.move_latencies.push_back(latency);
+    }
+
+    pub fn p50_latency(&self) -> Option<Duration> {
+        self.latency_percentile(50)
+    }
+
+    pub fn p95_latency(&self) -> Option<Duration> {
+        self.latency_percentile(95)
+    }
+
+    pub fn p99_latency(&self) -> Option<Duration> {
+        self.latency_percentile(99)
+    }
+
+    // Nearest rank, the smallest latency that at least `percent` of the moves are at or under
+    fn latency_percentile(&self, percent: usize) -> Option<Duration> {
+        let mut sorted: Vec<Duration> = self.move_latencies.iter().copied().collect();
+        sorted.sort();
+        let rank = (percent * sorted.len()).div_ceil(100);
+        sorted.get(rank.saturating_sub(1)).copied()
+    }
+
+    // Forgets the offer if it's too old to accept
+    fn current_draw_offer(&mut self) -> Option<&mut DrawOffer> {
+        let timeout = self.draw_offer_timeout;
+        if matches!(&self.draw_offer, Some(offer) if offer.offered_at.elapsed() >= timeout) {
+            debug!("Draw offer expired");
+            self.draw_offer = None;
+        }
+        self.draw_offer.as_mut()
+    }
+}
+
+/// A rule for every game, checked before the move gets to the game. A move that breaks it is
+/// treated like an invalid move, the player gets the error back and is out of the game
+pub trait MoveValidator: Send + Sync + std::fmt::Debug {
+    /// The error for the player, or None if the move may go on to the game
+    fn validate(&self, player: &str, move_json: &str, info: &ControllerInfo) -> Option<ToClient>;
+}
+
+/// Turns away a move that is the same as the last one from the same player, e.g. a bot that
+/// doesn't read the state and sends one move over and over. The last move is remembered across
+/// games, so it only fits games where the same move twice in a row is never useful
+#[derive(Debug, Default)]
+pub struct AntiSpamValidator {
+    last_moves: Mutex<HashMap<String, String>>,
+}
+
+impl MoveValidator for AntiSpamValidator {
+    fn validate(&self, player: &str, move_json: &str, _info: &ControllerInfo) -> Option<ToClient> {
+        let mut last_moves = self.last_moves.lock().unwrap();
+        if last_moves.get(player).map(String::as_str) == Some(move_json) {
+            return Some(messages::INVALID_MOVE);
+        }
+        last_moves.insert(player.to_string(), move_json.to_string());
+        None
+    }
+}
+
+pub const INITIAL_ELO: f64 = 1000.0;
+const ELO_K: f64 = 32.0;
+
+fn elo_expected(rating: f64, opponent_rating: f64) -> f64 {
+    1.0 / (1.0 + 10_f64.powf((opponent_rating - rating) / 400.0))
+}
+
+pub fn update_elo(winner: &str, loser: &str, k: f64, ratings: &mut HashMap<String, f64>) {
+    let winner_elo = *ratings.get(winner).unwrap_or(&INITIAL_ELO);
+    let loser_elo = *ratings.get(loser).unwrap_or(&INITIAL_ELO);
+    let winner_delta = k * (1.0 - elo_expected(winner_elo, loser_elo));
+    ratings.insert(winner.to_string(), winner_elo + winner_delta);
+    ratings.insert(loser.to_string(), loser_elo - winner_delta);
+}
+
+// Both scored 0.5, the lower rated one gains what the other loses
+pub fn update_elo_draw(a: &str, b: &str, k: f64, ratings: &mut HashMap<String, f64>) {
+    let a_elo = *ratings.get(a).unwrap_or(&INITIAL_ELO);
+    let b_elo = *ratings.get(b).unwrap_or(&INITIAL_ELO);
+    let a_delta = k * (0.5 - elo_expected(a_elo, b_elo));
+    ratings.insert(a.to_string(), a_elo + a_delta);
+    ratings.insert(b.to_string(), b_elo - a_delta);
+}
+
+const MAX_MOVE_HISTORY: usize = 10_000;
+// How many of the latest moves the latency percentiles are taken over
+const MOVE_LATENCIES: usize = 100;
+/// How often the controller logs that it's still alive
+pub const WATCHDOG_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct MoveRecord {
+    pub player: String,
+    pub serialized_move: String,
+    pub timestamp: SystemTime,
+    pub result_kind: &'static str,
+    pub color: druid::Color,
+    // Counts every move since the server started, from 1
+    pub turn: u32,
+}
+
+fn result_kind(result: &PlayerMoveResult) -> &'static str {
+    match result {
+        PlayerMoveResult::Ok(_) => "ok",
+        PlayerMoveResult::Win => "win",
+        PlayerMoveResult::Draw => "draw",
+        PlayerMoveResult::InvalidMove(_) => "invalid move",
+        PlayerMoveResult::InvalidFormat(_) => "invalid format",
+    }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum GameMode {
     Practice,
     Gating,
     Competition,
+    Tournament,
+    // Everyone watches, nobody is asked to move
+    Spectate,
 }
 
 fn player_info_to_user(info: &PlayerInfo) -> User {
@@ -103,20 +452,48 @@ fn player_info_to_user(info: &PlayerInfo) -> User {
     }
 }
 
-// struct GameRunningData {
-//     move_receiver: oneshot::Receiver<PlayerMoveMsg>,
-//     token: TurnToken,
-// }
-
-// impl GameRunningData {
-//     fn to_tuple(self) -> (oneshot::Receiver<PlayerMoveMsg>, TurnToken) {
-//         (self.move_receiver, self.token)
-//     }
-// }
+// Who the game is reset with. A tournament match against someone who has left is won without
+// playing it
+fn game_users(controller_info: &mut ControllerInfo, players: &PlayerTable) -> Vec<User> {
+    loop {
+        let names = controller_info.in_game(players);
+        match names.iter().find(|name| players.get(name).is_none()) {
+            Some(gone) if controller_info.game_mode == GameMode::Tournament => {
+                let winner = names.iter().find(|name| *name != gone).unwrap().clone();
+                debug!("{gone} isn't here, {winner} goes through");
+                controller_info.report_tournament_winner(&winner);
+            }
+            _ => {
+                return names
+                    .iter()
+                    .filter_map(|name| players.get(name))
+                    .map(player_info_to_user)
+                    .collect()
+            }
+        }
+    }
+}
 
-// fn run_data_to_some_tuple(run_data: GameRunningData) -> (Option<oneshot::Receiver<PlayerMoveMsg>>, Option<TurnToken>) {
+/// The turn the controller is currently waiting on
+struct RunningTurn<Fut> {
+    move_rx: oneshot::Receiver<PlayerMoveMsg>,
+    token: TurnToken,
+    // Kept around to ask again after a pause
+    state: gametraits::PlayerGameState,
+    // Fires when the player has used up their time for this turn
+    deadline: Option<Pin<Box<Fut>>>,
+    asked_at: Instant,
+}
 
-// }
+async fn until_deadline<Fut>(deadline: &mut Option<Pin<Box<Fut>>>)
+where
+    Fut: std::future::Future<Output = ()>,
+{
+    match deadline {
+        Some(deadline) => deadline.await,
+        None => std::future::pending().await,
+    }
+}
 
 // 1. Wait for any message.
 //      - If there are players playing, wait for move
@@ -134,115 +511,311 @@ fn player_info_to_user(info: &PlayerInfo) -> User {
 pub async fn controller_loop<Fut>(
     mut controller_rx: mpsc::Receiver<ControllerMsg>,
     ui_sender: UiSender,
+    status_tx: watch::Sender<ControllerInfo>,
+    metrics: Metrics,
+    mut event_log: EventLogger,
     mut game: Box<dyn AsyncGameTrait>,
     sleep_fn: &impl Fn(std::time::Duration) -> Fut,
 ) where
     Fut: std::future::Future<Output = ()>,
 {
-    let mut game_running_data: Option<(oneshot::Receiver<PlayerMoveMsg>, TurnToken)> = None;
-    let mut players = PlayerTable::new();
+    let mut game_running_data: Option<RunningTurn<Fut>> = None;
+    let mut players = PlayerTable::with_capacity(game.max_players());
     let mut controller_info = ControllerInfo::default();
+    let mut move_history = VecDeque::<MoveRecord>::new();
+    let mut waiting_queue = Lobby::new();
+    let mut counted_connections = 0;
+    let mut counted_players = Vec::<String>::new();
+    // Player name -> the session token they connected with, if they sent one
+    let mut session_tokens = HashMap::<String, String>::new();
+    // Timed with sleep_fn like everything else, so tests can move the clock
+    let mut watchdog = Box::pin(sleep_fn(WATCHDOG_INTERVAL));
+    // Set when a game starts, if there's a max game duration
+    let mut game_deadline: Option<Pin<Box<Fut>>> = None;
+    // Set when the last player leaves, if there's an idle timeout
+    let mut idle_deadline: Option<Pin<Box<Fut>>> = None;
+    let mut anyone_here = false;
+    let mut validators: Vec<Arc<dyn MoveValidator>> = Vec::new();
     ui_sender.send_new_state(game.get_paint());
 
     loop {
-        let event = if let Some(p_move_rx) = game_running_data.as_mut().map(|(recv, _)| recv) {
+        // While paused, or spectating with nobody asked to move, the current turn waits here
+        // untouched
+        let event = if let Some(RunningTurn {
+            move_rx, deadline, ..
+        }) = game_running_data
+            .as_mut()
+            .filter(|_| !controller_info.paused && controller_info.game_mode != GameMode::Spectate)
+        {
             debug!("Waiting for move or control Msg");
             select! {
                 v = controller_rx.recv() => { match v {
                     Some(msg) => Event::ControllerMsg(msg),
                     None => panic!("Connection accept loop dropped its TX"),
                 }}
-                mov_msg = p_move_rx => { match mov_msg {
+                mov_msg = move_rx => { match mov_msg {
                     Ok(msg) => Event::Move(msg),
                     Err(_) => Event::PlayerMoveDropped,
                 }}
+                _ = until_deadline(deadline) => Event::TurnTimeout,
+                _ = until_deadline(&mut game_deadline) => Event::GameTimeUp,
+                _ = until_deadline(&mut idle_deadline) => Event::IdleTimeout,
+                _ = &mut watchdog => Event::Watchdog,
             }
         } else {
             debug!("Waiting for Control Msg");
-            match controller_rx.recv().await {
-                Some(msg) => Event::ControllerMsg(msg),
-                None => panic!("Connection accept loop dropped its TX"),
+            select! {
+                v = controller_rx.recv() => { match v {
+                    Some(msg) => Event::ControllerMsg(msg),
+                    None => panic!("Connection accept loop dropped its TX"),
+                }}
+                _ = &mut watchdog => Event::Watchdog,
+                _ = until_deadline(&mut idle_deadline) => Event::IdleTimeout,
             }
         };
         info!("Event: {:?}", event);
+        // A move clicked in the UI goes on as if the player had sent it
+        let event = match event {
+            Event::ControllerMsg(ControllerMsg::ManualMove { player, x, y })
+                if controller_info.manual_input
+                    && !controller_info.paused
+                    && game_running_data.as_ref().map_or(false, |turn| {
+                        player.is_empty() || player == turn.token.user.name
+                    }) =>
+            {
+                let (move_err_tx, _) = oneshot::channel();
+                Event::Move(PlayerMoveMsg {
+                    mov: gametraits::PlayerMove {
+                        serialized: format!(r#"{{"x":{x},"y":{y}}}"#),
+                    },
+                    move_err_tx,
+                    received_at: Instant::now(),
+                })
+            }
+            Event::ControllerMsg(ControllerMsg::InjectMove(serialized))
+                if !controller_info.paused && game_running_data.is_some() =>
+            {
+                let (move_err_tx, _) = oneshot::channel();
+                Event::Move(PlayerMoveMsg {
+                    mov: gametraits::PlayerMove { serialized },
+                    move_err_tx,
+                    received_at: Instant::now(),
+                })
+            }
+            // A turn that outlived its time limit is given up on, in case its deadline never fired
+            Event::Watchdog => {
+                watchdog = Box::pin(sleep_fn(WATCHDOG_INTERVAL));
+                metrics.watchdog_ticks.inc();
+                info!(
+                    "controller alive: {} players, turn: {}",
+                    players.player_count(),
+                    controller_info.total_moves
+                );
+                let overdue = !controller_info.paused
+                    && controller_info.game_mode != GameMode::Spectate
+                    && matches!(
+                        (&game_running_data, controller_info.turn_time_limit),
+                        (Some(turn), Some(limit)) if turn.asked_at.elapsed() > limit
+                    );
+                if overdue {
+                    warn!("The current turn is past its time limit, giving up on it");
+                    Event::TurnTimeout
+                } else {
+                    Event::Watchdog
+                }
+            }
+            Event::Move(player_move) => {
+                let who_moved = &game_running_data.as_ref().unwrap().token.user.name;
+                match validators.iter().find_map(|validator| {
+                    validator.validate(who_moved, &player_move.mov.serialized, &controller_info)
+                }) {
+                    Some(error) => {
+                        // The connection closes after the error, like for any invalid move
+                        let _ = player_move.move_err_tx.send(error);
+                        Event::MoveRejected(player_move.mov)
+                    }
+                    None => Event::Move(player_move),
+                }
+            }
+            event => event,
+        };
 
         match event {
             Event::ControllerMsg(ControllerMsg::ImConnected(ImConnectedMsg {
                 player_name,
                 controller_to_player_sender,
+                session_token,
             })) => {
-                if players.get(&player_name).is_some() {
+                let same_session = session_token.is_some()
+                    && session_tokens.get(&player_name) == session_token.as_ref();
+                if same_session
+                    && players
+                        .reconnect_player(&player_name, controller_to_player_sender.clone())
+                        .is_some()
+                {
+                    info!("{player_name} reconnected");
+                    event_log
+                        .log("player-connected", &player_name, "reconnected")
+                        .await;
+                    game_running_data = welcome_back(
+                        &player_name,
+                        game_running_data,
+                        &mut players,
+                        &mut game,
+                        &controller_info,
+                        &sleep_fn,
+                    )
+                    .await;
+                } else if players.get(&player_name).is_some()
+                    || waiting_queue.iter().any(|(name, _)| *name == player_name)
+                {
                     warn!("Player {player_name} connected twice, rejecting second connection");
                     drop(controller_to_player_sender); // Not needed, but nice to be explicit about it
                 } else {
-                    let new_player =
-                        players.add_new_player(player_name, controller_to_player_sender);
-                    game.player_connected(player_info_to_user(new_player)).await;
-                    if game_running_data.is_none() {
-                        // The game is not running
-                        if controller_info.game_mode != GameMode::Gating {
-                            if let Some(gametraits::PlayerTurn { token, state }) =
-                                game.try_start_game().await
-                            {
-                                game_running_data = your_turn(
-                                    &mut players,
-                                    &mut game,
-                                    token,
-                                    state,
-                                    &controller_info,
-                                    &sleep_fn,
-                                )
-                                .await;
-                            }
-                        }
+                    event_log.log("player-connected", &player_name, "").await;
+                    match session_token {
+                        Some(token) => session_tokens.insert(player_name.clone(), token),
+                        None => session_tokens.remove(&player_name),
+                    };
+                    // Player might have disconnected, doesn't matter
+                    let _ = controller_to_player_sender
+                        .send(ControllerToPlayerMsg::ServerInfo(ServerInfo::new(
+                            &*game,
+                            &controller_info,
+                        )))
+                        .await;
+                    if game_running_data.is_none()
+                        && !matches!(
+                            controller_info.game_mode,
+                            GameMode::Gating | GameMode::Tournament
+                        )
+                    {
+                        // Waits in the lobby until there's enough players for a game
+                        waiting_queue.push_back((player_name, controller_to_player_sender));
+                        game_running_data = first_move_new_game(
+                            &mut game,
+                            &mut controller_info,
+                            &mut players,
+                            &mut waiting_queue,
+                            &ui_sender,
+                            &mut game_deadline,
+                            &sleep_fn,
+                        )
+                        .await;
+                    } else {
+                        seat_player(
+                            player_name,
+                            controller_to_player_sender,
+                            &mut game,
+                            &mut controller_info,
+                            &mut players,
+                        )
+                        .await;
                     }
                 }
             }
+            Event::ControllerMsg(ControllerMsg::ImSpectating(SpectatorMsg { name, tx })) => {
+                // Spectator might have disconnected, doesn't matter
+                let _ = tx
+                    .send(ControllerToPlayerMsg::ServerInfo(ServerInfo::new(
+                        &*game,
+                        &controller_info,
+                    )))
+                    .await;
+                let _ = tx
+                    .send(ControllerToPlayerMsg::PlayerList(player_names(
+                        &controller_info,
+                    )))
+                    .await;
+                players.add_spectator(name, tx);
+            }
             Event::ControllerMsg(ControllerMsg::ImDisconnected(name)) => {
-                let was_in_player_table = players.remove_player(&name);
-                if let Some((p_move_rx_2, token)) = game_running_data {
-                    if token.user.name == name {
-                        if let Some(gametraits::PlayerTurn {
-                            token: new_token,
-                            state,
-                        }) = game.current_player_disconnected(token).await
-                        {
-                            debug!("Current player disconnected, but game goes on");
-                            game_running_data = your_turn(
-                                &mut players,
-                                &mut game,
-                                new_token,
-                                state,
-                                &controller_info,
-                                &sleep_fn,
-                            )
-                            .await;
-                        } else {
-                            debug!("Current player disconnected, game stopped");
-                            game_running_data = None;
-                            game.reset(players.iter().map(player_info_to_user).collect())
-                                .await;
-                        }
-                    } else {
-                        debug!("Not the current player disconnected");
-                        // Not the current player disconnected
-                        // In some cases, the player might already be out of the game.
-                        if was_in_player_table {
-                            game.player_disconnected(&name).await;
-                        }
-                        game_running_data = Some((p_move_rx_2, token));
+                event_log.log("player-disconnected", &name, "").await;
+                if leave_lobby(&mut waiting_queue, &name).await.is_none() {
+                    game_running_data = player_left(
+                        &name,
+                        game_running_data,
+                        &mut players,
+                        &mut game,
+                        &mut controller_info,
+                        &sleep_fn,
+                    )
+                    .await;
+                }
+            }
+            Event::ControllerMsg(ControllerMsg::Kick(name)) => {
+                info!("Kicking {name}");
+                event_log.log("player-disconnected", &name, "kicked").await;
+                if let Some(tx) = leave_lobby(&mut waiting_queue, &name).await {
+                    // Player might have disconnected, doesn't matter
+                    let _ = tx.send(ControllerToPlayerMsg::Kicked).await;
+                } else {
+                    if let Some(player) = players.get(&name) {
+                        // Player might have disconnected, doesn't matter
+                        let _ = player.tx.send(ControllerToPlayerMsg::Kicked).await;
                     }
+                    game_running_data = player_left(
+                        &name,
+                        game_running_data,
+                        &mut players,
+                        &mut game,
+                        &mut controller_info,
+                        &sleep_fn,
+                    )
+                    .await;
                 }
             }
-            Event::ControllerMsg(ControllerMsg::GoToMode(new_mode)) => {
-                let open_gates = matches!(controller_info.game_mode, GameMode::Gating)
-                    && !matches!(new_mode, GameMode::Gating);
-                controller_info.game_mode = new_mode;
-                if open_gates {
-                    debug!("Open the gates");
-                    if let Some(gametraits::PlayerTurn { token, state }) =
-                        game.try_start_game().await
-                    {
+            Event::ControllerMsg(ControllerMsg::Resign(name)) => {
+                players.remove_player(&name);
+                if game_running_data.take().is_some() {
+                    debug!("{name} resigned, game over");
+                    event_log.log("game-over", &name, "resigned").await;
+                    metrics.games_completed.inc();
+                    ui_sender.send_game_over(&controller_info.score);
+                    let winners: Vec<String> = controller_info
+                        .in_game(&players)
+                        .into_iter()
+                        .filter(|winner| *winner != name)
+                        .collect();
+                    for winner in winners.iter() {
+                        update_elo(winner, &name, ELO_K, &mut controller_info.elo_ratings);
+                    }
+                    if let Some(winner) = winners.first() {
+                        controller_info.report_tournament_winner(winner);
+                    }
+                    announce_forfeit(name, &mut players).await;
+                    sleep_fn(controller_info.windelay).await;
+                    announce_score(&mut players, &controller_info).await;
+                    game.reset(game_users(&mut controller_info, &players)).await;
+                    game_running_data = first_move_new_game(
+                        &mut game,
+                        &mut controller_info,
+                        &mut players,
+                        &mut waiting_queue,
+                        &ui_sender,
+                        &mut game_deadline,
+                        &sleep_fn,
+                    )
+                    .await;
+                } else {
+                    game.player_disconnected(&name).await;
+                }
+            }
+            Event::ControllerMsg(ControllerMsg::PauseGame) => {
+                if !controller_info.paused {
+                    info!("Pausing game");
+                    controller_info.paused = true;
+                    send_to_everyone(&mut players, || ControllerToPlayerMsg::Paused).await;
+                }
+            }
+            Event::ControllerMsg(ControllerMsg::ResumeGame) => {
+                if controller_info.paused {
+                    info!("Resuming game");
+                    controller_info.paused = false;
+                    send_to_everyone(&mut players, || ControllerToPlayerMsg::Resumed).await;
+                    // Whatever the current player sent during the pause is thrown away, they get
+                    // asked again
+                    if let Some(RunningTurn { token, state, .. }) = game_running_data.take() {
                         game_running_data = your_turn(
                             &mut players,
                             &mut game,
@@ -254,15 +827,251 @@ pub async fn controller_loop<Fut>(
                         .await;
                     }
                 }
+            }
+            Event::ControllerMsg(ControllerMsg::GoToMode(GameMode::Tournament)) => {
+                warn!("A tournament needs a bracket, start it with StartTournament");
+            }
+            Event::ControllerMsg(ControllerMsg::GoToMode(new_mode)) => {
+                let leave_tournament = controller_info.game_mode == GameMode::Tournament;
+                let was_spectating = controller_info.game_mode == GameMode::Spectate;
+                let open_gates = matches!(
+                    controller_info.game_mode,
+                    GameMode::Gating | GameMode::Tournament
+                ) && !matches!(new_mode, GameMode::Gating);
+                controller_info.game_mode = new_mode;
+                if leave_tournament {
+                    debug!("Leaving the tournament");
+                    controller_info.bracket = None;
+                    // Only the last match was in the game, everyone is now
+                    game.reset(game_users(&mut controller_info, &players)).await;
+                    game_running_data = None;
+                }
+                if open_gates {
+                    debug!("Open the gates");
+                    game_running_data = first_move_new_game(
+                        &mut game,
+                        &mut controller_info,
+                        &mut players,
+                        &mut waiting_queue,
+                        &ui_sender,
+                        &mut game_deadline,
+                        &sleep_fn,
+                    )
+                    .await;
+                }
                 if matches!(controller_info.game_mode, GameMode::Gating) {
                     debug!("Gating");
                     controller_info.reset_scores();
-                    game.reset(players.iter().map(player_info_to_user).collect())
-                        .await;
+                    game.reset(game_users(&mut controller_info, &players)).await;
 
                     // Drop any incoming moves
                     game_running_data = None;
                 }
+                if was_spectating != (controller_info.game_mode == GameMode::Spectate) {
+                    // Either everyone watches the current turn now, or its player is asked again
+                    if let Some(RunningTurn { token, state, .. }) = game_running_data.take() {
+                        game_running_data = your_turn(
+                            &mut players,
+                            &mut game,
+                            token,
+                            state,
+                            &controller_info,
+                            &sleep_fn,
+                        )
+                        .await;
+                    }
+                }
+            }
+            Event::ControllerMsg(ControllerMsg::StartTournament) => {
+                let mut seeded: Vec<String> = players
+                    .iter()
+                    .map(|p| p.name.clone())
+                    .chain(waiting_queue.iter().map(|(name, _)| name.clone()))
+                    .collect();
+                if seeded.len() < 2 {
+                    warn!("Too few players for a tournament");
+                } else {
+                    // Stable, so equal ratings keep the order they connected in
+                    let elo = |name: &String| {
+                        *controller_info
+                            .elo_ratings
+                            .get(name)
+                            .unwrap_or(&INITIAL_ELO)
+                    };
+                    seeded.sort_by(|a, b| elo(b).total_cmp(&elo(a)));
+                    info!("Starting a tournament, seeded {seeded:?}");
+                    controller_info.game_mode = GameMode::Tournament;
+                    while let Some((name, tx)) = waiting_queue.pop_front() {
+                        seat_player(name, tx, &mut game, &mut controller_info, &mut players).await;
+                    }
+                    controller_info.bracket = Some(Bracket::new(seeded));
+                    game.reset(game_users(&mut controller_info, &players)).await;
+                    // Drop any incoming moves, the first match starts below
+                    game_running_data = None;
+                }
+            }
+            Event::ControllerMsg(ControllerMsg::Shutdown) => {
+                info!("Shutting down, telling all players");
+                send_to_all(&mut players, GameOverReason::Shutdown).await;
+                return;
+            }
+            Event::ControllerMsg(ControllerMsg::GetHistory(reply_tx)) => {
+                // Asker might have given up, doesn't matter
+                let _ = reply_tx.send(move_history.iter().cloned().collect());
+            }
+            Event::ControllerMsg(ControllerMsg::Chat { from, message }) => {
+                ui_sender.send_chat(&from, &message);
+                send_to_everyone(&mut players, || ControllerToPlayerMsg::Chat {
+                    from: from.clone(),
+                    message: message.clone(),
+                })
+                .await;
+            }
+            Event::ControllerMsg(ControllerMsg::Broadcast(message)) => {
+                info!("Announcing {message:?}");
+                send_to_everyone(&mut players, || {
+                    ControllerToPlayerMsg::Info(message.clone())
+                })
+                .await;
+                for (_, tx) in waiting_queue.iter() {
+                    // Player might have disconnected, doesn't matter
+                    let _ = tx.send(ControllerToPlayerMsg::Info(message.clone())).await;
+                }
+            }
+            Event::ControllerMsg(ControllerMsg::OfferDraw(name)) => {
+                if game_running_data.is_some() && controller_info.current_draw_offer().is_none() {
+                    info!("{name} offers a draw");
+                    controller_info.draw_offer = Some(DrawOffer {
+                        from: name.clone(),
+                        offered_at: Instant::now(),
+                        accepted: HashSet::new(),
+                    });
+                    for p in players.iter().filter(|p| p.name != name) {
+                        // Player might have disconnected, doesn't matter
+                        let _ =
+                            p.tx.send(ControllerToPlayerMsg::DrawOffer { from: name.clone() })
+                                .await;
+                    }
+                }
+            }
+            Event::ControllerMsg(ControllerMsg::AcceptDraw(name)) => {
+                let everyone_agrees = match controller_info.current_draw_offer() {
+                    Some(offer) if offer.from != name => {
+                        offer.accepted.insert(name);
+                        players
+                            .iter()
+                            .all(|p| p.name == offer.from || offer.accepted.contains(&p.name))
+                    }
+                    _ => false,
+                };
+                if everyone_agrees && game_running_data.take().is_some() {
+                    debug!("Draw accepted by everyone, game over");
+                    event_log.log("game-over", "", "draw").await;
+                    metrics.games_completed.inc();
+                    ui_sender.send_game_over(&controller_info.score);
+                    controller_info.rate_draw(&players);
+                    announce_draw(&mut players).await;
+                    sleep_fn(controller_info.windelay).await;
+                    announce_score(&mut players, &controller_info).await;
+                    game.reset(game_users(&mut controller_info, &players)).await;
+                    game_running_data = first_move_new_game(
+                        &mut game,
+                        &mut controller_info,
+                        &mut players,
+                        &mut waiting_queue,
+                        &ui_sender,
+                        &mut game_deadline,
+                        &sleep_fn,
+                    )
+                    .await;
+                }
+            }
+            Event::ControllerMsg(ControllerMsg::DeclineDraw(name)) => {
+                if controller_info.current_draw_offer().is_some() {
+                    info!("{name} declined the draw");
+                    controller_info.draw_offer = None;
+                }
+            }
+            Event::ControllerMsg(ControllerMsg::RequestUndo { player }) => {
+                let undone = match game_running_data {
+                    Some(_) => game.undo_last_move().await,
+                    None => None,
+                };
+                match undone {
+                    Some(gametraits::PlayerTurn { token, state }) => {
+                        info!(
+                            "{player} took back the last move, {} is up again",
+                            token.user.name
+                        );
+                        event_log.log("undo", &player, "").await;
+                        // The history goes back past the start of this game, the turn count doesn't
+                        if move_history.pop_back().is_some() {
+                            controller_info.total_moves =
+                                controller_info.total_moves.saturating_sub(1);
+                            controller_info.turn_number =
+                                controller_info.turn_number.saturating_sub(1);
+                        }
+                        ui_sender.send_new_state(game.get_paint());
+                        ui_sender.send_move_history(&move_history);
+                        send_to_everyone(&mut players, || ControllerToPlayerMsg::UndoGranted).await;
+                        // Whatever the current player is about to send is thrown away
+                        game_running_data = your_turn(
+                            &mut players,
+                            &mut game,
+                            token,
+                            state,
+                            &controller_info,
+                            &sleep_fn,
+                        )
+                        .await;
+                    }
+                    None => {
+                        let reason = if game_running_data.is_some() {
+                            "not supported"
+                        } else {
+                            "no game running"
+                        };
+                        if let Some(p) = players.get(&player) {
+                            // Player might have disconnected, doesn't matter
+                            let _ =
+                                p.tx.send(ControllerToPlayerMsg::UndoDenied {
+                                    reason: reason.to_string(),
+                                })
+                                .await;
+                        }
+                    }
+                }
+            }
+            Event::ControllerMsg(ControllerMsg::SetDrawOfferTimeout(timeout)) => {
+                controller_info.draw_offer_timeout = timeout
+            }
+            Event::ControllerMsg(ControllerMsg::SetEventLog(path)) => {
+                event_log.set_path(path).await
+            }
+            Event::ControllerMsg(ControllerMsg::GetPlayerCount(reply_tx)) => {
+                // Asker might have given up, doesn't matter
+                let _ = reply_tx.send(players.player_count());
+            }
+            Event::ControllerMsg(ControllerMsg::RefreshUi) => {
+                ui_sender.send_new_state(game.get_paint());
+                ui_sender.send_move_history(&move_history);
+            }
+            Event::ControllerMsg(ControllerMsg::ResetView) => ui_sender.send_reset_view(),
+            Event::ControllerMsg(ControllerMsg::EnableManualInput) => {
+                controller_info.manual_input = true
+            }
+            Event::ControllerMsg(ControllerMsg::DisableManualInput) => {
+                controller_info.manual_input = false
+            }
+            Event::ControllerMsg(ControllerMsg::ManualMove { player, x, y }) => {
+                warn!("Ignoring manual move {x},{y} for {player:?}, manual input is off or it's not their turn")
+            }
+            Event::ControllerMsg(ControllerMsg::InjectMove(serialized)) => {
+                warn!("Ignoring injected move {serialized:?}, no game is running or it's paused")
+            }
+            Event::ControllerMsg(ControllerMsg::GetStatus(reply_tx)) => {
+                // Asker might have given up, doesn't matter
+                let _ = reply_tx.send(controller_info.clone());
             }
             Event::ControllerMsg(ControllerMsg::ResetGame) => {
                 // TODO?
@@ -273,11 +1082,73 @@ pub async fn controller_loop<Fut>(
             Event::ControllerMsg(ControllerMsg::SetWinDelay(delay)) => {
                 controller_info.windelay = delay
             }
+            Event::ControllerMsg(ControllerMsg::SetTurnTimeLimit(limit)) => {
+                controller_info.turn_time_limit = limit
+            }
+            // Counts from the start of the next game
+            Event::ControllerMsg(ControllerMsg::SetMaxGameDuration(limit)) => {
+                controller_info.max_game_duration = limit
+            }
+            // Counts from the next time the last player leaves
+            Event::ControllerMsg(ControllerMsg::SetIdleTimeout(timeout)) => {
+                controller_info.idle_timeout = timeout
+            }
+            Event::ControllerMsg(ControllerMsg::SetMinPlayers(min)) => {
+                info!("At least {min} players from the next game on");
+                controller_info.min_players_override = Some(min)
+            }
+            Event::ControllerMsg(ControllerMsg::SetMaxPlayers(max)) => {
+                info!("At most {max} players from now on");
+                controller_info.max_players_override = Some(max);
+                players.set_capacity(max);
+            }
+            Event::ControllerMsg(ControllerMsg::AddValidator(validator)) => {
+                info!("Checking every move with {validator:?}");
+                validators.push(validator)
+            }
             Event::Move(player_move) => {
-                let (_, token) = game_running_data.unwrap();
+                let RunningTurn {
+                    token, asked_at, ..
+                } = game_running_data.unwrap();
                 let who_moved = token.user.name.clone();
+                let mover_color = token.user.color;
+                let serialized_move = player_move.mov.serialized.clone();
                 let move_result = game.player_moves(token, player_move.mov).await;
+                controller_info.record_latency(player_move.received_at.elapsed());
+                metrics.record_move(&move_result, asked_at.elapsed());
+                event_log.log("move", &who_moved, &serialized_move).await;
+                match &move_result {
+                    PlayerMoveResult::Win => {
+                        event_log
+                            .log("game-over", &who_moved, &format!("winner {who_moved}"))
+                            .await
+                    }
+                    PlayerMoveResult::Draw => match game.winner() {
+                        Some(winner) => {
+                            event_log
+                                .log("game-over", &who_moved, &format!("winner {winner}"))
+                                .await
+                        }
+                        None => event_log.log("game-over", &who_moved, "draw").await,
+                    },
+                    _ => (),
+                }
+                if move_history.len() == MAX_MOVE_HISTORY {
+                    move_history.pop_front();
+                }
+                controller_info.total_moves += 1;
+                controller_info.turn_number += 1;
+                let record = MoveRecord {
+                    player: who_moved.clone(),
+                    serialized_move,
+                    timestamp: SystemTime::now(),
+                    result_kind: result_kind(&move_result),
+                    color: mover_color,
+                    turn: controller_info.total_moves,
+                };
                 ui_sender.send_new_state(game.get_paint());
+                ui_sender.send_move(&record);
+                move_history.push_back(record);
                 match react_to_player_move(
                     who_moved,
                     move_result,
@@ -293,37 +1164,185 @@ pub async fn controller_loop<Fut>(
                         debug!("Move result: Game is over, probably too few players, after someone quit/got thrown out");
                         game_running_data = None;
                         sleep_fn(controller_info.windelay).await;
-                        game.reset(players.iter().map(player_info_to_user).collect())
-                            .await;
+                        game.reset(game_users(&mut controller_info, &players)).await;
                     }
-                    PlayerMovesReturn::NextMoveReceiver(next_receiver, next_token) => {
+                    PlayerMovesReturn::NextMoveReceiver(next_turn) => {
                         debug!(
                             "Move result: keep going, next player: {:?}",
-                            next_token.user.name
+                            next_turn.token.user.name
                         );
-                        game_running_data = Some((next_receiver, next_token));
+                        game_running_data = Some(next_turn);
                     }
                     PlayerMovesReturn::GameOver => {
                         debug!("Move result: Game over");
+                        ui_sender.send_game_over(&controller_info.score);
                         sleep_fn(controller_info.windelay).await;
-                        game.reset(players.iter().map(player_info_to_user).collect())
-                            .await;
+                        announce_score(&mut players, &controller_info).await;
+                        game.reset(game_users(&mut controller_info, &players)).await;
                         game_running_data = first_move_new_game(
                             &mut game,
                             &mut controller_info,
                             &mut players,
+                            &mut waiting_queue,
+                            &ui_sender,
+                            &mut game_deadline,
                             &sleep_fn,
                         )
                         .await;
                     }
                 }
             }
+            Event::MoveRejected(mov) => {
+                let RunningTurn { token, .. } = game_running_data.take().unwrap();
+                let who_moved = token.user.name.clone();
+                info!("Move from {who_moved} broke a rule, taking them out of the game");
+                metrics.invalid_moves_total.inc();
+                event_log
+                    .log("move-rejected", &who_moved, &mov.serialized)
+                    .await;
+                players.remove_player(&who_moved);
+                if let Some(gametraits::PlayerTurn { token, state }) =
+                    game.current_player_disconnected(token).await
+                {
+                    game_running_data = your_turn(
+                        &mut players,
+                        &mut game,
+                        token,
+                        state,
+                        &controller_info,
+                        &sleep_fn,
+                    )
+                    .await;
+                } else {
+                    debug!("Nobody left to play after the rejected move, game stopped");
+                    game.reset(game_users(&mut controller_info, &players)).await;
+                }
+            }
             Event::PlayerMoveDropped => {
                 // Do nothing, we'll eventually get an I'm disconnected message
             }
+            Event::Watchdog => (),
+            Event::IdleTimeout => {
+                idle_deadline = None;
+                info!("game idle, reset");
+                game.reset(vec![]).await;
+                ui_sender.send_new_state(game.get_paint());
+            }
+            Event::GameTimeUp => {
+                game_deadline = None;
+                if game_running_data.take().is_some() {
+                    info!("Game went on for too long, it's a draw");
+                    event_log.log("game-over", "", "draw, out of time").await;
+                    metrics.games_completed.inc();
+                    ui_sender.send_game_over(&controller_info.score);
+                    controller_info.rate_draw(&players);
+                    announce_draw(&mut players).await;
+                    sleep_fn(controller_info.windelay).await;
+                    announce_score(&mut players, &controller_info).await;
+                    game.reset(game_users(&mut controller_info, &players)).await;
+                    game_running_data = first_move_new_game(
+                        &mut game,
+                        &mut controller_info,
+                        &mut players,
+                        &mut waiting_queue,
+                        &ui_sender,
+                        &mut game_deadline,
+                        &sleep_fn,
+                    )
+                    .await;
+                }
+            }
+            Event::TurnTimeout => {
+                let RunningTurn { token, .. } = game_running_data.take().unwrap();
+                let who_timed_out = token.user.name.clone();
+                info!("Player {who_timed_out} ran out of time");
+                event_log
+                    .log("player-disconnected", &who_timed_out, "turn timeout")
+                    .await;
+                if let Some(player) = players.get(&who_timed_out) {
+                    // Player might have disconnected, doesn't matter
+                    let _ = player.tx.send(ControllerToPlayerMsg::TurnTimeout).await;
+                }
+                players.remove_player(&who_timed_out);
+                if let Some(gametraits::PlayerTurn { token, state }) =
+                    game.current_player_disconnected(token).await
+                {
+                    debug!("Current player timed out, but game goes on");
+                    game_running_data = your_turn(
+                        &mut players,
+                        &mut game,
+                        token,
+                        state,
+                        &controller_info,
+                        &sleep_fn,
+                    )
+                    .await;
+                } else {
+                    debug!("Current player timed out, game stopped");
+                    game.reset(game_users(&mut controller_info, &players)).await;
+                }
+            }
         } // End event match loop
-        controller_info.connected_users = players.iter().map(player_info_to_user).collect();
+        if game_running_data.is_none() && controller_info.game_mode == GameMode::Tournament {
+            // Next match, also after one that stopped because someone left
+            game_running_data = first_move_new_game(
+                &mut game,
+                &mut controller_info,
+                &mut players,
+                &mut waiting_queue,
+                &ui_sender,
+                &mut game_deadline,
+                &sleep_fn,
+            )
+            .await;
+        }
+        // No turn running means no game, whatever it was that ended it
+        if game_running_data.is_none() {
+            controller_info.turn_number = 0;
+            controller_info.game_start_time = None;
+        }
+        controller_info.current_player = game_running_data
+            .as_ref()
+            .map(|turn| turn.token.user.name.clone());
+        let someone_here = players.player_count() > 0 || !waiting_queue.is_empty();
+        if someone_here {
+            idle_deadline = None;
+        } else if anyone_here {
+            debug!("Last player left");
+            idle_deadline = controller_info
+                .idle_timeout
+                .map(|timeout| Box::pin(sleep_fn(timeout)));
+        }
+        anyone_here = someone_here;
+        broadcast_player_list_if_changed(&mut players, &mut controller_info).await;
+        controller_info.player_stats = players
+            .iter()
+            .map(|p| {
+                let stats = PlayerStats {
+                    move_count: p.move_count,
+                    connection_time: p.connection_time,
+                };
+                (p.name.clone(), stats)
+            })
+            .collect();
         ui_sender.send_controller_info(&controller_info);
+        status_tx.send_replace(controller_info.clone());
+        // Other games might share the metrics, only add what changed here
+        let connections = (players.player_count() + players.spectators().len()) as i64;
+        metrics
+            .active_connections
+            .add(connections - counted_connections);
+        counted_connections = connections;
+        for name in counted_players
+            .iter()
+            .filter(|name| players.get(name).is_none())
+        {
+            metrics.forget_player(name);
+        }
+        for p in players.iter() {
+            metrics.set_player_moves(&p.name, p.move_count);
+        }
+        counted_players = players.iter().map(|p| p.name.clone()).collect();
     }
 }
 
@@ -331,59 +1350,343 @@ pub async fn controller_loop<Fut>(
 enum Event {
     ControllerMsg(ControllerMsg),
     Move(PlayerMoveMsg),
+    // A validator said no and the player has been told, the game never sees the move
+    MoveRejected(gametraits::PlayerMove),
     PlayerMoveDropped,
+    TurnTimeout,
+    Watchdog,
+    // The game has gone on for longer than the max game duration
+    GameTimeUp,
+    // Nobody has been around for the idle timeout
+    IdleTimeout,
+}
+
+async fn player_left<Fut>(
+    name: &str,
+    game_running_data: Option<RunningTurn<Fut>>,
+    players: &mut PlayerTable,
+    game: &mut Box<dyn AsyncGameTrait>,
+    controller_info: &mut ControllerInfo,
+    sleep_fn: &impl Fn(std::time::Duration) -> Fut,
+) -> Option<RunningTurn<Fut>>
+where
+    Fut: std::future::Future<Output = ()>,
+{
+    let was_in_game = controller_info.in_game(players).iter().any(|n| n == name);
+    players.remove_player(name);
+    let running_turn = game_running_data?;
+    if running_turn.token.user.name == name {
+        if let Some(gametraits::PlayerTurn {
+            token: new_token,
+            state,
+        }) = game.current_player_disconnected(running_turn.token).await
+        {
+            debug!("Current player disconnected, but game goes on");
+            your_turn(players, game, new_token, state, controller_info, sleep_fn).await
+        } else {
+            debug!("Current player disconnected, game stopped");
+            game.reset(game_users(controller_info, players)).await;
+            None
+        }
+    } else {
+        debug!("Not the current player disconnected");
+        // Not the current player disconnected
+        // In some cases, the player might already be out of the game.
+        if was_in_game {
+            game.player_disconnected(name).await;
+        }
+        Some(running_turn)
+    }
+}
+
+// Catches a reconnected player up, and asks them again if they were the one being waited on
+async fn welcome_back<Fut>(
+    name: &str,
+    game_running_data: Option<RunningTurn<Fut>>,
+    players: &mut PlayerTable,
+    game: &mut Box<dyn AsyncGameTrait>,
+    controller_info: &ControllerInfo,
+    sleep_fn: &impl Fn(std::time::Duration) -> Fut,
+) -> Option<RunningTurn<Fut>>
+where
+    Fut: std::future::Future<Output = ()>,
+{
+    if let Some(player) = players.get(name) {
+        // Player might have disconnected again, doesn't matter
+        let _ = player
+            .tx
+            .send(ControllerToPlayerMsg::ServerInfo(ServerInfo::new(
+                &**game,
+                controller_info,
+            )))
+            .await;
+        let _ = player
+            .tx
+            .send(ControllerToPlayerMsg::PlayerList(player_names(
+                controller_info,
+            )))
+            .await;
+        if controller_info.paused {
+            let _ = player.tx.send(ControllerToPlayerMsg::Paused).await;
+        }
+    }
+    match game_running_data {
+        // The old connection had the move channel, it's gone with it
+        Some(RunningTurn { token, state, .. }) if token.user.name == name => {
+            your_turn(players, game, token, state, controller_info, sleep_fn).await
+        }
+        running => running,
+    }
 }
 
 async fn send_to_all(players: &mut PlayerTable, msg: GameOverReason) {
+    send_to_everyone(players, || ControllerToPlayerMsg::GameOver(msg.clone())).await;
+}
+
+// Players and spectators alike
+async fn send_to_everyone(players: &mut PlayerTable, make_msg: impl Fn() -> ControllerToPlayerMsg) {
+    send_to_each(players, |_| make_msg()).await;
+}
+
+// Like `send_to_everyone`, but the message can depend on who gets it
+async fn send_to_each(players: &mut PlayerTable, make_msg: impl Fn(&str) -> ControllerToPlayerMsg) {
     let mut disconnected_players = Vec::<String>::new();
     for p in players.iter() {
-        if p.tx
-            .send(ControllerToPlayerMsg::GameOver(msg.clone()))
-            .await
-            .is_err()
-        {
+        if p.tx.send(make_msg(&p.name)).await.is_err() {
             disconnected_players.push(p.name.clone());
         }
     }
     for name in disconnected_players {
         players.remove_player(&name);
     }
+
+    let mut disconnected_spectators = Vec::<String>::new();
+    for s in players.spectators() {
+        if s.tx.send(make_msg(&s.name)).await.is_err() {
+            disconnected_spectators.push(s.name.clone());
+        }
+    }
+    for name in disconnected_spectators {
+        players.remove_spectator(&name);
+    }
+}
+
+fn player_names(controller_info: &ControllerInfo) -> Vec<String> {
+    controller_info
+        .connected_users
+        .iter()
+        .map(|u| u.name.clone())
+        .collect()
+}
+
+// Keeps `connected_users` up to date, and tells everyone when someone joined or left
+async fn broadcast_player_list_if_changed(
+    players: &mut PlayerTable,
+    controller_info: &mut ControllerInfo,
+) {
+    let before = player_names(controller_info);
+    controller_info.connected_users = players.iter().map(player_info_to_user).collect();
+    let names = player_names(controller_info);
+    if names != before {
+        send_to_everyone(players, || ControllerToPlayerMsg::PlayerList(names.clone())).await;
+    }
+}
+
+async fn send_to_spectators(players: &mut PlayerTable, state: &gametraits::PlayerGameState) {
+    let mut disconnected_spectators = Vec::<String>::new();
+    for s in players.spectators() {
+        if s.tx
+            .send(ControllerToPlayerMsg::GameState(state.clone()))
+            .await
+            .is_err()
+        {
+            disconnected_spectators.push(s.name.clone());
+        }
+    }
+    for name in disconnected_spectators {
+        players.remove_spectator(&name);
+    }
 }
 
-async fn announce_winner(winner_name: String, players: &mut PlayerTable) {
-    send_to_all(players, GameOverReason::Winner(winner_name)).await;
+// The one knocked out of a tournament is told so, everyone else hears who won
+async fn announce_winner(
+    winner_name: String,
+    eliminated: Option<String>,
+    players: &mut PlayerTable,
+) {
+    send_to_each(players, |name| {
+        ControllerToPlayerMsg::GameOver(match &eliminated {
+            Some(loser) if loser == name => GameOverReason::Eliminated,
+            _ => GameOverReason::Winner(winner_name.clone()),
+        })
+    })
+    .await;
+}
+
+async fn game_won(winner: String, controller_info: &mut ControllerInfo, players: &mut PlayerTable) {
+    controller_info.add_player_win(&winner);
+    controller_info.rate_win(&winner, players);
+    let eliminated = controller_info.report_tournament_winner(&winner);
+    announce_winner(winner, eliminated, players).await;
 }
 
 async fn announce_draw(players: &mut PlayerTable) {
     send_to_all(players, GameOverReason::Draw).await;
 }
 
-enum PlayerMovesReturn {
+// Between the game over and the reset, so the standings are in before the next board shows up
+async fn announce_score(players: &mut PlayerTable, controller_info: &ControllerInfo) {
+    let standings = controller_info.standings();
+    send_to_everyone(players, || ControllerToPlayerMsg::Score(standings.clone())).await;
+}
+
+// Sent before the first turn, nobody is dropped here, `your_turn` deals with whoever is gone
+async fn announce_game_started(
+    game: &mut Box<dyn AsyncGameTrait>,
+    controller_info: &ControllerInfo,
+    players: &PlayerTable,
+) {
+    let description = game.describe();
+    let names = controller_info.in_game(players);
+    let make_msg = || ControllerToPlayerMsg::GameStarted {
+        game: description.clone(),
+        players: names.clone(),
+    };
+    for p in players.iter() {
+        let _ = p.tx.send(make_msg()).await;
+    }
+    for s in players.spectators() {
+        let _ = s.tx.send(make_msg()).await;
+    }
+}
+
+async fn announce_forfeit(player: String, players: &mut PlayerTable) {
+    send_to_all(players, GameOverReason::Forfeit { player }).await;
+}
+
+enum PlayerMovesReturn<Fut> {
     None,
-    NextMoveReceiver(oneshot::Receiver<PlayerMoveMsg>, TurnToken),
+    NextMoveReceiver(RunningTurn<Fut>),
     GameOver,
 }
 
-impl From<Option<(oneshot::Receiver<PlayerMoveMsg>, TurnToken)>> for PlayerMovesReturn {
-    fn from(a: Option<(oneshot::Receiver<PlayerMoveMsg>, TurnToken)>) -> Self {
+impl<Fut> From<Option<RunningTurn<Fut>>> for PlayerMovesReturn<Fut> {
+    fn from(a: Option<RunningTurn<Fut>>) -> Self {
         match a {
-            Some((receiver, token)) => PlayerMovesReturn::NextMoveReceiver(receiver, token),
+            Some(running_turn) => PlayerMovesReturn::NextMoveReceiver(running_turn),
             None => PlayerMovesReturn::None,
         }
     }
 }
 
+// Players that wait for enough others to show up, in the order they came
+type Lobby = VecDeque<(String, mpsc::Sender<ControllerToPlayerMsg>)>;
+
+async fn tell_queue_positions(waiting_queue: &Lobby) {
+    for (i, (_, tx)) in waiting_queue.iter().enumerate() {
+        // Player might have disconnected, doesn't matter
+        let _ = tx
+            .send(ControllerToPlayerMsg::Waiting {
+                queue_position: i + 1,
+            })
+            .await;
+    }
+}
+
+// Whoever is left waiting moves up
+async fn leave_lobby(
+    waiting_queue: &mut Lobby,
+    name: &str,
+) -> Option<mpsc::Sender<ControllerToPlayerMsg>> {
+    let i = waiting_queue.iter().position(|(n, _)| n == name)?;
+    let (_, tx) = waiting_queue.remove(i)?;
+    tell_queue_positions(waiting_queue).await;
+    Some(tx)
+}
+
+// Into the player table and the game, or turned away if the game can't take more players
+async fn seat_player(
+    name: String,
+    tx: mpsc::Sender<ControllerToPlayerMsg>,
+    game: &mut Box<dyn AsyncGameTrait>,
+    controller_info: &mut ControllerInfo,
+    players: &mut PlayerTable,
+) {
+    if let Err(e) = players.add_new_player(name.clone(), tx.clone()) {
+        info!("Turning {name} away: {e}");
+        // Player might have disconnected, doesn't matter
+        let _ = tx.send(ControllerToPlayerMsg::GameFull).await;
+        return;
+    }
+    controller_info
+        .elo_ratings
+        .entry(name.clone())
+        .or_insert(INITIAL_ELO);
+    broadcast_player_list_if_changed(players, controller_info).await;
+    // Already gone if they couldn't even take the player list
+    if let Some(new_player) = players.get(&name) {
+        if controller_info.paused {
+            // Player might have disconnected, doesn't matter
+            let _ = new_player.tx.send(ControllerToPlayerMsg::Paused).await;
+        }
+        // Latecomers to a tournament sit it out, they're in the next game after it
+        if controller_info.game_mode != GameMode::Tournament {
+            game.player_connected(player_info_to_user(new_player)).await;
+        }
+    }
+}
+
+// Starts a game with everyone in the lobby, or sends everyone to the lobby if there's too few
 async fn first_move_new_game<Fut>(
     game: &mut Box<dyn AsyncGameTrait>,
     controller_info: &mut ControllerInfo,
     players: &mut PlayerTable,
+    waiting_queue: &mut Lobby,
+    ui_sender: &UiSender,
+    game_deadline: &mut Option<Pin<Box<Fut>>>,
     sleep_fn: &impl Fn(std::time::Duration) -> Fut,
-) -> Option<(oneshot::Receiver<PlayerMoveMsg>, TurnToken)>
+) -> Option<RunningTurn<Fut>>
 where
     Fut: std::future::Future<Output = ()>,
 {
+    // An offer made during the last game doesn't carry over, neither does its clock
+    controller_info.draw_offer = None;
+    *game_deadline = None;
+    controller_info.turn_number = 0;
+    controller_info.game_start_time = None;
+    let min_players = controller_info.min_players(&**game);
+    if controller_info.game_mode == GameMode::Tournament {
+        // The game was reset with the next match, if there is one
+        if controller_info.in_game(players).is_empty() {
+            return None;
+        }
+    } else if players.player_count() + waiting_queue.len() < min_players {
+        debug!("Too few players for a game, waiting for more");
+        // Players from the last game have waited the longest
+        let mut lobby: Lobby = players
+            .iter()
+            .map(|p| (p.name.clone(), p.tx.clone()))
+            .collect();
+        for (name, _) in lobby.iter() {
+            players.remove_player(name);
+            game.player_disconnected(name).await;
+        }
+        lobby.append(waiting_queue);
+        *waiting_queue = lobby;
+        tell_queue_positions(waiting_queue).await;
+        return None;
+    }
+    while let Some((name, tx)) = waiting_queue.pop_front() {
+        seat_player(name, tx, game, controller_info, players).await;
+    }
     match game.try_start_game().await {
         Some(PlayerTurn { token, state }) => {
+            announce_game_started(game, controller_info, players).await;
+            ui_sender.send_game_started();
+            controller_info.game_start_time = Some(Instant::now());
+            *game_deadline = controller_info
+                .max_game_duration
+                .map(|limit| Box::pin(sleep_fn(limit)));
             your_turn(players, game, token, state, controller_info, sleep_fn).await
         }
         None => None,
@@ -398,10 +1701,11 @@ async fn react_to_player_move<Fut>(
     players: &mut PlayerTable,
     move_err_tx: oneshot::Sender<messages::ToClient>,
     sleep_fn: &impl Fn(std::time::Duration) -> Fut,
-) -> PlayerMovesReturn
+) -> PlayerMovesReturn<Fut>
 where
     Fut: std::future::Future<Output = ()>,
 {
+    players.record_move(&who_moved);
     match player_move_result {
         PlayerMoveResult::Ok(PlayerTurn { token, state }) => {
             your_turn(players, game, token, state, controller_info, sleep_fn)
@@ -409,14 +1713,23 @@ where
                 .into()
         }
         PlayerMoveResult::Draw => {
-            debug!("Game over, draw");
-            announce_draw(players).await;
+            match game.winner() {
+                // Whoever moved lost, e.g. by taking the last object in misère nim
+                Some(winner) => {
+                    debug!("Game over, {who_moved} lost");
+                    game_won(winner, controller_info, players).await;
+                }
+                None => {
+                    debug!("Game over, draw");
+                    controller_info.rate_draw(players);
+                    announce_draw(players).await;
+                }
+            }
             PlayerMovesReturn::GameOver
         }
         PlayerMoveResult::Win => {
             debug!("Game over, win");
-            announce_winner(who_moved.clone(), players).await;
-            controller_info.add_player_win(&who_moved);
+            game_won(who_moved, controller_info, players).await;
             PlayerMovesReturn::GameOver
         }
         PlayerMoveResult::InvalidMove(maybe_player_turn) => {
@@ -439,7 +1752,7 @@ where
         }
         PlayerMoveResult::InvalidFormat(maybe_player_turn) => {
             debug!("Invalid move format");
-            move_err_tx.send(messages::INVALID_MESSAGE_FORMAT).unwrap();
+            let _ = move_err_tx.send(messages::INVALID_MESSAGE_FORMAT); // Player might have disconnected, doesn't matter
             players.remove_player(&who_moved);
             match maybe_player_turn {
                 Some(PlayerTurn { token, state }) => your_turn(
@@ -465,7 +1778,7 @@ async fn your_turn<Fut>(
     mut p_game_state: gametraits::PlayerGameState,
     controller_info: &ControllerInfo,
     sleep_fn: &impl Fn(std::time::Duration) -> Fut,
-) -> Option<(oneshot::Receiver<PlayerMoveMsg>, TurnToken)>
+) -> Option<RunningTurn<Fut>>
 where
     Fut: std::future::Future<Output = ()>,
 {
@@ -473,8 +1786,33 @@ where
         if matches!(controller_info.game_mode, GameMode::Gating) {
             return None;
         }
-        sleep_fn(controller_info.turndelay).await;
         let (mov_tx, mov_rx) = oneshot::channel::<PlayerMoveMsg>();
+        if controller_info.paused {
+            // Nobody is asked to move until the game is resumed
+            return Some(RunningTurn {
+                move_rx: mov_rx,
+                token: turn_token,
+                state: p_game_state,
+                deadline: None,
+                asked_at: Instant::now(),
+            });
+        }
+        if controller_info.game_mode == GameMode::Spectate {
+            // Shown to everyone like it is to spectators, the move comes from `InjectMove`.
+            // `send_to_everyone` already includes the spectators
+            send_to_everyone(players, || {
+                ControllerToPlayerMsg::GameState(p_game_state.clone())
+            })
+            .await;
+            return Some(RunningTurn {
+                move_rx: mov_rx,
+                token: turn_token,
+                state: p_game_state,
+                deadline: None,
+                asked_at: Instant::now(),
+            });
+        }
+        sleep_fn(controller_info.turndelay).await;
         let new_player = players.get(&turn_token.user.name).unwrap();
         debug!("Sending 'your turn' to {}", new_player.name);
         if new_player
@@ -497,22 +1835,46 @@ where
                 }
             }
         } else {
-            return Some((mov_rx, turn_token));
+            send_to_spectators(players, &p_game_state).await;
+            return Some(RunningTurn {
+                move_rx: mov_rx,
+                token: turn_token,
+                state: p_game_state,
+                deadline: controller_info
+                    .turn_time_limit
+                    .map(|limit| Box::pin(sleep_fn(limit))),
+                asked_at: Instant::now(),
+            });
         }
     }
 }
 
 pub enum UiSender {
     Real(ExtEventSink),
+    // One of several games, the UI only gets its updates while it's showing that game
+    Slot {
+        sink: ExtEventSink,
+        slot: usize,
+        shown: Arc<AtomicUsize>,
+    },
     Fake,
 }
 
 impl UiSender {
+    fn sink(&self) -> Option<&ExtEventSink> {
+        match self {
+            UiSender::Real(tx) => Some(tx),
+            UiSender::Slot { sink, slot, shown } if shown.load(Ordering::Relaxed) == *slot => {
+                Some(sink)
+            }
+            UiSender::Slot { .. } | UiSender::Fake => None,
+        }
+    }
+
     fn send_new_state(&self, p_state: Box<dyn gametraits::Paint>) {
         debug!("Sending new game state to UI");
-        match self {
-            UiSender::Real(tx) => Self::real_send_new_state(tx, p_state),
-            UiSender::Fake => (),
+        if let Some(tx) = self.sink() {
+            Self::real_send_new_state(tx, p_state)
         }
     }
 
@@ -522,15 +1884,75 @@ impl UiSender {
     }
 
     fn send_controller_info(&self, controller_info: &ControllerInfo) {
-        match self {
-            UiSender::Fake => (),
-            UiSender::Real(tx) => tx
-                .submit_command(
-                    ui::UI_UPDATE_CONTROLLER_INFO_COMMAND,
-                    controller_info.clone(),
-                    druid::Target::Global,
-                )
-                .unwrap(),
+        if let Some(tx) = self.sink() {
+            tx.submit_command(
+                ui::UI_UPDATE_CONTROLLER_INFO_COMMAND,
+                controller_info.clone(),
+                druid::Target::Global,
+            )
+            .unwrap()
+        }
+    }
+
+    fn send_chat(&self, from: &str, message: &str) {
+        if let Some(tx) = self.sink() {
+            tx.submit_command(
+                ui::UI_CHAT_COMMAND,
+                (from.to_string(), message.to_string()),
+                druid::Target::Global,
+            )
+            .unwrap()
+        }
+    }
+
+    fn send_move_history(&self, move_history: &VecDeque<MoveRecord>) {
+        if let Some(tx) = self.sink() {
+            tx.submit_command(
+                ui::UI_UPDATE_MOVE_HISTORY_COMMAND,
+                move_history
+                    .iter()
+                    .skip(move_history.len().saturating_sub(ui::UI_MOVE_HISTORY))
+                    .map(ui::MoveHistoryEntry::from)
+                    .collect::<Vec<_>>(),
+                druid::Target::Global,
+            )
+            .unwrap()
+        }
+    }
+
+    fn send_game_started(&self) {
+        if let Some(tx) = self.sink() {
+            tx.submit_command(ui::UI_GAME_STARTED_COMMAND, (), druid::Target::Global)
+                .unwrap()
+        }
+    }
+
+    fn send_game_over(&self, score: &HashMap<String, u64>) {
+        if let Some(tx) = self.sink() {
+            tx.submit_command(
+                ui::UI_GAME_OVER_COMMAND,
+                score.clone(),
+                druid::Target::Global,
+            )
+            .unwrap()
+        }
+    }
+
+    fn send_reset_view(&self) {
+        if let Some(tx) = self.sink() {
+            tx.submit_command(ui::UI_RESET_VIEW_COMMAND, (), druid::Target::Global)
+                .unwrap()
+        }
+    }
+
+    fn send_move(&self, record: &MoveRecord) {
+        if let Some(tx) = self.sink() {
+            tx.submit_command(
+                ui::UI_MOVE_HISTORY_COMMAND,
+                ui::MoveHistoryEntry::from(record),
+                druid::Target::Global,
+            )
+            .unwrap()
         }
     }
 }
@@ -538,6 +1960,8 @@ impl UiSender {
 pub struct PlayerMoveMsg {
     pub mov: gametraits::PlayerMove,
     pub move_err_tx: oneshot::Sender<messages::ToClient>,
+    // When the connection read the move
+    pub received_at: Instant,
 }
 
 impl std::fmt::Debug for PlayerMoveMsg {
@@ -547,21 +1971,318 @@ impl std::fmt::Debug for PlayerMoveMsg {
 }
 
 pub enum ControllerToPlayerMsg {
+    // The first thing everyone gets after logging in
+    ServerInfo(ServerInfo),
     YourTurn(gametraits::PlayerGameState, oneshot::Sender<PlayerMoveMsg>),
+    // For spectators, who only watch
+    GameState(gametraits::PlayerGameState),
     GameOver(GameOverReason),
+    TurnTimeout,
+    Kicked,
+    Paused,
+    Resumed,
+    PlayerList(Vec<String>),
+    GameStarted { game: String, players: Vec<String> },
+    Chat { from: String, message: String },
+    DrawOffer { from: String },
+    // The last move was taken back, whoever made it is asked again
+    UndoGranted,
+    // Only to whoever asked
+    UndoDenied { reason: String },
+    // An announcement from the host
+    Info(String),
+    // Everyone's wins so far, after every game
+    Score(Vec<PlayerScore>),
+    // Not enough players for a game yet, 1 is next in line
+    Waiting { queue_position: usize },
+    // The game already has as many players as it can take
+    GameFull,
+}
+
+/// What a client can know about the server before the first game state shows up
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ServerInfo {
+    pub version: String,
+    pub game: String,
+    pub min_players: usize,
+    pub max_players: usize,
+    // None for games that aren't played on a board
+    pub board_width: Option<usize>,
+    pub board_height: Option<usize>,
+}
+
+impl ServerInfo {
+    pub fn new(game: &dyn AsyncGameTrait, controller_info: &ControllerInfo) -> Self {
+        let board = ui::board_size(&*game.get_paint());
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            game: game.describe(),
+            min_players: controller_info.min_players(game),
+            max_players: controller_info.max_players(game),
+            board_width: board.map(|(width, _)| width),
+            board_height: board.map(|(_, height)| height),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PlayerScore {
+    pub player: String,
+    pub wins: u64,
 }
 
 #[derive(Clone)]
 pub enum GameOverReason {
     Winner(String),
     Draw,
+    Forfeit { player: String },
+    // Lost a tournament match
+    Eliminated,
+    // Nobody won, the server is going away
+    Shutdown,
 }
 
 impl ControllerSender {
     pub fn send(&self, msg: ControllerMsg) {
-        let tx2 = self.tx.clone();
+        let tx2 = self.txs[self.shown.load(Ordering::Relaxed)].clone();
         self.rt_handle.spawn(async move {
             tx2.send(msg).await.unwrap();
         });
     }
+
+    pub fn kick(&self, name: &str) {
+        self.send(ControllerMsg::Kick(name.to_string()));
+    }
+
+    pub fn game_count(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn show_game(&self, slot: usize) {
+        self.shown.store(slot, Ordering::Relaxed);
+        self.send(ControllerMsg::RefreshUi);
+    }
+
+    /// Blocks until the shown game's controller answers, not to be called from inside the runtime
+    pub fn get_status(&self) -> ControllerInfo {
+        let (tx, rx) = oneshot::channel();
+        self.send(ControllerMsg::GetStatus(tx));
+        rx.blocking_recv().expect("Controller loop is gone")
+    }
+}
+
+mod duration_millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(d)?))
+    }
+}
+
+mod option_duration_millis {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        duration.map(|d| d.as_millis() as u64).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(d)?.map(Duration::from_millis))
+    }
+}
+
+// TODO: Drop once game-types has serde for User
+mod users_serde {
+    use code_challenge_game_types::gametraits::User;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct SerdeUser {
+        name: String,
+        // [r, g, b, a]
+        color: [u8; 4],
+    }
+
+    pub fn serialize<S: Serializer>(users: &[User], s: S) -> Result<S::Ok, S::Error> {
+        users
+            .iter()
+            .map(|u| {
+                let (r, g, b, a) = u.color.as_rgba8();
+                SerdeUser {
+                    name: u.name.clone(),
+                    color: [r, g, b, a],
+                }
+            })
+            .collect::<Vec<_>>()
+            .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<User>, D::Error> {
+        Ok(Vec::<SerdeUser>::deserialize(d)?
+            .into_iter()
+            .map(
+                |SerdeUser {
+                     name,
+                     color: [r, g, b, a],
+                 }| User {
+                    name,
+                    color: druid::Color::rgba8(r, g, b, a),
+                },
+            )
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn anti_spam_turns_away_the_same_move_twice() {
+        let validator = AntiSpamValidator::default();
+        let info = ControllerInfo::default();
+        assert!(validator.validate("alice", r#"{"x":1}"#, &info).is_none());
+        assert!(validator.validate("alice", r#"{"x":1}"#, &info).is_some());
+        // Someone else's move doesn't count
+        assert!(validator.validate("bob", r#"{"x":1}"#, &info).is_none());
+        assert!(validator.validate("alice", r#"{"x":2}"#, &info).is_none());
+        assert!(validator.validate("alice", r#"{"x":1}"#, &info).is_none());
+    }
+
+    #[test]
+    fn latency_percentiles() {
+        let mut info = ControllerInfo::default();
+        assert_eq!(info.p50_latency(), None);
+        // Recorded out of order, 1 to 100 ms
+        for ms in (1..=100).rev() {
+            info.record_latency(Duration::from_millis(ms));
+        }
+        assert_eq!(info.p50_latency(), Some(Duration::from_millis(50)));
+        assert_eq!(info.p95_latency(), Some(Duration::from_millis(95)));
+        assert_eq!(info.p99_latency(), Some(Duration::from_millis(99)));
+
+        let json: serde_json::Value = serde_json::from_str(&info.to_json()).unwrap();
+        assert_eq!(json["latency-p99-ms"], 99);
+    }
+
+    #[test]
+    fn only_the_latest_latencies_count() {
+        let mut info = ControllerInfo::default();
+        for _ in 0..MOVE_LATENCIES {
+            info.record_latency(Duration::from_secs(10));
+        }
+        for _ in 0..MOVE_LATENCIES {
+            info.record_latency(Duration::from_millis(3));
+        }
+        assert_eq!(info.move_latencies.len(), MOVE_LATENCIES);
+        assert_eq!(info.p99_latency(), Some(Duration::from_millis(3)));
+    }
+
+    #[test]
+    fn a_single_latency_is_every_percentile() {
+        let mut info = ControllerInfo::default();
+        info.record_latency(Duration::from_millis(7));
+        assert_eq!(info.p50_latency(), Some(Duration::from_millis(7)));
+        assert_eq!(info.p99_latency(), Some(Duration::from_millis(7)));
+    }
+
+    #[test]
+    fn standings_are_by_wins_then_name() {
+        let info = ControllerInfo {
+            score: HashMap::from([
+                ("bob".to_string(), 1),
+                ("alice".to_string(), 3),
+                ("adam".to_string(), 1),
+            ]),
+            ..ControllerInfo::default()
+        };
+        let standings: Vec<(&str, u64)> = info
+            .standings()
+            .iter()
+            .map(|s| (s.player.as_str(), s.wins))
+            .collect();
+        assert_eq!(standings, [("alice", 3), ("adam", 1), ("bob", 1)]);
+    }
+
+    #[test]
+    fn controller_info_json_round_trip() {
+        let info = ControllerInfo {
+            connected_users: vec![User {
+                name: "zeldo".to_string(),
+                color: druid::Color::rgb8(10, 20, 30),
+            }],
+            game_mode: GameMode::Competition,
+            score: HashMap::from([("zeldo".to_string(), 3), ("user2".to_string(), 1)]),
+            turn_time_limit: Some(Duration::from_millis(1500)),
+            max_game_duration: Some(Duration::from_secs(600)),
+            total_moves: 12,
+            paused: true,
+            ..Default::default()
+        };
+        let json = info.to_json();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["game-mode"], "competition");
+        assert_eq!(value["score"]["zeldo"], 3);
+        assert_eq!(value["turndelay"], 200);
+        assert_eq!(value["turn-time-limit"], 1500);
+        assert_eq!(value["max-game-duration"], 600_000);
+        assert_eq!(value["total-moves"], 12);
+        assert_eq!(value["connected-users"][0]["name"], "zeldo");
+
+        let back: ControllerInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, info);
+    }
+
+    #[test]
+    fn game_start_is_sent_as_how_long_ago_it_was() {
+        let info = ControllerInfo {
+            game_start_time: Instant::now().checked_sub(Duration::from_secs(42)),
+            ..Default::default()
+        };
+        let value: serde_json::Value = serde_json::from_str(&info.to_json()).unwrap();
+        assert_eq!(value["game-start-time-secs-ago"], 42);
+
+        let value: serde_json::Value =
+            serde_json::from_str(&ControllerInfo::default().to_json()).unwrap();
+        assert_eq!(value["game-start-time-secs-ago"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn favourite_gains_little_from_a_win() {
+        let mut ratings =
+            HashMap::from([("strong".to_string(), 1500.0), ("weak".to_string(), 1000.0)]);
+        update_elo("strong", "weak", 32.0, &mut ratings);
+        assert!((ratings["strong"] - 1501.70).abs() < 0.01, "{ratings:?}");
+        assert!((ratings["weak"] - 998.30).abs() < 0.01, "{ratings:?}");
+    }
+
+    #[test]
+    fn underdog_gains_a_lot_from_a_win() {
+        let mut ratings =
+            HashMap::from([("strong".to_string(), 1500.0), ("weak".to_string(), 1000.0)]);
+        update_elo("weak", "strong", 32.0, &mut ratings);
+        assert!((ratings["weak"] - 1030.30).abs() < 0.01, "{ratings:?}");
+        assert!((ratings["strong"] - 1469.70).abs() < 0.01, "{ratings:?}");
+    }
+
+    #[test]
+    fn draw_between_equals_changes_nothing() {
+        let mut ratings = HashMap::new();
+        update_elo_draw("a", "b", 32.0, &mut ratings);
+        assert_eq!(ratings["a"], INITIAL_ELO);
+        assert_eq!(ratings["b"], INITIAL_ELO);
+
+        ratings.insert("a".to_string(), 1200.0);
+        update_elo_draw("a", "b", 32.0, &mut ratings);
+        assert!(ratings["a"] < 1200.0);
+        assert!(ratings["b"] > INITIAL_ELO);
+        assert!((ratings["a"] + ratings["b"] - 2200.0).abs() < 1e-9);
+    }
 }
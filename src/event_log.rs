@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use chrono::{SecondsFormat, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+};
+
+#[derive(Serialize)]
+struct LoggedEvent<'a> {
+    ts: String,
+    event: &'a str,
+    player: &'a str,
+    data: &'a str,
+}
+
+/// Appends game events to a file, one JSON object per line. Does nothing until a path is set.
+#[derive(Default)]
+pub struct EventLogger {
+    file: Option<BufWriter<File>>,
+}
+
+impl EventLogger {
+    /// Starts logging to `path`, or stops logging if it's `None`
+    pub async fn set_path(&mut self, path: Option<PathBuf>) {
+        self.file = None;
+        let Some(path) = path else {
+            info!("Event log off");
+            return;
+        };
+        match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+        {
+            Ok(file) => {
+                info!("Logging events to {}", path.display());
+                self.file = Some(BufWriter::new(file));
+            }
+            Err(e) => warn!("Couldn't open event log {}: {e}", path.display()),
+        }
+    }
+
+    pub async fn log(&mut self, event: &str, player: &str, data: &str) {
+        let Some(file) = self.file.as_mut() else {
+            return;
+        };
+        let line = serde_json::to_string(&LoggedEvent {
+            ts: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            event,
+            player,
+            data,
+        })
+        .unwrap()
+            + "\n";
+        // Flushed every line, so whoever reads the file sees complete games
+        let written = match file.write_all(line.as_bytes()).await {
+            Ok(()) => file.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            warn!("Couldn't write to the event log: {e}");
+        }
+    }
+}
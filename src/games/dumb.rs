@@ -10,7 +10,6 @@ use druid::{
     piet::{Text, TextLayoutBuilder},
     Color, FontFamily, RenderContext,
 };
-use log::debug;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Clone, Eq, PartialEq)]
@@ -18,15 +17,65 @@ pub struct PlayerState {
     num: u32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+}
+
+/// `{"op":"add"|"sub"|"mul","value":N}`, or just `{"add":N}`
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(from = "MoveFormat")]
 pub struct PlayerMove {
-    add: u32,
+    op: Op,
+    value: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MoveFormat {
+    Op { op: Op, value: u32 },
+    // From before there was anything but adding
+    Add { add: u32 },
+}
+
+impl From<MoveFormat> for PlayerMove {
+    fn from(format: MoveFormat) -> Self {
+        match format {
+            MoveFormat::Op { op, value } => PlayerMove { op, value },
+            MoveFormat::Add { add } => PlayerMove {
+                op: Op::Add,
+                value: add,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameConfig {
+    // Whoever brings the count here wins
+    pub target: u32,
+    pub allow_sub: bool,
+    pub allow_mul: bool,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            target: 100,
+            allow_sub: false,
+            allow_mul: false,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Game {
     count: PlayerState,
     players: TurnTracker,
+    config: GameConfig,
 }
 
 impl Default for Game {
@@ -37,11 +86,45 @@ impl Default for Game {
 
 impl Game {
     pub fn new() -> Self {
+        Self::with_config(GameConfig::default())
+    }
+
+    pub fn new_with_target(target: u32) -> Self {
+        Self::with_config(GameConfig {
+            target,
+            ..GameConfig::default()
+        })
+    }
+
+    pub fn with_config(config: GameConfig) -> Self {
         Game {
             count: PlayerState { num: 0 },
             players: TurnTracker::new(Vec::new()),
+            config,
         }
     }
+
+    // TODO: Move to GameTrait::describe once game-types has it
+    pub fn describe(&self) -> String {
+        let mut ops = vec!["add"];
+        if self.config.allow_sub {
+            ops.push("sub");
+        }
+        if self.config.allow_mul {
+            ops.push("mul");
+        }
+        format!(
+            "Count to {}, allowed operations: {}",
+            self.config.target,
+            ops.join(", ")
+        )
+    }
+
+    /// The count has reached the target
+    // TODO: Make this the override of GameTrait::is_over once game-types has it
+    pub fn is_over(&self) -> bool {
+        self.count.num >= self.config.target
+    }
 }
 
 impl gametraits::GameTrait for Game {
@@ -51,7 +134,6 @@ impl gametraits::GameTrait for Game {
         player_move: gametraits::PlayerMove,
     ) -> PlayerMoveResult {
         let user = &token.user;
-        debug!("{user:?} made a move {player_move:?}");
         match gametraits::to_player_move::<PlayerMove>(&player_move) {
             None => {
                 self.players.remove_player(&user.name);
@@ -64,7 +146,18 @@ impl gametraits::GameTrait for Game {
                 }
             }
             Some(mov) => {
-                make_move(self, user, mov);
+                if make_move(self, user, mov) == InternalMoveResult::InvalidMove {
+                    self.players.remove_player(&user.name);
+                    return PlayerMoveResult::InvalidMove(self.players.advance_player().map(|p| {
+                        PlayerTurn {
+                            token: TurnToken { user: p },
+                            state: gametraits::to_game_state(&self.count),
+                        }
+                    }));
+                }
+                if self.count.num >= self.config.target {
+                    return PlayerMoveResult::Win;
+                }
                 let next_player = self.players.advance_player().unwrap();
                 PlayerMoveResult::Ok(PlayerTurn {
                     token: TurnToken { user: next_player },
@@ -93,16 +186,16 @@ impl gametraits::GameTrait for Game {
     }
 
     fn try_start_game(&mut self) -> Option<PlayerTurn> {
-        Some(PlayerTurn {
-            token: TurnToken {
-                user: self.players.advance_player().unwrap(),
-            },
+        // Nobody to start with
+        self.players.advance_player().map(|user| PlayerTurn {
+            token: TurnToken { user },
             state: gametraits::to_game_state(&self.count),
         })
     }
 
-    fn reset(&mut self, _users: Vec<User>) {
-        todo!()
+    fn reset(&mut self, users: Vec<User>) {
+        self.count.num = 0;
+        self.players = TurnTracker::new(users);
     }
 }
 
@@ -136,9 +229,223 @@ pub fn make_ptr(players: Vec<gametraits::User>) -> Box<dyn GameTrait> {
     Box::new(Game {
         count: PlayerState { num: 0 },
         players: TurnTracker::new(players),
+        config: GameConfig::default(),
     })
 }
 
-fn make_move(state: &mut Game, _user: &User, p_move: PlayerMove) {
-    state.count.num += p_move.add;
+#[derive(Debug, PartialEq, Eq)]
+enum InternalMoveResult {
+    InvalidMove,
+    Ok,
+}
+
+// The count never goes below 0 or past u32::MAX
+fn make_move(state: &mut Game, _user: &User, p_move: PlayerMove) -> InternalMoveResult {
+    let num = state.count.num;
+    state.count.num = match p_move.op {
+        Op::Add => num.saturating_add(p_move.value),
+        Op::Sub if state.config.allow_sub => num.saturating_sub(p_move.value),
+        // Times zero would be a quick way back to the start
+        Op::Mul if state.config.allow_mul && p_move.value != 0 => num.saturating_mul(p_move.value),
+        _ => return InternalMoveResult::InvalidMove,
+    };
+    InternalMoveResult::Ok
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn started_game(target: u32) -> (Game, TurnToken) {
+        let mut game = Game::new_with_target(target);
+        game.player_connected(User {
+            name: "player1".to_string(),
+            color: Color::rgb8(0, 0, 0),
+        });
+        let token = game.try_start_game().unwrap().token;
+        (game, token)
+    }
+
+    fn add(n: u32) -> gametraits::PlayerMove {
+        gametraits::PlayerMove {
+            serialized: format!(r#"{{"add":{n}}}"#),
+        }
+    }
+
+    #[test]
+    fn reaching_the_target_wins() {
+        let (mut game, token) = started_game(10);
+        assert!(matches!(
+            game.player_moves(token, add(10)),
+            PlayerMoveResult::Win
+        ));
+    }
+
+    #[test]
+    fn over_once_the_target_is_reached() {
+        let (mut game, token) = started_game(10);
+        assert!(!game.is_over());
+        let PlayerMoveResult::Ok(turn) = game.player_moves(token, add(9)) else {
+            panic!("Expected the game to go on");
+        };
+        assert!(!game.is_over());
+        game.player_moves(turn.token, add(1));
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn going_past_the_target_wins() {
+        let (mut game, token) = started_game(10);
+        assert!(matches!(
+            game.player_moves(token, add(25)),
+            PlayerMoveResult::Win
+        ));
+    }
+
+    #[test]
+    fn short_of_the_target_goes_on() {
+        let (mut game, token) = started_game(10);
+        match game.player_moves(token, add(9)) {
+            PlayerMoveResult::Ok(turn) => {
+                assert_eq!(
+                    turn.state.serialized,
+                    gametraits::to_game_state(&PlayerState { num: 9 }).serialized
+                )
+            }
+            _ => panic!("Expected the game to go on"),
+        }
+    }
+
+    fn all_ops() -> Game {
+        Game::with_config(GameConfig {
+            target: 1000,
+            allow_sub: true,
+            allow_mul: true,
+        })
+    }
+
+    fn user() -> User {
+        User {
+            name: "player1".to_string(),
+            color: Color::rgb8(0, 0, 0),
+        }
+    }
+
+    fn mv(op: Op, value: u32) -> PlayerMove {
+        PlayerMove { op, value }
+    }
+
+    #[test]
+    fn each_op_does_its_arithmetic() {
+        let mut game = all_ops();
+        assert_eq!(
+            make_move(&mut game, &user(), mv(Op::Add, 7)),
+            InternalMoveResult::Ok
+        );
+        assert_eq!(game.count.num, 7);
+        assert_eq!(
+            make_move(&mut game, &user(), mv(Op::Mul, 3)),
+            InternalMoveResult::Ok
+        );
+        assert_eq!(game.count.num, 21);
+        assert_eq!(
+            make_move(&mut game, &user(), mv(Op::Sub, 5)),
+            InternalMoveResult::Ok
+        );
+        assert_eq!(game.count.num, 16);
+        assert_eq!(
+            make_move(&mut game, &user(), mv(Op::Sub, 50)),
+            InternalMoveResult::Ok
+        );
+        assert_eq!(game.count.num, 0);
+    }
+
+    #[test]
+    fn times_zero_is_invalid() {
+        let mut game = all_ops();
+        make_move(&mut game, &user(), mv(Op::Add, 7));
+        assert_eq!(
+            make_move(&mut game, &user(), mv(Op::Mul, 0)),
+            InternalMoveResult::InvalidMove
+        );
+        assert_eq!(game.count.num, 7);
+    }
+
+    #[test]
+    fn ops_not_in_the_config_are_invalid() {
+        let mut game = Game::new();
+        assert_eq!(
+            make_move(&mut game, &user(), mv(Op::Sub, 1)),
+            InternalMoveResult::InvalidMove
+        );
+        assert_eq!(
+            make_move(&mut game, &user(), mv(Op::Mul, 2)),
+            InternalMoveResult::InvalidMove
+        );
+        assert_eq!(game.describe(), "Count to 100, allowed operations: add");
+        assert_eq!(
+            all_ops().describe(),
+            "Count to 1000, allowed operations: add, sub, mul"
+        );
+    }
+
+    #[test]
+    fn both_move_formats_parse() {
+        let parse = |s: &str| serde_json::from_str::<PlayerMove>(s).ok();
+        assert_eq!(parse(r#"{"add":5}"#), Some(mv(Op::Add, 5)));
+        assert_eq!(parse(r#"{"op":"add","value":5}"#), Some(mv(Op::Add, 5)));
+        assert_eq!(parse(r#"{"op":"sub","value":2}"#), Some(mv(Op::Sub, 2)));
+        assert_eq!(parse(r#"{"op":"mul","value":3}"#), Some(mv(Op::Mul, 3)));
+        assert_eq!(parse(r#"{"op":"div","value":3}"#), None);
+        assert_eq!(parse(r#"{"sub":3}"#), None);
+    }
+
+    #[test]
+    fn invalid_move_passes_the_turn() {
+        let mut game = all_ops();
+        game.player_connected(user());
+        game.player_connected(User {
+            name: "player2".to_string(),
+            color: Color::rgb8(100, 100, 100),
+        });
+        let token = game.try_start_game().unwrap().token;
+        let times_zero = gametraits::PlayerMove {
+            serialized: r#"{"op":"mul","value":0}"#.to_string(),
+        };
+        let PlayerMoveResult::InvalidMove(Some(turn)) = game.player_moves(token, times_zero) else {
+            panic!("Expected the next player's turn");
+        };
+        assert_eq!(turn.token.user.name, "player2");
+    }
+
+    #[test]
+    fn no_game_without_players() {
+        let mut game = Game::new();
+        assert!(game.try_start_game().is_none());
+        game.player_connected(user());
+        let turn = game.try_start_game().unwrap();
+        assert_eq!(turn.token.user.name, "player1");
+        assert_eq!(
+            turn.state.serialized,
+            gametraits::to_game_state(&PlayerState { num: 0 }).serialized
+        );
+    }
+
+    #[test]
+    fn reset_starts_the_count_over() {
+        let (mut game, token) = started_game(10);
+        let PlayerMoveResult::Ok(turn) = game.player_moves(token, add(4)) else {
+            panic!("Expected the game to go on");
+        };
+        game.player_moves(turn.token, add(3));
+        assert_eq!(game.count, PlayerState { num: 7 });
+        let user = User {
+            name: "player2".to_string(),
+            color: Color::rgb8(100, 100, 100),
+        };
+        game.reset(vec![user]);
+        assert_eq!(game.count, PlayerState { num: 0 });
+        let turn = game.try_start_game().unwrap();
+        assert_eq!(turn.token.user.name, "player2");
+    }
 }
@@ -1,5 +1,5 @@
 use itertools::Itertools;
-use std::{any::Any, iter::repeat};
+use std::{any::Any, fmt, iter::repeat, sync::Mutex};
 
 use code_challenge_game_types::gametraits::{
     self, GameTrait, PlayerMoveResult, PlayerTurn, TurnToken, User,
@@ -12,9 +12,10 @@ use druid::{
     Color, FontFamily, Point, Rect, RenderContext,
 };
 use log::debug;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize, Serializer};
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize)]
 pub struct PlayerMove {
     x: usize,
     y: usize,
@@ -23,8 +24,33 @@ pub struct PlayerMove {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Game {
     board: Board,
+    // Number of pieces in a row needed to win
+    win_length: usize,
     winner: Option<(User, FirstAndLast)>,
     players: TurnTracker,
+    rules: RulesVariant,
+    // Forgotten as soon as the turn order changes some other way than by a move
+    last_move: Option<LastMove>,
+}
+
+// What `undo_last_move` needs to take the last move back
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct LastMove {
+    user: User,
+    x: usize,
+    y: usize,
+    // The turn order from when the move was asked for, going back to it puts the same player up
+    players: TurnTracker,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RulesVariant {
+    /// Any row of at least `win_length` wins
+    #[default]
+    Freestyle,
+    /// The first player (Black) may not make a double three, a double four or an overline,
+    /// see `is_forbidden_renju`
+    Renju,
 }
 
 #[derive(Clone, Serialize, Debug, PartialEq, Eq)]
@@ -33,6 +59,91 @@ struct Board {
     cells: Vec<Cell>,
     width: usize,
     height: usize,
+    // Everyone who has placed a piece, in the order of their first one
+    #[serde(skip)]
+    placed_by: Vec<String>,
+    // XOR of the `ZOBRIST` values of every piece on the board, kept up to date in `try_place`
+    #[serde(rename = "hash")]
+    board_hash: u64,
+}
+
+// The same seed every time, so the same board hashes the same from game to game
+const ZOBRIST_SEED: u64 = 0x676f_6d6f_6b75;
+
+// One for the whole process, every board of every size and every game hashes with it
+static ZOBRIST: Mutex<ZobristKeys> = Mutex::new(ZobristKeys {
+    players: Vec::new(),
+    table: ZobristTable {
+        seed: ZOBRIST_SEED,
+        num_cells: 0,
+        num_players: 0,
+        values: Vec::new(),
+    },
+});
+
+/// A random value for every (cell, player) pair, for bots that want to recognize a board they
+/// have seen before without comparing every cell
+#[derive(Debug)]
+pub struct ZobristTable {
+    seed: u64,
+    num_cells: usize,
+    num_players: usize,
+    // One row of `num_cells` per player
+    values: Vec<u64>,
+}
+
+impl ZobristTable {
+    pub fn new(seed: u64, num_cells: usize, num_players: usize) -> Self {
+        // A generator per player, so a bigger table starts out with the values of a smaller one
+        let values = (0..num_players)
+            .flat_map(|player| {
+                let mut rng = StdRng::seed_from_u64(seed.wrapping_add(player as u64));
+                (0..num_cells).map(move |_| rng.gen())
+            })
+            .collect();
+        Self {
+            seed,
+            num_cells,
+            num_players,
+            values,
+        }
+    }
+
+    fn value(&self, cell_index: usize, player_index: usize) -> u64 {
+        self.values[player_index * self.num_cells + cell_index]
+    }
+}
+
+// The table, and the index of every player it has been asked about
+#[derive(Debug)]
+struct ZobristKeys {
+    // In the order they first placed a piece anywhere, so a name is keyed the same in every game
+    players: Vec<String>,
+    table: ZobristTable,
+}
+
+fn zobrist_value(cell_index: usize, player: &str) -> u64 {
+    let mut keys = ZOBRIST.lock().unwrap();
+    let player_index = match keys.players.iter().position(|name| name == player) {
+        Some(index) => index,
+        None => {
+            keys.players.push(player.to_string());
+            keys.players.len() - 1
+        }
+    };
+    if cell_index >= keys.table.num_cells || player_index >= keys.table.num_players {
+        // The values handed out so far stay the same, hashes already worked out with them hold
+        let num_cells = keys
+            .table
+            .num_cells
+            .max((cell_index + 1).next_power_of_two());
+        let num_players = keys
+            .table
+            .num_players
+            .max((player_index + 1).next_power_of_two());
+        keys.table = ZobristTable::new(keys.table.seed, num_cells, num_players);
+    }
+    keys.table.value(cell_index, player_index)
 }
 
 #[derive(Clone, Serialize, Debug, PartialEq, Eq)]
@@ -54,29 +165,50 @@ where
 type FirstAndLast = ((i32, i32), (i32, i32));
 
 impl Board {
-    fn try_place(&mut self, user: &User, x: usize, y: usize) -> PlaceResult {
+    fn try_place(&mut self, user: &User, x: usize, y: usize, win_length: usize) -> PlaceResult {
         match self.at_mut(x as i32, y as i32) {
             None => PlaceResult::InvalidMove,
             Some(Cell::Occupied(_)) => PlaceResult::InvalidMove,
             Some(cell @ Cell::Empty) => {
                 *cell = Cell::Occupied(user.clone());
-                self.check_for_win_around(x, y)
+                if !self.placed_by.contains(&user.name) {
+                    self.placed_by.push(user.name.clone());
+                }
+                self.board_hash ^= zobrist_value(y * self.width + x, &user.name);
+                self.check_for_win_around(x, y, win_length)
             }
         }
     }
 
-    fn check_for_win_around(&self, x: usize, y: usize) -> PlaceResult {
+    fn check_for_win_around(&self, x: usize, y: usize, win_length: usize) -> PlaceResult {
         let x = x as i32;
         let y = y as i32;
+        // How far from (x, y) a winning row can reach
+        let reach = win_length as i32 - 1;
+        let span = (2 * reach + 1) as usize;
 
         let winning_coords = self
-            .range_contains_win(repeat(x).take(9), y - 4..y + 5)
+            .range_contains_win(repeat(x).take(span), y - reach..y + reach + 1, win_length)
             .or_else(|| {
-                self.range_contains_win(x - 4..x + 5, y - 4..y + 5)
+                self.range_contains_win(
+                    x - reach..x + reach + 1,
+                    y - reach..y + reach + 1,
+                    win_length,
+                )
+                .or_else(|| {
+                    self.range_contains_win(
+                        x - reach..x + reach + 1,
+                        repeat(y).take(span),
+                        win_length,
+                    )
                     .or_else(|| {
-                        self.range_contains_win(x - 4..x + 5, repeat(y).take(9))
-                            .or_else(|| self.range_contains_win(x - 4..x + 5, (y - 4..y + 5).rev()))
+                        self.range_contains_win(
+                            x - reach..x + reach + 1,
+                            (y - reach..y + reach + 1).rev(),
+                            win_length,
+                        )
                     })
+                })
             });
 
         match winning_coords {
@@ -101,7 +233,12 @@ impl Board {
         }
     }
 
-    fn range_contains_win<T1, T2>(&self, x_range: T1, y_range: T2) -> Option<FirstAndLast>
+    fn range_contains_win<T1, T2>(
+        &self,
+        x_range: T1,
+        y_range: T2,
+        win_length: usize,
+    ) -> Option<FirstAndLast>
     where
         T1: Iterator<Item = i32>,
         T2: Iterator<Item = i32>,
@@ -115,7 +252,7 @@ impl Board {
             .map(|a| a.collect::<Vec<(i32, i32)>>())
             .max_by(|a, b| a.len().cmp(&b.len()))
             .and_then(|a| {
-                if a.len() >= 5 {
+                if a.len() >= win_length {
                     Some((*a.first().unwrap(), *a.last().unwrap()))
                 } else {
                     None
@@ -123,8 +260,92 @@ impl Board {
             })
     }
 
+    // Empties a cell `try_place` filled
+    fn take_back(&mut self, user: &User, x: usize, y: usize) {
+        let index = y * self.width + x;
+        self.cells[index] = Cell::Empty;
+        self.board_hash ^= zobrist_value(index, &user.name);
+        // Their first piece is the only one that puts them in `placed_by`, and it's the latest
+        let still_placed = self
+            .cells
+            .iter()
+            .any(|cell| matches!(cell, Cell::Occupied(u) if u.name == user.name));
+        if !still_placed {
+            self.placed_by.retain(|name| *name != user.name);
+        }
+    }
+
+    /// Zobrist hash of the pieces on the board, the same board always gets the same hash no matter
+    /// the order the pieces were placed in
+    pub fn hash(&self) -> u64 {
+        self.board_hash
+    }
+
     fn is_full(&self) -> bool {
-        !self.cells.iter().any(|c| matches!(c, Cell::Empty))
+        self.legal_moves().next().is_none()
+    }
+
+    // A, B, C... by who placed their first piece first
+    fn mark(&self, user: &User) -> char {
+        match self.placed_by.iter().position(|name| *name == user.name) {
+            Some(i) if i < 26 => (b'A' + i as u8) as char,
+            _ => '?',
+        }
+    }
+
+    // Letters along the top, row numbers from 1 down the side
+    fn render(&self, cell_text: impl Fn(&Cell) -> String) -> String {
+        let label_width = self.height.to_string().len();
+        let mut lines = vec![format!("{:label_width$}", "")];
+        for x in 0..self.width {
+            lines[0].push(' ');
+            lines[0].push(if x < 26 {
+                (b'A' + x as u8) as char
+            } else {
+                '?'
+            });
+        }
+        for y in 0..self.height {
+            let mut line = format!("{:>label_width$}", y + 1);
+            for x in 0..self.width {
+                line.push(' ');
+                line += &cell_text(self.at(x as i32, y as i32).unwrap());
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+
+    /// Like `Display`, with each player's letter in their own color
+    #[cfg(feature = "colored")]
+    #[allow(dead_code)]
+    pub fn to_string_colored(&self) -> String {
+        use colored::Colorize;
+        self.render(|cell| match cell {
+            Cell::Empty => ".".to_string(),
+            Cell::Occupied(user) => {
+                let (r, g, b, _) = user.color.as_rgba8();
+                self.mark(user).to_string().truecolor(r, g, b).to_string()
+            }
+        })
+    }
+
+    /// Every empty cell as `(x, y)`, row by row
+    pub fn legal_moves(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| matches!(cell, Cell::Empty))
+            .map(|(i, _)| (i % self.width, i / self.width))
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(|cell| match cell {
+            Cell::Empty => ".".to_string(),
+            Cell::Occupied(user) => self.mark(user).to_string(),
+        }))
     }
 }
 
@@ -135,16 +356,93 @@ enum PlaceResult {
 }
 
 impl Game {
-    pub fn new(w: usize, h: usize, players: Vec<User>) -> Self {
+    pub fn new(w: usize, h: usize, win_length: usize, players: Vec<User>) -> Self {
         Self {
             board: Board {
                 width: w,
                 height: h,
                 cells: repeat(Cell::Empty).take(w * h).collect::<Vec<Cell>>(),
+                placed_by: Vec::new(),
+                board_hash: 0,
             },
+            win_length,
             winner: None,
             players: TurnTracker::new(players),
+            rules: RulesVariant::Freestyle,
+            last_move: None,
+        }
+    }
+
+    pub fn with_rules(mut self, rules: RulesVariant) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// (columns, rows), the UI uses it to find the cell under a click
+    pub fn board_size(&self) -> (usize, usize) {
+        (self.board.width, self.board.height)
+    }
+
+    // TODO: Move to GameTrait::describe once game-types has it
+    pub fn describe(&self) -> String {
+        let rules = match self.rules {
+            RulesVariant::Freestyle => "",
+            RulesVariant::Renju => ", renju rules",
+        };
+        format!(
+            "Gomoku {}x{}, {} in a row{rules}",
+            self.board.width, self.board.height, self.win_length
+        )
+    }
+
+    // TODO: Move into GameTrait once game-types has min_players/max_players
+    pub fn min_players(&self) -> usize {
+        2
+    }
+
+    // Everyone takes turns on the same board, win detection tells the players apart
+    pub fn max_players(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Whether `player_move` would be accepted, without placing anything
+    // TODO: Make this the override of GameTrait::validate_move once game-types has it
+    pub fn validate_move(&self, _token: &TurnToken, player_move: &gametraits::PlayerMove) -> bool {
+        match gametraits::to_player_move::<PlayerMove>(player_move) {
+            Some(PlayerMove { x, y }) => {
+                x < self.board.width
+                    && y < self.board.height
+                    && matches!(self.board.at(x as i32, y as i32), Some(Cell::Empty))
+            }
+            None => false,
+        }
+    }
+
+    /// Someone has won or the board is full, nothing more can be placed until a reset
+    // TODO: Make this the override of GameTrait::is_over once game-types has it
+    pub fn is_over(&self) -> bool {
+        self.winner.is_some() || self.board.is_full()
+    }
+
+    /// Takes the last move back and gives its player the turn again. Only one move back, and
+    /// nothing once the game is over, a win stands
+    // TODO: Make this the override of GameTrait::undo_last_move once game-types has it
+    pub fn undo_last_move(&mut self) -> Option<PlayerTurn> {
+        if self.is_over() {
+            return None;
         }
+        let LastMove {
+            user,
+            x,
+            y,
+            players,
+        } = self.last_move.take()?;
+        self.board.take_back(&user, x, y);
+        self.players = players;
+        Some(PlayerTurn {
+            token: TurnToken { user },
+            state: gametraits::to_game_state(&self.board),
+        })
     }
 }
 
@@ -155,7 +453,8 @@ impl gametraits::GameTrait for Game {
         player_move: gametraits::PlayerMove,
     ) -> PlayerMoveResult {
         let user = &token.user;
-        debug!("{user:?} made a move {player_move:?}");
+        let players_before = self.players.clone();
+        self.last_move = None;
         match gametraits::to_player_move::<PlayerMove>(&player_move) {
             Some(mov) => match make_move(self, user, mov) {
                 InternalMoveResult::InvalidMove => {
@@ -170,6 +469,12 @@ impl gametraits::GameTrait for Game {
                     }
                 }
                 InternalMoveResult::Ok => {
+                    self.last_move = Some(LastMove {
+                        user: user.clone(),
+                        x: mov.x,
+                        y: mov.y,
+                        players: players_before,
+                    });
                     let p = self.players.advance_player().unwrap();
                     debug!("next player: {}", p.name);
                     PlayerMoveResult::Ok(PlayerTurn {
@@ -195,14 +500,17 @@ impl gametraits::GameTrait for Game {
     }
 
     fn player_connected(&mut self, user: User) {
+        self.last_move = None;
         self.players.add_player(user);
     }
 
     fn player_disconnected(&mut self, username: &str) {
+        self.last_move = None;
         self.players.remove_player(username);
     }
 
     fn current_player_disconnected(&mut self, player_token: TurnToken) -> Option<PlayerTurn> {
+        self.last_move = None;
         self.players.remove_player(&player_token.user.name);
 
         match self.players.advance_player() {
@@ -226,7 +534,8 @@ impl gametraits::GameTrait for Game {
     }
 
     fn reset(&mut self, users: Vec<User>) {
-        *self = Game::new(self.board.width, self.board.height, users);
+        *self = Game::new(self.board.width, self.board.height, self.win_length, users)
+            .with_rules(self.rules);
     }
 }
 
@@ -302,8 +611,8 @@ impl gametraits::Paint for Game {
     }
 }
 
-pub fn make_ptr(w: usize, h: usize, players: Vec<User>) -> Box<dyn GameTrait> {
-    Box::new(Game::new(w, h, players))
+pub fn make_ptr(w: usize, h: usize, win_length: usize, players: Vec<User>) -> Box<dyn GameTrait> {
+    Box::new(Game::new(w, h, win_length, players))
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -315,7 +624,26 @@ enum InternalMoveResult {
 }
 
 fn make_move(state: &mut Game, user: &User, p_move: PlayerMove) -> InternalMoveResult {
-    match state.board.try_place(user, p_move.x, p_move.y) {
+    // Black is whoever placed the first piece, or is about to
+    let is_black = state
+        .board
+        .placed_by
+        .first()
+        .map_or(true, |first| *first == user.name);
+    if state.rules == RulesVariant::Renju
+        && is_black
+        && matches!(
+            state.board.at(p_move.x as i32, p_move.y as i32),
+            Some(Cell::Empty)
+        )
+        && is_forbidden_renju(&state.board, user, p_move.x, p_move.y)
+    {
+        return InternalMoveResult::InvalidMove;
+    }
+    match state
+        .board
+        .try_place(user, p_move.x, p_move.y, state.win_length)
+    {
         PlaceResult::InvalidMove => InternalMoveResult::InvalidMove,
         PlaceResult::Ok => {
             if state.board.is_full() {
@@ -331,12 +659,109 @@ fn make_move(state: &mut Game, user: &User, p_move: PlayerMove) -> InternalMoveR
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Spot {
+    Own,
+    Empty,
+    // Someone else's piece or off the board
+    Blocked,
+}
+
+// How far out from the new piece a row can matter, one more than a five
+const RENJU_REACH: i32 = 5;
+const RENJU_LINE: usize = 2 * RENJU_REACH as usize + 1;
+const RENJU_CENTER: usize = RENJU_REACH as usize;
+
+/// Whether `user` placing at (x, y) makes an overline (more than 5 in a row), two fours or two
+/// open threes. Making exactly five is never forbidden, it wins. Simplified from the full rules:
+/// a three counts as open without checking whether the move that would make it a four is itself
+/// forbidden, and two fours in the same line count as one
+fn is_forbidden_renju(board: &Board, user: &User, x: usize, y: usize) -> bool {
+    let lines = [(1, 0), (0, 1), (1, 1), (1, -1)].map(|(dx, dy)| {
+        let mut line = [Spot::Blocked; RENJU_LINE];
+        for (i, spot) in line.iter_mut().enumerate() {
+            let offset = i as i32 - RENJU_REACH;
+            *spot = match board.at(x as i32 + offset * dx, y as i32 + offset * dy) {
+                _ if offset == 0 => Spot::Own,
+                Some(Cell::Empty) => Spot::Empty,
+                Some(Cell::Occupied(u)) if u.name == user.name => Spot::Own,
+                _ => Spot::Blocked,
+            };
+        }
+        line
+    });
+
+    let run_lengths = lines.map(|line| run_length(&run_through_center(&line)));
+    if run_lengths.contains(&5) {
+        return false;
+    }
+    if run_lengths.iter().any(|&len| len > 5) {
+        return true;
+    }
+    let fours = lines.iter().filter(|line| is_four(line)).count();
+    let open_threes = lines
+        .iter()
+        .filter(|line| !is_four(line) && is_open_three(line))
+        .count();
+    fours >= 2 || open_threes >= 2
+}
+
+// First and last index of the unbroken row of own pieces through the center
+fn run_through_center(line: &[Spot; RENJU_LINE]) -> (usize, usize) {
+    let mut first = RENJU_CENTER;
+    while first > 0 && line[first - 1] == Spot::Own {
+        first -= 1;
+    }
+    let mut last = RENJU_CENTER;
+    while last < RENJU_LINE - 1 && line[last + 1] == Spot::Own {
+        last += 1;
+    }
+    (first, last)
+}
+
+fn run_length((first, last): &(usize, usize)) -> usize {
+    last - first + 1
+}
+
+// The row through the center if one more piece went on `i`
+fn run_with(line: &[Spot; RENJU_LINE], i: usize) -> (usize, usize) {
+    let mut filled = *line;
+    filled[i] = Spot::Own;
+    run_through_center(&filled)
+}
+
+// One more piece makes exactly five
+fn is_four(line: &[Spot; RENJU_LINE]) -> bool {
+    (0..RENJU_LINE).any(|i| line[i] == Spot::Empty && run_length(&run_with(line, i)) == 5)
+}
+
+// One more piece makes a four that's open at both ends, and neither end makes an overline
+fn is_open_three(line: &[Spot; RENJU_LINE]) -> bool {
+    (0..RENJU_LINE).any(|i| {
+        if line[i] != Spot::Empty {
+            return false;
+        }
+        let run = run_with(line, i);
+        let (first, last) = run;
+        run_length(&run) == 4
+            && first >= 2
+            && last + 2 < RENJU_LINE
+            && line[first - 1] == Spot::Empty
+            && line[last + 1] == Spot::Empty
+            && line[first - 2] != Spot::Own
+            && line[last + 2] != Spot::Own
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     macro_rules! test_init {
         ($game:ident, $p1:ident, $p2:ident, $p3:ident, $mov_ok:ident) => {
+            test_init!($game, $p1, $p2, $p3, $mov_ok, 5);
+        };
+        ($game:ident, $p1:ident, $p2:ident, $p3:ident, $mov_ok:ident, $win_length:expr) => {
             let $p1 = User {
                 name: "player1".to_string(),
                 color: Color::rgb8(0, 0, 0),
@@ -349,7 +774,12 @@ mod test {
                 name: "player3".to_string(),
                 color: Color::rgb8(200, 200, 200),
             };
-            let mut $game = Game::new(10, 10, vec![$p1.clone(), $p2.clone(), $p3.clone()]);
+            let mut $game = Game::new(
+                10,
+                10,
+                $win_length,
+                vec![$p1.clone(), $p2.clone(), $p3.clone()],
+            );
             let mut $mov_ok = |u, x, y| {
                 assert_eq!(
                     make_move(&mut $game, u, PlayerMove { x, y }),
@@ -369,6 +799,194 @@ mod test {
         );
     }
 
+    #[test]
+    fn board_as_text() {
+        let p1 = User {
+            name: "player1".to_string(),
+            color: Color::rgb8(0, 0, 0),
+        };
+        let p2 = User {
+            name: "player2".to_string(),
+            color: Color::rgb8(100, 100, 100),
+        };
+        let mut game = Game::new(5, 5, 5, vec![p1.clone(), p2.clone()]);
+        // player2 goes first, so they're A
+        make_move(&mut game, &p2, PlayerMove { x: 2, y: 2 });
+        make_move(&mut game, &p1, PlayerMove { x: 0, y: 0 });
+        make_move(&mut game, &p2, PlayerMove { x: 4, y: 1 });
+        make_move(&mut game, &p1, PlayerMove { x: 3, y: 4 });
+
+        assert_eq!(
+            game.board.to_string(),
+            [
+                "  A B C D E",
+                "1 B . . . .",
+                "2 . . . . A",
+                "3 . . A . .",
+                "4 . . . . .",
+                "5 . . . B .",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn wide_labels_line_up() {
+        let game = Game::new(2, 10, 5, Vec::new());
+        let text = game.board.to_string();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "   A B");
+        assert_eq!(lines[1], " 1 . .");
+        assert_eq!(lines[10], "10 . .");
+    }
+
+    #[test]
+    fn legal_moves_are_the_empty_cells() {
+        let p1 = User {
+            name: "player1".to_string(),
+            color: Color::rgb8(0, 0, 0),
+        };
+        let mut game = Game::new(5, 5, 5, vec![p1.clone()]);
+        assert_eq!(game.board.legal_moves().count(), 25);
+
+        let taken = [(0, 0), (4, 0), (2, 2), (1, 3), (4, 4)];
+        for (x, y) in taken {
+            make_move(&mut game, &p1, PlayerMove { x, y });
+        }
+        let expected: Vec<_> = (0..5)
+            .flat_map(|y| (0..5).map(move |x| (x, y)))
+            .filter(|pos| !taken.contains(pos))
+            .collect();
+        assert_eq!(game.board.legal_moves().collect::<Vec<_>>(), expected);
+        assert_eq!(expected.len(), 20);
+    }
+
+    // From scratch, what `try_place` keeps up to date one piece at a time
+    fn full_hash(board: &Board) -> u64 {
+        board
+            .cells
+            .iter()
+            .enumerate()
+            .filter_map(|(i, cell)| match cell {
+                Cell::Occupied(user) => Some(zobrist_value(i, &user.name)),
+                Cell::Empty => None,
+            })
+            .fold(0, |hash, value| hash ^ value)
+    }
+
+    #[test]
+    fn hash_is_kept_up_to_date() {
+        test_init!(game, p1, p2, p3, _mov_ok);
+        assert_eq!(game.board.hash(), 0);
+        let moves = [
+            (&p1, 0, 0),
+            (&p2, 9, 9),
+            (&p3, 4, 5),
+            (&p1, 1, 0),
+            (&p2, 0, 9),
+        ];
+        for (user, x, y) in moves {
+            let before = game.board.hash();
+            make_move(&mut game, user, PlayerMove { x, y });
+            assert_ne!(game.board.hash(), before);
+            assert_eq!(game.board.hash(), full_hash(&game.board));
+        }
+        // Taken already, nothing changes
+        let before = game.board.hash();
+        make_move(&mut game, &p3, PlayerMove { x: 4, y: 5 });
+        assert_eq!(game.board.hash(), before);
+    }
+
+    #[test]
+    fn same_board_same_hash() {
+        test_init!(game, p1, p2, _p3, mov_ok);
+        mov_ok(&p1, 2, 2);
+        mov_ok(&p2, 3, 3);
+        mov_ok(&p1, 2, 3);
+        let mut other = Game::new(10, 10, 5, vec![p1.clone(), p2.clone()]);
+        make_move(&mut other, &p1, PlayerMove { x: 2, y: 3 });
+        make_move(&mut other, &p2, PlayerMove { x: 3, y: 3 });
+        make_move(&mut other, &p1, PlayerMove { x: 2, y: 2 });
+        assert_eq!(game.board.hash(), other.board.hash());
+
+        let state = gametraits::to_game_state(&game.board);
+        let json: serde_json::Value = serde_json::from_str(&state.serialized).unwrap();
+        assert_eq!(json["hash"], game.board.hash());
+    }
+
+    #[test]
+    fn move_order_does_not_change_the_hash() {
+        test_init!(game, p1, p2, _p3, mov_ok);
+        mov_ok(&p1, 2, 2);
+        mov_ok(&p2, 3, 3);
+        mov_ok(&p1, 2, 3);
+        mov_ok(&p2, 4, 4);
+        // The same pieces, with p2 going first and both placing theirs in another order
+        let mut other = Game::new(10, 10, 5, vec![p2.clone(), p1.clone()]);
+        make_move(&mut other, &p2, PlayerMove { x: 4, y: 4 });
+        make_move(&mut other, &p1, PlayerMove { x: 2, y: 3 });
+        make_move(&mut other, &p2, PlayerMove { x: 3, y: 3 });
+        make_move(&mut other, &p1, PlayerMove { x: 2, y: 2 });
+        assert_eq!(game.board.hash(), other.board.hash());
+    }
+
+    #[test]
+    fn zobrist_table_is_seeded() {
+        let table = ZobristTable::new(7, 9, 2);
+        assert_eq!(table.values.len(), 18);
+        assert_eq!(table.values, ZobristTable::new(7, 9, 2).values);
+        assert_ne!(table.values, ZobristTable::new(8, 9, 2).values);
+        // Growing the table keeps what's already there
+        let bigger = ZobristTable::new(7, 16, 3);
+        for player in 0..2 {
+            for cell in 0..9 {
+                assert_eq!(table.value(cell, player), bigger.value(cell, player));
+            }
+        }
+    }
+
+    #[test]
+    fn nobody_shares_zobrist_values() {
+        let values: std::collections::HashSet<_> = (0..100)
+            .map(|i| zobrist_value(4, &format!("zobrist{i}")))
+            .collect();
+        assert_eq!(values.len(), 100);
+        assert_eq!(zobrist_value(4, "zobrist7"), zobrist_value(4, "zobrist7"));
+    }
+
+    fn serialized_move(x: i64, y: i64) -> gametraits::PlayerMove {
+        gametraits::PlayerMove {
+            serialized: format!(r#"{{"x":{x},"y":{y}}}"#),
+        }
+    }
+
+    #[test]
+    fn validate_move_occupied_and_out_of_bounds() {
+        test_init!(game, p1, _p2, _p3, mov_ok);
+        mov_ok(&p1, 3, 4);
+        let token = TurnToken { user: p1.clone() };
+
+        assert!(!game.validate_move(&token, &serialized_move(3, 4)));
+        assert!(!game.validate_move(&token, &serialized_move(10, 0)));
+        assert!(!game.validate_move(&token, &serialized_move(0, 10)));
+        assert!(!game.validate_move(&token, &serialized_move(-1, 0)));
+        assert!(!game.validate_move(
+            &token,
+            &gametraits::PlayerMove {
+                serialized: "not a move".to_string()
+            }
+        ));
+        assert!(game.validate_move(&token, &serialized_move(4, 4)));
+    }
+
+    #[test]
+    fn validate_move_leaves_board_alone() {
+        test_init!(game, p1, _p2, _p3, _mov_ok);
+        let before = game.clone();
+        assert!(game.validate_move(&TurnToken { user: p1 }, &serialized_move(0, 0)));
+        assert_eq!(game, before);
+    }
+
     #[test]
     fn normal_game() {
         test_init!(game, p1, p2, _p3, mov_ok);
@@ -386,6 +1004,119 @@ mod test {
         );
     }
 
+    #[test]
+    fn over_after_a_win() {
+        test_init!(game, p1, _p2, _p3, mov_ok);
+        mov_ok(&p1, 0, 0);
+        mov_ok(&p1, 1, 0);
+        mov_ok(&p1, 2, 0);
+        mov_ok(&p1, 3, 0);
+        assert!(!game.is_over());
+        make_move(&mut game, &p1, PlayerMove { x: 4, y: 0 });
+        assert!(game.is_over());
+    }
+
+    fn two_players() -> (User, User) {
+        let p1 = User {
+            name: "player1".to_string(),
+            color: Color::rgb8(0, 0, 0),
+        };
+        let p2 = User {
+            name: "player2".to_string(),
+            color: Color::rgb8(100, 100, 100),
+        };
+        (p1, p2)
+    }
+
+    fn next_turn(result: PlayerMoveResult) -> PlayerTurn {
+        match result {
+            PlayerMoveResult::Ok(turn) => turn,
+            _ => panic!("Expected the game to go on"),
+        }
+    }
+
+    #[test]
+    fn undo_mid_game_gives_the_turn_back() {
+        let (p1, p2) = two_players();
+        let mut game = Game::new(10, 10, 5, vec![p1, p2]);
+        let token = game.try_start_game().unwrap().token;
+        let token = next_turn(game.player_moves(token, serialized_move(2, 2))).token;
+        let before = game.clone();
+        let turn = next_turn(game.player_moves(token, serialized_move(3, 3)));
+        assert_eq!(turn.token.user.name, "player1");
+
+        let undone = game.undo_last_move().unwrap();
+        assert_eq!(undone.token.user.name, "player2");
+        assert_eq!(game.board, before.board);
+        assert_eq!(game.board.hash(), before.board.hash());
+        // Only the one move
+        assert!(game.undo_last_move().is_none());
+
+        // player2 moves again, and player1 is next like before
+        let turn = next_turn(game.player_moves(undone.token, serialized_move(4, 4)));
+        assert_eq!(turn.token.user.name, "player1");
+    }
+
+    #[test]
+    fn undo_of_the_first_piece_forgets_its_player() {
+        let (p1, p2) = two_players();
+        let mut game = Game::new(10, 10, 5, vec![p1, p2]);
+        let token = game.try_start_game().unwrap().token;
+        next_turn(game.player_moves(token, serialized_move(2, 2)));
+        game.undo_last_move().unwrap();
+        assert!(game.board.placed_by.is_empty());
+        assert_eq!(game.board.hash(), 0);
+    }
+
+    #[test]
+    fn undo_after_a_win_is_refused() {
+        let (p1, p2) = two_players();
+        let mut game = Game::new(10, 10, 2, vec![p1, p2]);
+        let token = game.try_start_game().unwrap().token;
+        let token = next_turn(game.player_moves(token, serialized_move(0, 0))).token;
+        let token = next_turn(game.player_moves(token, serialized_move(0, 5))).token;
+        assert!(matches!(
+            game.player_moves(token, serialized_move(1, 0)),
+            PlayerMoveResult::Win
+        ));
+        assert!(game.undo_last_move().is_none());
+        assert!(matches!(game.board.at(1, 0), Some(Cell::Occupied(_))));
+    }
+
+    #[test]
+    fn nothing_to_undo_before_the_first_move() {
+        let (p1, p2) = two_players();
+        let mut game = Game::new(10, 10, 5, vec![p1, p2]);
+        game.try_start_game();
+        assert!(game.undo_last_move().is_none());
+    }
+
+    #[test]
+    fn over_after_a_draw() {
+        let p1 = User {
+            name: "player1".to_string(),
+            color: Color::rgb8(0, 0, 0),
+        };
+        let p2 = User {
+            name: "player2".to_string(),
+            color: Color::rgb8(100, 100, 100),
+        };
+        // Too small for three in a row
+        let mut game = Game::new(2, 2, 3, vec![p1.clone(), p2.clone()]);
+        for (user, x, y) in [(&p1, 0, 0), (&p2, 1, 0), (&p1, 0, 1)] {
+            assert_eq!(
+                make_move(&mut game, user, PlayerMove { x, y }),
+                InternalMoveResult::Ok
+            );
+            assert!(!game.is_over());
+        }
+        assert_eq!(
+            make_move(&mut game, &p2, PlayerMove { x: 1, y: 1 }),
+            InternalMoveResult::Draw
+        );
+        assert!(game.is_over());
+    }
+
     #[test]
     fn different_players_dont_streak() {
         test_init!(game, p1, p2, _p3, mov_ok);
@@ -466,4 +1197,156 @@ mod test {
             InternalMoveResult::Win
         );
     }
+
+    #[test]
+    fn four_wins_with_win_length_four() {
+        test_init!(game, p1, _p2, _p3, mov_ok, 4);
+
+        mov_ok(&p1, 3, 3);
+        mov_ok(&p1, 4, 3);
+        mov_ok(&p1, 5, 3);
+        assert_eq!(
+            make_move(&mut game, &p1, PlayerMove { x: 6, y: 3 }),
+            InternalMoveResult::Win
+        );
+    }
+
+    #[test]
+    fn four_doesnt_win_with_win_length_five() {
+        test_init!(game, p1, _p2, _p3, mov_ok, 5);
+
+        mov_ok(&p1, 3, 3);
+        mov_ok(&p1, 4, 3);
+        mov_ok(&p1, 5, 3);
+        mov_ok(&p1, 6, 3);
+    }
+
+    #[test]
+    fn six_wins_with_win_length_six() {
+        test_init!(game, p1, _p2, _p3, mov_ok, 6);
+
+        mov_ok(&p1, 2, 2);
+        mov_ok(&p1, 3, 3);
+        mov_ok(&p1, 4, 4);
+        mov_ok(&p1, 5, 5);
+        mov_ok(&p1, 6, 6);
+        assert_eq!(
+            make_move(&mut game, &p1, PlayerMove { x: 7, y: 7 }),
+            InternalMoveResult::Win
+        );
+    }
+
+    #[test]
+    fn six_doesnt_win_with_win_length_seven() {
+        test_init!(game, p1, _p2, _p3, mov_ok, 7);
+
+        mov_ok(&p1, 2, 0);
+        mov_ok(&p1, 2, 1);
+        mov_ok(&p1, 2, 2);
+        mov_ok(&p1, 2, 3);
+        mov_ok(&p1, 2, 4);
+        mov_ok(&p1, 2, 5);
+    }
+
+    fn renju_places(game: &mut Game, user: &User, moves: &[(usize, usize)]) {
+        for &(x, y) in moves {
+            assert_eq!(
+                make_move(game, user, PlayerMove { x, y }),
+                InternalMoveResult::Ok
+            );
+        }
+    }
+
+    #[test]
+    fn renju_overline_is_forbidden() {
+        test_init!(game, p1, _p2, _p3, _mov_ok);
+        let mut game = game.with_rules(RulesVariant::Renju);
+
+        renju_places(&mut game, &p1, &[(0, 0), (1, 0), (2, 0), (4, 0), (5, 0)]);
+        assert_eq!(
+            make_move(&mut game, &p1, PlayerMove { x: 3, y: 0 }),
+            InternalMoveResult::InvalidMove
+        );
+        assert_eq!(game.board.at(3, 0), Some(&Cell::Empty));
+    }
+
+    #[test]
+    fn renju_double_four_is_forbidden() {
+        test_init!(game, p1, _p2, _p3, _mov_ok);
+        let mut game = game.with_rules(RulesVariant::Renju);
+
+        renju_places(
+            &mut game,
+            &p1,
+            &[(1, 4), (2, 4), (3, 4), (4, 1), (4, 2), (4, 3)],
+        );
+        assert_eq!(
+            make_move(&mut game, &p1, PlayerMove { x: 4, y: 4 }),
+            InternalMoveResult::InvalidMove
+        );
+    }
+
+    #[test]
+    fn renju_double_three_is_forbidden() {
+        test_init!(game, p1, _p2, _p3, _mov_ok);
+        let mut game = game.with_rules(RulesVariant::Renju);
+
+        renju_places(&mut game, &p1, &[(2, 4), (3, 4), (4, 2), (4, 3)]);
+        assert_eq!(
+            make_move(&mut game, &p1, PlayerMove { x: 4, y: 4 }),
+            InternalMoveResult::InvalidMove
+        );
+    }
+
+    #[test]
+    fn renju_five_still_wins() {
+        test_init!(game, p1, _p2, _p3, _mov_ok);
+        let mut game = game.with_rules(RulesVariant::Renju);
+
+        // Also a four going down, but the five wins
+        renju_places(
+            &mut game,
+            &p1,
+            &[(0, 4), (1, 4), (2, 4), (3, 4), (4, 1), (4, 2), (4, 3)],
+        );
+        assert_eq!(
+            make_move(&mut game, &p1, PlayerMove { x: 4, y: 4 }),
+            InternalMoveResult::Win
+        );
+    }
+
+    #[test]
+    fn renju_leaves_white_alone() {
+        test_init!(game, p1, p2, _p3, _mov_ok);
+        let mut game = game.with_rules(RulesVariant::Renju);
+
+        renju_places(&mut game, &p1, &[(9, 9)]);
+        renju_places(&mut game, &p2, &[(2, 4), (3, 4), (4, 2), (4, 3), (4, 4)]);
+        renju_places(&mut game, &p2, &[(0, 0), (1, 0), (2, 0), (4, 0), (5, 0)]);
+        assert_eq!(
+            make_move(&mut game, &p2, PlayerMove { x: 3, y: 0 }),
+            InternalMoveResult::Win
+        );
+    }
+
+    #[test]
+    fn freestyle_allows_double_three() {
+        test_init!(game, p1, _p2, _p3, mov_ok);
+
+        mov_ok(&p1, 2, 4);
+        mov_ok(&p1, 3, 4);
+        mov_ok(&p1, 4, 2);
+        mov_ok(&p1, 4, 3);
+        mov_ok(&p1, 4, 4);
+    }
+
+    #[test]
+    fn describe_has_the_board_and_rules() {
+        let game = Game::new(15, 10, 5, Vec::new());
+        assert_eq!(game.describe(), "Gomoku 15x10, 5 in a row");
+        assert_eq!(
+            game.with_rules(RulesVariant::Renju).describe(),
+            "Gomoku 15x10, 5 in a row, renju rules"
+        );
+    }
 }
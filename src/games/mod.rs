@@ -1,2 +1,8 @@
 pub mod dumb;
 pub mod gomoku;
+pub mod nim;
+pub mod number_guess;
+pub mod reversi;
+pub mod rps;
+pub mod tictactoe;
+pub mod wordle;
@@ -0,0 +1,370 @@
+use std::any::Any;
+
+use code_challenge_game_types::gametraits::{
+    self, GameTrait, PlayerMoveResult, PlayerTurn, TurnToken, User,
+};
+use code_challenge_game_types::TurnTracker;
+
+use druid::{
+    piet::{Text, TextLayoutBuilder},
+    Color, FontFamily, Rect, RenderContext,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerMove {
+    pile: usize,
+    take: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GameVariant {
+    /// Whoever takes the last object wins
+    #[default]
+    Normal,
+    /// Whoever takes the last object loses
+    Misere,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Game {
+    state: State,
+    // What `reset` goes back to
+    initial_piles: Vec<u32>,
+    winner: Option<String>,
+    players: TurnTracker,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+struct State {
+    piles: Vec<u32>,
+    variant: GameVariant,
+}
+
+impl Game {
+    pub fn new(piles: Vec<u32>, variant: GameVariant, players: Vec<User>) -> Self {
+        Self {
+            state: State {
+                piles: piles.clone(),
+                variant,
+            },
+            initial_piles: piles,
+            winner: None,
+            players: TurnTracker::new(players),
+        }
+    }
+
+    // TODO: Move to GameTrait::describe once game-types has it
+    pub fn describe(&self) -> String {
+        let piles: Vec<_> = self.initial_piles.iter().map(u32::to_string).collect();
+        let variant = match self.state.variant {
+            GameVariant::Normal => "",
+            GameVariant::Misere => ", misère",
+        };
+        format!("Nim, piles {}{variant}", piles.join(" "))
+    }
+
+    // TODO: Move into GameTrait once game-types has min_players/max_players
+    pub fn min_players(&self) -> usize {
+        2
+    }
+
+    pub fn max_players(&self) -> usize {
+        2
+    }
+
+    // TODO: Drop once PlayerMoveResult can say who won, see AsyncGameTrait::winner
+    pub fn winner(&self) -> Option<String> {
+        self.winner.clone()
+    }
+
+    fn turn(&self, user: User) -> PlayerTurn {
+        PlayerTurn {
+            token: TurnToken { user },
+            state: gametraits::to_game_state(&self.state),
+        }
+    }
+
+    fn invalid_move_next_turn(&mut self, user: &User) -> Option<PlayerTurn> {
+        self.players.remove_player(&user.name);
+        self.players.advance_player().map(|p| self.turn(p))
+    }
+}
+
+impl gametraits::GameTrait for Game {
+    fn player_moves(
+        &mut self,
+        token: TurnToken,
+        player_move: gametraits::PlayerMove,
+    ) -> PlayerMoveResult {
+        let user = &token.user;
+        match gametraits::to_player_move::<PlayerMove>(&player_move) {
+            Some(mov) => match make_move(self, mov) {
+                InternalMoveResult::InvalidMove => {
+                    PlayerMoveResult::InvalidMove(self.invalid_move_next_turn(user))
+                }
+                InternalMoveResult::Ok => {
+                    let p = self.players.advance_player().unwrap();
+                    PlayerMoveResult::Ok(self.turn(p))
+                }
+                InternalMoveResult::Win => {
+                    self.winner = Some(user.name.clone());
+                    PlayerMoveResult::Win
+                }
+                InternalMoveResult::Draw => PlayerMoveResult::Draw,
+                // Win always means the player who moved, the controller finds the opponent in
+                // `winner` instead
+                InternalMoveResult::Loss => {
+                    self.winner = self.players.advance_player().map(|p| p.name);
+                    PlayerMoveResult::Draw
+                }
+            },
+            None => PlayerMoveResult::InvalidFormat(self.invalid_move_next_turn(user)),
+        }
+    }
+
+    fn player_connected(&mut self, user: User) {
+        self.players.add_player(user);
+    }
+
+    fn player_disconnected(&mut self, username: &str) {
+        self.players.remove_player(username);
+    }
+
+    fn current_player_disconnected(&mut self, player_token: TurnToken) -> Option<PlayerTurn> {
+        self.players.remove_player(&player_token.user.name);
+        self.players.advance_player().map(|user| self.turn(user))
+    }
+
+    fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        self.players.advance_player().map(|user| self.turn(user))
+    }
+
+    fn reset(&mut self, users: Vec<User>) {
+        *self = Game::new(self.initial_piles.clone(), self.state.variant, users);
+    }
+}
+
+impl gametraits::Paint for Game {
+    fn eq(&self, other: &dyn gametraits::Paint) -> bool {
+        self == gametraits::Paint::as_any(other)
+            .downcast_ref::<Game>()
+            .unwrap()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn paint(&self, ctx: &mut druid::PaintCtx) {
+        const TEXT_HEIGHT: f64 = 40.0;
+        let size = ctx.size();
+        let piles = &self.state.piles;
+        let slot_width = size.width / piles.len().max(1) as f64;
+        // The tallest pile there has ever been fills the height, so bars only ever shrink
+        let tallest = self.initial_piles.iter().copied().max().unwrap_or(0).max(1);
+        let unit = (size.height - 2.0 * TEXT_HEIGHT) / tallest as f64;
+        let bar_color = Color::rgb8(70, 130, 180);
+        for (i, count) in piles.iter().enumerate() {
+            let left = i as f64 * slot_width + slot_width * 0.25;
+            let bottom = size.height - TEXT_HEIGHT;
+            ctx.fill(
+                Rect::new(
+                    left,
+                    bottom - *count as f64 * unit,
+                    left + slot_width * 0.5,
+                    bottom,
+                ),
+                &bar_color,
+            );
+            let layout = ctx
+                .text()
+                .new_text_layout(format!("{i}: {count}"))
+                .font(FontFamily::SERIF, 24.0)
+                .text_color(Color::rgb8(0, 0, 0))
+                .build()
+                .unwrap();
+            ctx.draw_text(&layout, (left, bottom + 5.0));
+        }
+
+        let status = match &self.winner {
+            Some(winner) => format!("Winner! {winner}"),
+            None if piles.iter().all(|pile| *pile == 0) => "Draw!".to_string(),
+            None => return,
+        };
+        let layout = ctx
+            .text()
+            .new_text_layout(status)
+            .font(FontFamily::SERIF, 24.0)
+            .text_color(Color::rgb8(0, 0, 0))
+            .build()
+            .unwrap();
+        ctx.draw_text(&layout, (100.0, 5.0));
+    }
+}
+
+pub fn make_ptr(players: Vec<User>) -> Box<dyn GameTrait> {
+    Box::new(Game::new(vec![3, 4, 5], GameVariant::Normal, players))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum InternalMoveResult {
+    InvalidMove,
+    Ok,
+    Win,
+    // The player who moved lost the game
+    Loss,
+    Draw,
+}
+
+fn make_move(state: &mut Game, p_move: PlayerMove) -> InternalMoveResult {
+    let piles = &mut state.state.piles;
+    // Nothing to take from the start, nobody can win
+    if piles.iter().all(|pile| *pile == 0) {
+        return InternalMoveResult::Draw;
+    }
+    match piles.get_mut(p_move.pile) {
+        Some(pile) if (1..=*pile).contains(&p_move.take) => *pile -= p_move.take,
+        _ => return InternalMoveResult::InvalidMove,
+    }
+    if piles.iter().any(|pile| *pile > 0) {
+        return InternalMoveResult::Ok;
+    }
+    match state.state.variant {
+        GameVariant::Normal => InternalMoveResult::Win,
+        GameVariant::Misere => InternalMoveResult::Loss,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn user(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            color: Color::rgb8(0, 0, 0),
+        }
+    }
+
+    fn started_game(piles: Vec<u32>, variant: GameVariant) -> (Game, TurnToken) {
+        let mut game = Game::new(piles, variant, vec![user("player1"), user("player2")]);
+        let token = game.try_start_game().unwrap().token;
+        (game, token)
+    }
+
+    fn take(pile: usize, take: u32) -> gametraits::PlayerMove {
+        gametraits::PlayerMove {
+            serialized: format!(r#"{{"pile":{pile},"take":{take}}}"#),
+        }
+    }
+
+    fn next(result: PlayerMoveResult) -> TurnToken {
+        match result {
+            PlayerMoveResult::Ok(turn) => turn.token,
+            _ => panic!("Expected the game to go on"),
+        }
+    }
+
+    #[test]
+    fn taking_more_than_there_is_is_invalid() {
+        let (mut game, token) = started_game(vec![3, 4], GameVariant::Normal);
+        assert_eq!(token.user.name, "player1");
+        match game.player_moves(token, take(0, 4)) {
+            PlayerMoveResult::InvalidMove(Some(turn)) => {
+                assert_eq!(turn.token.user.name, "player2")
+            }
+            _ => panic!("Expected an invalid move"),
+        }
+        assert_eq!(game.state.piles, vec![3, 4]);
+    }
+
+    #[test]
+    fn nothing_and_no_such_pile_are_invalid() {
+        let mut game = Game::new(vec![3, 4], GameVariant::Normal, Vec::new());
+        assert_eq!(
+            make_move(&mut game, PlayerMove { pile: 0, take: 0 }),
+            InternalMoveResult::InvalidMove
+        );
+        assert_eq!(
+            make_move(&mut game, PlayerMove { pile: 2, take: 1 }),
+            InternalMoveResult::InvalidMove
+        );
+        assert_eq!(
+            make_move(&mut game, PlayerMove { pile: 1, take: 4 }),
+            InternalMoveResult::Ok
+        );
+        assert_eq!(game.state.piles, vec![3, 0]);
+    }
+
+    #[test]
+    fn last_object_wins_in_normal() {
+        let (mut game, token) = started_game(vec![2, 1], GameVariant::Normal);
+        let token = next(game.player_moves(token, take(0, 2)));
+        assert_eq!(token.user.name, "player2");
+        assert!(matches!(
+            game.player_moves(token, take(1, 1)),
+            PlayerMoveResult::Win
+        ));
+        assert_eq!(game.winner.as_deref(), Some("player2"));
+    }
+
+    #[test]
+    fn last_object_loses_in_misere() {
+        let (mut game, token) = started_game(vec![2, 1], GameVariant::Misere);
+        // player1 leaves one object, player2 has to take it
+        let token = next(game.player_moves(token, take(0, 2)));
+        assert!(matches!(
+            game.player_moves(token, take(1, 1)),
+            PlayerMoveResult::Draw
+        ));
+        assert_eq!(game.winner().as_deref(), Some("player1"));
+    }
+
+    #[test]
+    fn empty_piles_are_a_draw() {
+        let (mut game, token) = started_game(vec![0, 0], GameVariant::Normal);
+        assert!(matches!(
+            game.player_moves(token, take(0, 1)),
+            PlayerMoveResult::Draw
+        ));
+        assert_eq!(game.winner, None);
+        let (mut game, token) = started_game(Vec::new(), GameVariant::Misere);
+        assert!(matches!(
+            game.player_moves(token, take(0, 1)),
+            PlayerMoveResult::Draw
+        ));
+    }
+
+    #[test]
+    fn reset_restores_the_piles() {
+        let (mut game, token) = started_game(vec![3, 4, 5], GameVariant::Misere);
+        let token = next(game.player_moves(token, take(2, 5)));
+        game.player_moves(token, take(0, 1));
+        assert_eq!(game.state.piles, vec![2, 4, 0]);
+        game.reset(vec![user("player3")]);
+        assert_eq!(game.state.piles, vec![3, 4, 5]);
+        assert_eq!(game.state.variant, GameVariant::Misere);
+        let turn = game.try_start_game().unwrap();
+        assert_eq!(turn.token.user.name, "player3");
+    }
+
+    #[test]
+    fn state_has_piles_and_variant() {
+        let game = Game::new(vec![1, 2], GameVariant::Misere, Vec::new());
+        assert_eq!(
+            serde_json::to_string(&game.state).unwrap(),
+            r#"{"piles":[1,2],"variant":"misere"}"#
+        );
+    }
+
+    #[test]
+    fn describe_has_piles_and_variant() {
+        let game = Game::new(vec![3, 4, 5], GameVariant::Normal, Vec::new());
+        assert_eq!(game.describe(), "Nim, piles 3 4 5");
+        let game = Game::new(vec![1], GameVariant::Misere, Vec::new());
+        assert_eq!(game.describe(), "Nim, piles 1, misère");
+    }
+}
@@ -0,0 +1,329 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+
+use code_challenge_game_types::gametraits::{
+    self, GameTrait, PlayerMoveResult, PlayerTurn, TurnToken, User,
+};
+use code_challenge_game_types::TurnTracker;
+
+use druid::{
+    piet::{Text, TextLayoutBuilder},
+    Color, FontFamily, RenderContext,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+const MAX_GUESSES: u32 = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerMove {
+    guess: u32,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Hint {
+    TooHigh,
+    TooLow,
+    Correct,
+}
+
+// What a player is told on their turn, only about their own guesses
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+struct PlayerState {
+    hint: Option<Hint>,
+    guesses_left: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    rng: StdRng,
+    target: u32,
+    guesses: BTreeMap<String, PlayerState>,
+    winner: Option<User>,
+    players: TurnTracker,
+}
+
+// The rng is left out, two games showing the same thing are equal
+impl PartialEq for Game {
+    fn eq(&self, other: &Self) -> bool {
+        self.target == other.target
+            && self.guesses == other.guesses
+            && self.winner == other.winner
+            && self.players == other.players
+    }
+}
+
+impl Game {
+    pub fn new(players: Vec<User>) -> Self {
+        Self::with_rng(StdRng::from_rng(rand::thread_rng()).unwrap(), players)
+    }
+
+    pub fn with_rng(mut rng: StdRng, players: Vec<User>) -> Self {
+        Self {
+            target: pick_target(&mut rng),
+            rng,
+            guesses: BTreeMap::new(),
+            winner: None,
+            players: TurnTracker::new(players),
+        }
+    }
+
+    // TODO: Move to GameTrait::describe once game-types has it
+    pub fn describe(&self) -> String {
+        format!("Guess the number from 1 to 100, {MAX_GUESSES} guesses")
+    }
+
+    // TODO: Move into GameTrait once game-types has min_players/max_players
+    pub fn min_players(&self) -> usize {
+        1
+    }
+
+    fn turn(&self, user: User) -> PlayerTurn {
+        let state = self
+            .guesses
+            .get(&user.name)
+            .cloned()
+            .unwrap_or(PlayerState {
+                hint: None,
+                guesses_left: MAX_GUESSES,
+            });
+        PlayerTurn {
+            token: TurnToken { user },
+            state: gametraits::to_game_state(&state),
+        }
+    }
+
+    fn eliminate_next_turn(&mut self, user: &User) -> Option<PlayerTurn> {
+        self.players.remove_player(&user.name);
+        self.players.advance_player().map(|p| self.turn(p))
+    }
+}
+
+fn pick_target(rng: &mut StdRng) -> u32 {
+    rng.gen_range(1..=100)
+}
+
+impl gametraits::GameTrait for Game {
+    fn player_moves(
+        &mut self,
+        token: TurnToken,
+        player_move: gametraits::PlayerMove,
+    ) -> PlayerMoveResult {
+        let user = &token.user;
+        match gametraits::to_player_move::<PlayerMove>(&player_move) {
+            Some(mov) => match make_move(self, user, mov) {
+                InternalMoveResult::Ok => {
+                    let p = self.players.advance_player().unwrap();
+                    PlayerMoveResult::Ok(self.turn(p))
+                }
+                InternalMoveResult::Win => PlayerMoveResult::Win,
+                InternalMoveResult::OutOfGuesses => {
+                    PlayerMoveResult::InvalidMove(self.eliminate_next_turn(user))
+                }
+            },
+            None => PlayerMoveResult::InvalidFormat(self.eliminate_next_turn(user)),
+        }
+    }
+
+    fn player_connected(&mut self, user: User) {
+        self.players.add_player(user);
+    }
+
+    fn player_disconnected(&mut self, username: &str) {
+        self.players.remove_player(username);
+    }
+
+    fn current_player_disconnected(&mut self, player_token: TurnToken) -> Option<PlayerTurn> {
+        self.players.remove_player(&player_token.user.name);
+        self.players.advance_player().map(|user| self.turn(user))
+    }
+
+    fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        self.target = pick_target(&mut self.rng);
+        self.players.advance_player().map(|user| self.turn(user))
+    }
+
+    fn reset(&mut self, users: Vec<User>) {
+        *self = Game::with_rng(self.rng.clone(), users);
+    }
+}
+
+impl gametraits::Paint for Game {
+    fn eq(&self, other: &dyn gametraits::Paint) -> bool {
+        self == gametraits::Paint::as_any(other)
+            .downcast_ref::<Game>()
+            .unwrap()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn paint(&self, ctx: &mut druid::PaintCtx) {
+        let mut lines = vec![format!("Number: {}", self.target)];
+        for (name, state) in &self.guesses {
+            let hint = match state.hint {
+                Some(Hint::TooHigh) => "too high",
+                Some(Hint::TooLow) => "too low",
+                Some(Hint::Correct) => "correct",
+                None => "",
+            };
+            lines.push(format!(
+                "{name}: {} guesses left, {hint}",
+                state.guesses_left
+            ));
+        }
+        if let Some(winner) = &self.winner {
+            lines.push(format!("Winner! {}", winner.name));
+        }
+
+        let text = ctx.text();
+        let layout = text
+            .new_text_layout(lines.join("\n"))
+            .font(FontFamily::SERIF, 24.0)
+            .text_color(Color::rgb8(0, 0, 0))
+            .build()
+            .unwrap();
+        ctx.draw_text(&layout, (100.0, 25.0));
+    }
+}
+
+pub fn make_ptr(players: Vec<User>) -> Box<dyn GameTrait> {
+    Box::new(Game::new(players))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum InternalMoveResult {
+    Ok,
+    Win,
+    OutOfGuesses,
+}
+
+fn make_move(state: &mut Game, user: &User, p_move: PlayerMove) -> InternalMoveResult {
+    let target = state.target;
+    let player = state
+        .guesses
+        .entry(user.name.clone())
+        .or_insert(PlayerState {
+            hint: None,
+            guesses_left: MAX_GUESSES,
+        });
+    player.guesses_left = player.guesses_left.saturating_sub(1);
+    let hint = match p_move.guess {
+        guess if guess > target => Hint::TooHigh,
+        guess if guess < target => Hint::TooLow,
+        _ => Hint::Correct,
+    };
+    player.hint = Some(hint);
+
+    if hint == Hint::Correct {
+        state.winner = Some(user.clone());
+        InternalMoveResult::Win
+    } else if player.guesses_left == 0 {
+        InternalMoveResult::OutOfGuesses
+    } else {
+        InternalMoveResult::Ok
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    macro_rules! test_init {
+        ($game:ident, $target:ident, $p1:ident, $p2:ident, $mov:ident) => {
+            let $p1 = User {
+                name: "player1".to_string(),
+                color: Color::rgb8(0, 0, 0),
+            };
+            let $p2 = User {
+                name: "player2".to_string(),
+                color: Color::rgb8(100, 100, 100),
+            };
+            let mut $game =
+                Game::with_rng(StdRng::seed_from_u64(7), vec![$p1.clone(), $p2.clone()]);
+            $game.try_start_game();
+            let $target = $game.target;
+            let mut $mov = |u, guess| make_move(&mut $game, u, PlayerMove { guess });
+        };
+    }
+
+    #[test]
+    fn same_seed_same_number() {
+        let mut first = Game::with_rng(StdRng::seed_from_u64(7), Vec::new());
+        let mut second = Game::with_rng(StdRng::seed_from_u64(7), Vec::new());
+        first.try_start_game();
+        second.try_start_game();
+        assert_eq!(first.target, second.target);
+        assert!((1..=100).contains(&first.target));
+    }
+
+    #[test]
+    fn hints_point_at_the_number() {
+        test_init!(game, target, p1, _p2, _mov);
+        let mut guess = |guess| {
+            make_move(&mut game, &p1, PlayerMove { guess });
+            game.guesses[&p1.name].hint
+        };
+        assert_eq!(guess(target + 1), Some(Hint::TooHigh));
+        assert_eq!(guess(target - 1), Some(Hint::TooLow));
+        assert_eq!(game.guesses[&p1.name].guesses_left, MAX_GUESSES - 2);
+    }
+
+    #[test]
+    fn correct_guess_wins() {
+        test_init!(game, target, p1, p2, mov);
+        assert_eq!(mov(&p1, target + 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, target), InternalMoveResult::Win);
+        assert_eq!(game.guesses[&p2.name].hint, Some(Hint::Correct));
+        assert_eq!(game.winner, Some(p2));
+    }
+
+    #[test]
+    fn out_of_guesses_after_ten() {
+        test_init!(game, target, p1, _p2, mov);
+        let wrong = target + 1;
+        for _ in 1..MAX_GUESSES {
+            assert_eq!(mov(&p1, wrong), InternalMoveResult::Ok);
+        }
+        assert_eq!(mov(&p1, wrong), InternalMoveResult::OutOfGuesses);
+    }
+
+    #[test]
+    fn eliminated_player_leaves_the_others_playing() {
+        test_init!(game, target, p1, p2, _mov);
+        game.guesses.insert(
+            p1.name.clone(),
+            PlayerState {
+                hint: Some(Hint::TooLow),
+                guesses_left: 1,
+            },
+        );
+        let wrong = gametraits::PlayerMove {
+            serialized: format!(r#"{{"guess":{}}}"#, target + 1),
+        };
+        let token = TurnToken { user: p1.clone() };
+        let PlayerMoveResult::InvalidMove(Some(turn)) = game.player_moves(token, wrong) else {
+            panic!("Expected the next player's turn");
+        };
+        assert_eq!(turn.token.user, p2);
+    }
+
+    #[test]
+    fn solo_play() {
+        let p1 = User {
+            name: "player1".to_string(),
+            color: Color::rgb8(0, 0, 0),
+        };
+        let mut game = Game::with_rng(StdRng::seed_from_u64(3), vec![p1.clone()]);
+        let turn = game.try_start_game().unwrap();
+        assert_eq!(turn.token.user, p1);
+        let target = game.target;
+        assert_eq!(
+            make_move(&mut game, &p1, PlayerMove { guess: target }),
+            InternalMoveResult::Win
+        );
+    }
+}
@@ -0,0 +1,517 @@
+use std::any::Any;
+
+use code_challenge_game_types::gametraits::{
+    self, GameTrait, PlayerMoveResult, PlayerTurn, TurnToken, User,
+};
+use code_challenge_game_types::TurnTracker;
+
+use druid::{
+    kurbo::Circle,
+    piet::{Text, TextLayoutBuilder},
+    Color, FontFamily, Point, Rect, RenderContext,
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+const SIZE: usize = 8;
+
+const DIRECTIONS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerMove {
+    x: usize,
+    y: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Game {
+    board: Board,
+    winner: Option<Disc>,
+    players: TurnTracker,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+struct Board {
+    cells: Vec<Cell>,
+    // Black is whoever moves first, white whoever moves second
+    black: Option<String>,
+    white: Option<String>,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Cell {
+    Empty,
+    Black,
+    White,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Disc {
+    Black,
+    White,
+}
+
+impl Disc {
+    fn opponent(self) -> Disc {
+        match self {
+            Disc::Black => Disc::White,
+            Disc::White => Disc::Black,
+        }
+    }
+
+    fn cell(self) -> Cell {
+        match self {
+            Disc::Black => Cell::Black,
+            Disc::White => Cell::White,
+        }
+    }
+}
+
+impl Board {
+    fn new() -> Self {
+        let mut cells = vec![Cell::Empty; SIZE * SIZE];
+        let mid = SIZE / 2;
+        cells[(mid - 1) * SIZE + mid - 1] = Cell::White;
+        cells[(mid - 1) * SIZE + mid] = Cell::Black;
+        cells[mid * SIZE + mid - 1] = Cell::Black;
+        cells[mid * SIZE + mid] = Cell::White;
+        Self {
+            cells,
+            black: None,
+            white: None,
+        }
+    }
+
+    fn at(&self, x: i32, y: i32) -> Option<Cell> {
+        if x < 0 || y < 0 || x >= SIZE as i32 || y >= SIZE as i32 {
+            None
+        } else {
+            Some(self.cells[y as usize * SIZE + x as usize])
+        }
+    }
+
+    /// Every disc that would be flipped by `disc` playing at (x, y), empty if it isn't a legal move
+    fn flips(&self, disc: Disc, x: usize, y: usize) -> Vec<(usize, usize)> {
+        if self.at(x as i32, y as i32) != Some(Cell::Empty) {
+            return Vec::new();
+        }
+        let mut flips = Vec::new();
+        for (dx, dy) in DIRECTIONS {
+            let mut line = Vec::new();
+            let (mut cx, mut cy) = (x as i32 + dx, y as i32 + dy);
+            while self.at(cx, cy) == Some(disc.opponent().cell()) {
+                line.push((cx as usize, cy as usize));
+                cx += dx;
+                cy += dy;
+            }
+            // The line only counts if it's closed off by one of our own
+            if self.at(cx, cy) == Some(disc.cell()) {
+                flips.extend(line);
+            }
+        }
+        flips
+    }
+
+    fn has_legal_move(&self, disc: Disc) -> bool {
+        (0..SIZE).any(|x| (0..SIZE).any(|y| !self.flips(disc, x, y).is_empty()))
+    }
+
+    fn is_over(&self) -> bool {
+        !self.has_legal_move(Disc::Black) && !self.has_legal_move(Disc::White)
+    }
+
+    fn count(&self, disc: Disc) -> usize {
+        self.cells.iter().filter(|c| **c == disc.cell()).count()
+    }
+
+    fn disc_of(&mut self, name: &str) -> Option<Disc> {
+        if self.black.is_none() {
+            self.black = Some(name.to_string());
+        } else if self.white.is_none() && self.black.as_deref() != Some(name) {
+            self.white = Some(name.to_string());
+        }
+        if self.black.as_deref() == Some(name) {
+            Some(Disc::Black)
+        } else if self.white.as_deref() == Some(name) {
+            Some(Disc::White)
+        } else {
+            // Only two can play
+            None
+        }
+    }
+}
+
+impl Game {
+    pub fn new(players: Vec<User>) -> Self {
+        Self {
+            board: Board::new(),
+            winner: None,
+            players: TurnTracker::new(players),
+        }
+    }
+
+    /// (columns, rows), always 8x8
+    pub fn board_size(&self) -> (usize, usize) {
+        (SIZE, SIZE)
+    }
+
+    // TODO: Move to GameTrait::describe once game-types has it
+    pub fn describe(&self) -> String {
+        format!("Reversi {SIZE}x{SIZE}")
+    }
+
+    // TODO: Move into GameTrait once game-types has min_players/max_players
+    pub fn min_players(&self) -> usize {
+        2
+    }
+
+    pub fn max_players(&self) -> usize {
+        2
+    }
+
+    fn turn(&self, user: User) -> PlayerTurn {
+        PlayerTurn {
+            token: TurnToken { user },
+            state: gametraits::to_game_state(&self.board),
+        }
+    }
+
+    fn invalid_move_next_turn(&mut self, user: &User) -> Option<PlayerTurn> {
+        self.players.remove_player(&user.name);
+        self.players.advance_player().map(|p| self.turn(p))
+    }
+}
+
+impl gametraits::GameTrait for Game {
+    fn player_moves(
+        &mut self,
+        token: TurnToken,
+        player_move: gametraits::PlayerMove,
+    ) -> PlayerMoveResult {
+        let user = &token.user;
+        match gametraits::to_player_move::<PlayerMove>(&player_move) {
+            Some(mov) => match make_move(self, user, mov) {
+                InternalMoveResult::InvalidMove => {
+                    PlayerMoveResult::InvalidMove(self.invalid_move_next_turn(user))
+                }
+                InternalMoveResult::Ok => {
+                    let p = self.players.advance_player().unwrap();
+                    PlayerMoveResult::Ok(self.turn(p))
+                }
+                InternalMoveResult::Pass => {
+                    debug!("Opponent can't move, {} goes again", user.name);
+                    PlayerMoveResult::Ok(self.turn(user.clone()))
+                }
+                InternalMoveResult::Win => PlayerMoveResult::Win,
+                InternalMoveResult::Draw => PlayerMoveResult::Draw,
+                // TODO: Announce the opponent as winner once PlayerMoveResult can say who won,
+                // Win always means the player who moved
+                InternalMoveResult::Loss => PlayerMoveResult::Draw,
+            },
+            None => PlayerMoveResult::InvalidFormat(self.invalid_move_next_turn(user)),
+        }
+    }
+
+    fn player_connected(&mut self, user: User) {
+        self.players.add_player(user);
+    }
+
+    fn player_disconnected(&mut self, username: &str) {
+        self.players.remove_player(username);
+    }
+
+    fn current_player_disconnected(&mut self, player_token: TurnToken) -> Option<PlayerTurn> {
+        self.players.remove_player(&player_token.user.name);
+        self.players.advance_player().map(|user| self.turn(user))
+    }
+
+    fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        self.players.advance_player().map(|user| self.turn(user))
+    }
+
+    fn reset(&mut self, users: Vec<User>) {
+        *self = Game::new(users);
+    }
+}
+
+impl gametraits::Paint for Game {
+    fn eq(&self, other: &dyn gametraits::Paint) -> bool {
+        self == gametraits::Paint::as_any(other)
+            .downcast_ref::<Game>()
+            .unwrap()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn paint(&self, ctx: &mut druid::PaintCtx) {
+        let cell_width = ctx.size().width.min(ctx.size().height) / SIZE as f64;
+
+        const CELL_SPACING: f64 = 2_f64;
+        let felt = Color::rgb8(0, 110, 50);
+        for x in 0..SIZE {
+            for y in 0..SIZE {
+                let origin = Point {
+                    x: x as f64 * cell_width,
+                    y: y as f64 * cell_width,
+                };
+                ctx.fill(
+                    Rect::from_origin_size(
+                        origin,
+                        (cell_width - CELL_SPACING, cell_width - CELL_SPACING),
+                    ),
+                    &felt,
+                );
+                let disc_color = match self.board.at(x as i32, y as i32) {
+                    Some(Cell::Black) => Color::BLACK,
+                    Some(Cell::White) => Color::WHITE,
+                    _ => continue,
+                };
+                let center = Point {
+                    x: origin.x + cell_width / 2_f64,
+                    y: origin.y + cell_width / 2_f64,
+                };
+                ctx.fill(Circle::new(center, cell_width * 0.4), &disc_color);
+            }
+        }
+
+        let status = match self.winner {
+            Some(Disc::Black) => Some(format!("Winner! {}", self.board.black.as_ref().unwrap())),
+            Some(Disc::White) => Some(format!("Winner! {}", self.board.white.as_ref().unwrap())),
+            None if self.board.is_over() => Some("Draw!".to_string()),
+            None => None,
+        };
+        if let Some(status) = status {
+            let text = ctx.text();
+            let layout = text
+                .new_text_layout(status)
+                .font(FontFamily::SERIF, 24.0)
+                .text_color(Color::rgb8(255, 255, 255))
+                .build()
+                .unwrap();
+            ctx.draw_text(&layout, (100.0, 25.0));
+        }
+    }
+}
+
+pub fn make_ptr(players: Vec<User>) -> Box<dyn GameTrait> {
+    Box::new(Game::new(players))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum InternalMoveResult {
+    InvalidMove,
+    Ok,
+    // The opponent has no legal move, so the same player goes again
+    Pass,
+    Win,
+    Loss,
+    Draw,
+}
+
+fn make_move(state: &mut Game, user: &User, p_move: PlayerMove) -> InternalMoveResult {
+    let Some(disc) = state.board.disc_of(&user.name) else {
+        return InternalMoveResult::InvalidMove;
+    };
+    let PlayerMove { x, y } = p_move;
+    let flips = state.board.flips(disc, x, y);
+    if flips.is_empty() {
+        return InternalMoveResult::InvalidMove;
+    }
+    state.board.cells[y * SIZE + x] = disc.cell();
+    for (fx, fy) in flips {
+        state.board.cells[fy * SIZE + fx] = disc.cell();
+    }
+
+    if state.board.has_legal_move(disc.opponent()) {
+        InternalMoveResult::Ok
+    } else if state.board.has_legal_move(disc) {
+        InternalMoveResult::Pass
+    } else {
+        // Covers a full board too, nobody can move on that
+        let mine = state.board.count(disc);
+        let theirs = state.board.count(disc.opponent());
+        if mine > theirs {
+            state.winner = Some(disc);
+            InternalMoveResult::Win
+        } else if mine < theirs {
+            state.winner = Some(disc.opponent());
+            InternalMoveResult::Loss
+        } else {
+            InternalMoveResult::Draw
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn user(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            color: Color::rgb8(0, 0, 0),
+        }
+    }
+
+    // 'B' black, 'W' white, anything else empty. Black is "black", white is "white"
+    fn game_from_rows(rows: [&str; SIZE]) -> Game {
+        let mut game = Game::new(vec![user("black"), user("white")]);
+        game.board.black = Some("black".to_string());
+        game.board.white = Some("white".to_string());
+        game.board.cells = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                'B' => Cell::Black,
+                'W' => Cell::White,
+                _ => Cell::Empty,
+            })
+            .collect();
+        assert_eq!(game.board.cells.len(), SIZE * SIZE);
+        game
+    }
+
+    fn mov(game: &mut Game, name: &str, x: usize, y: usize) -> InternalMoveResult {
+        make_move(game, &user(name), PlayerMove { x, y })
+    }
+
+    #[test]
+    fn opening_move_flips_one() {
+        let mut game = Game::new(vec![user("p1"), user("p2")]);
+        assert_eq!(mov(&mut game, "p1", 4, 5), InternalMoveResult::Ok);
+        assert_eq!(game.board.count(Disc::Black), 4);
+        assert_eq!(game.board.count(Disc::White), 1);
+        assert_eq!(game.board.black.as_deref(), Some("p1"));
+
+        assert_eq!(mov(&mut game, "p2", 5, 5), InternalMoveResult::Ok);
+        assert_eq!(game.board.white.as_deref(), Some("p2"));
+    }
+
+    #[test]
+    fn move_without_flips_is_invalid() {
+        let mut game = Game::new(vec![user("p1"), user("p2")]);
+        assert_eq!(mov(&mut game, "p1", 0, 0), InternalMoveResult::InvalidMove);
+        // Occupied
+        assert_eq!(mov(&mut game, "p1", 3, 3), InternalMoveResult::InvalidMove);
+        // Out of bounds
+        assert_eq!(mov(&mut game, "p1", 8, 0), InternalMoveResult::InvalidMove);
+    }
+
+    #[test]
+    fn third_player_cant_move() {
+        let mut game = Game::new(vec![user("p1"), user("p2"), user("p3")]);
+        assert_eq!(mov(&mut game, "p1", 4, 5), InternalMoveResult::Ok);
+        assert_eq!(mov(&mut game, "p2", 5, 5), InternalMoveResult::Ok);
+        assert_eq!(mov(&mut game, "p3", 5, 4), InternalMoveResult::InvalidMove);
+    }
+
+    #[test]
+    fn corner_capture() {
+        let mut game = game_from_rows([
+            ".WWWWWB.", //
+            "WW......", //
+            "W.W.....", //
+            "B.......", //
+            "........", //
+            "........", //
+            "........", //
+            "......BW", //
+        ]);
+        assert_eq!(mov(&mut game, "black", 0, 0), InternalMoveResult::Ok);
+        // The row and the column are taken, the diagonal isn't closed off
+        for x in 0..=6 {
+            assert_eq!(game.board.at(x, 0), Some(Cell::Black));
+        }
+        for y in 0..=3 {
+            assert_eq!(game.board.at(0, y), Some(Cell::Black));
+        }
+        assert_eq!(game.board.at(1, 1), Some(Cell::White));
+        assert_eq!(game.board.at(2, 2), Some(Cell::White));
+    }
+
+    #[test]
+    fn flips_in_several_directions() {
+        let mut game = game_from_rows([
+            "B..B..B.", //
+            ".W.W.W..", //
+            "..WWW...", //
+            "BWW.WWB.", //
+            "..WWW...", //
+            ".W.W.W..", //
+            "B..B..B.", //
+            "........", //
+        ]);
+        // Nothing left for white, so black takes it
+        assert_eq!(mov(&mut game, "black", 3, 3), InternalMoveResult::Win);
+        assert_eq!(game.board.count(Disc::White), 0);
+    }
+
+    #[test]
+    fn opponent_without_moves_is_passed() {
+        // Afterwards white can't flank anything, black can still play at (2, 7)
+        let mut game = game_from_rows([
+            "BWW.....", //
+            "........", //
+            "........", //
+            "........", //
+            "........", //
+            "........", //
+            "........", //
+            "BW......", //
+        ]);
+        assert_eq!(mov(&mut game, "black", 3, 0), InternalMoveResult::Pass);
+        assert_eq!(game.board.at(1, 0), Some(Cell::Black));
+        assert_eq!(game.board.at(2, 0), Some(Cell::Black));
+        assert_eq!(mov(&mut game, "black", 2, 7), InternalMoveResult::Win);
+    }
+
+    #[test]
+    fn more_pieces_at_the_end_wins() {
+        let mut game = game_from_rows([
+            "BBBBBBBB", //
+            "BBBBBBBB", //
+            "BBBBBBBB", //
+            "BBBBBBBB", //
+            "WWWWWWWW", //
+            "WWWWWWWW", //
+            "WWWWWWWB", //
+            "WWWWWW.W", //
+        ]);
+        // Fills the board
+        assert_eq!(mov(&mut game, "black", 6, 7), InternalMoveResult::Win);
+        assert_eq!(game.board.count(Disc::Black), 40);
+        assert_eq!(game.board.count(Disc::White), 24);
+        assert_eq!(game.winner, Some(Disc::Black));
+    }
+
+    #[test]
+    fn fewer_pieces_at_the_end_loses() {
+        let mut game = game_from_rows([
+            "WWWWWWWW", //
+            "WWWWWWWW", //
+            "BBBBBBBB", //
+            "BBBBBBBB", //
+            "BBBBBBBB", //
+            "BBBBBBBB", //
+            "BBBBBBBW", //
+            "BBBBBB.B", //
+        ]);
+        assert_eq!(mov(&mut game, "white", 6, 7), InternalMoveResult::Loss);
+        assert_eq!(game.board.count(Disc::White), 28);
+        assert_eq!(game.board.count(Disc::Black), 36);
+        assert_eq!(game.winner, Some(Disc::Black));
+    }
+}
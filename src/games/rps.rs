@@ -0,0 +1,368 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+
+use code_challenge_game_types::gametraits::{
+    self, GameTrait, PlayerMoveResult, PlayerTurn, TurnToken, User,
+};
+use code_challenge_game_types::TurnTracker;
+
+use druid::{
+    piet::{Text, TextLayoutBuilder},
+    Color, FontFamily, RenderContext,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Choice {
+    Rock,
+    Paper,
+    Scissors,
+}
+
+impl Choice {
+    fn beats(self, other: Choice) -> bool {
+        matches!(
+            (self, other),
+            (Choice::Rock, Choice::Scissors)
+                | (Choice::Paper, Choice::Rock)
+                | (Choice::Scissors, Choice::Paper)
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerMove {
+    choice: Choice,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Game {
+    // The first choice of a round, kept hidden until the second player has chosen too
+    pending: Option<(User, Choice)>,
+    state: State,
+    best_of_three: bool,
+    players: TurnTracker,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+struct State {
+    last_round: Option<Round>,
+    // Rounds won so far, only goes above 1 when playing best of three
+    score: BTreeMap<String, u32>,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+struct Round {
+    choices: BTreeMap<String, Choice>,
+    // None when both chose the same
+    winner: Option<String>,
+}
+
+impl Game {
+    pub fn new(best_of_three: bool, players: Vec<User>) -> Self {
+        Self {
+            pending: None,
+            state: State {
+                last_round: None,
+                score: BTreeMap::new(),
+            },
+            best_of_three,
+            players: TurnTracker::new(players),
+        }
+    }
+
+    // TODO: Move to GameTrait::describe once game-types has it
+    pub fn describe(&self) -> String {
+        if self.best_of_three {
+            "Rock paper scissors, best of three".to_string()
+        } else {
+            "Rock paper scissors".to_string()
+        }
+    }
+
+    // TODO: Move into GameTrait once game-types has min_players/max_players
+    pub fn min_players(&self) -> usize {
+        2
+    }
+
+    pub fn max_players(&self) -> usize {
+        2
+    }
+
+    fn rounds_to_win(&self) -> u32 {
+        if self.best_of_three {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn turn(&self, user: User) -> PlayerTurn {
+        PlayerTurn {
+            token: TurnToken { user },
+            state: gametraits::to_game_state(&self.state),
+        }
+    }
+
+    fn forget_pending(&mut self, username: &str) {
+        if matches!(&self.pending, Some((user, _)) if user.name == username) {
+            self.pending = None;
+        }
+    }
+
+    fn invalid_move_next_turn(&mut self, user: &User) -> Option<PlayerTurn> {
+        self.forget_pending(&user.name);
+        self.players.remove_player(&user.name);
+        self.players.advance_player().map(|p| self.turn(p))
+    }
+}
+
+impl gametraits::GameTrait for Game {
+    fn player_moves(
+        &mut self,
+        token: TurnToken,
+        player_move: gametraits::PlayerMove,
+    ) -> PlayerMoveResult {
+        let user = &token.user;
+        match gametraits::to_player_move::<PlayerMove>(&player_move) {
+            Some(mov) => match make_move(self, user, mov) {
+                InternalMoveResult::Ok => {
+                    let p = self.players.advance_player().unwrap();
+                    PlayerMoveResult::Ok(self.turn(p))
+                }
+                InternalMoveResult::Win => PlayerMoveResult::Win,
+                InternalMoveResult::Draw => PlayerMoveResult::Draw,
+                // TODO: Announce the opponent as winner once PlayerMoveResult can say who won,
+                // Win always means the player who moved
+                InternalMoveResult::Loss => PlayerMoveResult::Draw,
+            },
+            None => PlayerMoveResult::InvalidFormat(self.invalid_move_next_turn(user)),
+        }
+    }
+
+    fn player_connected(&mut self, user: User) {
+        self.players.add_player(user);
+    }
+
+    fn player_disconnected(&mut self, username: &str) {
+        self.forget_pending(username);
+        self.players.remove_player(username);
+    }
+
+    fn current_player_disconnected(&mut self, player_token: TurnToken) -> Option<PlayerTurn> {
+        self.forget_pending(&player_token.user.name);
+        self.players.remove_player(&player_token.user.name);
+        self.players.advance_player().map(|user| self.turn(user))
+    }
+
+    fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        self.players.advance_player().map(|user| self.turn(user))
+    }
+
+    fn reset(&mut self, users: Vec<User>) {
+        *self = Game::new(self.best_of_three, users);
+    }
+}
+
+impl gametraits::Paint for Game {
+    fn eq(&self, other: &dyn gametraits::Paint) -> bool {
+        self == gametraits::Paint::as_any(other)
+            .downcast_ref::<Game>()
+            .unwrap()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn paint(&self, ctx: &mut druid::PaintCtx) {
+        let mut lines = Vec::new();
+        if let Some(round) = &self.state.last_round {
+            for (name, choice) in &round.choices {
+                lines.push(format!("{name}: {choice:?}"));
+            }
+            match &round.winner {
+                Some(winner) => lines.push(format!("{winner} takes the round")),
+                None => lines.push("Same choice, no one takes the round".to_string()),
+            }
+        }
+        if self.best_of_three {
+            for (name, wins) in &self.state.score {
+                lines.push(format!("{name}: {wins} won"));
+            }
+        }
+        if let Some((user, _)) = &self.pending {
+            lines.push(format!("{} has chosen", user.name));
+        }
+
+        let text = ctx.text();
+        let layout = text
+            .new_text_layout(lines.join("\n"))
+            .font(FontFamily::SERIF, 24.0)
+            .text_color(Color::rgb8(0, 0, 0))
+            .build()
+            .unwrap();
+        ctx.draw_text(&layout, (100.0, 25.0));
+    }
+}
+
+pub fn make_ptr(players: Vec<User>) -> Box<dyn GameTrait> {
+    Box::new(Game::new(false, players))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum InternalMoveResult {
+    Ok,
+    Win,
+    // The player who moved lost the game
+    Loss,
+    Draw,
+}
+
+fn make_move(state: &mut Game, user: &User, p_move: PlayerMove) -> InternalMoveResult {
+    let (first, first_choice) = match state.pending.take() {
+        Some((first, choice)) if first.name != user.name => (first, choice),
+        _ => {
+            state.pending = Some((user.clone(), p_move.choice));
+            return InternalMoveResult::Ok;
+        }
+    };
+
+    let winner = if p_move.choice.beats(first_choice) {
+        Some(user.name.clone())
+    } else if first_choice.beats(p_move.choice) {
+        Some(first.name.clone())
+    } else {
+        None
+    };
+    state.state.last_round = Some(Round {
+        choices: BTreeMap::from([
+            (first.name.clone(), first_choice),
+            (user.name.clone(), p_move.choice),
+        ]),
+        winner: winner.clone(),
+    });
+
+    let Some(winner) = winner else {
+        // Best of three replays a tied round, a single round is simply a draw
+        return if state.best_of_three {
+            InternalMoveResult::Ok
+        } else {
+            InternalMoveResult::Draw
+        };
+    };
+    let rounds_to_win = state.rounds_to_win();
+    let wins = state.state.score.entry(winner.clone()).or_insert(0);
+    *wins += 1;
+    if *wins < rounds_to_win {
+        InternalMoveResult::Ok
+    } else if winner == user.name {
+        InternalMoveResult::Win
+    } else {
+        InternalMoveResult::Loss
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    macro_rules! test_init {
+        ($game:ident, $best_of_three:expr, $p1:ident, $p2:ident, $mov:ident) => {
+            let $p1 = User {
+                name: "player1".to_string(),
+                color: Color::rgb8(0, 0, 0),
+            };
+            let $p2 = User {
+                name: "player2".to_string(),
+                color: Color::rgb8(100, 100, 100),
+            };
+            let mut $game = Game::new($best_of_three, vec![$p1.clone(), $p2.clone()]);
+            let mut $mov = |u, choice| make_move(&mut $game, u, PlayerMove { choice });
+        };
+    }
+
+    #[test]
+    fn all_combinations() {
+        use Choice::*;
+        use InternalMoveResult::*;
+        // (first choice, second choice, result for the second one to choose)
+        let combinations = [
+            (Rock, Rock, Draw),
+            (Rock, Paper, Win),
+            (Rock, Scissors, Loss),
+            (Paper, Rock, Loss),
+            (Paper, Paper, Draw),
+            (Paper, Scissors, Win),
+            (Scissors, Rock, Win),
+            (Scissors, Paper, Loss),
+            (Scissors, Scissors, Draw),
+        ];
+        for (first, second, expected) in combinations {
+            test_init!(game, false, p1, p2, mov);
+            assert_eq!(mov(&p1, first), Ok);
+            assert_eq!(mov(&p2, second), expected, "{first:?} against {second:?}");
+            let winner = game.state.last_round.unwrap().winner;
+            match expected {
+                Win => assert_eq!(winner, Some(p2.name.clone())),
+                Loss => assert_eq!(winner, Some(p1.name.clone())),
+                _ => assert_eq!(winner, None),
+            }
+        }
+    }
+
+    #[test]
+    fn first_choice_is_hidden() {
+        test_init!(game, false, p1, _p2, mov);
+        assert_eq!(mov(&p1, Choice::Rock), InternalMoveResult::Ok);
+        let state = serde_json::to_string(&game.state).unwrap();
+        assert_eq!(state, r#"{"last-round":null,"score":{}}"#);
+    }
+
+    #[test]
+    fn state_shows_previous_round() {
+        test_init!(game, true, p1, p2, mov);
+        mov(&p1, Choice::Rock);
+        assert_eq!(mov(&p2, Choice::Paper), InternalMoveResult::Ok);
+        let state = serde_json::to_string(&game.state).unwrap();
+        assert_eq!(
+            state,
+            r#"{"last-round":{"choices":{"player1":"rock","player2":"paper"},"winner":"player2"},"score":{"player2":1}}"#
+        );
+    }
+
+    #[test]
+    fn best_of_three_needs_two_rounds() {
+        test_init!(game, true, p1, p2, mov);
+        mov(&p1, Choice::Rock);
+        assert_eq!(mov(&p2, Choice::Paper), InternalMoveResult::Ok);
+        // A tie doesn't count
+        mov(&p1, Choice::Rock);
+        assert_eq!(mov(&p2, Choice::Rock), InternalMoveResult::Ok);
+        mov(&p1, Choice::Scissors);
+        assert_eq!(mov(&p2, Choice::Paper), InternalMoveResult::Ok);
+        mov(&p1, Choice::Scissors);
+        assert_eq!(mov(&p2, Choice::Rock), InternalMoveResult::Win);
+    }
+
+    #[test]
+    fn best_of_three_loss_for_the_last_mover() {
+        test_init!(game, true, p1, p2, mov);
+        mov(&p1, Choice::Paper);
+        assert_eq!(mov(&p2, Choice::Rock), InternalMoveResult::Ok);
+        mov(&p1, Choice::Paper);
+        assert_eq!(mov(&p2, Choice::Rock), InternalMoveResult::Loss);
+        assert_eq!(game.state.score[&p1.name], 2);
+    }
+
+    #[test]
+    fn leaving_drops_pending_choice() {
+        test_init!(game, false, p1, _p2, mov);
+        mov(&p1, Choice::Rock);
+        gametraits::GameTrait::player_disconnected(&mut game, &p1.name);
+        assert_eq!(game.pending, None);
+    }
+}
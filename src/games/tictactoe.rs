@@ -0,0 +1,409 @@
+use std::any::Any;
+
+use code_challenge_game_types::gametraits::{
+    self, GameTrait, PlayerMoveResult, PlayerTurn, TurnToken, User,
+};
+use code_challenge_game_types::TurnTracker;
+
+use druid::{
+    kurbo::{Circle, Line},
+    piet::{Text, TextLayoutBuilder},
+    Color, FontFamily, Point, RenderContext,
+};
+use serde::{Deserialize, Serialize, Serializer};
+
+const SIZE: usize = 3;
+
+// Every row, column and diagonal, as (x, y)
+const LINES: [[(usize, usize); 3]; 8] = [
+    [(0, 0), (1, 0), (2, 0)],
+    [(0, 1), (1, 1), (2, 1)],
+    [(0, 2), (1, 2), (2, 2)],
+    [(0, 0), (0, 1), (0, 2)],
+    [(1, 0), (1, 1), (1, 2)],
+    [(2, 0), (2, 1), (2, 2)],
+    [(0, 0), (1, 1), (2, 2)],
+    [(2, 0), (1, 1), (0, 2)],
+];
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerMove {
+    x: usize,
+    y: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Game {
+    board: Board,
+    // Whoever moves first plays X, everyone else O
+    x_player: Option<String>,
+    winner: Option<(User, [(usize, usize); 3])>,
+    players: TurnTracker,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+struct Board {
+    cells: Vec<Cell>,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Cell {
+    Empty,
+    #[serde(serialize_with = "ser_occupied")]
+    Occupied(User),
+}
+
+fn ser_occupied<S>(user: &User, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.serialize_str(&user.name)
+}
+
+impl Board {
+    fn at(&self, x: usize, y: usize) -> Option<&Cell> {
+        if x < SIZE && y < SIZE {
+            Some(&self.cells[y * SIZE + x])
+        } else {
+            None
+        }
+    }
+
+    fn owner(&self, x: usize, y: usize) -> Option<&User> {
+        match self.at(x, y) {
+            Some(Cell::Occupied(user)) => Some(user),
+            _ => None,
+        }
+    }
+
+    fn winning_line(&self) -> Option<[(usize, usize); 3]> {
+        LINES.into_iter().find(|line| {
+            let owners = line.map(|(x, y)| self.owner(x, y).map(|u| &u.name));
+            owners[0].is_some() && owners.iter().all(|o| *o == owners[0])
+        })
+    }
+
+    fn is_full(&self) -> bool {
+        !self.cells.iter().any(|c| matches!(c, Cell::Empty))
+    }
+}
+
+impl Game {
+    pub fn new(players: Vec<User>) -> Self {
+        Self {
+            board: Board {
+                cells: vec![Cell::Empty; SIZE * SIZE],
+            },
+            x_player: None,
+            winner: None,
+            players: TurnTracker::new(players),
+        }
+    }
+
+    /// (columns, rows), always 3x3
+    pub fn board_size(&self) -> (usize, usize) {
+        (SIZE, SIZE)
+    }
+
+    // TODO: Move to GameTrait::describe once game-types has it
+    pub fn describe(&self) -> String {
+        "Tic-tac-toe".to_string()
+    }
+
+    // TODO: Move into GameTrait once game-types has min_players/max_players
+    pub fn min_players(&self) -> usize {
+        2
+    }
+
+    pub fn max_players(&self) -> usize {
+        2
+    }
+
+    fn invalid_move_next_turn(&mut self, user: &User) -> Option<PlayerTurn> {
+        self.players.remove_player(&user.name);
+        self.players.advance_player().map(|p| PlayerTurn {
+            token: TurnToken { user: p },
+            state: gametraits::to_game_state(&self.board),
+        })
+    }
+}
+
+impl gametraits::GameTrait for Game {
+    fn player_moves(
+        &mut self,
+        token: TurnToken,
+        player_move: gametraits::PlayerMove,
+    ) -> PlayerMoveResult {
+        let user = &token.user;
+        match gametraits::to_player_move::<PlayerMove>(&player_move) {
+            Some(mov) => match make_move(self, user, mov) {
+                InternalMoveResult::InvalidMove => {
+                    PlayerMoveResult::InvalidMove(self.invalid_move_next_turn(user))
+                }
+                InternalMoveResult::Ok => {
+                    let p = self.players.advance_player().unwrap();
+                    PlayerMoveResult::Ok(PlayerTurn {
+                        token: TurnToken { user: p },
+                        state: gametraits::to_game_state(&self.board),
+                    })
+                }
+                InternalMoveResult::Win => PlayerMoveResult::Win,
+                InternalMoveResult::Draw => PlayerMoveResult::Draw,
+            },
+            None => PlayerMoveResult::InvalidFormat(self.invalid_move_next_turn(user)),
+        }
+    }
+
+    fn player_connected(&mut self, user: User) {
+        self.players.add_player(user);
+    }
+
+    fn player_disconnected(&mut self, username: &str) {
+        self.players.remove_player(username);
+    }
+
+    fn current_player_disconnected(&mut self, player_token: TurnToken) -> Option<PlayerTurn> {
+        self.players.remove_player(&player_token.user.name);
+
+        self.players.advance_player().map(|user| PlayerTurn {
+            token: TurnToken { user },
+            state: gametraits::to_game_state(&self.board),
+        })
+    }
+
+    fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        self.players.advance_player().map(|user| PlayerTurn {
+            token: TurnToken { user },
+            state: gametraits::to_game_state(&self.board),
+        })
+    }
+
+    fn reset(&mut self, users: Vec<User>) {
+        *self = Game::new(users);
+    }
+}
+
+impl gametraits::Paint for Game {
+    fn eq(&self, other: &dyn gametraits::Paint) -> bool {
+        self == gametraits::Paint::as_any(other)
+            .downcast_ref::<Game>()
+            .unwrap()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn paint(&self, ctx: &mut druid::PaintCtx) {
+        let cell_width = ctx.size().width.min(ctx.size().height) / SIZE as f64;
+        let board_width = cell_width * SIZE as f64;
+        let grid_color = Color::rgb8(180, 180, 180);
+        for i in 1..SIZE {
+            let offset = i as f64 * cell_width;
+            ctx.stroke(
+                Line::new((offset, 0.0), (offset, board_width)),
+                &grid_color,
+                3.0,
+            );
+            ctx.stroke(
+                Line::new((0.0, offset), (board_width, offset)),
+                &grid_color,
+                3.0,
+            );
+        }
+
+        let center = |x: usize, y: usize| Point {
+            x: (x as f64 + 0.5) * cell_width,
+            y: (y as f64 + 0.5) * cell_width,
+        };
+        let mark_reach = cell_width * 0.35;
+        for x in 0..SIZE {
+            for y in 0..SIZE {
+                let Some(user) = self.board.owner(x, y) else {
+                    continue;
+                };
+                let c = center(x, y);
+                if self.x_player.as_ref() == Some(&user.name) {
+                    ctx.stroke(
+                        Line::new(
+                            (c.x - mark_reach, c.y - mark_reach),
+                            (c.x + mark_reach, c.y + mark_reach),
+                        ),
+                        &user.color,
+                        8.0,
+                    );
+                    ctx.stroke(
+                        Line::new(
+                            (c.x + mark_reach, c.y - mark_reach),
+                            (c.x - mark_reach, c.y + mark_reach),
+                        ),
+                        &user.color,
+                        8.0,
+                    );
+                } else {
+                    ctx.stroke(Circle::new(c, mark_reach), &user.color, 8.0);
+                }
+            }
+        }
+
+        if let Some((winner, line)) = &self.winner {
+            let (x1, y1) = line[0];
+            let (x2, y2) = line[2];
+            ctx.stroke(
+                Line::new(center(x1, y1), center(x2, y2)),
+                &Color::PURPLE,
+                5.0,
+            );
+
+            let win_text = format!("Winner! {}", winner.name);
+            let text = ctx.text();
+            let layout = text
+                .new_text_layout(win_text)
+                .font(FontFamily::SERIF, 24.0)
+                .text_color(Color::rgb8(0, 0, 0))
+                .build()
+                .unwrap();
+            ctx.draw_text(&layout, (100.0, 25.0));
+        } else if self.board.is_full() {
+            let text = ctx.text();
+            let layout = text
+                .new_text_layout("Draw!".to_string())
+                .font(FontFamily::SERIF, 24.0)
+                .text_color(Color::rgb8(0, 0, 0))
+                .build()
+                .unwrap();
+            ctx.draw_text(&layout, (100.0, 25.0));
+        }
+    }
+}
+
+pub fn make_ptr(players: Vec<User>) -> Box<dyn GameTrait> {
+    Box::new(Game::new(players))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum InternalMoveResult {
+    InvalidMove,
+    Ok,
+    Win,
+    Draw,
+}
+
+fn make_move(state: &mut Game, user: &User, p_move: PlayerMove) -> InternalMoveResult {
+    let PlayerMove { x, y } = p_move;
+    if !matches!(state.board.at(x, y), Some(Cell::Empty)) {
+        return InternalMoveResult::InvalidMove;
+    }
+    state.board.cells[y * SIZE + x] = Cell::Occupied(user.clone());
+    state.x_player.get_or_insert_with(|| user.name.clone());
+
+    if let Some(line) = state.board.winning_line() {
+        state.winner = Some((user.clone(), line));
+        InternalMoveResult::Win
+    } else if state.board.is_full() {
+        InternalMoveResult::Draw
+    } else {
+        InternalMoveResult::Ok
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    macro_rules! test_init {
+        ($game:ident, $p1:ident, $p2:ident, $mov:ident) => {
+            let $p1 = User {
+                name: "player1".to_string(),
+                color: Color::rgb8(0, 0, 0),
+            };
+            let $p2 = User {
+                name: "player2".to_string(),
+                color: Color::rgb8(100, 100, 100),
+            };
+            let mut $game = Game::new(vec![$p1.clone(), $p2.clone()]);
+            let mut $mov = |u, x, y| make_move(&mut $game, u, PlayerMove { x, y });
+        };
+    }
+
+    #[test]
+    fn win_row() {
+        test_init!(game, p1, p2, mov);
+        assert_eq!(mov(&p1, 0, 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 0, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 1, 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 1, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 2, 1), InternalMoveResult::Win);
+    }
+
+    #[test]
+    fn win_column() {
+        test_init!(game, p1, p2, mov);
+        assert_eq!(mov(&p1, 2, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 0, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 2, 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 0, 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 2, 2), InternalMoveResult::Win);
+    }
+
+    #[test]
+    fn win_diagonal() {
+        test_init!(game, p1, p2, mov);
+        assert_eq!(mov(&p1, 0, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 1, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 1, 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 2, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 2, 2), InternalMoveResult::Win);
+    }
+
+    #[test]
+    fn win_anti_diagonal() {
+        test_init!(game, p1, p2, mov);
+        assert_eq!(mov(&p1, 2, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 0, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 1, 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 1, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 0, 2), InternalMoveResult::Win);
+    }
+
+    #[test]
+    fn draw() {
+        test_init!(game, p1, p2, mov);
+        // [1 2 1]
+        // [1 2 2]
+        // [2 1 1]
+        assert_eq!(mov(&p1, 0, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 1, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 2, 0), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 1, 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 0, 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 2, 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 1, 2), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 0, 2), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, 2, 2), InternalMoveResult::Draw);
+    }
+
+    #[test]
+    fn invalid_move_space_occupied() {
+        test_init!(game, p1, p2, mov);
+        assert_eq!(mov(&p1, 1, 1), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, 1, 1), InternalMoveResult::InvalidMove);
+    }
+
+    #[test]
+    fn invalid_move_out_of_bounds() {
+        test_init!(game, p1, _p2, mov);
+        assert_eq!(mov(&p1, 3, 0), InternalMoveResult::InvalidMove);
+        assert_eq!(mov(&p1, 0, 3), InternalMoveResult::InvalidMove);
+    }
+
+    #[test]
+    fn first_mover_plays_x() {
+        test_init!(game, p1, p2, mov);
+        mov(&p2, 1, 1);
+        mov(&p1, 0, 0);
+        assert_eq!(game.x_player, Some(p2.name.clone()));
+    }
+}
@@ -0,0 +1,403 @@
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+
+use code_challenge_game_types::gametraits::{
+    self, GameTrait, PlayerMoveResult, PlayerTurn, TurnToken, User,
+};
+use code_challenge_game_types::TurnTracker;
+
+use druid::{
+    piet::{Text, TextLayoutBuilder},
+    Color, FontFamily, Point, Rect, RenderContext,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+const WORD_LEN: usize = 5;
+const MAX_GUESSES: usize = 6;
+const WORDS: &str = include_str!("wordle_words.txt");
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerMove {
+    word: String,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Status {
+    Correct,
+    Present,
+    Absent,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+struct Letter {
+    letter: char,
+    status: Status,
+}
+
+// What a player is shown on their turn, only their own guesses
+#[derive(Clone, Serialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+struct PlayerState {
+    guesses: Vec<Vec<Letter>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    rng: StdRng,
+    target: String,
+    guesses: BTreeMap<String, PlayerState>,
+    winner: Option<User>,
+    players: TurnTracker,
+}
+
+// The rng is left out, two games showing the same thing are equal
+impl PartialEq for Game {
+    fn eq(&self, other: &Self) -> bool {
+        self.target == other.target
+            && self.guesses == other.guesses
+            && self.winner == other.winner
+            && self.players == other.players
+    }
+}
+
+impl Game {
+    pub fn new(players: Vec<User>) -> Self {
+        Self::with_rng(StdRng::from_rng(rand::thread_rng()).unwrap(), players)
+    }
+
+    pub fn with_rng(mut rng: StdRng, players: Vec<User>) -> Self {
+        Self {
+            target: pick_target(&mut rng),
+            rng,
+            guesses: BTreeMap::new(),
+            winner: None,
+            players: TurnTracker::new(players),
+        }
+    }
+
+    // TODO: Move to GameTrait::describe once game-types has it
+    pub fn describe(&self) -> String {
+        format!("Wordle, {WORD_LEN} letters, {MAX_GUESSES} guesses")
+    }
+
+    // TODO: Move into GameTrait once game-types has min_players/max_players
+    pub fn min_players(&self) -> usize {
+        1
+    }
+
+    fn turn(&self, user: User) -> PlayerTurn {
+        let state = self.guesses.get(&user.name).cloned().unwrap_or_default();
+        PlayerTurn {
+            token: TurnToken { user },
+            state: gametraits::to_game_state(&state),
+        }
+    }
+
+    fn eliminate_next_turn(&mut self, user: &User) -> Option<PlayerTurn> {
+        self.players.remove_player(&user.name);
+        self.players.advance_player().map(|p| self.turn(p))
+    }
+}
+
+fn pick_target(rng: &mut StdRng) -> String {
+    let words: Vec<&str> = WORDS.lines().filter(|w| !w.is_empty()).collect();
+    words.choose(rng).unwrap().to_string()
+}
+
+/// Letters in the right spot are correct, the rest are present as long as the target has
+/// some of that letter left that isn't already matched
+fn feedback(target: &str, guess: &str) -> Vec<Letter> {
+    let target: Vec<char> = target.chars().collect();
+    let guess: Vec<char> = guess.chars().collect();
+    let mut unmatched: HashMap<char, usize> = HashMap::new();
+    for (t, g) in target.iter().zip(&guess) {
+        if t != g {
+            *unmatched.entry(*t).or_insert(0) += 1;
+        }
+    }
+    target
+        .iter()
+        .zip(&guess)
+        .map(|(t, g)| {
+            let status = if t == g {
+                Status::Correct
+            } else {
+                match unmatched.get_mut(g) {
+                    Some(left) if *left > 0 => {
+                        *left -= 1;
+                        Status::Present
+                    }
+                    _ => Status::Absent,
+                }
+            };
+            Letter { letter: *g, status }
+        })
+        .collect()
+}
+
+impl gametraits::GameTrait for Game {
+    fn player_moves(
+        &mut self,
+        token: TurnToken,
+        player_move: gametraits::PlayerMove,
+    ) -> PlayerMoveResult {
+        let user = &token.user;
+        match gametraits::to_player_move::<PlayerMove>(&player_move) {
+            Some(mov) => match make_move(self, user, mov) {
+                InternalMoveResult::InvalidMove | InternalMoveResult::OutOfGuesses => {
+                    PlayerMoveResult::InvalidMove(self.eliminate_next_turn(user))
+                }
+                InternalMoveResult::Ok => {
+                    let p = self.players.advance_player().unwrap();
+                    PlayerMoveResult::Ok(self.turn(p))
+                }
+                InternalMoveResult::Win => PlayerMoveResult::Win,
+            },
+            None => PlayerMoveResult::InvalidFormat(self.eliminate_next_turn(user)),
+        }
+    }
+
+    fn player_connected(&mut self, user: User) {
+        self.players.add_player(user);
+    }
+
+    fn player_disconnected(&mut self, username: &str) {
+        self.players.remove_player(username);
+    }
+
+    fn current_player_disconnected(&mut self, player_token: TurnToken) -> Option<PlayerTurn> {
+        self.players.remove_player(&player_token.user.name);
+        self.players.advance_player().map(|user| self.turn(user))
+    }
+
+    fn try_start_game(&mut self) -> Option<PlayerTurn> {
+        self.players.advance_player().map(|user| self.turn(user))
+    }
+
+    fn reset(&mut self, users: Vec<User>) {
+        *self = Game::with_rng(self.rng.clone(), users);
+    }
+}
+
+impl gametraits::Paint for Game {
+    fn eq(&self, other: &dyn gametraits::Paint) -> bool {
+        self == gametraits::Paint::as_any(other)
+            .downcast_ref::<Game>()
+            .unwrap()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn paint(&self, ctx: &mut druid::PaintCtx) {
+        // One grid per player, side by side, with the name above it
+        let cell_width = ctx.size().height / (MAX_GUESSES + 2) as f64;
+        let grid_width = cell_width * (WORD_LEN + 1) as f64;
+
+        const CELL_SPACING: f64 = 4_f64;
+        for (i, (name, state)) in self.guesses.iter().enumerate() {
+            let left = i as f64 * grid_width;
+            let text = ctx.text();
+            let layout = text
+                .new_text_layout(name.clone())
+                .font(FontFamily::SERIF, 18.0)
+                .text_color(Color::rgb8(0, 0, 0))
+                .build()
+                .unwrap();
+            ctx.draw_text(&layout, (left, 0.0));
+
+            for row in 0..MAX_GUESSES {
+                for col in 0..WORD_LEN {
+                    let origin = Point {
+                        x: left + col as f64 * cell_width,
+                        y: (row + 1) as f64 * cell_width,
+                    };
+                    let rect = Rect::from_origin_size(
+                        origin,
+                        (cell_width - CELL_SPACING, cell_width - CELL_SPACING),
+                    );
+                    let Some(letter) = state.guesses.get(row).map(|g| &g[col]) else {
+                        ctx.stroke(rect, &Color::rgb8(211, 214, 218), 2.0);
+                        continue;
+                    };
+                    let color = match letter.status {
+                        Status::Correct => Color::rgb8(106, 170, 100),
+                        Status::Present => Color::rgb8(201, 180, 88),
+                        Status::Absent => Color::rgb8(120, 124, 126),
+                    };
+                    ctx.fill(rect, &color);
+
+                    let text = ctx.text();
+                    let layout = text
+                        .new_text_layout(letter.letter.to_ascii_uppercase().to_string())
+                        .font(FontFamily::SANS_SERIF, cell_width * 0.6)
+                        .text_color(Color::WHITE)
+                        .build()
+                        .unwrap();
+                    ctx.draw_text(
+                        &layout,
+                        (origin.x + cell_width * 0.25, origin.y + cell_width * 0.1),
+                    );
+                }
+            }
+        }
+
+        if let Some(winner) = &self.winner {
+            let text = ctx.text();
+            let layout = text
+                .new_text_layout(format!("Winner! {}", winner.name))
+                .font(FontFamily::SERIF, 24.0)
+                .text_color(Color::rgb8(0, 0, 0))
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &layout,
+                (0.0, (MAX_GUESSES + 1) as f64 * cell_width + CELL_SPACING),
+            );
+        }
+    }
+}
+
+pub fn make_ptr(players: Vec<User>) -> Box<dyn GameTrait> {
+    Box::new(Game::new(players))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum InternalMoveResult {
+    InvalidMove,
+    Ok,
+    Win,
+    OutOfGuesses,
+}
+
+fn make_move(state: &mut Game, user: &User, p_move: PlayerMove) -> InternalMoveResult {
+    let word = p_move.word.to_ascii_lowercase();
+    if word.len() != WORD_LEN || !word.chars().all(|c| c.is_ascii_alphabetic()) {
+        return InternalMoveResult::InvalidMove;
+    }
+    let player = state.guesses.entry(user.name.clone()).or_default();
+    player.guesses.push(feedback(&state.target, &word));
+
+    if word == state.target {
+        state.winner = Some(user.clone());
+        InternalMoveResult::Win
+    } else if player.guesses.len() >= MAX_GUESSES {
+        InternalMoveResult::OutOfGuesses
+    } else {
+        InternalMoveResult::Ok
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    macro_rules! test_init {
+        ($game:ident, $target:expr, $p1:ident, $p2:ident, $mov:ident) => {
+            let $p1 = User {
+                name: "player1".to_string(),
+                color: Color::rgb8(0, 0, 0),
+            };
+            let $p2 = User {
+                name: "player2".to_string(),
+                color: Color::rgb8(100, 100, 100),
+            };
+            let mut $game =
+                Game::with_rng(StdRng::seed_from_u64(7), vec![$p1.clone(), $p2.clone()]);
+            $game.target = $target.to_string();
+            let mut $mov = |u, word: &str| {
+                make_move(
+                    &mut $game,
+                    u,
+                    PlayerMove {
+                        word: word.to_string(),
+                    },
+                )
+            };
+        };
+    }
+
+    fn statuses(target: &str, guess: &str) -> Vec<Status> {
+        feedback(target, guess)
+            .into_iter()
+            .map(|l| l.status)
+            .collect()
+    }
+
+    #[test]
+    fn word_list_is_five_letters() {
+        assert!(WORDS
+            .lines()
+            .all(|w| w.len() == WORD_LEN && w.chars().all(|c| c.is_ascii_lowercase())));
+    }
+
+    #[test]
+    fn all_three_statuses() {
+        use Status::*;
+        assert_eq!(
+            statuses("crane", "cater"),
+            [Correct, Present, Absent, Present, Present]
+        );
+        assert_eq!(
+            statuses("crane", "crane"),
+            [Correct, Correct, Correct, Correct, Correct]
+        );
+        assert_eq!(
+            statuses("crane", "moist"),
+            [Absent, Absent, Absent, Absent, Absent]
+        );
+    }
+
+    #[test]
+    fn repeated_letters_are_only_present_once() {
+        use Status::*;
+        // One 'e' in the target, already matched at the end
+        assert_eq!(
+            statuses("crane", "geese"),
+            [Absent, Absent, Absent, Absent, Correct]
+        );
+        // One 'a' in the target, only the first one guessed is present
+        assert_eq!(
+            statuses("crane", "banal"),
+            [Absent, Present, Present, Absent, Absent]
+        );
+    }
+
+    #[test]
+    fn feedback_encoding() {
+        let json = serde_json::to_string(&feedback("crane", "cheap")).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"letter":"c","status":"correct"},{"letter":"h","status":"absent"},{"letter":"e","status":"present"},{"letter":"a","status":"present"},{"letter":"p","status":"absent"}]"#
+        );
+    }
+
+    #[test]
+    fn win_mid_game() {
+        test_init!(game, "crane", p1, p2, mov);
+        assert_eq!(mov(&p1, "cheap"), InternalMoveResult::Ok);
+        assert_eq!(mov(&p2, "moist"), InternalMoveResult::Ok);
+        assert_eq!(mov(&p1, "CRANE"), InternalMoveResult::Win);
+        assert_eq!(game.winner, Some(p1));
+    }
+
+    #[test]
+    fn six_wrong_guesses_loses() {
+        test_init!(game, "crane", p1, _p2, mov);
+        for _ in 1..MAX_GUESSES {
+            assert_eq!(mov(&p1, "cheap"), InternalMoveResult::Ok);
+        }
+        assert_eq!(mov(&p1, "cheap"), InternalMoveResult::OutOfGuesses);
+    }
+
+    #[test]
+    fn not_a_five_letter_word() {
+        test_init!(game, "crane", p1, _p2, mov);
+        assert_eq!(mov(&p1, "cranes"), InternalMoveResult::InvalidMove);
+        assert_eq!(mov(&p1, "cr4ne"), InternalMoveResult::InvalidMove);
+        assert_eq!(mov(&p1, "crné"), InternalMoveResult::InvalidMove);
+    }
+}
@@ -1,7 +1,13 @@
+pub mod arena;
 pub mod async_game_trait;
+pub mod bracket;
+pub mod config;
 pub mod controller;
+pub mod event_log;
 pub mod games;
+pub mod metrics;
 pub mod network_wrap;
 pub mod player_table;
+pub mod status_server;
 pub mod ui;
 pub mod user_connection;
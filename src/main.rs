@@ -1,51 +1,380 @@
 #![feature(trait_upcasting)]
 
+pub mod arena;
 pub mod async_game_trait;
+pub mod bracket;
+pub mod config;
 pub mod controller;
+pub mod event_log;
 pub mod games;
+pub mod metrics;
 pub mod network_wrap;
 pub mod player_table;
+pub mod status_server;
 pub mod ui;
 pub mod user_connection;
 
-use games::gomoku;
+use arena::{ArenaController, GameSlot, SlotSetup};
+use clap::Parser;
+use config::{GameKind, ServerConfig};
+use games::{gomoku, nim, number_guess, reversi, rps, tictactoe, wordle};
 
 use code_challenge_game_types::gametraits;
-use controller::{ControllerMsg, UiSender};
+use controller::{ControllerInfo, ControllerMsg, UiSender};
 use druid::ExtEventSink;
+use event_log::EventLogger;
 
 use log::info;
-
-use async_game_trait::{AsyncGame, AsyncGameTrait};
-use tokio::sync::mpsc;
+use metrics::Metrics;
+
+use async_game_trait::{AsyncGame, AsyncGameTrait, GameMetrics, LoggedGame, MeteredGame};
+use std::path::PathBuf;
+use std::sync::{atomic::AtomicUsize, Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+use user_connection::{BcryptHasher, RateLimiter, SharedPasswordHasher, SharedRateLimiter};
+
+#[derive(Parser)]
+struct Args {
+    /// Server settings, see config.example.toml
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Which game to host, gomoku unless the config file says otherwise
+    #[arg(long, value_enum)]
+    game: Option<GameKind>,
+    /// Also accept WebSocket connections on this port
+    #[arg(long)]
+    ws_port: Option<u16>,
+    /// Connections beyond this are turned away with a "server full" error, 256 unless the
+    /// config file says otherwise
+    #[arg(long)]
+    max_connections: Option<usize>,
+    /// Also accept connections on a unix domain socket at this path
+    #[arg(long)]
+    unix_socket: Option<String>,
+    /// Serve TLS instead of plain TCP, using this PEM certificate chain
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<String>,
+    /// PKCS8 PEM private key belonging to --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<String>,
+    /// Rock-paper-scissors plays best of three rounds instead of a single one
+    #[arg(long)]
+    best_of_three: bool,
+    /// Gomoku is played with Renju rules, the first player may not make double threes, double
+    /// fours or overlines
+    #[arg(long)]
+    renju: bool,
+    /// How many objects each nim pile starts with
+    #[arg(long, value_delimiter = ',', default_values_t = [3, 4, 5])]
+    nim_piles: Vec<u32>,
+    /// Nim is played misère, whoever takes the last object loses
+    #[arg(long)]
+    misere: bool,
+    /// Serve the server status as JSON on GET /status, on port 7655 unless another one is given
+    #[arg(long, num_args = 0..=1, default_missing_value = "7655")]
+    status_port: Option<u16>,
+    /// Append every game event to this file, one JSON object per line
+    #[arg(long)]
+    event_log: Option<PathBuf>,
+    /// Run this many games side by side, new players join the one with the fewest players
+    #[arg(long, default_value_t = 1)]
+    num_games: usize,
+    /// Count and time the calls to the game, logged every minute as JSON
+    #[arg(long)]
+    metrics: bool,
+    /// Log every call to the game at debug level, see it with RUST_LOG=game=debug
+    #[arg(long)]
+    log_game_calls: bool,
+    /// A TCP client that takes longer than this many seconds to send a whole line, e.g. its
+    /// move, is disconnected
+    #[arg(long, default_value_t = network_wrap::DEFAULT_READ_TIMEOUT.as_secs())]
+    read_timeout_secs: u64,
+    /// A game still going after this many seconds ends in a draw, so a tournament can't get
+    /// stuck on one match
+    #[arg(long)]
+    max_game_duration_secs: Option<u64>,
+    /// Once everyone has left, the game is reset if nobody shows up for this many seconds
+    #[arg(long)]
+    idle_timeout_secs: Option<u64>,
+}
 
 #[tokio::main]
 pub async fn main() {
     env_logger::init();
-    let listener = network_wrap::bind("127.0.0.1:7654").await.unwrap();
+    let args = Args::parse();
+    let config = match &args.config {
+        Some(path) => ServerConfig::load(path).unwrap(),
+        None => ServerConfig::default(),
+    };
+
+    let listen_addr = &config.listen_addr;
+    let tcp_listener: Box<dyn network_wrap::Listener + Send> = match (&args.tls_cert, &args.tls_key)
+    {
+        (Some(cert), Some(key)) => {
+            info!("Accepting TLS connections on {listen_addr}");
+            Box::new(
+                network_wrap::bind_tls(listen_addr, cert, key)
+                    .await
+                    .unwrap(),
+            )
+        }
+        _ => Box::new(
+            network_wrap::bind_with_options(
+                listen_addr,
+                network_wrap::SocketOptions {
+                    read_timeout: Duration::from_secs(args.read_timeout_secs),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap(),
+        ),
+    };
+    let mut listeners: Vec<Box<dyn network_wrap::Listener + Send>> = vec![tcp_listener];
+    if let Some(ws_port) = args.ws_port {
+        let ws_addr = format!("127.0.0.1:{ws_port}");
+        info!("Accepting websocket connections on {ws_addr}");
+        listeners.push(Box::new(network_wrap::bind_ws(&ws_addr).await.unwrap()));
+    }
+    if let Some(path) = &args.unix_socket {
+        info!("Accepting unix socket connections on {path}");
+        listeners.push(Box::new(network_wrap::bind_unix(path).await.unwrap()));
+    }
+    let listener = network_wrap::MultiListener::new(listeners);
+
+    let game_kind = args.game.unwrap_or(config.game_type);
+    let game_metrics = Arc::new(GameMetrics::default());
+    if args.metrics {
+        let game_metrics = game_metrics.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(GAME_METRICS_INTERVAL).await;
+                info!("Game calls: {}", game_metrics.to_json());
+            }
+        });
+    }
+    let make_game = || {
+        let mut game = make_plain_game(game_kind, &config, &args);
+        if args.log_game_calls {
+            game = Box::new(LoggedGame::new(game));
+        }
+        if args.metrics {
+            game = Box::new(MeteredGame::with_metrics(game, game_metrics.clone()));
+        }
+        game
+    };
+    let channels: Vec<_> = (0..args.num_games)
+        .map(|_| mpsc::channel::<ControllerMsg>(config.channel_buffer))
+        .collect();
+    let controller_txs: Vec<_> = channels.iter().map(|(tx, _)| tx.clone()).collect();
+
+    let shown_game = Arc::new(AtomicUsize::new(0));
+    let ui_handle = start_ui(
+        controller_txs.clone(),
+        shown_game.clone(),
+        make_game().get_paint(),
+    )
+    .await;
+
+    let (status_tx, status_rx) = watch::channel(ControllerInfo::from(&config));
+    let metrics = Metrics::new();
+    if let Some(status_port) = args.status_port {
+        let status_addr = format!("127.0.0.1:{status_port}");
+        info!("Serving status on http://{status_addr}/status and metrics on /metrics");
+        tokio::spawn(status_server::serve(
+            status_addr.parse().unwrap(),
+            status_rx,
+            metrics.clone(),
+        ));
+    }
+
+    for tx in &controller_txs {
+        // The controller starts out with the built-in delays
+        if args.config.is_some() {
+            tx.send(ControllerMsg::SetTurnDelay(config.turn_delay()))
+                .await
+                .unwrap();
+            tx.send(ControllerMsg::SetWinDelay(config.win_delay()))
+                .await
+                .unwrap();
+        }
+        if let Some(secs) = args.max_game_duration_secs {
+            tx.send(ControllerMsg::SetMaxGameDuration(Some(
+                Duration::from_secs(secs),
+            )))
+            .await
+            .unwrap();
+        }
+        if let Some(secs) = args.idle_timeout_secs {
+            tx.send(ControllerMsg::SetIdleTimeout(Some(Duration::from_secs(
+                secs,
+            ))))
+            .await
+            .unwrap();
+        }
+        if let Some(path) = &args.event_log {
+            // First in the queue, so the log starts before anyone can connect
+            tx.send(ControllerMsg::SetEventLog(Some(path.clone())))
+                .await
+                .unwrap();
+        }
+    }
 
-    let controller_channel = mpsc::channel::<ControllerMsg>(1024);
-    let async_game = AsyncGame::make_ptr_from_game(gomoku::Game::new(20, 20, Vec::new()));
+    let shutdown_txs = controller_txs.clone();
+    tokio::spawn(async move {
+        tokio::signal::ctrl_c().await.unwrap();
+        info!("Got ctrl-c");
+        for tx in shutdown_txs {
+            let _ = tx.send(ControllerMsg::Shutdown).await;
+        }
+    });
 
-    let ui_handle = start_ui(controller_channel.0.clone(), async_game.get_paint()).await;
+    // The status page only covers the first game
+    let mut status_tx = Some(status_tx);
+    let slots = channels
+        .into_iter()
+        .enumerate()
+        .map(|(slot, channel)| SlotSetup {
+            channel,
+            game: make_game(),
+            ui_sender: if args.num_games == 1 {
+                UiSender::Real(ui_handle.clone())
+            } else {
+                UiSender::Slot {
+                    sink: ui_handle.clone(),
+                    slot,
+                    shown: shown_game.clone(),
+                }
+            },
+            status_tx: status_tx
+                .take()
+                .unwrap_or_else(|| watch::channel(ControllerInfo::from(&config)).0),
+        })
+        .collect();
 
     entry(
         listener,
-        UiSender::Real(ui_handle),
-        controller_channel,
-        async_game,
+        slots,
+        metrics,
+        Arc::new(BcryptHasher::default()),
+        args.max_connections.unwrap_or(config.max_connections),
+        Arc::new(Mutex::new(RateLimiter::new(
+            MAX_FAILED_LOGINS,
+            FAILED_LOGIN_WINDOW,
+        ))),
+        user_connection::AUTH_TIMEOUT,
     )
     .await;
 }
 
+/// The game picked at startup, before any of the wrappers asked for on the command line
+fn make_plain_game(
+    game_kind: GameKind,
+    config: &ServerConfig,
+    args: &Args,
+) -> Box<dyn AsyncGameTrait> {
+    match game_kind {
+        GameKind::Gomoku => {
+            let rules = if args.renju {
+                gomoku::RulesVariant::Renju
+            } else {
+                gomoku::RulesVariant::Freestyle
+            };
+            let game = gomoku::Game::new(config.board_width, config.board_height, 5, Vec::new())
+                .with_rules(rules);
+            Box::new(
+                plain(
+                    game,
+                    gomoku::Game::describe,
+                    gomoku::Game::min_players,
+                    gomoku::Game::max_players,
+                )
+                .with_undo(gomoku::Game::undo_last_move),
+            )
+        }
+        GameKind::Tictactoe => Box::new(plain(
+            tictactoe::Game::new(Vec::new()),
+            tictactoe::Game::describe,
+            tictactoe::Game::min_players,
+            tictactoe::Game::max_players,
+        )),
+        GameKind::Reversi => Box::new(plain(
+            reversi::Game::new(Vec::new()),
+            reversi::Game::describe,
+            reversi::Game::min_players,
+            reversi::Game::max_players,
+        )),
+        GameKind::Rps => Box::new(plain(
+            rps::Game::new(args.best_of_three, Vec::new()),
+            rps::Game::describe,
+            rps::Game::min_players,
+            rps::Game::max_players,
+        )),
+        GameKind::NumberGuess => Box::new(plain(
+            number_guess::Game::new(Vec::new()),
+            number_guess::Game::describe,
+            number_guess::Game::min_players,
+            |_| usize::MAX,
+        )),
+        GameKind::Wordle => Box::new(plain(
+            wordle::Game::new(Vec::new()),
+            wordle::Game::describe,
+            wordle::Game::min_players,
+            |_| usize::MAX,
+        )),
+        GameKind::Nim => {
+            let variant = if args.misere {
+                nim::GameVariant::Misere
+            } else {
+                nim::GameVariant::Normal
+            };
+            Box::new(
+                plain(
+                    nim::Game::new(args.nim_piles.clone(), variant, Vec::new()),
+                    nim::Game::describe,
+                    nim::Game::min_players,
+                    nim::Game::max_players,
+                )
+                .with_winner(nim::Game::winner),
+            )
+        }
+    }
+}
+
+// `game` with what the server tells the clients about it
+// TODO: Drop once game-types has GameTrait::describe/min_players/max_players
+fn plain<T>(
+    game: T,
+    describe: fn(&T) -> String,
+    min_players: fn(&T) -> usize,
+    max_players: fn(&T) -> usize,
+) -> AsyncGame<T>
+where
+    T: 'static + gametraits::GameTrait + Clone + gametraits::Paint,
+{
+    let description = describe(&game);
+    let (min, max) = (min_players(&game), max_players(&game));
+    AsyncGame::new(game)
+        .with_description(description)
+        .with_player_limits(min, max)
+}
+
+const GAME_METRICS_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_FAILED_LOGINS: u32 = 5;
+const FAILED_LOGIN_WINDOW: Duration = Duration::from_secs(60);
+
 async fn start_ui(
-    controller_tx: mpsc::Sender<ControllerMsg>,
+    controller_txs: Vec<mpsc::Sender<ControllerMsg>>,
+    shown_game: Arc<AtomicUsize>,
     game: Box<dyn gametraits::Paint>,
 ) -> ExtEventSink {
     let (ui_handle_tx, ui_handle_rx) = tokio::sync::oneshot::channel::<ExtEventSink>();
     let cswr = controller::ControllerSender {
         rt_handle: tokio::runtime::Handle::current(),
-        tx: controller_tx,
+        txs: controller_txs,
+        shown: shown_game,
     };
     std::thread::spawn(move || {
         ui::launch(ui_handle_tx, cswr, game);
@@ -62,64 +391,415 @@ async fn sleep_fn(delay: std::time::Duration) {
 
 async fn entry(
     listener: impl network_wrap::Listener,
-    update_game_sender: UiSender,
-    (tx, rx): (mpsc::Sender<ControllerMsg>, mpsc::Receiver<ControllerMsg>),
-    actual_game: Box<dyn AsyncGameTrait>,
+    slots: Vec<SlotSetup>,
+    metrics: Metrics,
+    hasher: SharedPasswordHasher,
+    max_connections: usize,
+    rate_limiter: SharedRateLimiter,
+    auth_timeout: Duration,
 ) {
+    let (stopped_tx, stopped_rx) = watch::channel(false);
+    let mut game_slots = Vec::new();
+    let mut controllers = JoinSet::new();
+    for SlotSetup {
+        channel: (tx, rx),
+        game,
+        ui_sender,
+        status_tx,
+    } in slots
+    {
+        game_slots.push(GameSlot { tx });
+        let metrics = metrics.clone();
+        controllers.spawn(async move {
+            controller::controller_loop(
+                rx,
+                ui_sender,
+                status_tx,
+                metrics,
+                EventLogger::default(),
+                game,
+                &sleep_fn,
+            )
+            .await;
+        });
+    }
     tokio::spawn(async move {
-        controller::controller_loop(rx, update_game_sender, actual_game, &sleep_fn).await;
+        // Every game has to be told to shut down
+        while controllers.join_next().await.is_some() {}
+        let _ = stopped_tx.send(true);
     });
 
-    user_connection::accept_connection_loop(listener, tx).await;
+    user_connection::accept_connection_loop(
+        listener,
+        ArenaController::new(game_slots),
+        hasher,
+        stopped_rx,
+        max_connections,
+        rate_limiter,
+        auth_timeout,
+    )
+    .await;
 }
 
 #[cfg(test)]
 mod test {
     use crate::network_wrap::get_test_channel;
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
 
     use super::*;
 
     const JSON_BASIC_STATE: &str = r#"{"your-turn":{"num":0}}"#;
 
-    async fn test_entry(fake_listener: impl network_wrap::Listener) {
-        entry(
-            fake_listener,
-            UiSender::Fake,
-            mpsc::channel::<ControllerMsg>(1024),
-            AsyncGame::make_ptr_from_game(games::dumb::Game::new()),
+    const TEST_MAX_CONNECTIONS: usize = 256;
+
+    // Lowest cost bcrypt allows, keeps logins fast in tests
+    fn test_hasher() -> SharedPasswordHasher {
+        Arc::new(BcryptHasher::new(4))
+    }
+
+    // Nobody is looking at the status in these tests
+    fn test_status_tx() -> watch::Sender<ControllerInfo> {
+        watch::channel(ControllerInfo::default()).0
+    }
+
+    fn test_slot_on(
+        channel: (mpsc::Sender<ControllerMsg>, mpsc::Receiver<ControllerMsg>),
+        game: Box<dyn AsyncGameTrait>,
+    ) -> SlotSetup {
+        SlotSetup {
+            channel,
+            game,
+            ui_sender: UiSender::Fake,
+            status_tx: test_status_tx(),
+        }
+    }
+
+    // Tests log in with wrong passwords on purpose, don't let that get in the way
+    const TEST_MAX_FAILED_LOGINS: u32 = 1000;
+
+    fn test_rate_limiter() -> SharedRateLimiter {
+        test_rate_limiter_with(TEST_MAX_FAILED_LOGINS)
+    }
+
+    fn test_rate_limiter_with(max_failed_logins: u32) -> SharedRateLimiter {
+        Arc::new(Mutex::new(RateLimiter::new(
+            max_failed_logins,
+            Duration::from_secs(60),
+        )))
+    }
+
+    // Longer than a driver waits for anything, so a controller stuck behind it fails the test
+    const HANG: Duration = Duration::from_secs(2);
+
+    // Counts like the dumb game, but gets stuck when asked to add 13
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct HangingGame(games::dumb::Game);
+
+    impl gametraits::GameTrait for HangingGame {
+        fn player_moves(
+            &mut self,
+            token: gametraits::TurnToken,
+            player_move: gametraits::PlayerMove,
+        ) -> gametraits::PlayerMoveResult {
+            if player_move.serialized.contains("13") {
+                std::thread::sleep(HANG);
+            }
+            self.0.player_moves(token, player_move)
+        }
+        fn player_connected(&mut self, user: gametraits::User) {
+            self.0.player_connected(user)
+        }
+        fn player_disconnected(&mut self, user: &str) {
+            self.0.player_disconnected(user)
+        }
+        fn current_player_disconnected(
+            &mut self,
+            token: gametraits::TurnToken,
+        ) -> Option<gametraits::PlayerTurn> {
+            self.0.current_player_disconnected(token)
+        }
+        fn try_start_game(&mut self) -> Option<gametraits::PlayerTurn> {
+            self.0.try_start_game()
+        }
+        fn reset(&mut self, users: Vec<gametraits::User>) {
+            self.0.reset(users)
+        }
+    }
+
+    impl gametraits::Paint for HangingGame {
+        fn paint(&self, ctx: &mut druid::PaintCtx) {
+            self.0.paint(ctx)
+        }
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+        fn eq(&self, other: &dyn gametraits::Paint) -> bool {
+            self == other.as_any().downcast_ref::<HangingGame>().unwrap()
+        }
+    }
+
+    // Turns the minimum down to one like an operator can, so a player can start a game alone
+    async fn solo_channel() -> (mpsc::Sender<ControllerMsg>, mpsc::Receiver<ControllerMsg>) {
+        let (tx, rx) = mpsc::channel::<ControllerMsg>(1024);
+        tx.send(ControllerMsg::SetMinPlayers(1)).await.unwrap();
+        (tx, rx)
+    }
+
+    fn test_event_log_path() -> PathBuf {
+        std::env::temp_dir().join("coding-challenge-event-log-test.ndjson")
+    }
+
+    fn hanging_game() -> Box<dyn AsyncGameTrait> {
+        AsyncGame::make_ptr_with_timeout(
+            HangingGame(games::dumb::Game::new_with_target(1000)),
+            Duration::from_millis(100),
         )
-        .await;
     }
 
-    async fn test_entry_gomoko(fake_listener: impl network_wrap::Listener) {
+    // How a flow test's server is set up, anything not set here is like `coding-challenge <args>`
+    // on the dumb game
+    struct TestSetup {
+        args: Args,
+        // Instead of the game `--game` picks
+        game: Option<fn() -> Box<dyn AsyncGameTrait>>,
+        // A player can start a game alone, see `solo_channel`
+        solo: bool,
+        max_failed_logins: u32,
+        auth_timeout: Duration,
+        with_ui: bool,
+    }
+
+    impl TestSetup {
+        fn new(args: &[&str]) -> Self {
+            Self {
+                args: Args::parse_from(["coding-challenge"].iter().chain(args)),
+                game: None,
+                solo: false,
+                max_failed_logins: TEST_MAX_FAILED_LOGINS,
+                auth_timeout: user_connection::AUTH_TIMEOUT,
+                with_ui: false,
+            }
+        }
+
+        // For `init_flow_test_spawn!`
+        fn entry(self) -> impl FnOnce(network_wrap::FakeListener) -> TestServer {
+            move |fake_listener| Box::pin(test_entry_with(fake_listener, self))
+        }
+    }
+
+    type TestServer = std::pin::Pin<Box<dyn std::future::Future<Output = ()>>>;
+
+    fn test_entry(args: &[&str]) -> impl FnOnce(network_wrap::FakeListener) -> TestServer {
+        TestSetup::new(args).entry()
+    }
+
+    async fn test_entry_with(listener: impl network_wrap::Listener, setup: TestSetup) {
+        let TestSetup {
+            args,
+            game: make_game,
+            solo,
+            max_failed_logins,
+            auth_timeout,
+            with_ui,
+        } = setup;
+        let mut slots = Vec::new();
+        for _ in 0..args.num_games {
+            let channel = if solo {
+                solo_channel().await
+            } else {
+                mpsc::channel::<ControllerMsg>(1024)
+            };
+            if let Some(path) = &args.event_log {
+                channel
+                    .0
+                    .send(ControllerMsg::SetEventLog(Some(path.clone())))
+                    .await
+                    .unwrap();
+            }
+            let game = match (make_game, args.game) {
+                (Some(make_game), _) => make_game(),
+                (None, Some(game_kind)) => {
+                    make_plain_game(game_kind, &ServerConfig::default(), &args)
+                }
+                (None, None) => dumb_game(),
+            };
+            slots.push(test_slot_on(channel, game));
+        }
+        if with_ui {
+            let slot = &mut slots[0];
+            let sink = start_ui(
+                vec![slot.channel.0.clone()],
+                Arc::new(AtomicUsize::new(0)),
+                slot.game.get_paint(),
+            )
+            .await;
+            slot.ui_sender = UiSender::Real(sink);
+        }
         entry(
-            fake_listener,
-            UiSender::Fake,
-            mpsc::channel::<ControllerMsg>(1024),
-            AsyncGame::make_ptr_from_game(games::gomoku::Game::new(20, 20, Vec::new())),
+            listener,
+            slots,
+            Metrics::new(),
+            test_hasher(),
+            args.max_connections.unwrap_or(TEST_MAX_CONNECTIONS),
+            test_rate_limiter_with(max_failed_logins),
+            auth_timeout,
         )
         .await;
     }
 
-    async fn test_entry_with_ui(fake_listener: impl network_wrap::Listener) {
-        let (tx, rx) = mpsc::channel::<ControllerMsg>(1024);
-        let async_game =
-            AsyncGame::make_ptr_from_game(games::gomoku::Game::new(20, 20, Vec::new()));
-        let sink = start_ui(tx.clone(), async_game.get_paint()).await;
-        entry(fake_listener, UiSender::Real(sink), (tx, rx), async_game).await;
-    }
-
     fn login_msg(user: &str, pass: &str) -> String {
         r#"{"auth":{"username":""#.to_string() + user + r#"","password":""# + pass + r#""}}"#
     }
 
+    // The games the flow tests are played on, and what the server tells the clients about them
+    #[derive(Clone, Copy)]
+    enum TestGame {
+        Dumb,
+        Gomoku,
+        // Gomoku with the minimum turned down to one, see `solo_channel`
+        SoloGomoku,
+        Tictactoe,
+        // Piles of 1 and 2
+        Nim,
+        // Two piles of 1, whoever takes the last one loses
+        MisereNim,
+    }
+
+    impl TestGame {
+        fn describe(self) -> &'static str {
+            match self {
+                TestGame::Dumb => "Count to 1000, allowed operations: add",
+                TestGame::Gomoku | TestGame::SoloGomoku => "Gomoku 20x20, 5 in a row",
+                TestGame::Tictactoe => "Tic-tac-toe",
+                TestGame::Nim => "Nim, piles 1 2",
+                TestGame::MisereNim => "Nim, piles 1 1, misère",
+            }
+        }
+
+        fn player_limits(self) -> (usize, usize) {
+            match self {
+                TestGame::Dumb | TestGame::SoloGomoku => (1, usize::MAX),
+                TestGame::Gomoku => (2, usize::MAX),
+                TestGame::Tictactoe | TestGame::Nim | TestGame::MisereNim => (2, 2),
+            }
+        }
+
+        fn board(self) -> Option<(usize, usize)> {
+            match self {
+                TestGame::Dumb | TestGame::Nim | TestGame::MisereNim => None,
+                TestGame::Gomoku | TestGame::SoloGomoku => Some((20, 20)),
+                TestGame::Tictactoe => Some((3, 3)),
+            }
+        }
+    }
+
+    fn dumb_game() -> Box<dyn AsyncGameTrait> {
+        let game = games::dumb::Game::new_with_target(1000);
+        let description = game.describe();
+        Box::new(AsyncGame::new(game).with_description(description))
+    }
+
+    // The same gomoku the server hosts when started without any options
+    fn gomoku_game() -> Box<dyn AsyncGameTrait> {
+        make_plain_game(
+            GameKind::Gomoku,
+            &ServerConfig::default(),
+            &Args::parse_from(["coding-challenge"]),
+        )
+    }
+
+    fn server_info(game: TestGame) -> String {
+        let (min_players, max_players) = game.player_limits();
+        let board = game.board();
+        serde_json::json!({ "server-info": {
+            "version": env!("CARGO_PKG_VERSION"),
+            "game": game.describe(),
+            "min-players": min_players,
+            "max-players": max_players,
+            "board-width": board.map(|(width, _)| width),
+            "board-height": board.map(|(_, height)| height),
+        } })
+        .to_string()
+    }
+
+    fn player_list(names: &[&str]) -> String {
+        serde_json::json!({ "player-list": names }).to_string()
+    }
+
+    fn game_started(game: TestGame, players: &[&str]) -> String {
+        serde_json::json!({ "game-started": { "game": game.describe(), "players": players } })
+            .to_string()
+    }
+
+    #[test]
+    fn every_game_kind_says_what_it_is() {
+        let config = ServerConfig::default();
+        let args = Args::parse_from(["coding-challenge"]);
+        let info = |kind| {
+            let game = make_plain_game(kind, &config, &args);
+            let info = controller::ServerInfo::new(&*game, &ControllerInfo::default());
+            (
+                info.game,
+                info.min_players,
+                info.max_players,
+                info.board_width,
+            )
+        };
+        assert_eq!(
+            info(GameKind::Gomoku),
+            ("Gomoku 20x20, 5 in a row".into(), 2, usize::MAX, Some(20))
+        );
+        assert_eq!(
+            info(GameKind::Tictactoe),
+            ("Tic-tac-toe".into(), 2, 2, Some(3))
+        );
+        assert_eq!(
+            info(GameKind::Reversi),
+            ("Reversi 8x8".into(), 2, 2, Some(8))
+        );
+        assert_eq!(
+            info(GameKind::Rps),
+            ("Rock paper scissors".into(), 2, 2, None)
+        );
+        assert_eq!(
+            info(GameKind::NumberGuess),
+            (
+                "Guess the number from 1 to 100, 10 guesses".into(),
+                1,
+                usize::MAX,
+                None
+            )
+        );
+        assert_eq!(
+            info(GameKind::Wordle),
+            ("Wordle, 5 letters, 6 guesses".into(), 1, usize::MAX, None)
+        );
+        assert_eq!(info(GameKind::Nim), ("Nim, piles 3 4 5".into(), 2, 2, None));
+    }
+
+    #[test]
+    fn gomoku_limits_come_from_the_game() {
+        let game = gomoku::Game::new(20, 20, 5, Vec::new());
+        let hosted = gomoku_game();
+        assert_eq!(hosted.min_players(), game.min_players());
+        assert_eq!(hosted.max_players(), game.max_players());
+    }
+
     #[tokio::test]
     async fn test_two_player_flow() {
-        init_flow_test_spawn!(driver, test_entry);
+        init_flow_test_spawn!(driver, test_entry(&[]));
 
         let mut user = driver.connect_user("zeldo").await;
         driver.send(&mut user, &login_msg("zeldo", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
 
+        driver.receive(&mut user, &player_list(&["zeldo"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::Dumb, &["zeldo"]))
+            .await;
         driver.receive(&mut user, JSON_BASIC_STATE).await;
         driver.send(&mut user, r#"{"move":{"add": 5}}"#).await;
 
@@ -134,8 +814,18 @@ mod test {
 
         let mut user2 = driver.connect_user("user2").await;
         driver.send(&mut user2, &login_msg("user2", "pass")).await;
+        driver
+            .receive(&mut user2, &server_info(TestGame::Dumb))
+            .await;
+        driver
+            .receive(&mut user2, &player_list(&["zeldo", "user2"]))
+            .await;
 
         driver.send(&mut user, r#"{"move":{"add":3}}"#).await;
+        // Arrived while zeldo was thinking, so it comes after the move
+        driver
+            .receive(&mut user, &player_list(&["zeldo", "user2"]))
+            .await;
         driver
             .receive(&mut user2, r#"{"your-turn":{"num":13}}"#)
             .await;
@@ -164,11 +854,18 @@ mod test {
 
     #[tokio::test]
     async fn test_one_player_drops() {
-        init_flow_test_spawn!(driver, test_entry);
+        init_flow_test_spawn!(driver, test_entry(&[]));
 
         let mut user = driver.connect_user("user").await;
 
         driver.send(&mut user, &login_msg("user", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
+        driver.receive(&mut user, &player_list(&["user"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::Dumb, &["user"]))
+            .await;
         driver
             .receive(&mut user, r#"{"your-turn":{"num":0}}"#)
             .await;
@@ -179,28 +876,48 @@ mod test {
 
     #[tokio::test]
     async fn invalid_auth() {
-        init_flow_test_spawn!(driver, test_entry);
+        init_flow_test_spawn!(driver, test_entry(&[]));
 
         let mut user = driver.connect_user("user").await;
 
         driver
             .send(&mut user, r#"{"auth":{"blarh":"user","password":"bleah"}}"#)
             .await;
-        driver
-            .receive(
-                &mut user,
-                r#"{"error":{"reason":"invalid message format"}}"#,
-            )
-            .await;
+        driver.receive_error(&mut user, 1001).await;
+        driver.expect_disconnect(&mut user).await;
+    }
+
+    #[tokio::test]
+    async fn silent_connection_times_out() {
+        init_flow_test_spawn!(
+            driver,
+            TestSetup {
+                auth_timeout: Duration::from_millis(200),
+                ..TestSetup::new(&[])
+            }
+            .entry()
+        );
+
+        let mut user = driver.connect_user("user").await;
+
+        driver.receive_error(&mut user, 1012).await;
+        driver.expect_disconnect(&mut user).await;
     }
 
     #[tokio::test]
     async fn wrong_pass() {
-        init_flow_test_spawn!(driver, test_entry);
+        init_flow_test_spawn!(driver, test_entry(&[]));
 
         let mut user = driver.connect_user("user").await;
 
         driver.send(&mut user, &login_msg("user", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
+        driver.receive(&mut user, &player_list(&["user"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::Dumb, &["user"]))
+            .await;
         driver
             .receive(&mut user, r#"{"your-turn":{"num":0}}"#)
             .await;
@@ -212,17 +929,50 @@ mod test {
         driver
             .send(&mut user, &login_msg("user", "wrong pass"))
             .await;
+        driver.receive_error(&mut user, 1002).await;
+        driver.expect_disconnect(&mut user).await;
+    }
+
+    #[tokio::test]
+    async fn protocol_version() {
+        init_flow_test_spawn!(driver, test_entry(&[]));
+
+        let mut user = driver.connect_user("zeldo").await;
+        driver
+            .send(
+                &mut user,
+                r#"{"auth":{"username":"zeldo","password":"pass","protocol_version":2}}"#,
+            )
+            .await;
+        driver.receive_error(&mut user, 1010).await;
+        driver.expect_disconnect(&mut user).await;
+
+        let mut user = driver.connect_user("zeldo_again").await;
+        driver
+            .send(
+                &mut user,
+                r#"{"auth":{"username":"zeldo","password":"pass","protocol_version":1}}"#,
+            )
+            .await;
         driver
-            .receive(&mut user, r#"{"error":{"reason":"wrong password"}}"#)
+            .receive(&mut user, &server_info(TestGame::Dumb))
             .await;
+        driver.receive(&mut user, &player_list(&["zeldo"])).await;
     }
 
     #[tokio::test]
     async fn wrong_format_move() {
-        init_flow_test_spawn!(driver, test_entry);
+        init_flow_test_spawn!(driver, test_entry(&[]));
 
         let mut user = driver.connect_user("zeldo").await;
         driver.send(&mut user, &login_msg("user", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
+        driver.receive(&mut user, &player_list(&["user"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::Dumb, &["user"]))
+            .await;
 
         driver.receive(&mut user, JSON_BASIC_STATE).await;
         driver.send(&mut user, r#"{"move":{"add": 5}}"#).await;
@@ -232,20 +982,29 @@ mod test {
             .await;
         driver.send(&mut user, r#"{"sub": 5}"#).await;
 
-        driver
-            .receive(
-                &mut user,
-                r#"{"error":{"reason":"invalid message format"}}"#,
-            )
-            .await;
+        driver.receive_error(&mut user, 1001).await;
     }
 
     #[tokio::test]
     async fn invalid_move() {
-        init_flow_test_spawn!(driver, test_entry_gomoko);
+        init_flow_test_spawn!(
+            driver,
+            TestSetup {
+                solo: true,
+                ..TestSetup::new(&["--game", "gomoku"])
+            }
+            .entry()
+        );
 
         let mut user = driver.connect_user("zeldo").await;
         driver.send(&mut user, &login_msg("user", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::SoloGomoku))
+            .await;
+        driver.receive(&mut user, &player_list(&["user"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::SoloGomoku, &["user"]))
+            .await;
 
         driver.receive_anything(&mut user).await;
         driver.send(&mut user, r#"{"move":{"x":5,"y":5}}"#).await;
@@ -253,123 +1012,1343 @@ mod test {
         driver.receive_anything(&mut user).await;
         driver.send(&mut user, r#"{"move":{"x":5,"y":5}}"#).await;
 
-        driver
-            .receive(&mut user, r#"{"error":{"reason":"invalid move"}}"#)
-            .await;
+        driver.receive_error(&mut user, 1003).await;
     }
 
     #[tokio::test]
     async fn invalid_move_p2() {
-        init_flow_test_spawn!(driver, test_entry_gomoko);
+        init_flow_test_spawn!(
+            driver,
+            TestSetup {
+                solo: true,
+                ..TestSetup::new(&["--game", "gomoku"])
+            }
+            .entry()
+        );
 
         let mut p1 = driver.connect_user("player1").await;
         let mut p2 = driver.connect_user("player2").await;
         driver.send(&mut p1, &login_msg("player1", "pass")).await;
+        driver
+            .receive(&mut p1, &server_info(TestGame::SoloGomoku))
+            .await;
+        driver.receive(&mut p1, &player_list(&["player1"])).await;
+        driver
+            .receive(&mut p1, &game_started(TestGame::SoloGomoku, &["player1"]))
+            .await;
         driver.receive_anything(&mut p1).await;
 
         driver.send(&mut p2, &login_msg("player2", "pass")).await;
+        driver
+            .receive(&mut p2, &server_info(TestGame::SoloGomoku))
+            .await;
+        driver
+            .receive(&mut p2, &player_list(&["player1", "player2"]))
+            .await;
 
         driver.send(&mut p1, r#"{"move":{"x":5,"y":5}}"#).await;
+        driver
+            .receive(&mut p1, &player_list(&["player1", "player2"]))
+            .await;
 
         driver.receive_anything(&mut p2).await;
         driver.send(&mut p2, r#"{"move":{"x":5,"y":5}}"#).await;
 
-        driver
-            .receive(&mut p2, r#"{"error":{"reason":"invalid move"}}"#)
-            .await;
+        driver.receive_error(&mut p2, 1003).await;
 
         driver.receive_anything(&mut p1).await;
         driver.send(&mut p1, r#"{"move":{"x":5,"y":6}}"#).await;
 
+        // player2 is out after the invalid move
+        driver.receive(&mut p1, &player_list(&["player1"])).await;
         driver.receive_anything(&mut p1).await;
     }
 
-    #[tokio::test]
-    async fn win_twice() {
-        env_logger::init();
-        init_flow_test_spawn!(driver, test_entry_gomoko);
-
-        let mut user = driver.connect_user("zeldo").await;
-        driver.send(&mut user, &login_msg("zeldo", "pass")).await;
-
-        driver.receive_anything(&mut user).await;
-        driver.send(&mut user, r#"{"move":{"x":0,"y":0}}"#).await;
+    fn waiting(queue_position: usize) -> String {
+        serde_json::json!({ "info": { "status": "waiting", "queue-position": queue_position } })
+            .to_string()
+    }
 
-        driver.receive_anything(&mut user).await;
-        driver.send(&mut user, r#"{"move":{"x":1,"y":0}}"#).await;
-        driver.receive_anything(&mut user).await;
-        driver.send(&mut user, r#"{"move":{"x":2,"y":0}}"#).await;
-        driver.receive_anything(&mut user).await;
-        driver.send(&mut user, r#"{"move":{"x":3,"y":0}}"#).await;
-        driver.receive_anything(&mut user).await;
-        driver.send(&mut user, r#"{"move":{"x":4,"y":0}}"#).await;
+    #[tokio::test]
+    async fn gomoku_waits_for_a_second_player() {
+        init_flow_test_spawn!(driver, test_entry(&["--game", "gomoku"]));
 
+        let mut p1 = driver.connect_user("player1").await;
+        driver.send(&mut p1, &login_msg("player1", "pass")).await;
         driver
-            .receive(&mut user, r#"{"game-over":{"reason":"winner zeldo"}}"#)
+            .receive(&mut p1, &server_info(TestGame::Gomoku))
             .await;
+        driver.receive(&mut p1, &waiting(1)).await;
 
-        driver.receive_anything(&mut user).await;
-        driver.send(&mut user, r#"{"move":{"x":0,"y":0}}"#).await;
-
-        driver.receive_anything(&mut user).await;
-        driver.send(&mut user, r#"{"move":{"x":1,"y":0}}"#).await;
-        driver.receive_anything(&mut user).await;
-        driver.send(&mut user, r#"{"move":{"x":2,"y":0}}"#).await;
-        driver.receive_anything(&mut user).await;
-        driver.send(&mut user, r#"{"move":{"x":3,"y":0}}"#).await;
-        driver.receive_anything(&mut user).await;
-        driver.send(&mut user, r#"{"move":{"x":4,"y":0}}"#).await;
+        let mut p2 = driver.connect_user("player2").await;
+        driver.send(&mut p2, &login_msg("player2", "pass")).await;
+        driver
+            .receive(&mut p2, &server_info(TestGame::Gomoku))
+            .await;
+        let both = player_list(&["player1", "player2"]);
+        driver.receive(&mut p2, &both).await;
+        driver
+            .receive(
+                &mut p2,
+                &game_started(TestGame::Gomoku, &["player1", "player2"]),
+            )
+            .await;
 
+        driver.receive(&mut p1, &player_list(&["player1"])).await;
+        driver.receive(&mut p1, &both).await;
         driver
-            .receive(&mut user, r#"{"game-over":{"reason":"winner zeldo"}}"#)
+            .receive(
+                &mut p1,
+                &game_started(TestGame::Gomoku, &["player1", "player2"]),
+            )
             .await;
+        driver.receive_anything(&mut p1).await;
+        driver.send(&mut p1, r#"{"move":{"x":5,"y":5}}"#).await;
+        driver.receive_anything(&mut p2).await;
     }
 
     #[tokio::test]
-    async fn two_players_passive_drops() {
-        init_flow_test_spawn!(driver, test_entry);
+    async fn gomoku_move_can_be_taken_back() {
+        init_flow_test_spawn!(driver, test_entry(&["--game", "gomoku"]));
 
-        let mut user = driver.connect_user("user").await;
+        let mut p1 = driver.connect_user("player1").await;
+        driver.send(&mut p1, &login_msg("player1", "pass")).await;
+        driver.receive_anything(&mut p1).await;
+        driver.receive_anything(&mut p1).await;
+        let mut p2 = driver.connect_user("player2").await;
+        driver.send(&mut p2, &login_msg("player2", "pass")).await;
+        for _ in 0..3 {
+            driver.receive_anything(&mut p2).await;
+        }
+        for _ in 0..4 {
+            driver.receive_anything(&mut p1).await;
+        }
 
-        driver.send(&mut user, &login_msg("user", "pass")).await;
+        driver.send(&mut p1, r#"{"move":{"x":5,"y":5}}"#).await;
+        driver.receive_anything(&mut p2).await;
+        driver.send(&mut p2, r#"{"request-undo":null}"#).await;
+        driver.receive(&mut p1, r#"{"undo-granted":null}"#).await;
+        driver.receive(&mut p2, r#"{"undo-granted":null}"#).await;
+
+        // The cell is free again, the same move is fine
+        driver.receive_anything(&mut p1).await;
+        driver.send(&mut p1, r#"{"move":{"x":5,"y":5}}"#).await;
+        driver.receive_anything(&mut p2).await;
+        driver.send(&mut p2, r#"{"move":{"x":6,"y":6}}"#).await;
+        driver.receive_anything(&mut p1).await;
+    }
+
+    // Both players log in, player1 is sent the first state
+    async fn start_nim(
+        driver: &mut network_wrap::TestDriver,
+        game: TestGame,
+    ) -> (network_wrap::TestUser, network_wrap::TestUser) {
+        let mut p1 = driver.connect_user("player1").await;
+        driver.send(&mut p1, &login_msg("player1", "pass")).await;
+        driver.receive(&mut p1, &server_info(game)).await;
+        driver.receive(&mut p1, &waiting(1)).await;
+
+        let mut p2 = driver.connect_user("player2").await;
+        driver.send(&mut p2, &login_msg("player2", "pass")).await;
+        driver.receive(&mut p2, &server_info(game)).await;
+        let both = player_list(&["player1", "player2"]);
+        driver.receive(&mut p2, &both).await;
         driver
-            .receive(&mut user, r#"{"your-turn":{"num":0}}"#)
+            .receive(&mut p2, &game_started(game, &["player1", "player2"]))
             .await;
 
-        let mut user2 = driver.connect_user("zumba").await;
+        driver.receive(&mut p1, &player_list(&["player1"])).await;
+        driver.receive(&mut p1, &both).await;
+        driver
+            .receive(&mut p1, &game_started(game, &["player1", "player2"]))
+            .await;
+        (p1, p2)
+    }
 
-        driver.send(&mut user2, &login_msg("zumba", "pass")).await;
-        drop(user2);
+    #[tokio::test]
+    async fn nim_last_object_wins() {
+        init_flow_test_spawn!(driver, test_entry(&["--game", "nim", "--nim-piles", "1,2"]));
+        let (mut p1, mut p2) = start_nim(&mut driver, TestGame::Nim).await;
 
-        driver.send(&mut user, r#"{"move":{"add":1}}"#).await;
         driver
-            .receive(&mut user, r#"{"your-turn":{"num":1}}"#)
+            .receive(&mut p1, r#"{"piles":[1,2],"variant":"normal"}"#)
             .await;
-
-        driver.send(&mut user, r#"{"move":{"add":1}}"#).await;
         driver
-            .receive(&mut user, r#"{"your-turn":{"num":2}}"#)
+            .send(&mut p1, r#"{"move":{"pile":1,"take":2}}"#)
             .await;
-        driver.send(&mut user, r#"{"move":{"add":1}}"#).await;
         driver
-            .receive(&mut user, r#"{"your-turn":{"num":3}}"#)
+            .receive(&mut p2, r#"{"piles":[1,0],"variant":"normal"}"#)
+            .await;
+        driver
+            .send(&mut p2, r#"{"move":{"pile":0,"take":1}}"#)
             .await;
-    }
 
-    #[allow(dead_code)]
-    fn sleep_a_bit() {
-        std::thread::sleep(std::time::Duration::from_millis(400));
+        for user in [&mut p1, &mut p2] {
+            driver
+                .receive(user, r#"{"game-over":{"reason":"winner player2"}}"#)
+                .await;
+        }
     }
 
-    #[ignore]
     #[tokio::test]
-    async fn test_two_player_flow_with_ui() {
-        init_flow_test_spawn!(driver, test_entry_with_ui);
+    async fn misere_nim_last_object_loses() {
+        init_flow_test_spawn!(
+            driver,
+            test_entry(&["--game", "nim", "--nim-piles", "1,1", "--misere"])
+        );
+        let (mut p1, mut p2) = start_nim(&mut driver, TestGame::MisereNim).await;
 
-        let mut user = driver.connect_user("zeldo").await;
-        driver.send(&mut user, &login_msg("zeldo", "kermit")).await;
-        let mut user2 = driver.connect_user("user2").await;
-        driver.send(&mut user2, &login_msg("user2", "hello")).await;
+        driver
+            .receive(&mut p1, r#"{"piles":[1,1],"variant":"misere"}"#)
+            .await;
+        driver
+            .send(&mut p1, r#"{"move":{"pile":0,"take":1}}"#)
+            .await;
+        driver
+            .receive(&mut p2, r#"{"piles":[0,1],"variant":"misere"}"#)
+            .await;
+        // Nothing else to take, player2 has to lose
+        driver
+            .send(&mut p2, r#"{"move":{"pile":1,"take":1}}"#)
+            .await;
+
+        for user in [&mut p1, &mut p2] {
+            driver
+                .receive(user, r#"{"game-over":{"reason":"winner player1"}}"#)
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn third_player_is_turned_away_from_tictactoe() {
+        init_flow_test_spawn!(driver, test_entry(&["--game", "tictactoe"]));
+
+        let mut p1 = driver.connect_user("player1").await;
+        driver.send(&mut p1, &login_msg("player1", "pass")).await;
+        driver
+            .receive(&mut p1, &server_info(TestGame::Tictactoe))
+            .await;
+        driver.receive(&mut p1, &waiting(1)).await;
+
+        let mut p2 = driver.connect_user("player2").await;
+        driver.send(&mut p2, &login_msg("player2", "pass")).await;
+        driver
+            .receive(&mut p2, &server_info(TestGame::Tictactoe))
+            .await;
+        driver
+            .receive(&mut p2, &player_list(&["player1", "player2"]))
+            .await;
+        driver
+            .receive(
+                &mut p2,
+                &game_started(TestGame::Tictactoe, &["player1", "player2"]),
+            )
+            .await;
+
+        let mut p3 = driver.connect_user("player3").await;
+        driver.send(&mut p3, &login_msg("player3", "pass")).await;
+        driver
+            .receive(&mut p3, &server_info(TestGame::Tictactoe))
+            .await;
+        driver.receive_error(&mut p3, 1022).await;
+        driver.expect_disconnect(&mut p3).await;
+
+        // The two already playing never heard of player3
+        driver.receive(&mut p1, &player_list(&["player1"])).await;
+        driver
+            .receive(&mut p1, &player_list(&["player1", "player2"]))
+            .await;
+        driver
+            .receive(
+                &mut p1,
+                &game_started(TestGame::Tictactoe, &["player1", "player2"]),
+            )
+            .await;
+        driver.receive_anything(&mut p1).await;
+        driver.send(&mut p1, r#"{"move":{"x":1,"y":1}}"#).await;
+        driver.receive_anything(&mut p2).await;
+    }
+
+    #[tokio::test]
+    async fn controller_goes_on_past_a_hanging_move() {
+        init_flow_test_spawn!(
+            driver,
+            TestSetup {
+                game: Some(hanging_game),
+                ..TestSetup::new(&[])
+            }
+            .entry()
+        );
+
+        let mut p1 = driver.connect_user("player1").await;
+        driver.send(&mut p1, &login_msg("player1", "pass")).await;
+        // Server info, player list, game started and the first turn
+        for _ in 0..4 {
+            driver.receive_anything(&mut p1).await;
+        }
+        driver.send(&mut p1, r#"{"move":{"add":13}}"#).await;
+        driver.receive_error(&mut p1, 1003).await;
+
+        // The move is still stuck in the game, the next player is let in all the same
+        let mut p2 = driver.connect_user("player2").await;
+        driver.send(&mut p2, &login_msg("player2", "pass")).await;
+        driver.receive_anything(&mut p2).await;
+        driver.receive(&mut p2, &player_list(&["player2"])).await;
+    }
+
+    #[tokio::test]
+    async fn three_player_gomoku() {
+        init_flow_test_spawn!(driver, test_entry(&["--game", "gomoku"]));
+
+        let mut p1 = driver.connect_user("player1").await;
+        driver.send(&mut p1, &login_msg("player1", "pass")).await;
+        driver
+            .receive(&mut p1, &server_info(TestGame::Gomoku))
+            .await;
+        driver.receive(&mut p1, &waiting(1)).await;
+
+        let mut p2 = driver.connect_user("player2").await;
+        driver.send(&mut p2, &login_msg("player2", "pass")).await;
+        driver
+            .receive(&mut p2, &server_info(TestGame::Gomoku))
+            .await;
+        driver
+            .receive(&mut p2, &player_list(&["player1", "player2"]))
+            .await;
+        driver
+            .receive(
+                &mut p2,
+                &game_started(TestGame::Gomoku, &["player1", "player2"]),
+            )
+            .await;
+        driver.receive(&mut p1, &player_list(&["player1"])).await;
+        driver
+            .receive(&mut p1, &player_list(&["player1", "player2"]))
+            .await;
+        driver
+            .receive(
+                &mut p1,
+                &game_started(TestGame::Gomoku, &["player1", "player2"]),
+            )
+            .await;
+        driver.receive_anything(&mut p1).await;
+
+        // Joins while player1 is thinking, and is next after player2
+        let mut p3 = driver.connect_user("player3").await;
+        driver.send(&mut p3, &login_msg("player3", "pass")).await;
+        driver
+            .receive(&mut p3, &server_info(TestGame::Gomoku))
+            .await;
+        let everyone = player_list(&["player1", "player2", "player3"]);
+        driver.receive(&mut p3, &everyone).await;
+        driver.receive(&mut p2, &everyone).await;
+
+        // player1 and player2 never get more than two in a row, player3 gets five
+        for x in 0..5 {
+            let spread = [0, 1, 5, 6, 10][x];
+            driver
+                .send(&mut p1, &format!(r#"{{"move":{{"x":{spread},"y":0}}}}"#))
+                .await;
+            if x == 0 {
+                driver.receive(&mut p1, &everyone).await;
+            }
+            driver.receive_anything(&mut p2).await;
+            driver
+                .send(&mut p2, &format!(r#"{{"move":{{"x":{spread},"y":9}}}}"#))
+                .await;
+            driver.receive_anything(&mut p3).await;
+            driver
+                .send(&mut p3, &format!(r#"{{"move":{{"x":{x},"y":5}}}}"#))
+                .await;
+            if x < 4 {
+                driver.receive_anything(&mut p1).await;
+            }
+        }
+
+        for user in [&mut p1, &mut p2, &mut p3] {
+            driver
+                .receive(user, r#"{"game-over":{"reason":"winner player3"}}"#)
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn win_twice() {
+        env_logger::init();
+        init_flow_test_spawn!(
+            driver,
+            TestSetup {
+                solo: true,
+                ..TestSetup::new(&["--game", "gomoku"])
+            }
+            .entry()
+        );
+
+        let mut user = driver.connect_user("zeldo").await;
+        driver.send(&mut user, &login_msg("zeldo", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::SoloGomoku))
+            .await;
+        driver.receive(&mut user, &player_list(&["zeldo"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::SoloGomoku, &["zeldo"]))
+            .await;
+
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"move":{"x":0,"y":0}}"#).await;
+
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"move":{"x":1,"y":0}}"#).await;
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"move":{"x":2,"y":0}}"#).await;
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"move":{"x":3,"y":0}}"#).await;
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"move":{"x":4,"y":0}}"#).await;
+
+        driver
+            .receive(&mut user, r#"{"game-over":{"reason":"winner zeldo"}}"#)
+            .await;
+        driver
+            .receive(&mut user, r#"{"score":[{"player":"zeldo","wins":1}]}"#)
+            .await;
+
+        driver
+            .receive(&mut user, &game_started(TestGame::SoloGomoku, &["zeldo"]))
+            .await;
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"move":{"x":0,"y":0}}"#).await;
+
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"move":{"x":1,"y":0}}"#).await;
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"move":{"x":2,"y":0}}"#).await;
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"move":{"x":3,"y":0}}"#).await;
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"move":{"x":4,"y":0}}"#).await;
+
+        driver
+            .receive(&mut user, r#"{"game-over":{"reason":"winner zeldo"}}"#)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn event_log_has_the_whole_game() {
+        let _ = std::fs::remove_file(test_event_log_path());
+        let mut setup = TestSetup {
+            solo: true,
+            ..TestSetup::new(&["--game", "gomoku"])
+        };
+        setup.args.event_log = Some(test_event_log_path());
+        init_flow_test_spawn!(driver, setup.entry());
+
+        let mut user = driver.connect_user("zeldo").await;
+        driver.send(&mut user, &login_msg("zeldo", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::SoloGomoku))
+            .await;
+        driver.receive(&mut user, &player_list(&["zeldo"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::SoloGomoku, &["zeldo"]))
+            .await;
+        for x in 0..5 {
+            driver.receive_anything(&mut user).await;
+            driver
+                .send(&mut user, &format!(r#"{{"move":{{"x":{x},"y":0}}}}"#))
+                .await;
+        }
+        driver
+            .receive(&mut user, r#"{"game-over":{"reason":"winner zeldo"}}"#)
+            .await;
+
+        let events: Vec<serde_json::Value> = std::fs::read_to_string(test_event_log_path())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let kinds: Vec<&str> = events
+            .iter()
+            .map(|e| e["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "player-connected",
+                "move",
+                "move",
+                "move",
+                "move",
+                "move",
+                "game-over"
+            ]
+        );
+        assert!(events.iter().all(|e| e["player"] == "zeldo"));
+        assert_eq!(events[6]["data"], "winner zeldo");
+        assert!(events[0]["ts"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[tokio::test]
+    async fn players_are_spread_over_the_games() {
+        init_flow_test_spawn!(driver, test_entry(&["--num-games", "2"]));
+
+        let mut p1 = driver.connect_user("p1").await;
+        driver.send(&mut p1, &login_msg("p1", "pass")).await;
+        driver.receive(&mut p1, &server_info(TestGame::Dumb)).await;
+        driver.receive(&mut p1, &player_list(&["p1"])).await;
+        driver
+            .receive(&mut p1, &game_started(TestGame::Dumb, &["p1"]))
+            .await;
+        driver.receive(&mut p1, JSON_BASIC_STATE).await;
+
+        let mut p2 = driver.connect_user("p2").await;
+        driver.send(&mut p2, &login_msg("p2", "pass")).await;
+        driver.receive(&mut p2, &server_info(TestGame::Dumb)).await;
+        driver.receive(&mut p2, &player_list(&["p2"])).await;
+        driver
+            .receive(&mut p2, &game_started(TestGame::Dumb, &["p2"]))
+            .await;
+        driver.receive(&mut p2, JSON_BASIC_STATE).await;
+
+        let mut p3 = driver.connect_user("p3").await;
+        driver.send(&mut p3, &login_msg("p3", "pass")).await;
+        driver.receive(&mut p3, &server_info(TestGame::Dumb)).await;
+        driver.receive(&mut p3, &player_list(&["p1", "p3"])).await;
+
+        let mut p4 = driver.connect_user("p4").await;
+        driver.send(&mut p4, &login_msg("p4", "pass")).await;
+        driver.receive(&mut p4, &server_info(TestGame::Dumb)).await;
+        driver.receive(&mut p4, &player_list(&["p2", "p4"])).await;
+
+        // Each game counts on its own
+        driver.send(&mut p1, r#"{"move":{"add":5}}"#).await;
+        driver.receive(&mut p1, &player_list(&["p1", "p3"])).await;
+        driver.receive(&mut p3, r#"{"your-turn":{"num":5}}"#).await;
+
+        driver.send(&mut p2, r#"{"move":{"add":7}}"#).await;
+        driver.receive(&mut p2, &player_list(&["p2", "p4"])).await;
+        driver.receive(&mut p4, r#"{"your-turn":{"num":7}}"#).await;
+    }
+
+    #[tokio::test]
+    async fn resign_alone() {
+        init_flow_test_spawn!(
+            driver,
+            TestSetup {
+                solo: true,
+                ..TestSetup::new(&["--game", "gomoku"])
+            }
+            .entry()
+        );
+
+        let mut user = driver.connect_user("zeldo").await;
+        driver.send(&mut user, &login_msg("zeldo", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::SoloGomoku))
+            .await;
+        driver.receive(&mut user, &player_list(&["zeldo"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::SoloGomoku, &["zeldo"]))
+            .await;
+
+        driver.receive_anything(&mut user).await;
+        driver.send(&mut user, r#"{"resign":null}"#).await;
+        driver
+            .receive(&mut user, r#"{"game-over":{"reason":"resigned"}}"#)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn resign_mid_turn() {
+        init_flow_test_spawn!(
+            driver,
+            TestSetup {
+                solo: true,
+                ..TestSetup::new(&["--game", "gomoku"])
+            }
+            .entry()
+        );
+
+        let mut p1 = driver.connect_user("player1").await;
+        let mut p2 = driver.connect_user("player2").await;
+        driver.send(&mut p1, &login_msg("player1", "pass")).await;
+        driver
+            .receive(&mut p1, &server_info(TestGame::SoloGomoku))
+            .await;
+        driver.receive(&mut p1, &player_list(&["player1"])).await;
+        driver
+            .receive(&mut p1, &game_started(TestGame::SoloGomoku, &["player1"]))
+            .await;
+        driver.receive_anything(&mut p1).await;
+
+        driver.send(&mut p2, &login_msg("player2", "pass")).await;
+        driver
+            .receive(&mut p2, &server_info(TestGame::SoloGomoku))
+            .await;
+        driver
+            .receive(&mut p2, &player_list(&["player1", "player2"]))
+            .await;
+
+        driver.send(&mut p1, r#"{"move":{"x":5,"y":5}}"#).await;
+        driver
+            .receive(&mut p1, &player_list(&["player1", "player2"]))
+            .await;
+
+        driver.receive_anything(&mut p2).await;
+        driver.send(&mut p2, r#"{"resign":null}"#).await;
+        driver
+            .receive(&mut p2, r#"{"game-over":{"reason":"resigned"}}"#)
+            .await;
+
+        driver
+            .receive(&mut p1, r#"{"game-over":{"reason":"opponent resigned"}}"#)
+            .await;
+        // Resigning isn't a win for anyone
+        driver.receive(&mut p1, r#"{"score":[]}"#).await;
+
+        // A new round starts with the player that's left
+        driver
+            .receive(&mut p1, &game_started(TestGame::SoloGomoku, &["player1"]))
+            .await;
+        driver.receive_anything(&mut p1).await;
+        driver.send(&mut p1, r#"{"move":{"x":5,"y":5}}"#).await;
+        driver.receive(&mut p1, &player_list(&["player1"])).await;
+        driver.receive_anything(&mut p1).await;
+    }
+
+    #[tokio::test]
+    async fn shutdown_tells_everyone() {
+        let (tx, rx) = get_test_channel();
+        let fake_listener = network_wrap::get_fake_listener(rx);
+        let mut driver = network_wrap::TestDriver::new(tx);
+        let (controller_tx, controller_rx) = mpsc::channel::<ControllerMsg>(1024);
+        let controller_tx2 = controller_tx.clone();
+        let server = std::thread::spawn(|| {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async move {
+                entry(
+                    fake_listener,
+                    vec![test_slot_on((controller_tx2, controller_rx), dumb_game())],
+                    Metrics::new(),
+                    test_hasher(),
+                    TEST_MAX_CONNECTIONS,
+                    test_rate_limiter(),
+                    user_connection::AUTH_TIMEOUT,
+                )
+                .await;
+            })
+        });
+
+        // Keep the game from starting, so nobody is busy waiting for a move
+        controller_tx
+            .send(ControllerMsg::GoToMode(controller::GameMode::Gating))
+            .await
+            .unwrap();
+
+        let mut user = driver.connect_user("user").await;
+        driver.send(&mut user, &login_msg("user", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
+        let mut user2 = driver.connect_user("user2").await;
+        driver.send(&mut user2, &login_msg("user2", "pass")).await;
+        driver
+            .receive(&mut user2, &server_info(TestGame::Dumb))
+            .await;
+        // Let the logins reach the controller
+        sleep_a_bit();
+        driver.receive(&mut user, &player_list(&["user"])).await;
+        driver
+            .receive(&mut user, &player_list(&["user", "user2"]))
+            .await;
+        driver
+            .receive(&mut user2, &player_list(&["user", "user2"]))
+            .await;
+
+        controller_tx.send(ControllerMsg::Shutdown).await.unwrap();
+
+        driver
+            .receive(
+                &mut user,
+                r#"{"game-over":{"reason":"server shutting down"}}"#,
+            )
+            .await;
+        driver
+            .receive(
+                &mut user2,
+                r#"{"game-over":{"reason":"server shutting down"}}"#,
+            )
+            .await;
+        for user in [&mut user, &mut user2] {
+            driver.receive_error(user, 1011).await;
+            driver.expect_disconnect(user).await;
+        }
+
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn kicked_player_is_told_why() {
+        let (tx, rx) = get_test_channel();
+        let fake_listener = network_wrap::get_fake_listener(rx);
+        let mut driver = network_wrap::TestDriver::new(tx);
+        let (controller_tx, controller_rx) = mpsc::channel::<ControllerMsg>(1024);
+        let controller_tx2 = controller_tx.clone();
+        std::thread::spawn(|| {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async move {
+                entry(
+                    fake_listener,
+                    vec![test_slot_on((controller_tx2, controller_rx), dumb_game())],
+                    Metrics::new(),
+                    test_hasher(),
+                    TEST_MAX_CONNECTIONS,
+                    test_rate_limiter(),
+                    user_connection::AUTH_TIMEOUT,
+                )
+                .await;
+            })
+        });
+
+        let mut user = driver.connect_user("zeldo").await;
+        driver.send(&mut user, &login_msg("zeldo", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
+        driver.receive(&mut user, &player_list(&["zeldo"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::Dumb, &["zeldo"]))
+            .await;
+        driver.receive(&mut user, JSON_BASIC_STATE).await;
+
+        controller_tx
+            .send(ControllerMsg::Kick("zeldo".to_string()))
+            .await
+            .unwrap();
+        driver.receive_error(&mut user, 1021).await;
+        driver.expect_disconnect(&mut user).await;
+    }
+
+    #[tokio::test]
+    async fn slow_client_runs_out_of_time() {
+        let (tx, rx) = get_test_channel();
+        // Every line from the client takes longer to arrive than a turn lasts
+        let fake_listener = network_wrap::get_fake_listener_with_config(
+            rx,
+            network_wrap::FakeStreamConfig {
+                send_delay: None,
+                recv_delay: Some(Duration::from_millis(600)),
+            },
+        );
+        let mut driver = network_wrap::TestDriver::new(tx);
+        let (controller_tx, controller_rx) = mpsc::channel::<ControllerMsg>(1024);
+        let controller_tx2 = controller_tx.clone();
+        std::thread::spawn(|| {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async move {
+                entry(
+                    fake_listener,
+                    vec![test_slot_on((controller_tx2, controller_rx), dumb_game())],
+                    Metrics::new(),
+                    test_hasher(),
+                    TEST_MAX_CONNECTIONS,
+                    test_rate_limiter(),
+                    user_connection::AUTH_TIMEOUT,
+                )
+                .await;
+            })
+        });
+        controller_tx
+            .send(ControllerMsg::SetTurnTimeLimit(Some(
+                Duration::from_millis(200),
+            )))
+            .await
+            .unwrap();
+
+        let mut user = driver.connect_user("zeldo").await;
+        driver.send(&mut user, &login_msg("zeldo", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
+        driver.receive(&mut user, &player_list(&["zeldo"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::Dumb, &["zeldo"]))
+            .await;
+        driver.receive(&mut user, JSON_BASIC_STATE).await;
+
+        // Sent in time, but still on its way when the turn is up
+        driver.send(&mut user, r#"{"move":{"add": 5}}"#).await;
+        driver.receive_error(&mut user, 1020).await;
+        driver.expect_disconnect(&mut user).await;
+    }
+
+    #[tokio::test]
+    async fn server_full() {
+        init_flow_test_spawn!(driver, test_entry(&["--max-connections", "2"]));
+
+        let user1 = driver.connect_user("user1").await;
+        let _user2 = driver.connect_user("user2").await;
+
+        let mut user3 = driver.connect_user("user3").await;
+        driver.receive_error(&mut user3, 1005).await;
+        driver.expect_disconnect(&mut user3).await;
+
+        // A spot opens up when someone leaves
+        drop(user1);
+        sleep_a_bit();
+
+        let mut user4 = driver.connect_user("user4").await;
+        driver.send(&mut user4, &login_msg("user4", "pass")).await;
+        driver
+            .receive(&mut user4, &server_info(TestGame::Dumb))
+            .await;
+        driver.receive(&mut user4, &player_list(&["user4"])).await;
+        driver
+            .receive(&mut user4, &game_started(TestGame::Dumb, &["user4"]))
+            .await;
+        driver.receive(&mut user4, JSON_BASIC_STATE).await;
+    }
+
+    #[tokio::test]
+    async fn rate_limited_after_failed_logins() {
+        init_flow_test_spawn!(
+            driver,
+            TestSetup {
+                max_failed_logins: 2,
+                ..TestSetup::new(&[])
+            }
+            .entry()
+        );
+
+        let mut user = driver.connect_user("user").await;
+        driver.send(&mut user, &login_msg("user", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
+        driver.receive(&mut user, &player_list(&["user"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::Dumb, &["user"]))
+            .await;
+        driver.receive(&mut user, JSON_BASIC_STATE).await;
+
+        // All fake connections come from the same address
+        for i in 0..2 {
+            let mut attacker = driver.connect_user(&format!("attacker{i}")).await;
+            driver
+                .send(&mut attacker, &login_msg("user", "wrong pass"))
+                .await;
+            driver.receive_error(&mut attacker, 1002).await;
+        }
+
+        let mut attacker = driver.connect_user("attacker2").await;
+        driver.receive_error(&mut attacker, 1004).await;
+    }
+
+    #[tokio::test]
+    async fn spectator_follows_game() {
+        init_flow_test_spawn!(driver, test_entry(&[]));
+
+        let mut spectator = driver.connect_user("spectator").await;
+        driver
+            .send(
+                &mut spectator,
+                r#"{"auth":{"username":"spectator","password":"pass","spectate":true}}"#,
+            )
+            .await;
+        driver
+            .receive(&mut spectator, &server_info(TestGame::Dumb))
+            .await;
+        // Nobody is playing yet
+        driver.receive(&mut spectator, &player_list(&[])).await;
+
+        let mut user = driver.connect_user("zeldo").await;
+        driver.send(&mut user, &login_msg("zeldo", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
+        driver.receive(&mut user, &player_list(&["zeldo"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::Dumb, &["zeldo"]))
+            .await;
+        driver.receive(&mut user, JSON_BASIC_STATE).await;
+        driver
+            .receive(&mut spectator, &player_list(&["zeldo"]))
+            .await;
+        driver
+            .receive(&mut spectator, &game_started(TestGame::Dumb, &["zeldo"]))
+            .await;
+        driver.receive(&mut spectator, JSON_BASIC_STATE).await;
+
+        driver.send(&mut user, r#"{"move":{"add": 5}}"#).await;
+        driver
+            .receive(&mut user, r#"{"your-turn":{"num":5}}"#)
+            .await;
+        driver
+            .receive(&mut spectator, r#"{"your-turn":{"num":5}}"#)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn chat_reaches_the_other_player() {
+        init_flow_test_spawn!(driver, test_entry(&[]));
+
+        let mut user = driver.connect_user("zeldo").await;
+        driver.send(&mut user, &login_msg("zeldo", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
+        driver.receive(&mut user, &player_list(&["zeldo"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::Dumb, &["zeldo"]))
+            .await;
+        driver.receive(&mut user, JSON_BASIC_STATE).await;
+
+        let mut user2 = driver.connect_user("user2").await;
+        driver.send(&mut user2, &login_msg("user2", "pass")).await;
+        driver
+            .receive(&mut user2, &server_info(TestGame::Dumb))
+            .await;
+        driver
+            .receive(&mut user2, &player_list(&["zeldo", "user2"]))
+            .await;
+
+        let chat = r#"{"chat":{"from":"zeldo","message":"good luck"}}"#;
+        driver
+            .send(&mut user, r#"{"chat":{"message":"good luck"}}"#)
+            .await;
+        driver.receive(&mut user2, chat).await;
+
+        // Still zeldo's turn
+        driver.send(&mut user, r#"{"move":{"add":1}}"#).await;
+        driver
+            .receive(&mut user, &player_list(&["zeldo", "user2"]))
+            .await;
+        driver.receive(&mut user, chat).await;
+        driver
+            .receive(&mut user2, r#"{"your-turn":{"num":1}}"#)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn draw_by_agreement() {
+        init_flow_test_spawn!(
+            driver,
+            TestSetup {
+                solo: true,
+                ..TestSetup::new(&["--game", "gomoku"])
+            }
+            .entry()
+        );
+
+        let mut p1 = driver.connect_user("player1").await;
+        let mut p2 = driver.connect_user("player2").await;
+        driver.send(&mut p1, &login_msg("player1", "pass")).await;
+        driver
+            .receive(&mut p1, &server_info(TestGame::SoloGomoku))
+            .await;
+        driver.receive(&mut p1, &player_list(&["player1"])).await;
+        driver
+            .receive(&mut p1, &game_started(TestGame::SoloGomoku, &["player1"]))
+            .await;
+        driver.receive_anything(&mut p1).await;
+
+        driver.send(&mut p2, &login_msg("player2", "pass")).await;
+        driver
+            .receive(&mut p2, &server_info(TestGame::SoloGomoku))
+            .await;
+        driver
+            .receive(&mut p2, &player_list(&["player1", "player2"]))
+            .await;
+
+        driver.send(&mut p1, r#"{"offer-draw":null}"#).await;
+        driver
+            .receive(&mut p2, r#"{"draw-offer":{"from":"player1"}}"#)
+            .await;
+
+        // Offering doesn't use up the turn
+        driver.send(&mut p1, r#"{"move":{"x":5,"y":5}}"#).await;
+        driver
+            .receive(&mut p1, &player_list(&["player1", "player2"]))
+            .await;
+
+        driver.receive_anything(&mut p2).await;
+        driver.send(&mut p2, r#"{"accept-draw":null}"#).await;
+        driver
+            .receive(&mut p1, r#"{"game-over":{"reason":"draw"}}"#)
+            .await;
+        driver.receive(&mut p1, r#"{"score":[]}"#).await;
+        driver
+            .receive(
+                &mut p1,
+                &game_started(TestGame::SoloGomoku, &["player1", "player2"]),
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn two_players_passive_drops() {
+        init_flow_test_spawn!(driver, test_entry(&[]));
+
+        let mut user = driver.connect_user("user").await;
+
+        driver.send(&mut user, &login_msg("user", "pass")).await;
+        driver
+            .receive(&mut user, &server_info(TestGame::Dumb))
+            .await;
+        driver.receive(&mut user, &player_list(&["user"])).await;
+        driver
+            .receive(&mut user, &game_started(TestGame::Dumb, &["user"]))
+            .await;
+        driver
+            .receive(&mut user, r#"{"your-turn":{"num":0}}"#)
+            .await;
+
+        let mut user2 = driver.connect_user("zumba").await;
+
+        driver.send(&mut user2, &login_msg("zumba", "pass")).await;
+        drop(user2);
+
+        driver.send(&mut user, r#"{"move":{"add":1}}"#).await;
+        driver
+            .receive(&mut user, &player_list(&["user", "zumba"]))
+            .await;
+        driver
+            .receive(&mut user, r#"{"your-turn":{"num":1}}"#)
+            .await;
+
+        driver.send(&mut user, r#"{"move":{"add":1}}"#).await;
+        driver.receive(&mut user, &player_list(&["user"])).await;
+        driver
+            .receive(&mut user, r#"{"your-turn":{"num":2}}"#)
+            .await;
+        driver.send(&mut user, r#"{"move":{"add":1}}"#).await;
+        driver
+            .receive(&mut user, r#"{"your-turn":{"num":3}}"#)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn websocket_flow() {
+        let listener = network_wrap::bind_ws("127.0.0.1:7655").await.unwrap();
+        tokio::spawn(test_entry_with(listener, TestSetup::new(&[])));
+
+        let (mut ws, _) = tokio_tungstenite::connect_async("ws://127.0.0.1:7655")
+            .await
+            .unwrap();
+
+        ws.send(Message::Text(login_msg("zeldo", "pass")))
+            .await
+            .unwrap();
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::Text(server_info(TestGame::Dumb))
+        );
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::Text(player_list(&["zeldo"]))
+        );
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::Text(game_started(TestGame::Dumb, &["zeldo"]))
+        );
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::Text(JSON_BASIC_STATE.to_string())
+        );
+
+        ws.send(Message::Text(r#"{"move":{"add": 5}}"#.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::Text(r#"{"your-turn":{"num":5}}"#.to_string())
+        );
+
+        ws.send(Message::Text(r#"{"sub": 5}"#.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::Text(
+                r#"{"error":{"code":1001,"reason":"invalid message format"}}"#.to_string()
+            )
+        );
+    }
+
+    async fn tcp_client(
+        addr: &str,
+        first_bytes: &[u8],
+    ) -> tokio::io::BufStream<tokio::net::TcpStream> {
+        use tokio::io::AsyncWriteExt;
+
+        let mut stream =
+            tokio::io::BufStream::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+        stream.write_all(first_bytes).await.unwrap();
+        stream
+    }
+
+    async fn send_with(
+        stream: &mut tokio::io::BufStream<tokio::net::TcpStream>,
+        codec: network_wrap::Codec,
+        msg: &str,
+    ) {
+        use tokio::io::AsyncWriteExt;
+
+        let msg: serde_json::Value = serde_json::from_str(msg).unwrap();
+        stream
+            .write_all(&codec.encode(&msg).unwrap())
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+    }
+
+    async fn receive_with(
+        stream: &mut tokio::io::BufStream<tokio::net::TcpStream>,
+        codec: network_wrap::Codec,
+    ) -> serde_json::Value {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+        let body = match codec {
+            network_wrap::Codec::Json => {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                line.into_bytes()
+            }
+            network_wrap::Codec::MsgPack => {
+                let len = stream.read_u32().await.unwrap();
+                let mut body = vec![0; len as usize];
+                stream.read_exact(&mut body).await.unwrap();
+                body
+            }
+        };
+        codec.decode(&body).unwrap()
+    }
+
+    async fn codec_flow(addr: &str, codec: network_wrap::Codec, first_bytes: &[u8]) {
+        let listener = network_wrap::bind(addr).await.unwrap();
+        tokio::spawn(test_entry_with(listener, TestSetup::new(&[])));
+
+        let mut stream = tcp_client(addr, first_bytes).await;
+        send_with(&mut stream, codec, &login_msg("zeldo", "pass")).await;
+        assert_eq!(
+            receive_with(&mut stream, codec).await,
+            serde_json::from_str::<serde_json::Value>(&server_info(TestGame::Dumb)).unwrap()
+        );
+        assert_eq!(
+            receive_with(&mut stream, codec).await,
+            serde_json::json!({"player-list": ["zeldo"]})
+        );
+        assert_eq!(
+            receive_with(&mut stream, codec).await,
+            serde_json::from_str::<serde_json::Value>(&game_started(TestGame::Dumb, &["zeldo"]))
+                .unwrap()
+        );
+        assert_eq!(
+            receive_with(&mut stream, codec).await,
+            serde_json::from_str::<serde_json::Value>(JSON_BASIC_STATE).unwrap()
+        );
+
+        send_with(&mut stream, codec, r#"{"move":{"add": 5}}"#).await;
+        assert_eq!(
+            receive_with(&mut stream, codec).await,
+            serde_json::json!({"your-turn": {"num": 5}})
+        );
+    }
+
+    #[tokio::test]
+    async fn json_flow_over_tcp() {
+        codec_flow("127.0.0.1:7657", network_wrap::Codec::Json, &[]).await;
+    }
+
+    #[tokio::test]
+    async fn msgpack_flow_over_tcp() {
+        codec_flow(
+            "127.0.0.1:7658",
+            network_wrap::Codec::MsgPack,
+            &[network_wrap::MSGPACK_MAGIC],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn oversized_msgpack_frame_gets_an_error() {
+        use tokio::io::AsyncWriteExt;
+
+        let codec = network_wrap::Codec::MsgPack;
+        let listener = network_wrap::bind("127.0.0.1:7659").await.unwrap();
+        tokio::spawn(test_entry_with(listener, TestSetup::new(&[])));
+
+        let mut stream = tcp_client("127.0.0.1:7659", &[network_wrap::MSGPACK_MAGIC]).await;
+        send_with(&mut stream, codec, &login_msg("zeldo", "pass")).await;
+        for _ in 0..4 {
+            // Server info, player list, game started and the state
+            receive_with(&mut stream, codec).await;
+        }
+
+        // Only the length of a frame far over the limit, the body never comes
+        stream.write_u32(1 << 30).await.unwrap();
+        stream.flush().await.unwrap();
+        assert_eq!(
+            receive_with(&mut stream, codec).await["error"]["code"],
+            1006
+        );
+    }
+
+    #[tokio::test]
+    async fn unix_socket_flow() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufStream};
+
+        let path = std::env::temp_dir().join("coding-challenge-test-flow.sock");
+        let path = path.to_str().unwrap();
+        let listener = network_wrap::bind_unix(path).await.unwrap();
+        tokio::spawn(test_entry_with(listener, TestSetup::new(&[])));
+
+        let mut stream = BufStream::new(tokio::net::UnixStream::connect(path).await.unwrap());
+
+        let mut line = String::new();
+        stream
+            .write_all(format!("{}\n", login_msg("zeldo", "pass")).as_bytes())
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        stream.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), server_info(TestGame::Dumb));
+
+        line.clear();
+        stream.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), player_list(&["zeldo"]));
+
+        line.clear();
+        stream.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), game_started(TestGame::Dumb, &["zeldo"]));
+
+        line.clear();
+        stream.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), JSON_BASIC_STATE);
+
+        line.clear();
+        stream
+            .write_all(concat!(r#"{"move":{"add": 5}}"#, "\n").as_bytes())
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        stream.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), r#"{"your-turn":{"num":5}}"#);
+    }
+
+    #[tokio::test]
+    async fn unix_socket_file_is_cleaned_up() {
+        let path = std::env::temp_dir().join("coding-challenge-test-cleanup.sock");
+        // A stale file from an earlier run must not stop us from binding
+        std::fs::write(&path, "").unwrap();
+
+        let listener = network_wrap::bind_unix(path.to_str().unwrap())
+            .await
+            .unwrap();
+        assert!(path.exists());
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn tls_flow() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufStream};
+        use tokio_rustls::rustls;
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = std::env::temp_dir();
+        let cert_path = dir.join("coding-challenge-test-cert.pem");
+        let key_path = dir.join("coding-challenge-test-key.pem");
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+        let listener = network_wrap::bind_tls(
+            "127.0.0.1:7656",
+            cert_path.to_str().unwrap(),
+            key_path.to_str().unwrap(),
+        )
+        .await
+        .unwrap();
+        tokio::spawn(test_entry_with(listener, TestSetup::new(&[])));
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(cert.serialize_der().unwrap()))
+            .unwrap();
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(config));
+        let tcp = tokio::net::TcpStream::connect("127.0.0.1:7656")
+            .await
+            .unwrap();
+        let tls = connector
+            .connect(rustls::ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .unwrap();
+        let mut stream = BufStream::new(tls);
+
+        let mut line = String::new();
+        stream
+            .write_all(format!("{}\n", login_msg("zeldo", "pass")).as_bytes())
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        stream.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), server_info(TestGame::Dumb));
+
+        line.clear();
+        stream.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), player_list(&["zeldo"]));
+
+        line.clear();
+        stream.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), game_started(TestGame::Dumb, &["zeldo"]));
+
+        line.clear();
+        stream.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), JSON_BASIC_STATE);
+
+        line.clear();
+        stream
+            .write_all(concat!(r#"{"move":{"add": 5}}"#, "\n").as_bytes())
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        stream.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), r#"{"your-turn":{"num":5}}"#);
+    }
+
+    #[allow(dead_code)]
+    fn sleep_a_bit() {
+        std::thread::sleep(std::time::Duration::from_millis(400));
+    }
+
+    #[ignore]
+    #[tokio::test]
+    async fn test_two_player_flow_with_ui() {
+        init_flow_test_spawn!(
+            driver,
+            TestSetup {
+                solo: true,
+                with_ui: true,
+                ..TestSetup::new(&["--game", "gomoku"])
+            }
+            .entry()
+        );
+
+        let mut user = driver.connect_user("zeldo").await;
+        driver.send(&mut user, &login_msg("zeldo", "kermit")).await;
+        let mut user2 = driver.connect_user("user2").await;
+        driver.send(&mut user2, &login_msg("user2", "hello")).await;
+        // Server infos, player lists and the game start
+        driver.receive_anything(&mut user).await;
+        driver.receive_anything(&mut user).await;
+        driver.receive_anything(&mut user).await;
+        driver.receive_anything(&mut user2).await;
+        driver.receive_anything(&mut user2).await;
 
         driver.receive_anything(&mut user).await;
         driver.send(&mut user, r#"{"move":{"x": 5,"y":7}}"#).await;
@@ -379,6 +2358,8 @@ mod test {
         driver.send(&mut user2, r#"{"move":{"x": 1,"y":7}}"#).await;
         sleep_a_bit();
 
+        // The player list that came while zeldo was thinking
+        driver.receive_anything(&mut user).await;
         driver.receive_anything(&mut user).await;
         driver.send(&mut user, r#"{"move":{"x": 6,"y":8}}"#).await;
         sleep_a_bit();
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use code_challenge_game_types::gametraits::PlayerMoveResult;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+// Seconds, from "your turn" being sent until the move arrives
+const TURN_LATENCY_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Prometheus metrics kept by the controller, served on `/metrics` next to the status
+#[derive(Clone)]
+pub struct Metrics {
+    // Our own registry, so several servers (tests) in one process don't step on each other
+    registry: Registry,
+    pub active_connections: IntGauge,
+    pub moves_total: IntCounter,
+    pub games_completed: IntCounter,
+    pub invalid_moves_total: IntCounter,
+    pub turn_latency_seconds: Histogram,
+    // Stops going up if the controller is stuck
+    pub watchdog_ticks: IntCounter,
+    // Labeled by player, only the ones connected right now
+    player_moves: IntGaugeVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let active_connections =
+            IntGauge::new("active_connections", "Connected players and spectators").unwrap();
+        let moves_total = IntCounter::new("moves_total", "Moves made, valid or not").unwrap();
+        let games_completed =
+            IntCounter::new("games_completed", "Games that ended with a result").unwrap();
+        let invalid_moves_total = IntCounter::new(
+            "invalid_moves_total",
+            "Moves the game refused, invalid or badly formatted",
+        )
+        .unwrap();
+        let turn_latency_seconds = Histogram::with_opts(
+            HistogramOpts::new("turn_latency_seconds", "Time players take to answer")
+                .buckets(TURN_LATENCY_BUCKETS.to_vec()),
+        )
+        .unwrap();
+        let watchdog_ticks = IntCounter::new(
+            "controller_watchdog_ticks_total",
+            "Times the controller loop checked in, once a minute",
+        )
+        .unwrap();
+        let player_moves = IntGaugeVec::new(
+            Opts::new(
+                "player_moves",
+                "Moves made by a player since they connected",
+            ),
+            &["player"],
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(active_connections.clone()))
+            .unwrap();
+        registry.register(Box::new(moves_total.clone())).unwrap();
+        registry
+            .register(Box::new(games_completed.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(invalid_moves_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(turn_latency_seconds.clone()))
+            .unwrap();
+        registry.register(Box::new(watchdog_ticks.clone())).unwrap();
+        registry.register(Box::new(player_moves.clone())).unwrap();
+
+        Self {
+            registry,
+            active_connections,
+            moves_total,
+            games_completed,
+            invalid_moves_total,
+            turn_latency_seconds,
+            watchdog_ticks,
+            player_moves,
+        }
+    }
+
+    pub fn record_move(&self, result: &PlayerMoveResult, latency: Duration) {
+        self.moves_total.inc();
+        self.turn_latency_seconds.observe(latency.as_secs_f64());
+        match result {
+            PlayerMoveResult::Ok(_) => (),
+            PlayerMoveResult::Win | PlayerMoveResult::Draw => self.games_completed.inc(),
+            PlayerMoveResult::InvalidMove(_) | PlayerMoveResult::InvalidFormat(_) => {
+                self.invalid_moves_total.inc()
+            }
+        }
+    }
+
+    pub fn set_player_moves(&self, player: &str, moves: u64) {
+        self.player_moves
+            .with_label_values(&[player])
+            .set(moves as i64);
+    }
+
+    // Gone from the scrape once they leave, instead of showing their last count forever
+    pub fn forget_player(&self, player: &str) {
+        // Only an error if they were never counted, then there's nothing to forget
+        let _ = self.player_moves.remove_label_values(&[player]);
+    }
+
+    /// Everything in the Prometheus text format
+    pub fn gather(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
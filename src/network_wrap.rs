@@ -1,20 +1,194 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
 use async_trait::async_trait;
-use futures::TryFutureExt;
+use futures::{SinkExt, StreamExt};
 use log::debug;
 use log::warn;
 use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::io::BufStream;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
 
 pub type SStream = Box<dyn Stream>;
 
+/// How long a TCP client has to send a whole line once it's asked for one, thinking included
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the TCP listener is set up
+#[derive(Clone, Copy, Debug)]
+pub struct SocketOptions {
+    // Binds right away after a restart, instead of waiting out the old connections' TIME_WAIT
+    pub reuse_address: bool,
+    // Only on linux
+    pub reuse_port: bool,
+    pub read_timeout: Duration,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            reuse_address: true,
+            reuse_port: true,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+        }
+    }
+}
+
 pub async fn bind(addr: &str) -> Result<impl Listener, std::io::Error> {
-    TcpListener::bind(addr)
-        .map_ok(|inner| RealListener { inner })
-        .await
+    bind_with_options(addr, SocketOptions::default()).await
+}
+
+pub async fn bind_with_options(
+    addr: &str,
+    opts: SocketOptions,
+) -> Result<RealListener, std::io::Error> {
+    let addr = tokio::net::lookup_host(addr).await?.next().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("No address to bind for {addr}"),
+        )
+    })?;
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+    socket.set_reuse_address(opts.reuse_address)?;
+    #[cfg(target_os = "linux")]
+    socket.set_reuse_port(opts.reuse_port)?;
+    // Tokio wants it non-blocking
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(RealListener {
+        inner: TcpListener::from_std(socket.into())?,
+        read_timeout: opts.read_timeout,
+    })
+}
+
+pub async fn bind_ws(addr: &str) -> Result<impl Listener, Error> {
+    let inner = TcpListener::bind(addr).await?;
+    let (tx, rx) = mpsc::channel::<(WebSocketStream, SocketAddr)>(16);
+    // Handshakes are done in their own tasks, so a slow client can't hold up everyone else
+    tokio::spawn(async move {
+        loop {
+            let (tcp_stream, addr) = match inner.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Failed accepting websocket connection: {e}");
+                    continue;
+                }
+            };
+            if tx.is_closed() {
+                break;
+            }
+            let tx2 = tx.clone();
+            tokio::spawn(async move {
+                match tokio_tungstenite::accept_async(tcp_stream).await {
+                    Ok(inner) => {
+                        let _ = tx2.send((WebSocketStream { inner }, addr)).await;
+                    }
+                    Err(e) => debug!("Websocket handshake failed: {e}"),
+                }
+            });
+        }
+    });
+    Ok(WebSocketListener { rx })
+}
+
+/// Any stale socket file at `path` is removed first, and the file is removed again when the
+/// listener is dropped
+pub async fn bind_unix(path: &str) -> Result<impl Listener, std::io::Error> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let inner = tokio::net::UnixListener::bind(path)?;
+    Ok(UnixListener {
+        inner,
+        path: path.into(),
+    })
+}
+
+pub async fn bind_tls(
+    addr: &str,
+    cert_path: &str,
+    key_path: &str,
+) -> Result<impl Listener, std::io::Error> {
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(load_tls_config(cert_path, key_path)?));
+    let inner = TcpListener::bind(addr).await?;
+    let (tx, rx) = mpsc::channel::<(TlsStream, SocketAddr)>(16);
+    // Same as for websockets, handshakes get their own tasks
+    tokio::spawn(async move {
+        loop {
+            let (tcp_stream, addr) = match inner.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Failed accepting TLS connection: {e}");
+                    continue;
+                }
+            };
+            if tx.is_closed() {
+                break;
+            }
+            let tx2 = tx.clone();
+            let acceptor2 = acceptor.clone();
+            tokio::spawn(async move {
+                match acceptor2.accept(tcp_stream).await {
+                    Ok(tls_stream) => {
+                        let _ = tx2
+                            .send((
+                                TlsStream {
+                                    inner: BufStream::new(tls_stream),
+                                    codec: None,
+                                },
+                                addr,
+                            ))
+                            .await;
+                    }
+                    Err(e) => debug!("TLS handshake failed: {e}"),
+                }
+            });
+        }
+    });
+    Ok(TlsListener { rx })
+}
+
+fn load_tls_config(
+    cert_path: &str,
+    key_path: &str,
+) -> Result<tokio_rustls::rustls::ServerConfig, std::io::Error> {
+    use tokio_rustls::rustls;
+
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert_path)?))?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    let key = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(key_path)?))?
+        .into_iter()
+        .next()
+        .map(rustls::PrivateKey)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("No PKCS8 private key in {key_path}"),
+            )
+        })?;
+
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
 }
 
 #[derive(Debug)]
@@ -23,6 +197,15 @@ pub enum NetworkInteraction {
     Reading,
 }
 
+/// How slow the network of a fake connection is. Nothing is delayed by default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FakeStreamConfig {
+    // Added to every write, after the test has seen the data
+    pub send_delay: Option<tokio::time::Duration>,
+    // Added to every read, after the test has sent the line
+    pub recv_delay: Option<tokio::time::Duration>,
+}
+
 pub fn get_fake_listener(
     rx: mpsc::Receiver<(
         mpsc::Sender<NetworkInteraction>,
@@ -30,12 +213,29 @@ pub fn get_fake_listener(
         String,
     )>,
 ) -> FakeListener {
-    FakeListener { rx }
+    get_fake_listener_with_config(rx, FakeStreamConfig::default())
+}
+
+/// Every connection the listener accepts gets `config`
+pub fn get_fake_listener_with_config(
+    rx: mpsc::Receiver<(
+        mpsc::Sender<NetworkInteraction>,
+        mpsc::Receiver<String>,
+        String,
+    )>,
+    config: FakeStreamConfig,
+) -> FakeListener {
+    FakeListener { rx, config }
 }
 
 #[async_trait]
 pub trait Listener {
-    async fn accept(&mut self) -> Result<Box<dyn Stream + Send>, Error>;
+    /// The address is where the connection came from
+    async fn accept(&mut self) -> Result<(Box<dyn Stream + Send>, SocketAddr), Error>;
+}
+
+fn local_addr() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 0))
 }
 
 pub struct FakeListener {
@@ -44,13 +244,22 @@ pub struct FakeListener {
         mpsc::Receiver<String>,
         String,
     )>,
+    config: FakeStreamConfig,
 }
 
 #[async_trait]
 impl Listener for FakeListener {
-    async fn accept(&mut self) -> Result<Box<dyn Stream + Send>, Error> {
+    async fn accept(&mut self) -> Result<(Box<dyn Stream + Send>, SocketAddr), Error> {
         match self.rx.recv().await {
-            Some((tx, rx, name)) => Ok(Box::new(FakeStream { tx, rx, name })),
+            Some((tx, rx, name)) => Ok((
+                Box::new(FakeStream {
+                    tx,
+                    rx,
+                    name,
+                    config: self.config,
+                }),
+                local_addr(),
+            )),
             None => todo!(),
         }
     }
@@ -58,29 +267,99 @@ impl Listener for FakeListener {
 
 pub struct RealListener {
     inner: TcpListener,
+    read_timeout: Duration,
 }
 
 #[async_trait]
 impl Listener for RealListener {
-    async fn accept(&mut self) -> Result<Box<dyn Stream + Send>, Error> {
-        let a = self
-            .inner
-            .accept()
-            .map_ok(|(inner, _)| {
-                Box::new(RealStream {
-                    inner: BufStream::new(inner),
-                })
-            })
-            .map_err(|e| e.into())
-            .await;
+    async fn accept(&mut self) -> Result<(Box<dyn Stream + Send>, SocketAddr), Error> {
+        let (inner, addr) = self.inner.accept().await?;
+        Ok((
+            Box::new(RealStream {
+                inner: BufStream::new(inner),
+                codec: None,
+                read_timeout: self.read_timeout,
+            }),
+            addr,
+        ))
+    }
+}
+
+pub struct UnixListener {
+    inner: tokio::net::UnixListener,
+    path: std::path::PathBuf,
+}
+
+#[async_trait]
+impl Listener for UnixListener {
+    async fn accept(&mut self) -> Result<(Box<dyn Stream + Send>, SocketAddr), Error> {
+        let (inner, _) = self.inner.accept().await?;
+        // Unix sockets have no IP, everyone on them is local
+        Ok((
+            Box::new(UnixStream {
+                inner: BufStream::new(inner),
+                codec: None,
+            }),
+            local_addr(),
+        ))
+    }
+}
 
-        match a {
-            Ok(b) => Ok(b),
-            Err(e) => Err(e),
+impl Drop for UnixListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+pub struct WebSocketListener {
+    rx: mpsc::Receiver<(WebSocketStream, SocketAddr)>,
+}
+
+#[async_trait]
+impl Listener for WebSocketListener {
+    async fn accept(&mut self) -> Result<(Box<dyn Stream + Send>, SocketAddr), Error> {
+        match self.rx.recv().await {
+            Some((stream, addr)) => Ok((Box::new(stream), addr)),
+            None => Err(Error::Custom("Websocket listener stopped".to_string())),
+        }
+    }
+}
+
+pub struct TlsListener {
+    rx: mpsc::Receiver<(TlsStream, SocketAddr)>,
+}
+
+#[async_trait]
+impl Listener for TlsListener {
+    async fn accept(&mut self) -> Result<(Box<dyn Stream + Send>, SocketAddr), Error> {
+        match self.rx.recv().await {
+            Some((stream, addr)) => Ok((Box::new(stream), addr)),
+            None => Err(Error::Custom("TLS listener stopped".to_string())),
         }
     }
 }
 
+/// Accepts connections from several listeners at once, e.g. both TCP and WebSocket
+pub struct MultiListener {
+    listeners: Vec<Box<dyn Listener + Send>>,
+}
+
+impl MultiListener {
+    /// Panics on accept if `listeners` is empty
+    pub fn new(listeners: Vec<Box<dyn Listener + Send>>) -> Self {
+        Self { listeners }
+    }
+}
+
+#[async_trait]
+impl Listener for MultiListener {
+    async fn accept(&mut self) -> Result<(Box<dyn Stream + Send>, SocketAddr), Error> {
+        let (result, _, _) =
+            futures::future::select_all(self.listeners.iter_mut().map(|l| l.accept())).await;
+        result
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     ConnectionClosed,
@@ -93,6 +372,105 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(error: tokio_tungstenite::tungstenite::Error) -> Self {
+        use tokio_tungstenite::tungstenite::Error as WsError;
+        match error {
+            WsError::ConnectionClosed | WsError::AlreadyClosed => Error::ConnectionClosed,
+            e => Error::Custom(e.to_string()),
+        }
+    }
+}
+
+/// Wire format of a connection. Plain newline terminated JSON unless the client's very first
+/// byte is `MSGPACK_MAGIC`, then every message in both directions is a MessagePack frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Json,
+    MsgPack,
+}
+
+pub const MSGPACK_MAGIC: u8 = 0xCA;
+// Nobody has a reason to send a message anywhere near this big
+const MAX_MSGPACK_FRAME: usize = 1 << 20;
+
+impl Codec {
+    /// Bytes to put on the wire for one message. MessagePack frames are prefixed with their
+    /// length as a big endian u32
+    pub fn encode(&self, msg: &serde_json::Value) -> Result<Vec<u8>, Error> {
+        match self {
+            Codec::Json => {
+                let mut bytes =
+                    serde_json::to_vec(msg).map_err(|e| Error::Custom(e.to_string()))?;
+                bytes.push(b'\n');
+                Ok(bytes)
+            }
+            Codec::MsgPack => {
+                let body = rmp_serde::to_vec(msg).map_err(|e| Error::Custom(e.to_string()))?;
+                let mut bytes = (body.len() as u32).to_be_bytes().to_vec();
+                bytes.extend(body);
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// One message, without the newline or length prefix
+    pub fn decode(&self, bytes: &[u8]) -> Result<serde_json::Value, Error> {
+        match self {
+            Codec::Json => serde_json::from_slice(bytes).map_err(|e| Error::Custom(e.to_string())),
+            Codec::MsgPack => {
+                rmp_serde::from_slice(bytes).map_err(|e| Error::Custom(e.to_string()))
+            }
+        }
+    }
+}
+
+async fn negotiate_codec<S>(inner: &mut BufStream<S>) -> Result<Codec, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let first = inner.fill_buf().await?;
+    match first.first() {
+        None => Err(Error::ConnectionClosed),
+        Some(&MSGPACK_MAGIC) => {
+            inner.consume(1);
+            Ok(Codec::MsgPack)
+        }
+        Some(_) => Ok(Codec::Json),
+    }
+}
+
+// The rest of the server speaks JSON lines, so MessagePack is translated at the edge
+async fn read_msgpack<S>(inner: &mut BufStream<S>) -> Result<String, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let len = match inner.read_u32().await {
+        Ok(len) => len as usize,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            return Err(Error::ConnectionClosed)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if len > MAX_MSGPACK_FRAME {
+        return Err(Error::Custom(format!("MessagePack frame of {len} bytes")));
+    }
+    let mut body = vec![0; len];
+    inner.read_exact(&mut body).await?;
+    let msg = Codec::MsgPack.decode(&body)?;
+    Ok(msg.to_string() + "\n")
+}
+
+async fn write_msgpack<S>(inner: &mut BufStream<S>, data: &str) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let msg = Codec::Json.decode(data.trim_end().as_bytes())?;
+    inner.write_all(&Codec::MsgPack.encode(&msg)?).await?;
+    inner.flush().await?;
+    Ok(())
+}
+
 #[async_trait]
 pub trait Stream {
     async fn read_line(&mut self) -> Result<String, Error>;
@@ -104,6 +482,7 @@ pub struct FakeStream {
     tx: mpsc::Sender<NetworkInteraction>,
     rx: mpsc::Receiver<String>,
     name: String,
+    config: FakeStreamConfig,
 }
 
 #[async_trait]
@@ -118,6 +497,9 @@ impl Stream for FakeStream {
         debug!("Fake stream {} waiting for go", self.name);
         match self.rx.recv().await {
             Some(v) => {
+                if let Some(delay) = self.config.recv_delay {
+                    tokio::time::sleep(delay).await;
+                }
                 debug!("Test[{}] -> App: {}", self.name, v.trim());
                 Ok(v)
             }
@@ -137,36 +519,43 @@ impl Stream for FakeStream {
         }
 
         match self.rx.recv().await {
-            Some(_) => Ok(()),
-            None => Err(Error::ConnectionClosed),
+            Some(_) => (),
+            None => return Err(Error::ConnectionClosed),
+        }
+        if let Some(delay) = self.config.send_delay {
+            tokio::time::sleep(delay).await;
         }
+        Ok(())
     }
 }
 
 pub struct RealStream {
     inner: BufStream<TcpStream>,
+    // Decided by the first byte the client sends
+    codec: Option<Codec>,
+    // A line that takes longer than this is given up on, the connection counts as closed
+    read_timeout: Duration,
 }
 
 #[async_trait]
 impl Stream for RealStream {
     async fn read_line(&mut self) -> Result<String, Error> {
+        if self.codec.is_none() {
+            self.codec = Some(negotiate_codec(&mut self.inner).await?);
+        }
+        if self.codec == Some(Codec::MsgPack) {
+            return read_msgpack(&mut self.inner).await;
+        }
         let mut line = String::new();
-        const USE_TIMEOUT: bool = false;
-        let response = if USE_TIMEOUT {
-            match tokio::time::timeout(
-                std::time::Duration::from_millis(2000),
-                self.inner.read_line(&mut line),
-            )
-            .await
-            {
-                Ok(a) => a,
-                Err(_) => return Err(Error::Custom("Timeout".to_string())),
-            }
-        } else {
-            self.inner.read_line(&mut line).await
-        };
+        let response =
+            match tokio::time::timeout(self.read_timeout, self.inner.read_line(&mut line)).await {
+                Ok(response) => response,
+                Err(_) => {
+                    debug!("No full line within {:?}, giving up", self.read_timeout);
+                    return Err(Error::ConnectionClosed);
+                }
+            };
         match response {
-            // match self.inner.read_line(&mut line).await {
             Ok(0) => Err(Error::ConnectionClosed),
             Err(_) => Err(Error::Custom("Whoopsie".to_string())),
             Ok(_) => Ok(line),
@@ -174,6 +563,77 @@ impl Stream for RealStream {
     }
 
     async fn write(&mut self, data: &str) -> Result<(), Error> {
+        if self.codec == Some(Codec::MsgPack) {
+            return write_msgpack(&mut self.inner, data).await;
+        }
+        self.inner.write_all(data.as_bytes()).await?;
+
+        self.inner.flush().await?;
+        Ok(())
+    }
+}
+
+pub struct UnixStream {
+    inner: BufStream<tokio::net::UnixStream>,
+    // Decided by the first byte the client sends
+    codec: Option<Codec>,
+}
+
+#[async_trait]
+impl Stream for UnixStream {
+    async fn read_line(&mut self) -> Result<String, Error> {
+        if self.codec.is_none() {
+            self.codec = Some(negotiate_codec(&mut self.inner).await?);
+        }
+        if self.codec == Some(Codec::MsgPack) {
+            return read_msgpack(&mut self.inner).await;
+        }
+        let mut line = String::new();
+        match self.inner.read_line(&mut line).await {
+            Ok(0) => Err(Error::ConnectionClosed),
+            Err(e) => Err(e.into()),
+            Ok(_) => Ok(line),
+        }
+    }
+
+    async fn write(&mut self, data: &str) -> Result<(), Error> {
+        if self.codec == Some(Codec::MsgPack) {
+            return write_msgpack(&mut self.inner, data).await;
+        }
+        self.inner.write_all(data.as_bytes()).await?;
+
+        self.inner.flush().await?;
+        Ok(())
+    }
+}
+
+pub struct TlsStream {
+    inner: BufStream<tokio_rustls::server::TlsStream<TcpStream>>,
+    // Decided by the first byte the client sends
+    codec: Option<Codec>,
+}
+
+#[async_trait]
+impl Stream for TlsStream {
+    async fn read_line(&mut self) -> Result<String, Error> {
+        if self.codec.is_none() {
+            self.codec = Some(negotiate_codec(&mut self.inner).await?);
+        }
+        if self.codec == Some(Codec::MsgPack) {
+            return read_msgpack(&mut self.inner).await;
+        }
+        let mut line = String::new();
+        match self.inner.read_line(&mut line).await {
+            Ok(0) => Err(Error::ConnectionClosed),
+            Err(e) => Err(e.into()),
+            Ok(_) => Ok(line),
+        }
+    }
+
+    async fn write(&mut self, data: &str) -> Result<(), Error> {
+        if self.codec == Some(Codec::MsgPack) {
+            return write_msgpack(&mut self.inner, data).await;
+        }
         self.inner.write_all(data.as_bytes()).await?;
 
         self.inner.flush().await?;
@@ -181,6 +641,32 @@ impl Stream for RealStream {
     }
 }
 
+/// One text frame per line, in both directions
+pub struct WebSocketStream {
+    inner: tokio_tungstenite::WebSocketStream<TcpStream>,
+}
+
+#[async_trait]
+impl Stream for WebSocketStream {
+    async fn read_line(&mut self) -> Result<String, Error> {
+        loop {
+            match self.inner.next().await {
+                None | Some(Ok(Message::Close(_))) => return Err(Error::ConnectionClosed),
+                Some(Err(e)) => return Err(e.into()),
+                Some(Ok(Message::Text(text))) => return Ok(text + "\n"),
+                // Pings are answered by tungstenite, nothing else carries protocol data
+                Some(Ok(_)) => (),
+            }
+        }
+    }
+
+    async fn write(&mut self, data: &str) -> Result<(), Error> {
+        let frame = data.strip_suffix('\n').unwrap_or(data);
+        self.inner.send(Message::Text(frame.to_string())).await?;
+        Ok(())
+    }
+}
+
 pub struct TestDriver {
     // pinbox: Pin<Box<dyn Future<Output = ()>>>,
     // context: Context<'a>,
@@ -200,6 +686,8 @@ enum ReadOrSend {
 #[derive(Debug)]
 enum ExpectData {
     String(String),
+    // What a bot would look at, the reason can be worded any way
+    ErrorCode(u16),
     Anything,
 }
 
@@ -257,6 +745,11 @@ impl TestDriver {
         match tokio::time::timeout(std::time::Duration::from_millis(1000), user.rx.recv()).await {
             Ok(Some(NetworkInteraction::Sending(actual_data))) => match expected {
                 ExpectData::String(str) => assert_eq!(actual_data, str + "\n"),
+                ExpectData::ErrorCode(code) => {
+                    let json: serde_json::Value = serde_json::from_str(&actual_data)
+                        .unwrap_or_else(|_| panic!("Expected an error, got: {actual_data}"));
+                    assert_eq!(json["error"]["code"], code, "in {actual_data}");
+                }
                 ExpectData::Anything => (),
             },
             Ok(Some(NetworkInteraction::Reading)) => {
@@ -270,6 +763,20 @@ impl TestDriver {
         user.tx.send("".to_string()).await.unwrap();
     }
 
+    /// The app closed the connection, without sending or waiting for anything first
+    pub async fn expect_disconnect(&mut self, user: &mut TestUser) {
+        match tokio::time::timeout(std::time::Duration::from_millis(1000), user.rx.recv()).await {
+            Ok(None) => (),
+            Ok(Some(NetworkInteraction::Sending(data))) => {
+                panic!("Expected the app to disconnect the user, but it sent: {data}")
+            }
+            Ok(Some(NetworkInteraction::Reading)) => {
+                panic!("Expected the app to disconnect the user, but it's waiting for data")
+            }
+            Err(_) => panic!("Timeout waiting for the app to disconnect the user"),
+        }
+    }
+
     pub async fn receive_anything(&mut self, user: &mut TestUser) {
         self.internal_receive(user, ExpectData::Anything).await;
     }
@@ -279,6 +786,12 @@ impl TestDriver {
             .await;
     }
 
+    /// An `{"error":{"code":..}}` with this code, whatever its reason says
+    pub async fn receive_error(&mut self, user: &mut TestUser, code: u16) {
+        self.internal_receive(user, ExpectData::ErrorCode(code))
+            .await;
+    }
+
     pub fn poll(&mut self) {
         // for _ in 0..100 {
         //     let _poll_result: std::task::Poll<()> =
@@ -360,7 +873,7 @@ macro_rules! init_flow_test {
 
 #[macro_export]
 macro_rules! init_flow_test_spawn {
-    ($driver:ident, $func:ident) => {
+    ($driver:ident, $func:expr) => {
         let (tx, rx) = get_test_channel();
         let fake_listener = network_wrap::get_fake_listener(rx);
         let mut $driver = network_wrap::TestDriver::new(tx);
@@ -373,3 +886,48 @@ macro_rules! init_flow_test_spawn {
         })
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn port_can_be_bound_again_right_away() {
+        let mut listener = bind_with_options("127.0.0.1:0", SocketOptions::default())
+            .await
+            .unwrap();
+        let addr = listener.inner.local_addr().unwrap();
+        let _client = TcpStream::connect(addr).await.unwrap();
+        // The server closing first is what leaves the port in TIME_WAIT
+        let (stream, _) = listener.accept().await.unwrap();
+        drop(stream);
+        drop(listener);
+
+        bind_with_options(&addr.to_string(), SocketOptions::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn stalled_line_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (inner, _) = listener.accept().await.unwrap();
+        let mut stream = RealStream {
+            inner: BufStream::new(inner),
+            codec: None,
+            read_timeout: Duration::from_millis(100),
+        };
+
+        // Half a message, and then nothing
+        client.write_all(br#"{"move":"#).await.unwrap();
+        let started = std::time::Instant::now();
+        assert!(matches!(
+            stream.read_line().await,
+            Err(Error::ConnectionClosed)
+        ));
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+}
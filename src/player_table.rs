@@ -1,5 +1,5 @@
 use log::debug;
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Instant};
 
 use druid::Color;
 use tokio::sync::mpsc;
@@ -8,9 +8,25 @@ use crate::controller::ControllerToPlayerMsg;
 
 pub struct PlayerTable {
     players: Vec<PlayerInfo>,
+    spectators: Vec<SpectatorInfo>,
     paint_bucket: PaintBucket,
+    // Spectators don't count
+    max_capacity: Option<usize>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapacityError {
+    pub max: usize,
+}
+
+impl std::fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the table is full, {} players at most", self.max)
+    }
+}
+
+impl std::error::Error for CapacityError {}
+
 impl Default for PlayerTable {
     fn default() -> Self {
         Self::new()
@@ -21,10 +37,24 @@ impl PlayerTable {
     pub fn new() -> Self {
         Self {
             players: Vec::new(),
+            spectators: Vec::new(),
             paint_bucket: PaintBucket::new(),
+            max_capacity: None,
         }
     }
 
+    pub fn with_capacity(max: usize) -> Self {
+        Self {
+            max_capacity: Some(max),
+            ..Self::new()
+        }
+    }
+
+    /// Whoever is already seated stays, even if they are more than `max` now
+    pub fn set_capacity(&mut self, max: usize) {
+        self.max_capacity = Some(max);
+    }
+
     fn debug_print(&self, msg: &str) {
         let p = &self.players;
         debug!("{p:?}: {msg}");
@@ -34,19 +64,43 @@ impl PlayerTable {
         self.players.is_empty()
     }
 
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
     pub fn add_new_player(
         &mut self,
         name: String,
         channel: mpsc::Sender<ControllerToPlayerMsg>,
-    ) -> &PlayerInfo {
+    ) -> Result<&PlayerInfo, CapacityError> {
         self.remove_player(&name);
+        if let Some(max) = self.max_capacity {
+            if self.players.len() >= max {
+                return Err(CapacityError { max });
+            }
+        }
         self.players.push(PlayerInfo {
             color: self.paint_bucket.get(&name),
             name,
             tx: channel,
+            move_count: 0,
+            connection_time: Instant::now(),
         });
         self.debug_print("Added player");
-        self.players.last().unwrap()
+        Ok(self.players.last().unwrap())
+    }
+
+    /// Hands `name`'s seat to a new connection, keeping their place in turn, color and stats.
+    /// `None` if they aren't at the table
+    pub fn reconnect_player(
+        &mut self,
+        name: &str,
+        new_channel: mpsc::Sender<ControllerToPlayerMsg>,
+    ) -> Option<&PlayerInfo> {
+        let player = self.players.iter_mut().find(|p| p.name == name)?;
+        player.tx = new_channel;
+        debug!("Reconnected {name}");
+        Some(player)
     }
 
     pub fn remove_player(&mut self, name: &str) -> bool {
@@ -61,6 +115,7 @@ impl PlayerTable {
             }
         }
         self.players = new_players;
+        self.paint_bucket.release(name);
         self.debug_print("Removed player");
         was_removed
     }
@@ -69,9 +124,35 @@ impl PlayerTable {
         self.players.iter()
     }
 
+    pub fn record_move(&mut self, name: &str) {
+        if let Some(player) = self.players.iter_mut().find(|p| p.name == name) {
+            player.move_count += 1;
+        }
+    }
+
     pub(crate) fn get(&self, name: &str) -> Option<&PlayerInfo> {
         self.players.iter().find(|p| p.name == name)
     }
+
+    /// Whoever is painted `color`, for when the board is all there is to go on. Everyone past the
+    /// 21st player shares gray, so the first of them is returned
+    pub fn find_by_color(&self, color: &Color) -> Option<&PlayerInfo> {
+        let rgba = color.as_rgba_u32();
+        self.players.iter().find(|p| p.color.as_rgba_u32() == rgba)
+    }
+
+    pub fn add_spectator(&mut self, name: String, channel: mpsc::Sender<ControllerToPlayerMsg>) {
+        self.spectators.push(SpectatorInfo { name, tx: channel });
+        debug!("{:?}: Added spectator", self.spectators);
+    }
+
+    pub fn remove_spectator(&mut self, name: &str) {
+        self.spectators.retain(|s| s.name != name);
+    }
+
+    pub fn spectators(&self) -> std::slice::Iter<SpectatorInfo> {
+        self.spectators.iter()
+    }
 }
 
 #[derive(Clone)]
@@ -79,6 +160,9 @@ pub struct PlayerInfo {
     pub name: String,
     pub color: druid::Color,
     pub tx: mpsc::Sender<ControllerToPlayerMsg>,
+    // Both start over when the player connects again, `reconnect_player` keeps them
+    pub move_count: u64,
+    pub connection_time: Instant,
 }
 
 impl std::fmt::Debug for PlayerInfo {
@@ -87,9 +171,22 @@ impl std::fmt::Debug for PlayerInfo {
     }
 }
 
+#[derive(Clone)]
+pub struct SpectatorInfo {
+    pub name: String,
+    pub tx: mpsc::Sender<ControllerToPlayerMsg>,
+}
+
+impl std::fmt::Debug for SpectatorInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SpectatorInfo({})", self.name)
+    }
+}
+
 struct PaintBucket {
     free_paints: Vec<druid::Color>,
-    taken_paints: HashMap<String, druid::Color>,
+    // None for players that got the gray fallback, so it doesn't end up in free_paints
+    taken_paints: HashMap<String, Option<druid::Color>>,
 }
 
 impl PaintBucket {
@@ -124,13 +221,163 @@ impl PaintBucket {
 
     fn get(&mut self, name: &String) -> Color {
         match self.taken_paints.get(name) {
-            Some(color) => *color,
+            Some(color) => color.unwrap_or(Color::GRAY),
             None => {
                 // After all the colors are taken, everyone gets gray
-                let c = self.free_paints.pop().unwrap_or(Color::GRAY);
+                let c = self.free_paints.pop();
                 self.taken_paints.insert(name.to_string(), c);
-                c
+                c.unwrap_or(Color::GRAY)
             }
         }
     }
+
+    pub fn release(&mut self, name: &str) {
+        if let Some(Some(color)) = self.taken_paints.remove(name) {
+            self.free_paints.push(color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn add_players(table: &mut PlayerTable, prefix: &str, count: usize) -> Vec<Color> {
+        (0..count)
+            .map(|i| {
+                let (tx, _rx) = mpsc::channel(1);
+                table
+                    .add_new_player(format!("{prefix}{i}"), tx)
+                    .unwrap()
+                    .color
+            })
+            .collect()
+    }
+
+    #[test]
+    fn colors_are_returned_when_players_leave() {
+        let mut table = PlayerTable::new();
+        let first = add_players(&mut table, "first", 22);
+        // Only 21 colors, the last one gets the fallback
+        assert_eq!(first[21], Color::GRAY);
+        for i in 0..22 {
+            assert!(table.remove_player(&format!("first{i}")));
+        }
+
+        let second = add_players(&mut table, "second", 21);
+        for (i, color) in second.iter().enumerate() {
+            assert!(
+                !second[..i].contains(color),
+                "player {i} got a color that was already taken"
+            );
+        }
+    }
+
+    #[test]
+    fn reconnecting_keeps_color() {
+        let mut table = PlayerTable::new();
+        add_players(&mut table, "p", 3);
+        let color = table.get("p1").unwrap().color;
+        let (tx, _rx) = mpsc::channel(1);
+        assert_eq!(
+            table.add_new_player("p1".to_string(), tx).unwrap().color,
+            color
+        );
+    }
+
+    #[test]
+    fn full_table_takes_no_more_players() {
+        let mut table = PlayerTable::with_capacity(2);
+        add_players(&mut table, "p", 2);
+        let (tx, _rx) = mpsc::channel(1);
+        assert_eq!(
+            table.add_new_player("late".to_string(), tx.clone()).err(),
+            Some(CapacityError { max: 2 })
+        );
+        assert!(table.get("late").is_none());
+
+        // Someone already at the table can still reconnect
+        assert!(table.add_new_player("p0".to_string(), tx.clone()).is_ok());
+
+        table.remove_player("p1");
+        assert!(table.add_new_player("late".to_string(), tx).is_ok());
+        assert_eq!(table.player_count(), 2);
+    }
+
+    #[test]
+    fn shrinking_the_table_turns_away_newcomers_only() {
+        let mut table = PlayerTable::with_capacity(4);
+        add_players(&mut table, "p", 3);
+        table.set_capacity(2);
+        assert_eq!(table.player_count(), 3);
+        let (tx, _rx) = mpsc::channel(1);
+        assert_eq!(
+            table.add_new_player("late".to_string(), tx).err(),
+            Some(CapacityError { max: 2 })
+        );
+    }
+
+    #[test]
+    fn moves_are_counted_until_reconnect() {
+        let mut table = PlayerTable::new();
+        add_players(&mut table, "p", 2);
+        assert_eq!(table.get("p0").unwrap().move_count, 0);
+
+        table.record_move("p0");
+        table.record_move("nobody");
+        assert_eq!(table.get("p0").unwrap().move_count, 1);
+        assert_eq!(table.get("p1").unwrap().move_count, 0);
+
+        let connected = table.get("p0").unwrap().connection_time;
+        let (tx, _rx) = mpsc::channel(1);
+        let again = table.add_new_player("p0".to_string(), tx).unwrap();
+        assert_eq!(again.move_count, 0);
+        assert!(again.connection_time >= connected);
+    }
+
+    #[test]
+    fn reconnecting_keeps_the_seat() {
+        let mut table = PlayerTable::new();
+        add_players(&mut table, "p", 3);
+        table.record_move("p0");
+        let (tx, mut rx) = mpsc::channel(1);
+
+        let again = table.reconnect_player("p0", tx).unwrap();
+        assert_eq!(again.move_count, 1);
+        again.tx.try_send(ControllerToPlayerMsg::Paused).unwrap();
+        assert!(matches!(rx.try_recv(), Ok(ControllerToPlayerMsg::Paused)));
+        let order = table.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(order, ["p0", "p1", "p2"]);
+
+        let (tx, _rx) = mpsc::channel(1);
+        assert!(table.reconnect_player("nobody", tx).is_none());
+        assert_eq!(table.player_count(), 3);
+    }
+
+    #[test]
+    fn players_are_found_by_color() {
+        let mut table = PlayerTable::new();
+        let colors = add_players(&mut table, "p", 3);
+        assert_eq!(table.find_by_color(&colors[1]).unwrap().name, "p1");
+        assert!(table.find_by_color(&Color::rgb8(1, 2, 3)).is_none());
+
+        table.remove_player("p1");
+        assert!(table.find_by_color(&colors[1]).is_none());
+    }
+
+    #[test]
+    fn player_count_follows_joins_and_leaves() {
+        let mut table = PlayerTable::new();
+        assert_eq!(table.player_count(), 0);
+        add_players(&mut table, "p", 3);
+        assert_eq!(table.player_count(), 3);
+        table.remove_player("p0");
+        table.remove_player("nobody");
+        assert_eq!(table.player_count(), 2);
+        add_players(&mut table, "p", 1);
+        assert_eq!(table.player_count(), 3);
+        table.remove_player("p1");
+        table.remove_player("p2");
+        assert_eq!(table.player_count(), 1);
+    }
 }
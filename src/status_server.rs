@@ -0,0 +1,101 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::warn;
+use tokio::sync::watch;
+
+use crate::{controller::ControllerInfo, metrics::Metrics};
+
+/// Answers `GET /status` with the latest `ControllerInfo` from the controller, as JSON, and
+/// `GET /metrics` with the Prometheus metrics
+pub async fn serve(addr: SocketAddr, status_rx: watch::Receiver<ControllerInfo>, metrics: Metrics) {
+    let make_service = make_service_fn(move |_conn| {
+        let status_rx = status_rx.clone();
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let response = respond(&req, &status_rx, &metrics);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+    if let Err(e) = Server::bind(&addr).serve(make_service).await {
+        warn!("Status server stopped: {e}");
+    }
+}
+
+fn respond(
+    req: &Request<Body>,
+    status_rx: &watch::Receiver<ControllerInfo>,
+    metrics: &Metrics,
+) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/status") => Response::builder()
+            .header("content-type", "application/json")
+            .body(Body::from(status_rx.borrow().to_json()))
+            .unwrap(),
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("content-type", prometheus::TEXT_FORMAT)
+            .body(Body::from(metrics.gather()))
+            .unwrap(),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controller::GameMode;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn get(addr: &str, path: &str) -> String {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n")
+                    .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn status_is_served_as_json() {
+        let addr = "127.0.0.1:7661";
+        let (status_tx, status_rx) = watch::channel(ControllerInfo::default());
+        let metrics = Metrics::new();
+        tokio::spawn(serve(addr.parse().unwrap(), status_rx, metrics.clone()));
+        // Let the server bind
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        status_tx.send_modify(|info| {
+            info.game_mode = GameMode::Competition;
+            info.score.insert("zeldo".to_string(), 2);
+        });
+
+        let response = get(addr, "/status").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let status: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(status["game-mode"], "competition");
+        assert_eq!(status["score"]["zeldo"], 2);
+        assert_eq!(status["paused"], false);
+
+        metrics.moves_total.inc();
+        let response = get(addr, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.contains("\nmoves_total 1\n"), "{response}");
+
+        let response = get(addr, "/nothing-here").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"), "{response}");
+    }
+}
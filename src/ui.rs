@@ -1,23 +1,49 @@
-use std::time::Duration;
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
 
 use druid::im::Vector;
+use druid::kurbo::{BezPath, Line};
+use druid::piet::{Text, TextLayoutBuilder};
 use druid::text::Formatter;
 use druid::widget::{self, prelude::*, Button, EnvScope};
 use druid::widget::{Flex, Label};
 use druid::{
-    AppDelegate, AppLauncher, Command, Data, DelegateCtx, ExtEventSink, Handled, Lens, Selector,
-    Target, WidgetExt, WindowDesc,
+    Affine, AppDelegate, AppLauncher, Color, Command, Data, DelegateCtx, ExtEventSink, Handled,
+    Lens, Point, Selector, Target, TimerToken, Vec2, WidgetExt, WindowDesc,
 };
 use log::info;
 use log::{debug, warn};
 
+use crate::bracket::BracketSlot;
 use crate::controller;
 use crate::controller::{ControllerMsg, ControllerSender};
+use crate::games::{gomoku, reversi, tictactoe};
 use code_challenge_game_types::gametraits;
 
 pub const UI_UPDATE_COMMAND: Selector<Box<dyn gametraits::Paint>> = Selector::new("ui_update");
 pub const UI_UPDATE_CONTROLLER_INFO_COMMAND: Selector<controller::ControllerInfo> =
     Selector::new("ui_update_controller_info");
+// Replaces the whole history, when switching to another game
+pub const UI_UPDATE_MOVE_HISTORY_COMMAND: Selector<Vec<MoveHistoryEntry>> =
+    Selector::new("ui_update_move_history");
+// One move, sent along with the UI_UPDATE_COMMAND it led to
+pub const UI_MOVE_HISTORY_COMMAND: Selector<MoveHistoryEntry> = Selector::new("ui_move_history");
+pub const UI_GAME_STARTED_COMMAND: Selector<()> = Selector::new("ui_game_started");
+// Everyone's wins so far, sent when a game is over
+pub const UI_GAME_OVER_COMMAND: Selector<HashMap<String, u64>> = Selector::new("ui_game_over");
+// Back to no zoom and no pan, handled by GameWidget
+pub const UI_RESET_VIEW_COMMAND: Selector<()> = Selector::new("ui_reset_view");
+// (from, message)
+pub const UI_CHAT_COMMAND: Selector<(String, String)> = Selector::new("ui_chat");
+
+// How many of the latest chat messages the UI shows
+const UI_CHAT_LINES: usize = 50;
+// How many of the latest moves the UI shows
+pub const UI_MOVE_HISTORY: usize = 50;
+const MIN_ZOOM: f64 = 0.25;
+const MAX_ZOOM: f64 = 4.0;
+// How much one notch of the scroll wheel zooms
+const ZOOM_STEP: f64 = 1.1;
 
 #[derive(Clone, Lens, Data)]
 struct AppData {
@@ -27,6 +53,20 @@ struct AppData {
     controller_settings: ControllerSettings,
     connected_users: Vector<UiUser>,
     game_mode: GameMode,
+    move_history: Vector<MoveHistoryEntry>,
+    chat: Vector<UiChat>,
+    // Which of the games, when the server runs more than one
+    shown_game: usize,
+    // Round by round, empty when there's no tournament
+    bracket: Vector<Vector<String>>,
+    // Wins per player after each game
+    score_history: Vector<HashMap<String, u64>>,
+    // None between games
+    game_start: Option<Instant>,
+    // Moves made in the current game
+    turn_number: u32,
+    // Clicks on the board are moves instead of dragging it around
+    manual_input_enabled: bool,
 }
 
 #[derive(Clone, Data)]
@@ -34,13 +74,52 @@ struct UiUser {
     name: String,
     color: druid::Color,
     score: u64,
+    elo: f64,
+    move_count: u64,
+    // As of the last controller info, not ticking on its own
+    connected_for: Duration,
+}
+
+#[derive(Clone, Debug, Data)]
+pub struct MoveHistoryEntry {
+    pub player: String,
+    pub color: druid::Color,
+    pub move_text: String,
+    pub turn: u32,
+}
+
+impl From<&controller::MoveRecord> for MoveHistoryEntry {
+    fn from(record: &controller::MoveRecord) -> Self {
+        Self {
+            player: record.player.clone(),
+            color: record.color,
+            move_text: format!("{} ({})", record.serialized_move, record.result_kind),
+            turn: record.turn,
+        }
+    }
+}
+
+fn push_move_history(move_history: &mut Vector<MoveHistoryEntry>, entry: MoveHistoryEntry) {
+    move_history.push_back(entry);
+    while move_history.len() > UI_MOVE_HISTORY {
+        move_history.pop_front();
+    }
+}
+
+#[derive(Clone, Data)]
+struct UiChat {
+    from: String,
+    message: String,
 }
 
 #[derive(Clone, Lens, Data, PartialEq, Eq)]
 struct ControllerSettings {
     time_between_turns: std::time::Duration,
     time_after_win: std::time::Duration,
+    turn_time_limit: std::time::Duration,
     game_mode: GameMode,
+    // Typed in, not sent until "Broadcast" is pressed
+    announcement: String,
 }
 
 impl Default for ControllerSettings {
@@ -48,7 +127,10 @@ impl Default for ControllerSettings {
         Self {
             time_between_turns: std::time::Duration::from_millis(100),
             time_after_win: std::time::Duration::from_millis(600),
+            // No limit
+            turn_time_limit: std::time::Duration::ZERO,
             game_mode: GameMode::Practice,
+            announcement: String::new(),
         }
     }
 }
@@ -58,6 +140,8 @@ enum GameMode {
     Practice,
     Gating,
     Compete,
+    Tournament,
+    Spectate,
 }
 
 impl From<controller::GameMode> for GameMode {
@@ -66,6 +150,8 @@ impl From<controller::GameMode> for GameMode {
             controller::GameMode::Practice => GameMode::Practice,
             controller::GameMode::Gating => GameMode::Gating,
             controller::GameMode::Competition => GameMode::Compete,
+            controller::GameMode::Tournament => GameMode::Tournament,
+            controller::GameMode::Spectate => GameMode::Spectate,
         }
     }
 }
@@ -74,10 +160,115 @@ impl From<controller::GameMode> for GameMode {
 fn games_eq(left: &Box<dyn gametraits::Paint>, right: &Box<dyn gametraits::Paint>) -> bool {
     left.eq(&**right) // Hehe...
 }
-struct GameWidget {}
+
+// Where the board is shown, the game paints as if it wasn't zoomed or panned
+struct BoardView {
+    zoom: f64,
+    pan: Vec2,
+    // Where the mouse was last seen while dragging
+    drag_from: Option<Point>,
+}
+
+impl BoardView {
+    fn new() -> Self {
+        Self {
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+            drag_from: None,
+        }
+    }
+
+    fn view_transform(&self) -> Affine {
+        Affine::scale(self.zoom).then_translate(self.pan)
+    }
+
+    fn zoom_by(&mut self, wheel_delta: Vec2) {
+        let factor = if wheel_delta.y < 0.0 {
+            ZOOM_STEP
+        } else {
+            1.0 / ZOOM_STEP
+        };
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    // The (x, y) of the cell under `pos`, the same cell width as the games paint with.
+    // None if `pos` is off the board
+    fn cell_at(
+        &self,
+        pos: Point,
+        size: Size,
+        (columns, rows): (usize, usize),
+    ) -> Option<(usize, usize)> {
+        let cell_width = (size.width / columns as f64).min(size.height / rows as f64);
+        let on_board = self.view_transform().inverse() * pos;
+        if on_board.x < 0.0 || on_board.y < 0.0 {
+            return None;
+        }
+        let x = (on_board.x / cell_width) as usize;
+        let y = (on_board.y / cell_width) as usize;
+        (x < columns && y < rows).then_some((x, y))
+    }
+}
+
+// (columns, rows) of the games that are played by placing on a board
+pub(crate) fn board_size(game_state: &dyn gametraits::Paint) -> Option<(usize, usize)> {
+    let game = game_state.as_any();
+    if let Some(game) = game.downcast_ref::<gomoku::Game>() {
+        Some(game.board_size())
+    } else if let Some(game) = game.downcast_ref::<tictactoe::Game>() {
+        Some(game.board_size())
+    } else {
+        game.downcast_ref::<reversi::Game>()
+            .map(|game| game.board_size())
+    }
+}
+
+struct GameWidget {
+    view: BoardView,
+    controller_sender: ControllerSender,
+}
 
 impl Widget<AppData> for GameWidget {
-    fn event(&mut self, _ctx: &mut EventCtx, _event: &Event, _data: &mut AppData, _env: &Env) {}
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppData, _env: &Env) {
+        match event {
+            Event::Wheel(mouse) if mouse.wheel_delta.y != 0.0 => {
+                self.view.zoom_by(mouse.wheel_delta);
+                ctx.request_paint();
+            }
+            Event::MouseDown(mouse) if data.manual_input_enabled => {
+                // Clicks off the board do nothing
+                if let Some((x, y)) = board_size(&*data.game_state)
+                    .and_then(|board| self.view.cell_at(mouse.pos, ctx.size(), board))
+                {
+                    self.controller_sender.send(ControllerMsg::ManualMove {
+                        player: String::new(),
+                        x,
+                        y,
+                    });
+                }
+            }
+            Event::MouseDown(mouse) => {
+                self.view.drag_from = Some(mouse.pos);
+                ctx.set_active(true);
+            }
+            Event::MouseMove(mouse) => {
+                if let Some(from) = self.view.drag_from {
+                    self.view.pan += mouse.pos - from;
+                    self.view.drag_from = Some(mouse.pos);
+                    ctx.request_paint();
+                }
+            }
+            Event::MouseUp(_) => {
+                self.view.drag_from = None;
+                ctx.set_active(false);
+            }
+            Event::Command(cmd) if cmd.is(UI_RESET_VIEW_COMMAND) => {
+                self.view = BoardView::new();
+                ctx.request_paint();
+            }
+            _ => (),
+        }
+    }
 
     fn lifecycle(
         &mut self,
@@ -105,7 +296,11 @@ impl Widget<AppData> for GameWidget {
 
     fn paint(&mut self, ctx: &mut PaintCtx, _data: &AppData, _env: &Env) {
         debug!("Druid repainting");
-        _data.game_state.paint(ctx);
+        let transform = self.view.view_transform();
+        ctx.with_save(|ctx| {
+            ctx.transform(transform);
+            _data.game_state.paint(ctx);
+        });
     }
 }
 
@@ -129,13 +324,70 @@ impl AppDelegate<AppData> for Delegate {
             data.connected_users = info
                 .connected_users
                 .iter()
-                .map(|gametraits::User { name, color }| UiUser {
-                    name: name.clone(),
-                    color: *color,
-                    score: *info.score.get(name).unwrap_or(&0),
+                .map(|gametraits::User { name, color }| {
+                    let stats = info.player_stats.get(name);
+                    UiUser {
+                        name: name.clone(),
+                        color: *color,
+                        score: *info.score.get(name).unwrap_or(&0),
+                        elo: *info
+                            .elo_ratings
+                            .get(name)
+                            .unwrap_or(&controller::INITIAL_ELO),
+                        move_count: stats.map_or(0, |s| s.move_count),
+                        connected_for: stats
+                            .map_or(Duration::ZERO, |s| s.connection_time.elapsed()),
+                    }
                 })
                 .collect();
             data.game_mode = info.game_mode.clone().into();
+            data.manual_input_enabled = info.manual_input;
+            data.turn_number = info.turn_number;
+            data.bracket = info
+                .bracket
+                .iter()
+                .flat_map(|bracket| bracket.rounds())
+                .map(|round| {
+                    round
+                        .iter()
+                        .map(|slot| match slot {
+                            BracketSlot::Player(name) => name.clone(),
+                            BracketSlot::Bye => "bye".to_string(),
+                            BracketSlot::Undecided => "?".to_string(),
+                        })
+                        .collect()
+                })
+                .collect();
+            Handled::Yes
+        } else if let Some(move_history) = cmd.get(UI_UPDATE_MOVE_HISTORY_COMMAND) {
+            debug!("New move history received");
+            data.move_history = move_history.iter().cloned().collect();
+            Handled::Yes
+        } else if let Some(entry) = cmd.get(UI_MOVE_HISTORY_COMMAND) {
+            debug!("New move received");
+            push_move_history(&mut data.move_history, entry.clone());
+            Handled::Yes
+        } else if let Some(score) = cmd.get(UI_GAME_OVER_COMMAND) {
+            debug!("Game over received");
+            record_game_over(&mut data.score_history, score);
+            data.game_start = None;
+            Handled::Yes
+        } else if cmd.is(UI_GAME_STARTED_COMMAND) {
+            debug!("Game started received");
+            data.game_start = Some(Instant::now());
+            Handled::Yes
+        } else if cmd.is(UI_RESET_VIEW_COMMAND) {
+            // For GameWidget
+            Handled::No
+        } else if let Some((from, message)) = cmd.get(UI_CHAT_COMMAND) {
+            debug!("New chat message received");
+            data.chat.push_back(UiChat {
+                from: from.clone(),
+                message: message.clone(),
+            });
+            if data.chat.len() > UI_CHAT_LINES {
+                data.chat.pop_front();
+            }
             Handled::Yes
         } else {
             warn!("UI got command, but not handled");
@@ -169,10 +421,24 @@ impl Formatter<Duration> for DurationFormatter {
     }
 }
 
+// Who the log says asked for an undo from the settings
+const OPERATOR_NAME: &str = "operator";
+
 fn make_settings_widget(controller_sender: ControllerSender) -> impl Widget<ControllerSettings> {
     let cs2 = controller_sender.clone();
     let cs3 = controller_sender.clone();
     let cs4 = controller_sender.clone();
+    let cs6 = controller_sender.clone();
+    let cs7 = controller_sender.clone();
+    let cs8 = controller_sender.clone();
+    let cs9 = controller_sender.clone();
+    let cs10 = controller_sender.clone();
+    let cs11 = controller_sender.clone();
+    let cs12 = controller_sender.clone();
+    let cs13 = controller_sender.clone();
+    let cs14 = controller_sender.clone();
+    let cs15 = controller_sender.clone();
+    let cs16 = controller_sender.clone();
     let cs5 = controller_sender;
     Flex::column()
         .with_child(Label::new("Duration after win"))
@@ -185,10 +451,18 @@ fn make_settings_widget(controller_sender: ControllerSender) -> impl Widget<Cont
             widget::ValueTextBox::new(widget::TextBox::new(), DurationFormatter {})
                 .lens(ControllerSettings::time_between_turns),
         )
+        .with_child(Label::new("Turn time limit, 0 for none"))
+        .with_child(
+            widget::ValueTextBox::new(widget::TextBox::new(), DurationFormatter {})
+                .lens(ControllerSettings::turn_time_limit),
+        )
         .with_child(Button::new("Apply delays").on_click(
             move |_: &mut EventCtx, settings: &mut ControllerSettings, _: &Env| {
                 cs5.send(ControllerMsg::SetTurnDelay(settings.time_between_turns));
                 cs5.send(ControllerMsg::SetWinDelay(settings.time_after_win));
+                cs5.send(ControllerMsg::SetTurnTimeLimit(
+                    Some(settings.turn_time_limit).filter(|limit| !limit.is_zero()),
+                ));
             },
         ))
         .with_child(Button::new("Go").on_click(
@@ -201,42 +475,466 @@ fn make_settings_widget(controller_sender: ControllerSender) -> impl Widget<Cont
                 cs3.send(ControllerMsg::GoToMode(controller::GameMode::Gating))
             },
         ))
+        .with_child(Button::new("Spectate").on_click(
+            move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
+                cs15.send(ControllerMsg::GoToMode(controller::GameMode::Spectate))
+            },
+        ))
+        .with_child(Button::new("Tournament").on_click(
+            move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
+                cs8.send(ControllerMsg::StartTournament)
+            },
+        ))
         .with_child(Button::new("Reset").on_click(
             move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
                 cs4.send(ControllerMsg::ResetGame);
             },
         ))
+        .with_child(Button::new("Undo").on_click(
+            move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
+                cs16.send(ControllerMsg::RequestUndo {
+                    player: OPERATOR_NAME.to_string(),
+                });
+            },
+        ))
+        .with_child(Button::new("Pause").on_click(
+            move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
+                cs6.send(ControllerMsg::PauseGame);
+            },
+        ))
+        .with_child(Button::new("Resume").on_click(
+            move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
+                cs7.send(ControllerMsg::ResumeGame);
+            },
+        ))
+        .with_child(Button::new("Require 1").on_click(
+            move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
+                cs13.send(ControllerMsg::SetMinPlayers(1));
+            },
+        ))
+        .with_child(Button::new("Require 2").on_click(
+            move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
+                cs14.send(ControllerMsg::SetMinPlayers(2));
+            },
+        ))
+        .with_child(Button::new("Reset View").on_click(
+            move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
+                cs9.send(ControllerMsg::ResetView);
+            },
+        ))
+        .with_child(Button::new("Play by clicking").on_click(
+            move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
+                cs10.send(ControllerMsg::EnableManualInput);
+            },
+        ))
+        .with_child(Button::new("Stop playing by clicking").on_click(
+            move |_: &mut EventCtx, _: &mut ControllerSettings, _: &Env| {
+                cs11.send(ControllerMsg::DisableManualInput);
+            },
+        ))
+        .with_child(
+            widget::TextBox::new()
+                .with_placeholder("Announcement")
+                .lens(ControllerSettings::announcement),
+        )
+        .with_child(Button::new("Broadcast").on_click(
+            move |_: &mut EventCtx, settings: &mut ControllerSettings, _: &Env| {
+                if !settings.announcement.is_empty() {
+                    cs12.send(ControllerMsg::Broadcast(std::mem::take(
+                        &mut settings.announcement,
+                    )));
+                }
+            },
+        ))
 }
 
-fn make_widget_connected_users() -> impl Widget<Vector<UiUser>> {
-    Flex::column().with_child(widget::Scroll::new(widget::List::new(|| {
-        EnvScope::new(
-            |env, UiUser { color, .. }| env.set(druid::theme::TEXT_COLOR, *color),
-            Label::new(|u: &UiUser, _env: &_| format!("* {} - {}", u.name, u.score))
+fn make_widget_connected_users(controller_sender: ControllerSender) -> impl Widget<Vector<UiUser>> {
+    Flex::column().with_child(widget::Scroll::new(widget::List::new(move || {
+        let cs = controller_sender.clone();
+        Flex::row()
+            .with_child(EnvScope::new(
+                |env, UiUser { color, .. }| env.set(druid::theme::TEXT_COLOR, *color),
+                Label::new(|u: &UiUser, _env: &_| {
+                    format!(
+                        "* {} - {} (elo {:.0}, {} moves, {})",
+                        u.name,
+                        u.score,
+                        u.elo,
+                        u.move_count,
+                        format_connected_for(u.connected_for)
+                    )
+                })
                 .with_text_size(36.0),
-        )
+            ))
+            .with_child(
+                Button::new("Kick")
+                    .on_click(move |_: &mut EventCtx, u: &mut UiUser, _: &Env| cs.kick(&u.name)),
+            )
     })))
 }
 
+fn make_widget_move_history(
+) -> widget::Scroll<Vector<MoveHistoryEntry>, widget::List<MoveHistoryEntry>> {
+    widget::Scroll::new(widget::List::new(|| {
+        EnvScope::new(
+            |env, MoveHistoryEntry { color, .. }| env.set(druid::theme::TEXT_COLOR, *color),
+            Label::new(|m: &MoveHistoryEntry, _env: &_| {
+                format!("{}. {}: {}", m.turn, m.player, m.move_text)
+            }),
+        )
+    }))
+    .vertical()
+}
+
+fn make_widget_chat() -> impl Widget<Vector<UiChat>> {
+    widget::Scroll::new(widget::List::new(|| {
+        Label::new(|c: &UiChat, _env: &_| format!("{}: {}", c.from, c.message))
+            .with_line_break_mode(widget::LineBreaking::WordWrap)
+    }))
+    .vertical()
+}
+
+// A button per game, nothing when there's only one
+fn make_widget_game_selector(controller_sender: ControllerSender) -> impl Widget<usize> {
+    let mut selector = Flex::row();
+    if controller_sender.game_count() > 1 {
+        selector.add_child(Label::new(|shown: &usize, _env: &_| {
+            format!("Game {}", shown + 1)
+        }));
+        for slot in 0..controller_sender.game_count() {
+            let cs = controller_sender.clone();
+            selector.add_child(Button::new(format!("{}", slot + 1)).on_click(
+                move |_: &mut EventCtx, shown: &mut usize, _: &Env| {
+                    cs.show_game(slot);
+                    *shown = slot;
+                },
+            ));
+        }
+    }
+    selector
+}
+
+// A column per round, a box per player. The winner of two boxes is in the box between them in the
+// next column
+struct BracketWidget {}
+
+const BRACKET_BOX_WIDTH: f64 = 110.0;
+const BRACKET_BOX_HEIGHT: f64 = 22.0;
+const BRACKET_COLUMN_GAP: f64 = 20.0;
+
+impl Widget<Vector<Vector<String>>> for BracketWidget {
+    fn event(
+        &mut self,
+        _ctx: &mut EventCtx,
+        _event: &Event,
+        _data: &mut Vector<Vector<String>>,
+        _env: &Env,
+    ) {
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &Vector<Vector<String>>,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &Vector<Vector<String>>,
+        data: &Vector<Vector<String>>,
+        _env: &Env,
+    ) {
+        if old_data.len() != data.len() {
+            ctx.request_layout();
+        }
+        ctx.request_paint();
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &Vector<Vector<String>>,
+        _env: &Env,
+    ) -> Size {
+        let first_round = data.front().map_or(0, |round| round.len());
+        bc.constrain(Size::new(
+            data.len() as f64 * (BRACKET_BOX_WIDTH + BRACKET_COLUMN_GAP),
+            first_round as f64 * BRACKET_BOX_HEIGHT * 1.5,
+        ))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &Vector<Vector<String>>, env: &Env) {
+        let height = ctx.size().height;
+        for (column, round) in data.iter().enumerate() {
+            let x = column as f64 * (BRACKET_BOX_WIDTH + BRACKET_COLUMN_GAP);
+            let row_height = height / round.len() as f64;
+            for (row, name) in round.iter().enumerate() {
+                let y = row as f64 * row_height + (row_height - BRACKET_BOX_HEIGHT) / 2.0;
+                let rect =
+                    druid::Rect::from_origin_size((x, y), (BRACKET_BOX_WIDTH, BRACKET_BOX_HEIGHT));
+                ctx.stroke(rect, &env.get(druid::theme::BORDER_LIGHT), 1.0);
+                let layout = ctx
+                    .text()
+                    .new_text_layout(name.clone())
+                    .text_color(env.get(druid::theme::TEXT_COLOR))
+                    .build()
+                    .unwrap();
+                ctx.draw_text(&layout, (x + 4.0, y + 3.0));
+            }
+        }
+    }
+}
+
+fn record_game_over(
+    score_history: &mut Vector<HashMap<String, u64>>,
+    score: &HashMap<String, u64>,
+) {
+    score_history.push_back(score.clone());
+}
+
+// How long the current game has been going, MM:SS
+struct TimerWidget {
+    timer: TimerToken,
+}
+
+const TIMER_TICK: Duration = Duration::from_secs(1);
+
+fn format_elapsed(game_start: Option<Instant>, now: Instant) -> String {
+    let secs = game_start.map_or(0, |start| now.saturating_duration_since(start).as_secs());
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+// 3m42s, hours are counted in minutes
+fn format_connected_for(connected_for: Duration) -> String {
+    let secs = connected_for.as_secs();
+    format!("{}m{:02}s", secs / 60, secs % 60)
+}
+
+impl Widget<Option<Instant>> for TimerWidget {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        _data: &mut Option<Instant>,
+        _env: &Env,
+    ) {
+        if let Event::Timer(token) = event {
+            if *token == self.timer {
+                ctx.request_paint();
+                self.timer = ctx.request_timer(TIMER_TICK);
+            }
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        _data: &Option<Instant>,
+        _env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.timer = ctx.request_timer(TIMER_TICK);
+        }
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        _old_data: &Option<Instant>,
+        _data: &Option<Instant>,
+        _env: &Env,
+    ) {
+        ctx.request_paint();
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &Option<Instant>,
+        _env: &Env,
+    ) -> Size {
+        bc.constrain(Size::new(80.0, 24.0))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &Option<Instant>, env: &Env) {
+        let layout = ctx
+            .text()
+            .new_text_layout(format_elapsed(*data, Instant::now()))
+            .text_color(env.get(druid::theme::TEXT_COLOR))
+            .build()
+            .unwrap();
+        ctx.draw_text(&layout, (4.0, 3.0));
+    }
+}
+
+// Wins over time, a line per player. Game number goes right, wins go up
+struct ScoreHistoryWidget {}
+
+const SCORE_HISTORY_HEIGHT: f64 = 150.0;
+const SCORE_HISTORY_MARGIN: f64 = 10.0;
+const SCORE_HISTORY_COLORS: [Color; 6] = [
+    Color::rgb8(230, 80, 80),
+    Color::rgb8(80, 160, 230),
+    Color::rgb8(100, 200, 100),
+    Color::rgb8(230, 200, 60),
+    Color::rgb8(180, 100, 220),
+    Color::rgb8(240, 140, 50),
+];
+
+impl Widget<Vector<HashMap<String, u64>>> for ScoreHistoryWidget {
+    fn event(
+        &mut self,
+        _ctx: &mut EventCtx,
+        _event: &Event,
+        _data: &mut Vector<HashMap<String, u64>>,
+        _env: &Env,
+    ) {
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &Vector<HashMap<String, u64>>,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        _old_data: &Vector<HashMap<String, u64>>,
+        _data: &Vector<HashMap<String, u64>>,
+        _env: &Env,
+    ) {
+        ctx.request_paint();
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &Vector<HashMap<String, u64>>,
+        _env: &Env,
+    ) -> Size {
+        bc.constrain(Size::new(bc.max().width, SCORE_HISTORY_HEIGHT))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &Vector<HashMap<String, u64>>, env: &Env) {
+        let size = ctx.size();
+        let left = SCORE_HISTORY_MARGIN;
+        let bottom = size.height - SCORE_HISTORY_MARGIN;
+        let width = size.width - 2.0 * SCORE_HISTORY_MARGIN;
+        let height = size.height - 2.0 * SCORE_HISTORY_MARGIN;
+
+        let axis_color = env.get(druid::theme::BORDER_LIGHT);
+        ctx.stroke(
+            Line::new((left, SCORE_HISTORY_MARGIN), (left, bottom)),
+            &axis_color,
+            1.0,
+        );
+        ctx.stroke(
+            Line::new((left, bottom), (left + width, bottom)),
+            &axis_color,
+            1.0,
+        );
+
+        let max_wins = data
+            .iter()
+            .flat_map(|score| score.values())
+            .max()
+            .copied()
+            .unwrap_or(0)
+            .max(1);
+        // A game per step, the first game is at the y axis
+        let step = width / (data.len().max(2) - 1) as f64;
+        let names: BTreeSet<&String> = data.iter().flat_map(|score| score.keys()).collect();
+        for (i, name) in names.into_iter().enumerate() {
+            let mut path = BezPath::new();
+            for (game, score) in data.iter().enumerate() {
+                let wins = *score.get(name).unwrap_or(&0);
+                let point = Point::new(
+                    left + game as f64 * step,
+                    bottom - wins as f64 / max_wins as f64 * height,
+                );
+                if game == 0 {
+                    path.move_to(point);
+                } else {
+                    path.line_to(point);
+                }
+            }
+            ctx.stroke(
+                path,
+                &SCORE_HISTORY_COLORS[i % SCORE_HISTORY_COLORS.len()],
+                2.0,
+            );
+        }
+    }
+}
+
 fn make_widget_game_mode() -> impl Widget<GameMode> {
-    Label::new(|m: &GameMode, _env: &_| format!("{:?}", m.clone()))
+    Label::new(|m: &GameMode, _env: &_| match m {
+        // Stands out, nobody is playing on their own
+        GameMode::Spectate => "SPECTATE".to_string(),
+        m => format!("{:?}", m.clone()),
+    })
 }
 
 fn make_widget(controller_sender: ControllerSender) -> impl Widget<AppData> {
-    Flex::row()
+    let top_bar = Flex::row()
+        .with_child(
+            Label::new(|turn: &u32, _env: &_| format!("Turn: {turn}")).lens(AppData::turn_number),
+        )
+        .with_spacer(10.0)
+        .with_child(
+            TimerWidget {
+                timer: TimerToken::INVALID,
+            }
+            .lens(AppData::game_start),
+        );
+    let main_area = Flex::row()
         .with_child(
             Flex::column()
+                .with_child(
+                    make_widget_game_selector(controller_sender.clone()).lens(AppData::shown_game),
+                )
                 .with_flex_child(
-                    make_settings_widget(controller_sender).lens(AppData::controller_settings),
+                    make_settings_widget(controller_sender.clone())
+                        .lens(AppData::controller_settings),
                     1.0,
                 )
                 .with_flex_child(make_widget_game_mode().lens(AppData::game_mode), 1.0)
+                .with_child(BracketWidget {}.lens(AppData::bracket))
                 .with_flex_child(
-                    make_widget_connected_users().lens(AppData::connected_users),
+                    make_widget_connected_users(controller_sender.clone())
+                        .lens(AppData::connected_users),
                     1.0,
-                ),
+                )
+                .with_flex_child(make_widget_move_history().lens(AppData::move_history), 1.0)
+                .with_flex_child(make_widget_chat().lens(AppData::chat), 1.0),
         )
-        .with_flex_child(GameWidget {}, 1.0)
+        .with_flex_child(
+            Flex::column()
+                .with_flex_child(
+                    GameWidget {
+                        view: BoardView::new(),
+                        controller_sender,
+                    },
+                    1.0,
+                )
+                .with_child(ScoreHistoryWidget {}.lens(AppData::score_history)),
+            1.0,
+        );
+    Flex::column()
+        .with_child(top_bar)
+        .with_flex_child(main_area, 1.0)
 }
 
 pub fn launch(
@@ -272,6 +970,171 @@ pub fn launch(
             controller_settings: ControllerSettings::default(),
             connected_users: Vector::new(),
             game_mode: GameMode::Practice,
+            move_history: Vector::new(),
+            chat: Vector::new(),
+            shown_game: 0,
+            bracket: Vector::new(),
+            score_history: Vector::new(),
+            game_start: None,
+            turn_number: 0,
+            manual_input_enabled: false,
         })
         .expect("launch failed");
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(turn: u32) -> MoveHistoryEntry {
+        MoveHistoryEntry {
+            player: "p1".to_string(),
+            color: druid::Color::rgb8(0, 0, 0),
+            move_text: format!("move {turn}"),
+            turn,
+        }
+    }
+
+    #[test]
+    fn zooming_changes_the_cell_size() {
+        let cell = druid::Rect::new(0.0, 0.0, 10.0, 10.0);
+        let mut view = BoardView::new();
+        assert_eq!(view.view_transform().transform_rect_bbox(cell), cell);
+
+        view.zoom_by(Vec2::new(0.0, -1.0));
+        let zoomed_in = view.view_transform().transform_rect_bbox(cell);
+        assert!(zoomed_in.width() > cell.width());
+
+        view.zoom_by(Vec2::new(0.0, 1.0));
+        view.zoom_by(Vec2::new(0.0, 1.0));
+        let zoomed_out = view.view_transform().transform_rect_bbox(cell);
+        assert!(zoomed_out.width() < cell.width());
+    }
+
+    #[test]
+    fn zoom_stays_within_limits() {
+        let mut view = BoardView::new();
+        for _ in 0..100 {
+            view.zoom_by(Vec2::new(0.0, -1.0));
+        }
+        assert_eq!(view.zoom, MAX_ZOOM);
+        for _ in 0..100 {
+            view.zoom_by(Vec2::new(0.0, 1.0));
+        }
+        assert_eq!(view.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn panning_moves_the_board() {
+        let mut view = BoardView::new();
+        view.pan = Vec2::new(5.0, -3.0);
+        assert_eq!(
+            view.view_transform() * Point::new(1.0, 1.0),
+            Point::new(6.0, -2.0)
+        );
+    }
+
+    #[test]
+    fn clicks_find_the_cell_under_them() {
+        let size = Size::new(100.0, 100.0);
+        let mut view = BoardView::new();
+        assert_eq!(
+            view.cell_at(Point::new(15.0, 35.0), size, (10, 10)),
+            Some((1, 3))
+        );
+
+        view.zoom = 2.0;
+        view.pan = Vec2::new(10.0, 0.0);
+        assert_eq!(
+            view.cell_at(Point::new(15.0, 35.0), size, (10, 10)),
+            Some((0, 1))
+        );
+    }
+
+    #[test]
+    fn clicks_off_the_board_are_ignored() {
+        let size = Size::new(100.0, 50.0);
+        let view = BoardView::new();
+        // 5 px cells, the board is 50 px wide and leaves the right half empty
+        assert_eq!(view.cell_at(Point::new(60.0, 10.0), size, (10, 10)), None);
+        assert_eq!(view.cell_at(Point::new(-1.0, 10.0), size, (10, 10)), None);
+        assert_eq!(view.cell_at(Point::new(10.0, 50.0), size, (10, 10)), None);
+        assert_eq!(
+            view.cell_at(Point::new(49.0, 49.0), size, (10, 10)),
+            Some((9, 9))
+        );
+    }
+
+    #[test]
+    fn timer_is_zero_between_games() {
+        assert_eq!(format_elapsed(None, Instant::now()), "00:00");
+    }
+
+    #[test]
+    fn connection_time_is_minutes_and_seconds() {
+        assert_eq!(format_connected_for(Duration::ZERO), "0m00s");
+        assert_eq!(format_connected_for(Duration::from_secs(222)), "3m42s");
+        assert_eq!(
+            format_connected_for(Duration::from_secs(2 * 3600)),
+            "120m00s"
+        );
+    }
+
+    #[test]
+    fn timer_counts_up() {
+        let start = Instant::now();
+        assert_eq!(format_elapsed(Some(start), start), "00:00");
+        assert_eq!(
+            format_elapsed(Some(start), start + Duration::from_secs(59)),
+            "00:59"
+        );
+        assert_eq!(
+            format_elapsed(Some(start), start + Duration::from_secs(65)),
+            "01:05"
+        );
+        assert_eq!(
+            format_elapsed(Some(start), start + Duration::from_secs(61 * 60)),
+            "61:00"
+        );
+    }
+
+    #[test]
+    fn score_history_has_an_entry_per_game() {
+        let mut score_history = Vector::new();
+        let mut score = HashMap::new();
+        for winner in ["p1", "p2", "p1"] {
+            *score.entry(winner.to_string()).or_insert(0) += 1;
+            record_game_over(&mut score_history, &score);
+        }
+
+        let wins = |game: usize, name: &str| score_history[game].get(name).copied();
+        assert_eq!(score_history.len(), 3);
+        assert_eq!((wins(0, "p1"), wins(0, "p2")), (Some(1), None));
+        assert_eq!((wins(1, "p1"), wins(1, "p2")), (Some(1), Some(1)));
+        assert_eq!((wins(2, "p1"), wins(2, "p2")), (Some(2), Some(1)));
+    }
+
+    #[test]
+    fn move_history_grows_by_one_per_move() {
+        let mut move_history = Vector::new();
+        push_move_history(&mut move_history, entry(1));
+        assert_eq!(move_history.len(), 1);
+        push_move_history(&mut move_history, entry(2));
+        assert_eq!(move_history.len(), 2);
+        assert_eq!(move_history.back().unwrap().turn, 2);
+    }
+
+    #[test]
+    fn oldest_moves_are_dropped_at_the_cap() {
+        let mut move_history = Vector::new();
+        for turn in 1..=UI_MOVE_HISTORY as u32 + 3 {
+            push_move_history(&mut move_history, entry(turn));
+        }
+        assert_eq!(move_history.len(), UI_MOVE_HISTORY);
+        assert_eq!(move_history.front().unwrap().turn, 4);
+        assert_eq!(
+            move_history.back().unwrap().turn,
+            UI_MOVE_HISTORY as u32 + 3
+        );
+    }
+}
@@ -1,47 +1,257 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr},
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use log::debug;
-use tokio::sync::{mpsc, oneshot};
+use log::{debug, info, warn};
+use tokio::{
+    select,
+    sync::{mpsc, oneshot, watch, Semaphore},
+    task::JoinSet,
+};
 
 use code_challenge_game_types::gametraits;
 use code_challenge_game_types::messages::{self, Auth, GameOver, ToClient};
 
 use crate::{
+    arena::ArenaController,
     controller,
     controller::{ControllerMsg, ControllerToPlayerMsg, GameOverReason, PlayerMoveMsg},
     network_wrap,
 };
 
-type UserPassDb = Arc<Mutex<HashMap<String, String>>>;
+// Username -> password hash
+pub type UserPassDb = Arc<Mutex<HashMap<String, String>>>;
+
+pub trait PasswordHasher {
+    /// None when the password can't be hashed, the login is turned away
+    fn hash(&self, password: &str) -> Option<String>;
+    fn verify(&self, password: &str, hash: &str) -> bool;
+}
+
+pub type SharedPasswordHasher = Arc<dyn PasswordHasher + Send + Sync>;
+
+pub struct BcryptHasher {
+    cost: u32,
+}
+
+impl BcryptHasher {
+    pub fn new(cost: u32) -> Self {
+        Self { cost }
+    }
+}
+
+impl Default for BcryptHasher {
+    fn default() -> Self {
+        Self::new(bcrypt::DEFAULT_COST)
+    }
+}
+
+impl PasswordHasher for BcryptHasher {
+    fn hash(&self, password: &str) -> Option<String> {
+        bcrypt::hash(password, self.cost)
+            .map_err(|e| warn!("Could not hash password: {e}"))
+            .ok()
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> bool {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    }
+}
+
+// TODO: Move into messages::Error once game-types has error codes
+const ERROR_INVALID_FORMAT: u16 = 1001;
+const ERROR_WRONG_PASSWORD: u16 = 1002;
+const ERROR_INVALID_MOVE: u16 = 1003;
+const ERROR_RATE_LIMITED: u16 = 1004;
+const ERROR_SERVER_FULL: u16 = 1005;
+const ERROR_CONNECTION: u16 = 1006;
+const ERROR_UNSUPPORTED_PROTOCOL: u16 = 1010;
+const ERROR_SERVER_GONE: u16 = 1011;
+const ERROR_AUTH_TIMEOUT: u16 = 1012;
+const ERROR_TURN_TIMEOUT: u16 = 1020;
+const ERROR_KICKED: u16 = 1021;
+const ERROR_GAME_FULL: u16 = 1022;
+
+/// How long a new connection has to send its auth message
+pub const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Clients that say which protocol they speak have to speak this one
+pub const CURRENT_PROTOCOL_VERSION: u16 = 1;
+// TODO: Replace with messages::ToClient::Info once game-types has it
+const PAUSED: &str = r#"{"info":{"status":"paused"}}"#;
+const RESUMED: &str = r#"{"info":{"status":"resumed"}}"#;
+// TODO: Replace with messages::ToClient::UndoGranted once game-types has it
+const UNDO_GRANTED: &str = r#"{"undo-granted":null}"#;
+
+/// Counts failed logins per IP. Once an IP has `max_attempts` failures within `window` of the
+/// first one, it's turned away until the window is over
+pub struct RateLimiter {
+    // Failures, and when the first of them happened
+    counts: HashMap<IpAddr, (u32, Instant)>,
+    window: Duration,
+    max_attempts: u32,
+}
+
+pub type SharedRateLimiter = Arc<Mutex<RateLimiter>>;
+
+impl RateLimiter {
+    pub fn new(max_attempts: u32, window: Duration) -> Self {
+        Self {
+            counts: HashMap::new(),
+            window,
+            max_attempts,
+        }
+    }
+
+    pub fn is_limited(&mut self, ip: IpAddr, now: Instant) -> bool {
+        match self.counts.get(&ip) {
+            Some((failures, first_failure)) if now.duration_since(*first_failure) < self.window => {
+                *failures >= self.max_attempts
+            }
+            Some(_) => {
+                self.counts.remove(&ip);
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub fn record_failure(&mut self, ip: IpAddr, now: Instant) {
+        let (failures, first_failure) = self.counts.entry(ip).or_insert((0, now));
+        if now.duration_since(*first_failure) >= self.window {
+            *failures = 0;
+            *first_failure = now;
+        }
+        *failures += 1;
+    }
+}
 
 pub async fn accept_connection_loop(
     mut listener: impl network_wrap::Listener,
-    tx: mpsc::Sender<ControllerMsg>,
+    arena: ArenaController,
+    hasher: SharedPasswordHasher,
+    mut shutdown_rx: watch::Receiver<bool>,
+    max_connections: usize,
+    rate_limiter: SharedRateLimiter,
+    auth_timeout: Duration,
 ) {
     let user_password_db: UserPassDb = Arc::new(Mutex::new(HashMap::new()));
+    let connection_permits = Arc::new(Semaphore::new(max_connections));
+    let mut connections = JoinSet::new();
     loop {
         debug!("App is waiting for new connections");
-        let stream: Box<dyn network_wrap::Stream + Send> = listener.accept().await.unwrap();
+        let (stream, addr): (Box<dyn network_wrap::Stream + Send>, SocketAddr) = select! {
+            accepted = listener.accept() => accepted.unwrap(),
+            Some(_) = connections.join_next() => continue,
+            _ = shutdown_rx.changed() => break,
+        };
+        let permit = match connection_permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                info!("Rejecting connection, already {max_connections} connected");
+                send_error_and_close(stream, ERROR_SERVER_FULL, "server full").await;
+                continue;
+            }
+        };
         // A new task is spawned for each inbound socket. The socket is
         // moved to the new task and processed there.
-        let tx2 = tx.clone();
+        let arena2 = arena.clone();
         let db2 = user_password_db.clone();
-        tokio::spawn(async {
+        let hasher2 = hasher.clone();
+        let rate_limiter2 = rate_limiter.clone();
+        connections.spawn(async move {
+            // Hold on to the permit for as long as the user is connected
+            let _permit = permit;
             // throw away any error, it's okay, a dropped connection is handled just fine
-            match process_user_connection(stream, tx2, db2).await {
+            match process_user_connection(
+                stream,
+                addr,
+                arena2,
+                db2,
+                hasher2,
+                rate_limiter2,
+                auth_timeout,
+            )
+            .await
+            {
                 Ok(()) => debug!("User disconnected gracefully"),
-                Err(e) => debug!("User disconnected with error {e:?}"),
+                Err(ConnectionError::ConnectionClosed) => debug!("User closed the connection"),
+                Err(e) => warn!("User disconnected with error: {e}"),
             }
         });
     }
+
+    info!("Stopped accepting connections");
+    // Let the players get their last messages, anyone still connected after that is cut off
+    // when the set is dropped
+    let _ = tokio::time::timeout(std::time::Duration::from_secs(1), async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
 }
 
 #[derive(Debug)]
-enum MyErr {
-    AnyHow(String),
+pub enum ConnectionError {
+    IoError(std::io::Error),
+    AuthFailed(String),
+    AuthTimeout,
+    ControllerGone,
+    ConnectionClosed,
+    ParseError(String),
+    TurnTimeout,
+    Kicked,
+    GameFull,
+    MoveRejected,
+    RateLimited,
+    UnsupportedProtocol,
+}
+
+impl std::fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionError::IoError(e) => write!(f, "io error: {e}"),
+            ConnectionError::AuthFailed(reason) => write!(f, "auth failed: {reason}"),
+            ConnectionError::AuthTimeout => write!(f, "no auth in time"),
+            ConnectionError::ControllerGone => write!(f, "controller is gone"),
+            ConnectionError::ConnectionClosed => write!(f, "connection closed"),
+            ConnectionError::ParseError(e) => write!(f, "could not parse message: {e}"),
+            ConnectionError::TurnTimeout => write!(f, "turn timed out"),
+            ConnectionError::Kicked => write!(f, "kicked by server"),
+            ConnectionError::GameFull => write!(f, "game is full"),
+            ConnectionError::MoveRejected => write!(f, "move rejected by the game"),
+            ConnectionError::RateLimited => write!(f, "too many failed logins"),
+            ConnectionError::UnsupportedProtocol => write!(f, "unsupported protocol version"),
+        }
+    }
+}
+
+impl std::error::Error for ConnectionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConnectionError::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ConnectionError {
+    fn from(error: std::io::Error) -> Self {
+        ConnectionError::IoError(error)
+    }
+}
+
+impl From<network_wrap::Error> for ConnectionError {
+    fn from(error: network_wrap::Error) -> Self {
+        match error {
+            network_wrap::Error::ConnectionClosed => ConnectionError::ConnectionClosed,
+            network_wrap::Error::Custom(e) => {
+                ConnectionError::IoError(std::io::Error::new(std::io::ErrorKind::Other, e))
+            }
+        }
+    }
 }
 
 async fn write_json(stream: &mut Box<dyn network_wrap::Stream + Send>, v: messages::ToClient) {
@@ -51,104 +261,453 @@ async fn write_json(stream: &mut Box<dyn network_wrap::Stream + Send>, v: messag
         .unwrap()
 }
 
+async fn write_game_over(
+    stream: &mut Box<dyn network_wrap::Stream + Send>,
+    reason: GameOverReason,
+) {
+    let reason_str = match reason {
+        GameOverReason::Winner(winner) => "winner ".to_string() + &winner,
+        GameOverReason::Draw => "draw".to_string(),
+        GameOverReason::Forfeit { .. } => "opponent resigned".to_string(),
+        GameOverReason::Eliminated => "eliminated".to_string(),
+        GameOverReason::Shutdown => "server shutting down".to_string(),
+    };
+    write_json(stream, ToClient::GameOver(GameOver { reason: reason_str })).await;
+}
+
+async fn write_line(stream: &mut Box<dyn network_wrap::Stream + Send>, line: &str) {
+    // Player might already be gone, doesn't matter
+    let _ = stream.write(&(line.to_string() + "\n")).await;
+}
+
+// {"error":{"code":1011,"reason":"server shutting down"}}
+// TODO: Replace with messages::ToClient::Error once game-types has error codes
+async fn send_error_and_close(
+    mut stream: Box<dyn network_wrap::Stream + Send>,
+    code: u16,
+    reason: &str,
+) {
+    write_line(
+        &mut stream,
+        &serde_json::json!({ "error": { "code": code, "reason": reason } }).to_string(),
+    )
+    .await;
+    // The connection closes when the stream is dropped here
+}
+
+// Whatever the player was doing, there's no game to do it in anymore
+async fn controller_gone(stream: Box<dyn network_wrap::Stream + Send>) -> ConnectionError {
+    send_error_and_close(stream, ERROR_SERVER_GONE, "server shutting down").await;
+    ConnectionError::ControllerGone
+}
+
+// Not every stream error means the client is gone, e.g. an oversized MessagePack frame
+async fn stream_failed(
+    stream: Box<dyn network_wrap::Stream + Send>,
+    error: network_wrap::Error,
+) -> ConnectionError {
+    send_error_and_close(stream, ERROR_CONNECTION, "connection error").await;
+    error.into()
+}
+
+// {"player-list":["alice","bob"]}
+// TODO: Replace with messages::ToClient::PlayerList once game-types has it
+async fn write_player_list(stream: &mut Box<dyn network_wrap::Stream + Send>, names: &[String]) {
+    write_line(
+        stream,
+        &serde_json::json!({ "player-list": names }).to_string(),
+    )
+    .await;
+}
+
+// {"chat":{"from":"alice","message":"good luck"}}
+// TODO: Replace with messages::ToClient::Chat once game-types has it
+async fn write_chat(stream: &mut Box<dyn network_wrap::Stream + Send>, from: &str, message: &str) {
+    write_line(
+        stream,
+        &serde_json::json!({ "chat": { "from": from, "message": message } }).to_string(),
+    )
+    .await;
+}
+
+// {"draw-offer":{"from":"alice"}}
+// TODO: Replace with messages::ToClient::DrawOffer once game-types has it
+async fn write_draw_offer(stream: &mut Box<dyn network_wrap::Stream + Send>, from: &str) {
+    write_line(
+        stream,
+        &serde_json::json!({ "draw-offer": { "from": from } }).to_string(),
+    )
+    .await;
+}
+
+// {"info":{"message":"server restarts in 5 minutes"}}
+// TODO: Replace with messages::ToClient::Info once game-types has it
+async fn write_info(stream: &mut Box<dyn network_wrap::Stream + Send>, message: &str) {
+    write_line(
+        stream,
+        &serde_json::json!({ "info": { "message": message } }).to_string(),
+    )
+    .await;
+}
+
+// {"undo-denied":{"reason":"not supported"}}
+// TODO: Replace with messages::ToClient::UndoDenied once game-types has it
+async fn write_undo_denied(stream: &mut Box<dyn network_wrap::Stream + Send>, reason: &str) {
+    write_line(
+        stream,
+        &serde_json::json!({ "undo-denied": { "reason": reason } }).to_string(),
+    )
+    .await;
+}
+
+// {"score":[{"player":"alice","wins":3},{"player":"bob","wins":1}]}
+// TODO: Replace with messages::ToClient::Score once game-types has it
+async fn write_score(
+    stream: &mut Box<dyn network_wrap::Stream + Send>,
+    scores: &[controller::PlayerScore],
+) {
+    write_line(stream, &serde_json::json!({ "score": scores }).to_string()).await;
+}
+
+// {"info":{"status":"waiting","queue-position":2}}
+// TODO: Replace with messages::ToClient::Info once game-types has it
+async fn write_waiting(stream: &mut Box<dyn network_wrap::Stream + Send>, queue_position: usize) {
+    write_line(
+        stream,
+        &serde_json::json!({ "info": { "status": "waiting", "queue-position": queue_position } })
+            .to_string(),
+    )
+    .await;
+}
+
+// {"game-started":{"game":"Gomoku 20x20","players":["alice","bob"]}}
+// TODO: Replace with messages::ToClient::GameStarted once game-types has it
+async fn write_game_started(
+    stream: &mut Box<dyn network_wrap::Stream + Send>,
+    game: &str,
+    players: &[String],
+) {
+    write_line(
+        stream,
+        &serde_json::json!({ "game-started": { "game": game, "players": players } }).to_string(),
+    )
+    .await;
+}
+
+// {"server-info":{"version":"0.1.0","game":"Gomoku 20x20","min-players":1,...}}
+// TODO: Replace with messages::ToClient::ServerInfo once game-types has it
+async fn write_server_info(
+    stream: &mut Box<dyn network_wrap::Stream + Send>,
+    info: &controller::ServerInfo,
+) {
+    write_line(
+        stream,
+        &serde_json::json!({ "server-info": info }).to_string(),
+    )
+    .await;
+}
+
 async fn process_user_connection(
     mut stream: Box<dyn network_wrap::Stream + Send>,
-    tx: mpsc::Sender<ControllerMsg>,
+    addr: SocketAddr,
+    arena: ArenaController,
     mut user_pass_db: UserPassDb,
-) -> Result<(), MyErr> {
+    hasher: SharedPasswordHasher,
+    rate_limiter: SharedRateLimiter,
+    auth_timeout: Duration,
+) -> Result<(), ConnectionError> {
+    if rate_limiter
+        .lock()
+        .unwrap()
+        .is_limited(addr.ip(), Instant::now())
+    {
+        info!("Rejecting connection from {addr}, too many failed logins");
+        send_error_and_close(stream, ERROR_RATE_LIMITED, "rate limited").await;
+        return Err(ConnectionError::RateLimited);
+    }
     debug!("Got a connection, waiting for auth");
     let (player_game_state_tx, mut from_controller_rx) =
         mpsc::channel::<ControllerToPlayerMsg>(1024);
 
     // Step 1. Authorize
     let my_name;
-    match stream.read_line().await {
-        Err(network_wrap::Error::ConnectionClosed) => {
-            return Err(MyErr::AnyHow("Closed connection before auth".to_string()));
-        }
-        Err(_) => {
-            return Err(MyErr::AnyHow(
-                "Error reading line from connection before auth".to_string(),
-            ));
+    let spectating;
+    let tx;
+    let Ok(auth_line) = tokio::time::timeout(auth_timeout, stream.read_line()).await else {
+        info!("No auth from {addr} within {auth_timeout:?}, closing");
+        send_error_and_close(stream, ERROR_AUTH_TIMEOUT, "auth timeout").await;
+        return Err(ConnectionError::AuthTimeout);
+    };
+    match auth_line {
+        Err(e) => return Err(e.into()),
+        Ok(line) if !supported_protocol(&line) => {
+            // Before the password is looked at, so nobody gets registered by a client that
+            // can't play anyway
+            info!("Rejecting connection from {addr}, unsupported protocol version");
+            send_error_and_close(
+                stream,
+                ERROR_UNSUPPORTED_PROTOCOL,
+                "unsupported protocol version",
+            )
+            .await;
+            return Err(ConnectionError::UnsupportedProtocol);
         }
         Ok(line) => {
-            match authorize(&line, &mut user_pass_db) {
+            // bcrypt is slow on purpose, keep it off the async workers
+            let auth = {
+                let line = line.clone();
+                tokio::task::spawn_blocking(move || authorize(&line, &mut user_pass_db, &*hasher))
+                    .await
+                    .unwrap_or(Err(messages::WRONG_PASSWORD))
+            };
+            match auth {
                 Ok(name) => {
-                    if tx
-                        .send(ControllerMsg::ImConnected(controller::ImConnectedMsg {
+                    spectating = wants_to_spectate(&line);
+                    let connected_msg = if spectating {
+                        ControllerMsg::ImSpectating(controller::SpectatorMsg {
+                            name: name.clone(),
+                            tx: player_game_state_tx,
+                        })
+                    } else {
+                        ControllerMsg::ImConnected(controller::ImConnectedMsg {
                             player_name: name.clone(),
                             controller_to_player_sender: player_game_state_tx,
-                        }))
-                        .await
-                        .is_err()
-                    {
-                        return Err(MyErr::AnyHow(
-                            "Failed sending player connected to controller".to_string(),
-                        ));
-                    }
+                            session_token: session_token(&line),
+                        })
+                    };
+                    tx = match arena.join(connected_msg).await {
+                        Some(tx) => tx,
+                        None => return Err(controller_gone(stream).await),
+                    };
                     my_name = name.clone();
                     debug!("Authorization successful");
-                    // Send nothing, wait your turn then play!
+                    // The controller sends the server info first, then wait your turn and play!
                 }
                 Err(response) => {
-                    write_json(&mut stream, response).await;
-                    return Err(MyErr::AnyHow("Auth failed".to_string()));
+                    rate_limiter
+                        .lock()
+                        .unwrap()
+                        .record_failure(addr.ip(), Instant::now());
+                    let (code, reason) = auth_error(&response);
+                    send_error_and_close(stream, code, reason).await;
+                    return Err(ConnectionError::AuthFailed(reason.to_string()));
                 }
             }
         }
     }
 
+    if spectating {
+        return spectate(stream, from_controller_rx).await;
+    }
+
     // Step 2. loop -> send state -> get move
-    loop {
+    // Messages from the controller that arrived while we were waiting for the user
+    let mut backlog = VecDeque::<ControllerToPlayerMsg>::new();
+    'turns: loop {
         // Controller is telling us it's our turn
         debug!("[{my_name}] Waiting for game state from controller");
-        let (game_state, move_tx) = match from_controller_rx.recv().await {
+        let controller_msg = match backlog.pop_front() {
+            Some(msg) => Some(msg),
+            None => from_controller_rx.recv().await,
+        };
+        let (game_state, move_tx) = match controller_msg {
             Some(ControllerToPlayerMsg::YourTurn(s, move_tx)) => (s, move_tx),
             Some(ControllerToPlayerMsg::GameOver(reason)) => {
-                let reason_str = match reason {
-                    GameOverReason::Winner(winner) => "winner ".to_string() + &winner,
-                    GameOverReason::Draw => "draw".to_string(),
-                };
-                write_json(
-                    &mut stream,
-                    ToClient::GameOver(GameOver { reason: reason_str }),
-                )
-                .await;
+                write_game_over(&mut stream, reason).await;
+                continue;
+            }
+            // In spectate mode, we watch like a spectator does and are never asked to move
+            Some(ControllerToPlayerMsg::GameState(state)) => {
+                if let Err(e) = stream.write(&state.serialized).await {
+                    return Err(stream_failed(stream, e).await);
+                }
+                continue;
+            }
+            Some(ControllerToPlayerMsg::Paused) => {
+                write_line(&mut stream, PAUSED).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::Resumed) => {
+                write_line(&mut stream, RESUMED).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::ServerInfo(info)) => {
+                write_server_info(&mut stream, &info).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::PlayerList(names)) => {
+                write_player_list(&mut stream, &names).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::GameStarted { game, players }) => {
+                write_game_started(&mut stream, &game, &players).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::Chat { from, message }) => {
+                write_chat(&mut stream, &from, &message).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::DrawOffer { from }) => {
+                write_draw_offer(&mut stream, &from).await;
                 continue;
             }
-            None => return Err(MyErr::AnyHow("Controlled dropped me".to_string())),
+            Some(ControllerToPlayerMsg::Score(scores)) => {
+                write_score(&mut stream, &scores).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::UndoGranted) => {
+                write_line(&mut stream, UNDO_GRANTED).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::UndoDenied { reason }) => {
+                write_undo_denied(&mut stream, &reason).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::Info(message)) => {
+                write_info(&mut stream, &message).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::Waiting { queue_position }) => {
+                write_waiting(&mut stream, queue_position).await;
+                continue;
+            }
+            Some(ControllerToPlayerMsg::TurnTimeout) => {
+                // Our move arrived too late
+                send_error_and_close(stream, ERROR_TURN_TIMEOUT, "turn timeout").await;
+                return Err(ConnectionError::TurnTimeout);
+            }
+            Some(ControllerToPlayerMsg::Kicked) => {
+                send_error_and_close(stream, ERROR_KICKED, "kicked by server").await;
+                return Err(ConnectionError::Kicked);
+            }
+            Some(ControllerToPlayerMsg::GameFull) => {
+                send_error_and_close(stream, ERROR_GAME_FULL, "game is full").await;
+                return Err(ConnectionError::GameFull);
+            }
+            None => return Err(controller_gone(stream).await),
         };
 
         // Send game state to player
         debug!("[{my_name}] Got game state from controller, sending to network user");
-        match stream.write(&game_state.serialized).await {
-            Ok(()) => (),
-            Err(_) => {
-                tx.send(ControllerMsg::ImDisconnected(my_name.clone()))
-                    .await
-                    .unwrap();
-                return Err(MyErr::AnyHow("Player disconnected".to_string()));
+        if let Err(e) = stream.write(&game_state.serialized).await {
+            if tx
+                .send(ControllerMsg::ImDisconnected(my_name.clone()))
+                .await
+                .is_err()
+            {
+                return Err(controller_gone(stream).await);
             }
+            return Err(stream_failed(stream, e).await);
         }
 
         // Receive move from player
         debug!("[{my_name}] Game state sent, waiting for network reply from user");
-        let player_resp = match stream.read_line().await {
-            Err(_) => {
-                tx.send(ControllerMsg::ImDisconnected(my_name))
+        let read_result = loop {
+            let interrupted_by = {
+                // Messages that can wait until after the move don't cancel the read, a restarted
+                // read could lose whatever part of the line had already arrived
+                let read = stream.read_line();
+                tokio::pin!(read);
+                loop {
+                    select! {
+                        line = &mut read => break WhileReading::Line(line),
+                        msg = from_controller_rx.recv() => match msg {
+                            Some(
+                                msg @ (ControllerToPlayerMsg::YourTurn(..)
+                                | ControllerToPlayerMsg::GameState(_)
+                                | ControllerToPlayerMsg::ServerInfo(_)
+                                | ControllerToPlayerMsg::PlayerList(_)
+                                | ControllerToPlayerMsg::GameStarted { .. }
+                                | ControllerToPlayerMsg::Chat { .. }
+                                | ControllerToPlayerMsg::Info(_)
+                                | ControllerToPlayerMsg::DrawOffer { .. }
+                                | ControllerToPlayerMsg::Score(_)),
+                            ) => backlog.push_back(msg),
+                            msg => break WhileReading::Controller(msg),
+                        }
+                    }
+                }
+            };
+            match interrupted_by {
+                // These don't use up the turn, keep waiting for the move
+                WhileReading::Line(Ok(line)) => match side_message(&my_name, line.trim()) {
+                    Some(msg) => {
+                        if tx.send(msg).await.is_err() {
+                            return Err(controller_gone(stream).await);
+                        }
+                    }
+                    None => break Ok(line),
+                },
+                WhileReading::Line(line) => break line,
+                WhileReading::Controller(Some(ControllerToPlayerMsg::TurnTimeout)) => {
+                    send_error_and_close(stream, ERROR_TURN_TIMEOUT, "turn timeout").await;
+                    return Err(ConnectionError::TurnTimeout);
+                }
+                WhileReading::Controller(Some(ControllerToPlayerMsg::Kicked)) => {
+                    send_error_and_close(stream, ERROR_KICKED, "kicked by server").await;
+                    return Err(ConnectionError::Kicked);
+                }
+                // The game we're thinking about is already over
+                WhileReading::Controller(Some(ControllerToPlayerMsg::GameOver(reason))) => {
+                    write_game_over(&mut stream, reason).await
+                }
+                // A move sent during the pause is dropped, we'll be asked again on resume
+                WhileReading::Controller(Some(ControllerToPlayerMsg::Paused)) => {
+                    write_line(&mut stream, PAUSED).await
+                }
+                WhileReading::Controller(Some(ControllerToPlayerMsg::Resumed)) => {
+                    write_line(&mut stream, RESUMED).await
+                }
+                // We asked, and our turn went back to whoever made the undone move. Whatever we'd
+                // send for it is dropped, our next turn comes as a your-turn like any other
+                WhileReading::Controller(Some(ControllerToPlayerMsg::UndoGranted)) => {
+                    write_line(&mut stream, UNDO_GRANTED).await;
+                    continue 'turns;
+                }
+                WhileReading::Controller(Some(ControllerToPlayerMsg::UndoDenied { reason })) => {
+                    write_undo_denied(&mut stream, &reason).await
+                }
+                WhileReading::Controller(Some(msg)) => backlog.push_back(msg),
+                WhileReading::Controller(None) => return Err(controller_gone(stream).await),
+            }
+        };
+        let received_at = Instant::now();
+        let player_resp = match read_result {
+            Err(e) => {
+                if tx
+                    .send(ControllerMsg::ImDisconnected(my_name))
                     .await
-                    .unwrap();
-                return Err(MyErr::AnyHow(
-                    "Error reading line from connection".to_string(),
-                ));
+                    .is_err()
+                {
+                    return Err(controller_gone(stream).await);
+                }
+                return Err(stream_failed(stream, e).await);
             }
-            Ok(line) => Ok(line),
-        }?;
+            Ok(line) => line,
+        };
         debug!("[{my_name}] Got reply from network user");
 
+        if is_resign(player_resp.trim()) {
+            debug!("[{my_name}] Resigned");
+            if tx.send(ControllerMsg::Resign(my_name)).await.is_err() {
+                return Err(controller_gone(stream).await);
+            }
+            write_json(
+                &mut stream,
+                ToClient::GameOver(GameOver {
+                    reason: "resigned".to_string(),
+                }),
+            )
+            .await;
+            return Ok(());
+        }
+
         let player_move = match player_response_to_move(player_resp.trim()) {
-            Ok(p_move) => p_move,
-            Err(e) => return Err(e),
+            Ok(player_move) => player_move,
+            Err(e) => {
+                send_error_and_close(stream, ERROR_INVALID_FORMAT, "invalid message format").await;
+                return Err(e);
+            }
         };
 
         // Send player move to controller
@@ -157,56 +716,412 @@ async fn process_user_connection(
             .send(PlayerMoveMsg {
                 mov: player_move,
                 move_err_tx,
+                received_at,
             })
             .is_ok()
         {
             if let Ok(err) = move_err_rx.await {
-                write_json(&mut stream, err).await;
-                return Err(MyErr::AnyHow("Move failure".to_string()));
+                let (code, reason) = move_error(&err);
+                send_error_and_close(stream, code, reason).await;
+                return Err(ConnectionError::MoveRejected);
             }
         } // Else my move was dropped, whatever
     }
 }
 
+enum WhileReading {
+    Line(Result<String, network_wrap::Error>),
+    Controller(Option<ControllerToPlayerMsg>),
+}
+
+async fn spectate(
+    mut stream: Box<dyn network_wrap::Stream + Send>,
+    mut from_controller_rx: mpsc::Receiver<ControllerToPlayerMsg>,
+) -> Result<(), ConnectionError> {
+    loop {
+        match from_controller_rx.recv().await {
+            Some(ControllerToPlayerMsg::GameState(state)) => {
+                if let Err(e) = stream.write(&state.serialized).await {
+                    return Err(stream_failed(stream, e).await);
+                }
+            }
+            Some(ControllerToPlayerMsg::GameOver(reason)) => {
+                write_game_over(&mut stream, reason).await;
+            }
+            Some(ControllerToPlayerMsg::Paused) => write_line(&mut stream, PAUSED).await,
+            Some(ControllerToPlayerMsg::Resumed) => write_line(&mut stream, RESUMED).await,
+            Some(ControllerToPlayerMsg::ServerInfo(info)) => {
+                write_server_info(&mut stream, &info).await
+            }
+            Some(ControllerToPlayerMsg::PlayerList(names)) => {
+                write_player_list(&mut stream, &names).await
+            }
+            Some(ControllerToPlayerMsg::GameStarted { game, players }) => {
+                write_game_started(&mut stream, &game, &players).await
+            }
+            Some(ControllerToPlayerMsg::Chat { from, message }) => {
+                write_chat(&mut stream, &from, &message).await
+            }
+            Some(ControllerToPlayerMsg::Score(scores)) => write_score(&mut stream, &scores).await,
+            Some(ControllerToPlayerMsg::UndoGranted) => write_line(&mut stream, UNDO_GRANTED).await,
+            Some(ControllerToPlayerMsg::Info(message)) => write_info(&mut stream, &message).await,
+            // Spectators never get to move
+            Some(_) => (),
+            None => return Err(controller_gone(stream).await),
+        }
+    }
+}
+
 #[allow(dead_code)]
 fn json_error(err: &str) -> String {
     "{'error': '".to_string() + err + "'}"
 }
 
+// Until messages::Error has codes, the two errors `authorize` gives are told apart by their JSON
+fn auth_error(response: &ToClient) -> (u16, &'static str) {
+    if serde_json::to_string(response).ok() == serde_json::to_string(&messages::WRONG_PASSWORD).ok()
+    {
+        (ERROR_WRONG_PASSWORD, "wrong password")
+    } else {
+        (ERROR_INVALID_FORMAT, "invalid message format")
+    }
+}
+
+// The controller rejects moves with the messages::Error it has, told apart the same way
+fn move_error(response: &ToClient) -> (u16, &'static str) {
+    if serde_json::to_string(response).ok() == serde_json::to_string(&messages::INVALID_MOVE).ok() {
+        (ERROR_INVALID_MOVE, "invalid move")
+    } else {
+        (ERROR_INVALID_FORMAT, "invalid message format")
+    }
+}
+
 type Username = String;
 
-fn authorize(line: &str, user_pass_db: &mut UserPassDb) -> Result<Username, ToClient> {
+pub const MAX_USERNAME_LEN: usize = 32;
+
+// Names end up in board states and the UI, keep them boring
+fn valid_username(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() => (),
+        _ => return false,
+    }
+    name.len() <= MAX_USERNAME_LEN && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Checks the auth line against the stored password, a name not seen before is registered.
+/// Public for the fuzzer
+pub fn authorize(
+    line: &str,
+    user_pass_db: &mut UserPassDb,
+    hasher: &dyn PasswordHasher,
+) -> Result<Username, ToClient> {
     match serde_json::from_str::<messages::FromClient>(line) {
+        Ok(messages::FromClient::Auth(Auth { username, .. })) if !valid_username(&username) => {
+            Err(messages::INVALID_MESSAGE_FORMAT)
+        }
         Ok(messages::FromClient::Auth(Auth { username, password })) => {
-            let db_password = user_pass_db
-                .lock()
-                .unwrap()
-                .get(&username)
-                .map(|v| v.to_string());
-
-            match db_password {
-                Some(db_password) => {
-                    if db_password == password {
-                        Ok(username)
-                    } else {
-                        Err(messages::WRONG_PASSWORD)
-                    }
-                }
+            // Never hash while holding the lock, every other login would wait for it
+            let db_hash = user_pass_db.lock().unwrap().get(&username).cloned();
+            let db_hash = match db_hash {
+                Some(db_hash) => db_hash,
                 None => {
-                    user_pass_db
-                        .lock()
-                        .unwrap()
-                        .insert(username.clone(), password);
-                    Ok(username)
+                    let new_hash = hasher.hash(&password).ok_or(messages::WRONG_PASSWORD)?;
+                    let mut db = user_pass_db.lock().unwrap();
+                    let db_hash = db.entry(username.clone()).or_insert(new_hash.clone());
+                    if *db_hash == new_hash {
+                        return Ok(username);
+                    }
+                    // Someone registered the name while we were hashing
+                    db_hash.clone()
                 }
+            };
+            if hasher.verify(&password, &db_hash) {
+                Ok(username)
+            } else {
+                Err(messages::WRONG_PASSWORD)
             }
         }
         _ => Err(messages::INVALID_MESSAGE_FORMAT),
     }
 }
 
-fn player_response_to_move(line: &str) -> Result<gametraits::PlayerMove, MyErr> {
+// {"auth":{..., "spectate":true}}
+// TODO: Replace with messages::Auth::spectate once game-types has it
+fn wants_to_spectate(auth_line: &str) -> bool {
+    match serde_json::from_str::<serde_json::Value>(auth_line) {
+        Ok(auth) => auth["auth"]["spectate"] == serde_json::Value::Bool(true),
+        Err(_) => false,
+    }
+}
+
+// {"auth":{..., "session_token":"a1b2c3"}}, picked by the client
+// TODO: Replace with messages::Auth::session_token once game-types has it
+fn session_token(auth_line: &str) -> Option<String> {
+    match serde_json::from_str::<serde_json::Value>(auth_line) {
+        Ok(auth) => auth["auth"]["session_token"]
+            .as_str()
+            .filter(|token| !token.is_empty())
+            .map(str::to_string),
+        Err(_) => None,
+    }
+}
+
+// {"auth":{..., "protocol_version":1}}, old clients leave it out and are let in
+// TODO: Replace with messages::Auth::protocol_version once game-types has it
+fn supported_protocol(auth_line: &str) -> bool {
+    match serde_json::from_str::<serde_json::Value>(auth_line) {
+        Ok(auth) => match &auth["auth"]["protocol_version"] {
+            serde_json::Value::Null => true,
+            version => version.as_u64() == Some(CURRENT_PROTOCOL_VERSION.into()),
+        },
+        // Not for us to say, authorize turns it away
+        Err(_) => true,
+    }
+}
+
+// {"resign":null}
+// TODO: Replace with messages::FromClient::Resign once game-types has it
+fn is_resign(line: &str) -> bool {
+    bare_message(line).as_deref() == Some("resign")
+}
+
+// The name of a message without content, like {"resign":null}
+fn bare_message(line: &str) -> Option<String> {
+    match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(serde_json::Value::Object(msg)) if msg.len() == 1 => msg
+            .into_iter()
+            .find(|(_, v)| v.is_null())
+            .map(|(name, _)| name),
+        _ => None,
+    }
+}
+
+// Chat, or {"offer-draw":null}, {"accept-draw":null}, {"decline-draw":null} and
+// {"request-undo":null}
+// TODO: Replace with messages::FromClient::OfferDraw/AcceptDraw/DeclineDraw/RequestUndo once
+// game-types has them
+fn side_message(my_name: &str, line: &str) -> Option<ControllerMsg> {
+    let from = my_name.to_string();
+    if let Some(message) = chat_message(line) {
+        return Some(ControllerMsg::Chat { from, message });
+    }
+    match bare_message(line)?.as_str() {
+        "offer-draw" => Some(ControllerMsg::OfferDraw(from)),
+        "accept-draw" => Some(ControllerMsg::AcceptDraw(from)),
+        "decline-draw" => Some(ControllerMsg::DeclineDraw(from)),
+        "request-undo" => Some(ControllerMsg::RequestUndo { player: from }),
+        _ => None,
+    }
+}
+
+pub const MAX_CHAT_LEN: usize = 256;
+
+// {"chat":{"message":"good luck"}}, anything past MAX_CHAT_LEN characters is cut off
+// TODO: Replace with messages::FromClient::Chat once game-types has it
+fn chat_message(line: &str) -> Option<String> {
+    let msg = serde_json::from_str::<serde_json::Value>(line).ok()?;
+    let message = msg.as_object().filter(|m| m.len() == 1)?.get("chat")?["message"].as_str()?;
+    Some(message.chars().take(MAX_CHAT_LEN).collect())
+}
+
+fn player_response_to_move(line: &str) -> Result<gametraits::PlayerMove, ConnectionError> {
     Ok(gametraits::PlayerMove {
         serialized: line.to_string(),
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn username_validation() {
+        assert!(!valid_username(""));
+        assert!(!valid_username(&"a".repeat(MAX_USERNAME_LEN + 1)));
+        assert!(valid_username(&"a".repeat(MAX_USERNAME_LEN)));
+        assert!(!valid_username("zeldå"));
+        assert!(!valid_username("zel\ndo"));
+        assert!(!valid_username("1zeldo"));
+        assert!(!valid_username("_zeldo"));
+        assert!(!valid_username("zel\"do"));
+        assert!(valid_username("zeldo"));
+        assert!(valid_username("Player_2"));
+    }
+
+    #[test]
+    fn authorize_rejects_bad_username() {
+        let mut db: UserPassDb = Arc::new(Mutex::new(HashMap::new()));
+        let hasher = BcryptHasher::new(4);
+        let err = authorize(
+            r#"{"auth":{"username":"1zeldo","password":"pass"}}"#,
+            &mut db,
+            &hasher,
+        )
+        .unwrap_err();
+        assert_eq!(
+            serde_json::to_string(&err).unwrap(),
+            serde_json::to_string(&messages::INVALID_MESSAGE_FORMAT).unwrap()
+        );
+        assert!(db.lock().unwrap().is_empty());
+        assert_eq!(
+            authorize(
+                r#"{"auth":{"username":"zeldo","password":"pass"}}"#,
+                &mut db,
+                &hasher
+            )
+            .ok(),
+            Some("zeldo".to_string())
+        );
+    }
+
+    struct BrokenHasher;
+
+    impl PasswordHasher for BrokenHasher {
+        fn hash(&self, _password: &str) -> Option<String> {
+            None
+        }
+
+        fn verify(&self, _password: &str, _hash: &str) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn hasher_failure_turns_the_login_away() {
+        let mut db: UserPassDb = Arc::new(Mutex::new(HashMap::new()));
+        let err = authorize(
+            r#"{"auth":{"username":"zeldo","password":"pass"}}"#,
+            &mut db,
+            &BrokenHasher,
+        )
+        .unwrap_err();
+        assert_eq!(
+            serde_json::to_string(&err).unwrap(),
+            serde_json::to_string(&messages::WRONG_PASSWORD).unwrap()
+        );
+        assert!(db.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn auth_errors_get_codes() {
+        assert_eq!(
+            auth_error(&messages::WRONG_PASSWORD),
+            (ERROR_WRONG_PASSWORD, "wrong password")
+        );
+        assert_eq!(
+            auth_error(&messages::INVALID_MESSAGE_FORMAT),
+            (ERROR_INVALID_FORMAT, "invalid message format")
+        );
+    }
+
+    #[test]
+    fn move_errors_get_codes() {
+        assert_eq!(
+            move_error(&messages::INVALID_MOVE),
+            (ERROR_INVALID_MOVE, "invalid move")
+        );
+        assert_eq!(
+            move_error(&messages::INVALID_MESSAGE_FORMAT),
+            (ERROR_INVALID_FORMAT, "invalid message format")
+        );
+    }
+
+    #[test]
+    fn chat_is_cut_at_max_len() {
+        assert_eq!(
+            chat_message(r#"{"chat":{"message":"good luck"}}"#),
+            Some("good luck".to_string())
+        );
+        let long = "å".repeat(MAX_CHAT_LEN + 10);
+        let line = serde_json::json!({ "chat": { "message": long } }).to_string();
+        assert_eq!(chat_message(&line), Some("å".repeat(MAX_CHAT_LEN)));
+        assert_eq!(chat_message(r#"{"move":{"add":1}}"#), None);
+        assert_eq!(chat_message(r#"{"chat":{"message":1}}"#), None);
+    }
+
+    #[test]
+    fn protocol_version_is_optional() {
+        assert!(supported_protocol(
+            r#"{"auth":{"username":"zeldo","password":"pass"}}"#
+        ));
+        assert!(supported_protocol(
+            r#"{"auth":{"username":"zeldo","password":"pass","protocol_version":1}}"#
+        ));
+        assert!(!supported_protocol(
+            r#"{"auth":{"username":"zeldo","password":"pass","protocol_version":2}}"#
+        ));
+        assert!(!supported_protocol(
+            r#"{"auth":{"username":"zeldo","password":"pass","protocol_version":"1"}}"#
+        ));
+    }
+
+    #[test]
+    fn session_token_is_optional() {
+        assert_eq!(
+            session_token(r#"{"auth":{"username":"zeldo","password":"pass"}}"#),
+            None
+        );
+        assert_eq!(
+            session_token(
+                r#"{"auth":{"username":"zeldo","password":"pass","session_token":"a1"}}"#
+            ),
+            Some("a1".to_string())
+        );
+        assert_eq!(
+            session_token(r#"{"auth":{"username":"zeldo","password":"pass","session_token":""}}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn draw_messages_are_side_messages() {
+        assert!(matches!(
+            side_message("zeldo", r#"{"offer-draw":null}"#),
+            Some(ControllerMsg::OfferDraw(name)) if name == "zeldo"
+        ));
+        assert!(matches!(
+            side_message("zeldo", r#"{"accept-draw":null}"#),
+            Some(ControllerMsg::AcceptDraw(_))
+        ));
+        assert!(matches!(
+            side_message("zeldo", r#"{"decline-draw":null}"#),
+            Some(ControllerMsg::DeclineDraw(_))
+        ));
+        assert!(matches!(
+            side_message("zeldo", r#"{"request-undo":null}"#),
+            Some(ControllerMsg::RequestUndo { player }) if player == "zeldo"
+        ));
+        assert!(side_message("zeldo", r#"{"resign":null}"#).is_none());
+        assert!(side_message("zeldo", r#"{"move":{"add":1}}"#).is_none());
+        assert!(is_resign(r#"{"resign":null}"#));
+        assert!(!is_resign(r#"{"resign":null,"move":null}"#));
+    }
+
+    #[test]
+    fn rate_limiter_triggers_at_max_attempts() {
+        let mut limiter = RateLimiter::new(3, Duration::from_secs(60));
+        let now = Instant::now();
+        for _ in 0..2 {
+            limiter.record_failure(LOCALHOST, now);
+        }
+        assert!(!limiter.is_limited(LOCALHOST, now));
+        limiter.record_failure(LOCALHOST, now);
+        assert!(limiter.is_limited(LOCALHOST, now));
+
+        // Other addresses are not affected
+        assert!(!limiter.is_limited(IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1)), now));
+    }
+
+    #[test]
+    fn rate_limiter_forgets_after_window() {
+        let mut limiter = RateLimiter::new(1, Duration::from_secs(60));
+        let now = Instant::now();
+        limiter.record_failure(LOCALHOST, now);
+        assert!(limiter.is_limited(LOCALHOST, now + Duration::from_secs(59)));
+        assert!(!limiter.is_limited(LOCALHOST, now + Duration::from_secs(60)));
+    }
+}
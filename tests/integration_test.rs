@@ -3,19 +3,26 @@ use code_challenge_game_types::gametraits;
 use code_challenge_game_types::gametraits::{
     PlayerGameState, PlayerMove, PlayerMoveResult, PlayerTurn, TurnToken, User,
 };
+use code_challenge_game_types::messages::{self, ToClient};
 use coding_challenge::async_game_trait::AsyncGameTrait;
 use futures::channel::oneshot;
 use futures::pin_mut;
 use simplelog;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::task::Poll;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
 
+use coding_challenge::bracket::BracketSlot;
 use coding_challenge::controller;
+use coding_challenge::event_log::EventLogger;
+use coding_challenge::metrics::Metrics;
 
 #[derive(Debug)]
 struct TestSync<Arg1, RetVal>
@@ -49,6 +56,12 @@ where
     fn return_value(&mut self, value: RetVal) {
         self.return_channel.take().unwrap().send(value).unwrap();
     }
+
+    fn expect_no_call(&mut self, error_message: impl AsRef<str>) {
+        if let Ok((arg, _)) = self.channel.try_recv() {
+            panic!("Didn't expect '{}', got {arg:?}", error_message.as_ref());
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -106,6 +119,10 @@ struct MockGame {
     try_start_game_sync: MockSync<(), Option<String>>,
     move_sync: MockSync<(TurnToken, PlayerMove), PlayerMoveResult>,
     reset_sync: MockSync<(), ()>,
+    current_player_disconnected_sync: MockSync<TurnToken, Option<String>>,
+    undo_sync: MockSync<(), Option<String>>,
+    min_players: usize,
+    max_players: usize,
 }
 
 impl PartialEq for MockGame {
@@ -126,9 +143,13 @@ impl AsyncGameTrait for MockGame {
         self.move_sync.register((turn_token, player_move)).await
     }
 
-    async fn current_player_disconnected(&mut self, _turn_token: TurnToken) -> Option<PlayerTurn> {
+    async fn current_player_disconnected(&mut self, turn_token: TurnToken) -> Option<PlayerTurn> {
         println!("Controller -> Game: Current player disconnected");
-        None
+        let name = self
+            .current_player_disconnected_sync
+            .register(turn_token)
+            .await;
+        name.map(player_turn)
     }
 
     async fn try_start_game(&mut self) -> Option<PlayerTurn> {
@@ -166,9 +187,31 @@ impl AsyncGameTrait for MockGame {
         self.reset_sync.register(()).await;
     }
 
+    async fn undo_last_move(&mut self) -> Option<PlayerTurn> {
+        println!("Controller -> Game: undo last move");
+        let name = self.undo_sync.register(()).await;
+        name.map(player_turn)
+    }
+
     fn get_paint(&self) -> Box<dyn gametraits::Paint> {
         Box::new(FakePainter {})
     }
+
+    fn describe(&self) -> String {
+        "Mock game".to_owned()
+    }
+
+    fn min_players(&self) -> usize {
+        self.min_players
+    }
+
+    fn max_players(&self) -> usize {
+        self.max_players
+    }
+
+    fn winner(&self) -> Option<String> {
+        None
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -194,6 +237,8 @@ struct TestGame {
     try_start_game_sync: TestSync<(), Option<String>>,
     move_sync: TestSync<(TurnToken, PlayerMove), PlayerMoveResult>,
     reset_sync: TestSync<(), ()>,
+    current_player_disconnected_sync: TestSync<TurnToken, Option<String>>,
+    undo_sync: TestSync<(), Option<String>>,
 }
 
 impl TestGame {
@@ -245,13 +290,39 @@ impl TestGame {
         self.reset_sync.return_value(());
         self.poll_sut();
     }
+
+    fn expect_no_reset(&mut self) {
+        self.reset_sync.expect_no_call("reset");
+    }
+
+    fn expect_no_move(&mut self) {
+        self.move_sync.expect_no_call("move");
+    }
+
+    fn expect_current_player_disconnected(&mut self, player: &str, whos_turn: Option<&str>) {
+        let token = self
+            .current_player_disconnected_sync
+            .expect("current player disconnected");
+        assert_eq!(token.user.name, player);
+        self.current_player_disconnected_sync
+            .return_value(whos_turn.map(|s| s.to_owned()));
+        self.poll_sut();
+    }
+
+    fn expect_undo(&mut self, whos_turn: Option<&str>) {
+        self.undo_sync.expect("undo last move");
+        self.undo_sync.return_value(whos_turn.map(|s| s.to_owned()));
+        self.poll_sut();
+    }
 }
 
-fn make_test_game() -> (TestGame, MockGame) {
+fn make_test_game(min_players: usize, max_players: usize) -> (TestGame, MockGame) {
     let (g, h) = make_test_sync();
     let (i, j) = make_test_sync();
     let (k, l) = make_test_sync();
     let (m, n) = make_test_sync();
+    let (o, p) = make_test_sync();
+    let (q, r) = make_test_sync();
 
     (
         TestGame {
@@ -260,21 +331,63 @@ fn make_test_game() -> (TestGame, MockGame) {
             try_start_game_sync: i,
             move_sync: k,
             reset_sync: m,
+            current_player_disconnected_sync: o,
+            undo_sync: q,
         },
         MockGame {
             player_connect_sync: h,
             try_start_game_sync: j,
             move_sync: l,
             reset_sync: n,
+            current_player_disconnected_sync: p,
+            undo_sync: r,
+            min_players,
+            max_players,
         },
     )
 }
 
-async fn sleep_fn(_delay: std::time::Duration) {}
+// Time only goes by when the test says so. A sleep is done once the clock has passed its end
+#[derive(Clone, Default)]
+struct MockClock {
+    now: Rc<Cell<Duration>>,
+}
+
+impl MockClock {
+    fn sleep(&self, delay: Duration) -> MockSleep {
+        MockSleep {
+            now: self.now.clone(),
+            until: self.now.get() + delay,
+        }
+    }
+
+    fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+struct MockSleep {
+    now: Rc<Cell<Duration>>,
+    until: Duration,
+}
+
+impl Future for MockSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut core::task::Context<'_>) -> Poll<()> {
+        if self.now.get() >= self.until {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
 
 #[derive(Clone)]
 struct Sut {
     server_tx: mpsc::Sender<controller::ControllerMsg>,
+    metrics: Metrics,
+    clock: MockClock,
     sut_fut: Rc<RefCell<Pin<Box<dyn Future<Output = ()>>>>>,
 }
 
@@ -286,46 +399,119 @@ impl Sut {
     }
 
     fn start() -> (Sut, TestGame) {
+        Self::start_with_player_limits(1, usize::MAX)
+    }
+
+    fn start_with_player_limits(min_players: usize, max_players: usize) -> (Sut, TestGame) {
         println!("Starting game");
         let (server_tx, server_rx) = tokio::sync::mpsc::channel::<controller::ControllerMsg>(1);
-        let (mut test_game, server_game) = make_test_game();
+        let (mut test_game, server_game) = make_test_game(min_players, max_players);
         let boxed_server_game = Box::new(server_game);
-        let sut_fut = Box::pin(controller::controller_loop(
-            server_rx,
-            controller::UiSender::Fake,
-            boxed_server_game,
-            &sleep_fn,
-        ));
+        let metrics = Metrics::new();
+        let clock = MockClock::default();
+        let sut_fut = Box::pin({
+            let metrics = metrics.clone();
+            let clock = clock.clone();
+            async move {
+                let sleep_fn = move |delay| clock.sleep(delay);
+                controller::controller_loop(
+                    server_rx,
+                    controller::UiSender::Fake,
+                    watch::channel(controller::ControllerInfo::default()).0,
+                    metrics,
+                    EventLogger::default(),
+                    boxed_server_game,
+                    &sleep_fn,
+                )
+                .await
+            }
+        });
         let mut sut = Self {
             server_tx,
+            metrics,
+            clock,
             sut_fut: Rc::new(RefCell::new(sut_fut)),
         };
         test_game.sut = Some(sut.clone());
 
         sut.poll();
+        // The clock stands still unless a test moves it, the pauses would never end
+        sut.send_msg(controller::ControllerMsg::SetTurnDelay(Duration::ZERO));
+        sut.send_msg(controller::ControllerMsg::SetWinDelay(Duration::ZERO));
 
         (sut, test_game)
     }
 
-    fn connect_player(&mut self, name: impl AsRef<str>) -> Player {
-        println!("Connecting player {:?}", name.as_ref());
-        let (tx, rx) = mpsc::channel::<controller::ControllerToPlayerMsg>(15);
+    fn send_msg(&mut self, msg: controller::ControllerMsg) {
         {
-            let send_fut = self.server_tx.send(controller::ControllerMsg::ImConnected(
-                controller::ImConnectedMsg {
-                    player_name: name.as_ref().to_owned(),
-                    controller_to_player_sender: tx,
-                },
-            ));
+            let send_fut = self.server_tx.send(msg);
             pin_mut!(send_fut);
             let w = futures::task::noop_waker();
             let mut c = core::task::Context::from_waker(&w);
             match send_fut.as_mut().poll(&mut c) {
                 Poll::Ready(_) => (),
-                Poll::Pending => panic!("Server was not ready to receive new connection"),
+                Poll::Pending => panic!("Server was not ready to receive a controller message"),
             }
         }
         self.poll();
+    }
+
+    fn advance_time(&mut self, by: Duration) {
+        self.clock.advance(by);
+        self.poll();
+    }
+
+    fn get_history(&mut self) -> Vec<controller::MoveRecord> {
+        let (tx, mut rx) = tokio::sync::oneshot::channel();
+        self.send_msg(controller::ControllerMsg::GetHistory(tx));
+        rx.try_recv()
+            .expect("Controller didn't answer the history request")
+    }
+
+    fn get_status(&mut self) -> controller::ControllerInfo {
+        let (tx, mut rx) = tokio::sync::oneshot::channel();
+        self.send_msg(controller::ControllerMsg::GetStatus(tx));
+        rx.try_recv()
+            .expect("Controller didn't answer the status request")
+    }
+
+    fn get_player_count(&mut self) -> usize {
+        let (tx, mut rx) = tokio::sync::oneshot::channel();
+        self.send_msg(controller::ControllerMsg::GetPlayerCount(tx));
+        rx.try_recv()
+            .expect("Controller didn't answer the player count request")
+    }
+
+    fn connect_spectator(&mut self, name: impl AsRef<str>) -> Spectator {
+        println!("Connecting spectator {:?}", name.as_ref());
+        let (tx, rx) = mpsc::channel::<controller::ControllerToPlayerMsg>(64);
+        self.send_msg(controller::ControllerMsg::ImSpectating(
+            controller::SpectatorMsg {
+                name: name.as_ref().to_owned(),
+                tx,
+            },
+        ));
+        Spectator { rx }
+    }
+
+    fn connect_player(&mut self, name: impl AsRef<str>) -> Player {
+        self.connect_player_with_session(name, None)
+    }
+
+    fn connect_player_with_session(
+        &mut self,
+        name: impl AsRef<str>,
+        session_token: Option<&str>,
+    ) -> Player {
+        println!("Connecting player {:?}", name.as_ref());
+        let (tx, rx) = mpsc::channel::<controller::ControllerToPlayerMsg>(64);
+        self.send_msg(controller::ControllerMsg::ImConnected(
+            controller::ImConnectedMsg {
+                player_name: name.as_ref().to_owned(),
+                controller_to_player_sender: tx,
+                session_token: session_token.map(str::to_string),
+            },
+        ));
         Player {
             name: name.as_ref().to_owned(),
             rx,
@@ -335,6 +521,79 @@ impl Sut {
     }
 }
 
+// Player lists and game starts come along with joins, leaves and new games, most tests don't
+// care about them
+fn poll_skipping_announcements(
+    rx: &mut mpsc::Receiver<controller::ControllerToPlayerMsg>,
+) -> Poll<Option<controller::ControllerToPlayerMsg>> {
+    let w = futures::task::noop_waker();
+    let mut c = core::task::Context::from_waker(&w);
+    loop {
+        let fut = rx.recv();
+        pin_mut!(fut);
+        match fut.as_mut().poll(&mut c) {
+            Poll::Ready(Some(
+                controller::ControllerToPlayerMsg::ServerInfo(_)
+                | controller::ControllerToPlayerMsg::PlayerList(_)
+                | controller::ControllerToPlayerMsg::GameStarted { .. }
+                | controller::ControllerToPlayerMsg::Score(_),
+            )) => continue,
+            other => return other,
+        }
+    }
+}
+
+// Everyone gets the server info when they join and the score after every game, only the tests
+// about them look at them
+fn poll_skipping_info_and_score(
+    rx: &mut mpsc::Receiver<controller::ControllerToPlayerMsg>,
+) -> Poll<Option<controller::ControllerToPlayerMsg>> {
+    let w = futures::task::noop_waker();
+    let mut c = core::task::Context::from_waker(&w);
+    loop {
+        let fut = rx.recv();
+        pin_mut!(fut);
+        match fut.as_mut().poll(&mut c) {
+            Poll::Ready(Some(
+                controller::ControllerToPlayerMsg::ServerInfo(_)
+                | controller::ControllerToPlayerMsg::Score(_),
+            )) => continue,
+            other => return other,
+        }
+    }
+}
+
+struct Spectator {
+    rx: mpsc::Receiver<controller::ControllerToPlayerMsg>,
+}
+
+impl Spectator {
+    fn expect_state(&mut self, expected_state: &str) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::GameState(state))) => {
+                assert_eq!(state.serialized, expected_state)
+            }
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::YourTurn(_, _))) => {
+                panic!("Spectator was asked to move")
+            }
+            _ => panic!("Expected spectator to get a game state"),
+        }
+    }
+
+    fn expect_game_over(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::GameOver(_))) => (),
+            _ => panic!("Expected spectator to get game over"),
+        }
+    }
+
+    fn expect_nothing(&mut self) {
+        if let Poll::Ready(_) = poll_skipping_announcements(&mut self.rx) {
+            panic!("Spectator got something unexpected")
+        }
+    }
+}
+
 struct Player {
     name: String,
     rx: mpsc::Receiver<controller::ControllerToPlayerMsg>,
@@ -345,11 +604,7 @@ struct Player {
 impl Player {
     fn expect_my_turn(&mut self) {
         assert!(self.tx.is_none());
-        let fut = self.rx.recv();
-        pin_mut!(fut);
-        let w = futures::task::noop_waker();
-        let mut c = core::task::Context::from_waker(&w);
-        let move_sender = match fut.as_mut().poll(&mut c) {
+        let move_sender = match poll_skipping_announcements(&mut self.rx) {
             Poll::Pending => panic!(),
             Poll::Ready(None) => panic!(),
             Poll::Ready(Some(controller::ControllerToPlayerMsg::YourTurn(_, move_sender))) => {
@@ -360,9 +615,208 @@ impl Player {
         self.tx = Some(move_sender);
     }
 
+    fn expect_turn_timeout(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::TurnTimeout)) => (),
+            _ => panic!("Expected {} to be told their turn timed out", self.name),
+        }
+        self.tx = None;
+    }
+
+    fn expect_paused(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::Paused)) => (),
+            _ => panic!("Expected {} to be told the game is paused", self.name),
+        }
+        // Any move now is thrown away
+        self.tx = None;
+    }
+
+    fn expect_resumed(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::Resumed)) => (),
+            _ => panic!("Expected {} to be told the game is resumed", self.name),
+        }
+    }
+
+    fn expect_nothing(&mut self) {
+        if let Poll::Ready(_) = poll_skipping_announcements(&mut self.rx) {
+            panic!("{} got something unexpected", self.name)
+        }
+    }
+
+    fn expect_game_over(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::GameOver(_))) => (),
+            _ => panic!("Expected {} to get game over", self.name),
+        }
+        self.tx = None;
+    }
+
+    fn expect_kicked(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::Kicked)) => (),
+            _ => panic!("Expected {} to be kicked", self.name),
+        }
+        self.tx = None;
+    }
+
+    fn expect_player_list(&mut self, expected: &[&str]) {
+        match poll_skipping_info_and_score(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::PlayerList(names))) => {
+                assert_eq!(names, expected)
+            }
+            _ => panic!("Expected {} to get the player list", self.name),
+        }
+    }
+
+    fn expect_chat(&mut self, expected_from: &str, expected_message: &str) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::Chat { from, message })) => {
+                assert_eq!(from, expected_from);
+                assert_eq!(message, expected_message);
+            }
+            _ => panic!("Expected {} to get a chat message", self.name),
+        }
+    }
+
+    fn expect_game_state(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::GameState(_))) => (),
+            _ => panic!(
+                "Expected {} to be shown the game without a move to make",
+                self.name
+            ),
+        }
+    }
+
+    fn expect_info(&mut self, expected_message: &str) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::Info(message))) => {
+                assert_eq!(message, expected_message)
+            }
+            _ => panic!("Expected {} to get an announcement", self.name),
+        }
+    }
+
+    fn expect_draw_offer(&mut self, expected_from: &str) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::DrawOffer { from })) => {
+                assert_eq!(from, expected_from)
+            }
+            _ => panic!("Expected {} to be offered a draw", self.name),
+        }
+    }
+
+    fn expect_draw(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::GameOver(
+                controller::GameOverReason::Draw,
+            ))) => (),
+            _ => panic!("Expected {} to be told the game is a draw", self.name),
+        }
+        self.tx = None;
+    }
+
+    fn expect_undo_granted(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::UndoGranted)) => (),
+            _ => panic!("Expected {} to be told the last move was undone", self.name),
+        }
+        // A move for the undone turn is thrown away
+        self.tx = None;
+    }
+
+    fn expect_undo_denied(&mut self, expected_reason: &str) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::UndoDenied { reason })) => {
+                assert_eq!(reason, expected_reason)
+            }
+            _ => panic!("Expected {} to be denied the undo", self.name),
+        }
+    }
+
+    fn expect_game_full(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::GameFull)) => (),
+            _ => panic!("Expected {} to be told the game is full", self.name),
+        }
+    }
+
+    fn expect_eliminated(&mut self) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::GameOver(
+                controller::GameOverReason::Eliminated,
+            ))) => (),
+            _ => panic!("Expected {} to be eliminated", self.name),
+        }
+        self.tx = None;
+    }
+
+    // Who plays the next game, whatever games ended before it
+    fn next_game_started(&mut self) -> Vec<String> {
+        let w = futures::task::noop_waker();
+        let mut c = core::task::Context::from_waker(&w);
+        loop {
+            let fut = self.rx.recv();
+            pin_mut!(fut);
+            match fut.as_mut().poll(&mut c) {
+                Poll::Ready(Some(
+                    controller::ControllerToPlayerMsg::ServerInfo(_)
+                    | controller::ControllerToPlayerMsg::PlayerList(_)
+                    | controller::ControllerToPlayerMsg::GameOver(_)
+                    | controller::ControllerToPlayerMsg::Score(_),
+                )) => continue,
+                Poll::Ready(Some(controller::ControllerToPlayerMsg::GameStarted {
+                    players,
+                    ..
+                })) => return players,
+                _ => panic!("Expected {} to be told a game started", self.name),
+            }
+        }
+    }
+
+    fn expect_waiting(&mut self, expected_position: usize) {
+        match poll_skipping_announcements(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::Waiting { queue_position })) => {
+                assert_eq!(queue_position, expected_position);
+            }
+            _ => panic!("Expected {} to be waiting in the lobby", self.name),
+        }
+    }
+
+    fn expect_game_started(&mut self, expected_players: &[&str]) {
+        match poll_skipping_info_and_score(&mut self.rx) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::GameStarted { game, players })) => {
+                assert_eq!(game, "Mock game");
+                assert_eq!(players, expected_players);
+            }
+            _ => panic!("Expected {} to be told the game started", self.name),
+        }
+    }
+
+    fn expect_score(&mut self, expected: &[(&str, u64)]) {
+        let fut = self.rx.recv();
+        pin_mut!(fut);
+        let w = futures::task::noop_waker();
+        let mut c = core::task::Context::from_waker(&w);
+        match fut.as_mut().poll(&mut c) {
+            Poll::Ready(Some(controller::ControllerToPlayerMsg::Score(scores))) => {
+                let scores: Vec<(&str, u64)> =
+                    scores.iter().map(|s| (s.player.as_str(), s.wins)).collect();
+                assert_eq!(scores, expected);
+            }
+            _ => panic!("Expected {} to get the score", self.name),
+        }
+    }
+
     fn send_move(&mut self, mv: impl AsRef<str>) {
-        use code_challenge_game_types::messages::ToClient;
-        let (a, _b) = tokio::sync::oneshot::channel::<ToClient>();
+        self.send_move_and_get_error(mv);
+    }
+
+    // The error the controller sent back, if any
+    fn send_move_and_get_error(&mut self, mv: impl AsRef<str>) -> Option<ToClient> {
+        let (a, mut b) = tokio::sync::oneshot::channel::<ToClient>();
         self.tx
             .take()
             .unwrap()
@@ -371,9 +825,11 @@ impl Player {
                     serialized: mv.as_ref().to_owned(),
                 },
                 move_err_tx: a,
+                received_at: std::time::Instant::now(),
             })
             .unwrap();
         self.sut.poll();
+        b.try_recv().ok()
     }
 }
 
@@ -518,82 +974,1277 @@ fn player_with_same_name_connected_twice() {
 }
 
 #[test]
-fn same_player_repeat() {
+fn same_session_takes_over_the_seat() {
     let (mut sut, mut game) = Sut::start();
-
-    sut.connect_player("p1");
+    let p1 = sut.connect_player_with_session("p1", Some("abc"));
     game.expect_player_connected("p1");
     game.expect_try_start_game(None);
-
     let mut p2 = sut.connect_player("p2");
     game.expect_player_connected("p2");
-    game.expect_try_start_game(None);
-
-    sut.connect_player("p3");
-    game.expect_player_connected("p3");
-    game.expect_try_start_game(Some("p2"));
-
-    for _ in 1..5 {
-        p2.expect_my_turn();
-        p2.send_move("mv");
-        game.expect_move("p2", "mv", ok_move("p2", ""));
-    }
+    game.expect_try_start_game(Some("p1"));
 
+    // The game never hears about it, p1 is asked again on the new connection
+    let mut p1_v2 = sut.connect_player_with_session("p1", Some("abc"));
+    drop(p1);
+    p1_v2.expect_my_turn();
+    game.expect_no_reset();
+    p1_v2.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
     p2.expect_my_turn();
-    p2.send_move("mv");
-    game.expect_move("p2", "mv", PlayerMoveResult::Win);
+    assert_eq!(sut.get_status().connected_users.len(), 2);
 }
 
 #[test]
-fn illegal_move_game_stops() {
+fn other_session_is_turned_away() {
     let (mut sut, mut game) = Sut::start();
+    let _p1 = sut.connect_player_with_session("p1", Some("abc"));
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
 
-    let mut players = connect_n_players(&mut sut, &mut game, 5);
+    for token in [Some("xyz"), None] {
+        let mut p1_v2 = sut.connect_player_with_session("p1", token);
+        match poll_skipping_announcements(&mut p1_v2.rx) {
+            Poll::Ready(None) => (),
+            _ => panic!("Server should have dropped the sender"),
+        }
+    }
+}
 
-    sut.connect_player("last");
-    game.expect_player_connected("last");
-    game.expect_try_start_game(Some("Player4"));
-    players[4].expect_my_turn();
-    players[4].send_move("mv");
-    game.expect_move("Player4", "mv", PlayerMoveResult::InvalidMove(None));
-    game.expect_reset();
+#[test]
+fn server_info_comes_first() {
+    let (mut sut, _game) = Sut::start_with_player_limits(2, 4);
+
+    let mut p1 = sut.connect_player("p1");
+    let fut = p1.rx.recv();
+    pin_mut!(fut);
+    let w = futures::task::noop_waker();
+    let mut c = core::task::Context::from_waker(&w);
+    match fut.as_mut().poll(&mut c) {
+        Poll::Ready(Some(controller::ControllerToPlayerMsg::ServerInfo(info))) => {
+            assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+            assert_eq!(info.game, "Mock game");
+            assert_eq!((info.min_players, info.max_players), (2, 4));
+            // The mock doesn't paint a board
+            assert_eq!((info.board_width, info.board_height), (None, None));
+        }
+        _ => panic!("Expected the server info before anything else"),
+    }
+    p1.expect_waiting(1);
 }
 
 #[test]
-fn illegal_move_game_must_go_on() {
+fn everyone_gets_the_player_list() {
     let (mut sut, mut game) = Sut::start();
 
-    let mut players = connect_n_players(&mut sut, &mut game, 5);
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+    p1.expect_player_list(&["p1"]);
 
-    sut.connect_player("last");
-    game.expect_player_connected("last");
-    game.expect_try_start_game(Some("Player4"));
-    players[4].expect_my_turn();
-    players[4].send_move("mv");
-    game.expect_move(
-        "Player4",
-        "mv",
-        PlayerMoveResult::InvalidMove(Some(player_turn("Player2"))),
-    );
-    players[2].expect_my_turn();
-    players[2].send_move("2");
-    game.expect_move("Player2", "2", PlayerMoveResult::Draw);
+    let mut p2 = sut.connect_player("p2");
+    game.expect_player_connected("p2");
+    game.expect_try_start_game(None);
+    p1.expect_player_list(&["p1", "p2"]);
+    p2.expect_player_list(&["p1", "p2"]);
+
+    let mut p3 = sut.connect_player("p3");
+    game.expect_player_connected("p3");
+    game.expect_try_start_game(Some("p2"));
+    p1.expect_player_list(&["p1", "p2", "p3"]);
+    p2.expect_player_list(&["p1", "p2", "p3"]);
+    p3.expect_player_list(&["p1", "p2", "p3"]);
 }
 
 #[test]
-fn player_drops_then_gets_illegal_move_result() {
+fn game_started_comes_before_the_first_turn() {
     let (mut sut, mut game) = Sut::start();
+
     let mut p1 = sut.connect_player("p1");
     game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+    p1.expect_player_list(&["p1"]);
+
+    let mut p2 = sut.connect_player("p2");
+    game.expect_player_connected("p2");
     game.expect_try_start_game(Some("p1"));
+    p1.expect_player_list(&["p1", "p2"]);
+    p1.expect_game_started(&["p1", "p2"]);
     p1.expect_my_turn();
+    p2.expect_player_list(&["p1", "p2"]);
+    p2.expect_game_started(&["p1", "p2"]);
+    p2.expect_nothing();
+
     p1.send_move("mv");
-    drop(p1);
-    game.expect_move("p1", "mv", PlayerMoveResult::InvalidMove(None));
+    game.expect_move("p1", "mv", PlayerMoveResult::Win);
     game.expect_reset();
+    game.expect_try_start_game(Some("p2"));
+    p1.expect_game_over();
+    p1.expect_game_started(&["p1", "p2"]);
+    p2.expect_game_over();
+    p2.expect_game_started(&["p1", "p2"]);
+    p2.expect_my_turn();
+}
 
-    let mut p1 = sut.connect_player("p1");
+#[test]
+fn chat_goes_to_everyone() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut players = connect_n_players(&mut sut, &mut game, 2);
+    let mut spectator = sut.connect_spectator("watcher");
+
+    sut.send_msg(controller::ControllerMsg::Chat {
+        from: "Player0".to_owned(),
+        message: "good luck".to_owned(),
+    });
+    players[0].expect_chat("Player0", "good luck");
+    players[1].expect_chat("Player0", "good luck");
+    match poll_skipping_announcements(&mut spectator.rx) {
+        Poll::Ready(Some(controller::ControllerToPlayerMsg::Chat { message, .. })) => {
+            assert_eq!(message, "good luck")
+        }
+        _ => panic!("Expected the spectator to get the chat message"),
+    }
+}
+
+#[test]
+fn broadcast_reaches_only_those_connected() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut players = connect_n_players(&mut sut, &mut game, 2);
+    let mut spectator = sut.connect_spectator("watcher");
+
+    sut.send_msg(controller::ControllerMsg::Broadcast(
+        "server restarts in 5 minutes".to_owned(),
+    ));
+    players[0].expect_info("server restarts in 5 minutes");
+    players[1].expect_info("server restarts in 5 minutes");
+    match poll_skipping_announcements(&mut spectator.rx) {
+        Poll::Ready(Some(controller::ControllerToPlayerMsg::Info(message))) => {
+            assert_eq!(message, "server restarts in 5 minutes")
+        }
+        _ => panic!("Expected the spectator to get the announcement"),
+    }
+
+    // Nothing is kept around for whoever connects later
+    let mut late = sut.connect_player("late");
+    game.expect_player_connected("late");
+    game.expect_try_start_game(None);
+    late.expect_nothing();
+}
+
+#[test]
+fn same_player_repeat() {
+    let (mut sut, mut game) = Sut::start();
+
+    sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+
+    let mut p2 = sut.connect_player("p2");
+    game.expect_player_connected("p2");
+    game.expect_try_start_game(None);
+
+    sut.connect_player("p3");
+    game.expect_player_connected("p3");
+    game.expect_try_start_game(Some("p2"));
+
+    for _ in 1..5 {
+        p2.expect_my_turn();
+        p2.send_move("mv");
+        game.expect_move("p2", "mv", ok_move("p2", ""));
+    }
+
+    p2.expect_my_turn();
+    p2.send_move("mv");
+    game.expect_move("p2", "mv", PlayerMoveResult::Win);
+}
+
+#[test]
+fn illegal_move_game_stops() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut players = connect_n_players(&mut sut, &mut game, 5);
+
+    sut.connect_player("last");
+    game.expect_player_connected("last");
+    game.expect_try_start_game(Some("Player4"));
+    players[4].expect_my_turn();
+    players[4].send_move("mv");
+    game.expect_move("Player4", "mv", PlayerMoveResult::InvalidMove(None));
+    game.expect_reset();
+}
+
+#[test]
+fn illegal_move_game_must_go_on() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut players = connect_n_players(&mut sut, &mut game, 5);
+
+    sut.connect_player("last");
+    game.expect_player_connected("last");
+    game.expect_try_start_game(Some("Player4"));
+    players[4].expect_my_turn();
+    players[4].send_move("mv");
+    game.expect_move(
+        "Player4",
+        "mv",
+        PlayerMoveResult::InvalidMove(Some(player_turn("Player2"))),
+    );
+    players[2].expect_my_turn();
+    players[2].send_move("2");
+    game.expect_move("Player2", "2", PlayerMoveResult::Draw);
+}
+
+#[test]
+fn player_drops_then_gets_illegal_move_result() {
+    let (mut sut, mut game) = Sut::start();
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    drop(p1);
+    game.expect_move("p1", "mv", PlayerMoveResult::InvalidMove(None));
+    game.expect_reset();
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+}
+
+#[test]
+fn unresponsive_player_is_skipped() {
+    let (mut sut, mut game) = Sut::start();
+    sut.send_msg(controller::ControllerMsg::SetTurnTimeLimit(Some(
+        Duration::from_millis(100),
+    )));
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+
+    let mut p2 = sut.connect_player("p2");
+    game.expect_player_connected("p2");
+    game.expect_try_start_game(Some("p1"));
+
+    // p1 never answers
+    p1.expect_my_turn();
+    sut.advance_time(Duration::from_millis(99));
+    p1.expect_nothing();
+    sut.advance_time(Duration::from_millis(1));
+    p1.expect_turn_timeout();
+    game.expect_current_player_disconnected("p1", Some("p2"));
+
+    // The game goes on with p2, who doesn't answer either
+    p2.expect_my_turn();
+    sut.advance_time(Duration::from_millis(100));
+    p2.expect_turn_timeout();
+    game.expect_current_player_disconnected("p2", None);
+    game.expect_reset();
+}
+
+#[test]
+fn watchdog_ticks_every_interval() {
+    let (mut sut, _game) = Sut::start();
+
+    sut.advance_time(controller::WATCHDOG_INTERVAL - Duration::from_secs(1));
+    assert_eq!(sut.metrics.watchdog_ticks.get(), 0);
+    sut.advance_time(Duration::from_secs(1));
+    assert_eq!(sut.metrics.watchdog_ticks.get(), 1);
+    sut.advance_time(controller::WATCHDOG_INTERVAL);
+    assert_eq!(sut.metrics.watchdog_ticks.get(), 2);
+}
+
+#[test]
+fn watchdog_leaves_a_turn_without_time_limit_alone() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+
+    sut.advance_time(controller::WATCHDOG_INTERVAL);
+    assert_eq!(sut.metrics.watchdog_ticks.get(), 1);
+    p1.expect_nothing();
+
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+}
+
+#[test]
+fn game_past_max_duration_is_a_draw() {
+    let (mut sut, mut game) = Sut::start();
+    sut.send_msg(controller::ControllerMsg::SetMaxGameDuration(Some(
+        Duration::from_secs(60),
+    )));
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+    sut.advance_time(Duration::from_secs(30));
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+
+    sut.advance_time(Duration::from_secs(29));
+    p1.expect_nothing();
+    p2.expect_nothing();
+    sut.advance_time(Duration::from_secs(1));
+    game.expect_reset();
+    game.expect_try_start_game(Some("p2"));
+    p1.expect_draw();
+    p2.expect_draw();
+    p2.expect_my_turn();
+
+    // The next game has a clock of its own
+    sut.advance_time(Duration::from_secs(59));
+    p1.expect_nothing();
+    p2.expect_nothing();
+}
+
+#[test]
+fn games_have_no_max_duration_by_default() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+
+    sut.advance_time(Duration::from_secs(24 * 60 * 60));
+    p1.expect_nothing();
+    p2.expect_nothing();
+    assert_eq!(sut.get_status().max_game_duration, None);
+}
+
+fn disconnect(sut: &mut Sut, player: &str) {
+    sut.send_msg(controller::ControllerMsg::ImDisconnected(
+        player.to_string(),
+    ));
+}
+
+#[test]
+fn empty_game_is_reset_after_idle_timeout() {
+    let (mut sut, mut game) = Sut::start();
+    sut.send_msg(controller::ControllerMsg::SetIdleTimeout(Some(
+        Duration::from_secs(300),
+    )));
+    let _p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+
+    disconnect(&mut sut, "p1");
+    sut.advance_time(Duration::from_secs(299));
+    game.expect_no_reset();
+    sut.advance_time(Duration::from_secs(1));
+    game.expect_reset();
+
+    // Only once
+    sut.advance_time(Duration::from_secs(300));
+    game.expect_no_reset();
+}
+
+#[test]
+fn idle_timer_starts_over_when_someone_joins() {
+    let (mut sut, mut game) = Sut::start();
+    sut.send_msg(controller::ControllerMsg::SetIdleTimeout(Some(
+        Duration::from_secs(300),
+    )));
+    let _p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+    disconnect(&mut sut, "p1");
+    sut.advance_time(Duration::from_secs(200));
+
+    let _p2 = sut.connect_player("p2");
+    game.expect_player_connected("p2");
+    game.expect_try_start_game(None);
+    sut.advance_time(Duration::from_secs(200));
+    game.expect_no_reset();
+
+    disconnect(&mut sut, "p2");
+    sut.advance_time(Duration::from_secs(299));
+    game.expect_no_reset();
+    sut.advance_time(Duration::from_secs(1));
+    game.expect_reset();
+}
+
+// Turns away one particular move
+#[derive(Debug)]
+struct RejectMove(&'static str);
+
+impl controller::MoveValidator for RejectMove {
+    fn validate(
+        &self,
+        _player: &str,
+        move_json: &str,
+        _info: &controller::ControllerInfo,
+    ) -> Option<ToClient> {
+        (move_json == self.0).then_some(messages::INVALID_MOVE)
+    }
+}
+
+#[test]
+fn validator_takes_the_player_out_before_the_game_sees_the_move() {
+    let (mut sut, mut game) = Sut::start();
+    sut.send_msg(controller::ControllerMsg::AddValidator(Arc::new(
+        RejectMove("bad"),
+    )));
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+
+    p1.expect_my_turn();
+    assert!(p1.send_move_and_get_error("mv").is_none());
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+
+    let error = p2
+        .send_move_and_get_error("bad")
+        .expect("Expected the move to be rejected");
+    assert_eq!(
+        serde_json::to_string(&error).unwrap(),
+        serde_json::to_string(&messages::INVALID_MOVE).unwrap()
+    );
+    game.expect_current_player_disconnected("p2", Some("p1"));
+    p1.expect_my_turn();
+    assert_eq!(sut.get_player_count(), 1);
+    assert_eq!(sut.get_history().len(), 1);
+}
+
+#[test]
+fn anti_spam_rejects_the_same_move_twice() {
+    let (mut sut, mut game) = Sut::start();
+    sut.send_msg(controller::ControllerMsg::AddValidator(Arc::new(
+        controller::AntiSpamValidator::default(),
+    )));
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p1", ""));
+    p1.expect_my_turn();
+    assert!(p1.send_move_and_get_error("mv").is_some());
+    game.expect_current_player_disconnected("p1", Some("p2"));
+    p2.expect_my_turn();
+}
+
+#[test]
+fn kick_current_player() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+
+    let mut p2 = sut.connect_player("p2");
+    game.expect_player_connected("p2");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+
+    sut.send_msg(controller::ControllerMsg::Kick("p1".to_string()));
+    p1.expect_kicked();
+    game.expect_current_player_disconnected("p1", Some("p2"));
+
+    p2.expect_my_turn();
+    p2.send_move("mv");
+    game.expect_move("p2", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+}
+
+#[test]
+fn kick_waiting_player() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+
+    let mut p2 = sut.connect_player("p2");
+    game.expect_player_connected("p2");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+
+    // p1 keeps the turn, the game just forgets about p2
+    sut.send_msg(controller::ControllerMsg::Kick("p2".to_string()));
+    p2.expect_kicked();
+
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p1", ""));
+    p1.expect_my_turn();
+}
+
+#[test]
+fn pause_before_game_starts() {
+    let (mut sut, mut game) = Sut::start();
+    sut.send_msg(controller::ControllerMsg::PauseGame);
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_paused();
+    p1.expect_nothing();
+
+    sut.send_msg(controller::ControllerMsg::ResumeGame);
+    p1.expect_resumed();
+    p1.expect_my_turn();
+}
+
+#[test]
+fn pause_mid_game() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+
+    sut.send_msg(controller::ControllerMsg::PauseGame);
+    p1.expect_paused();
+    p1.expect_nothing();
+
+    sut.send_msg(controller::ControllerMsg::ResumeGame);
+    p1.expect_resumed();
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p1", ""));
+    p1.expect_my_turn();
+}
+
+#[test]
+fn resume_gives_turn_to_current_player() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+
+    let mut p2 = sut.connect_player("p2");
+    game.expect_player_connected("p2");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+
+    sut.send_msg(controller::ControllerMsg::PauseGame);
+    p1.expect_paused();
+    p2.expect_paused();
+
+    sut.send_msg(controller::ControllerMsg::ResumeGame);
+    p1.expect_resumed();
+    p2.expect_resumed();
+    p2.expect_my_turn();
+    p1.expect_nothing();
+}
+
+#[test]
+fn double_pause_is_same_as_one() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+
+    sut.send_msg(controller::ControllerMsg::PauseGame);
+    sut.send_msg(controller::ControllerMsg::PauseGame);
+    p1.expect_paused();
+    p1.expect_nothing();
+
+    sut.send_msg(controller::ControllerMsg::ResumeGame);
+    p1.expect_resumed();
+    p1.expect_my_turn();
+    p1.expect_nothing();
+}
+
+#[test]
+fn turn_time_limit_can_be_turned_off() {
+    let (mut sut, mut game) = Sut::start();
+    sut.send_msg(controller::ControllerMsg::SetTurnTimeLimit(Some(
+        Duration::from_millis(100),
+    )));
+    sut.send_msg(controller::ControllerMsg::SetTurnTimeLimit(None));
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+
+    sut.advance_time(Duration::from_millis(100));
+    p1.expect_nothing();
+
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p1", ""));
+    p1.expect_my_turn();
+}
+
+#[test]
+fn no_turn_time_limit_by_default() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+
+    // Nothing happens while p1 is thinking
+    sut.poll();
+    sut.poll();
+
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p1", ""));
+    p1.expect_my_turn();
+}
+
+#[test]
+fn move_history_grows_with_each_move() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+    assert!(sut.get_history().is_empty());
+
+    p1.send_move("first");
+    game.expect_move("p1", "first", ok_move("p1", ""));
+    p1.expect_my_turn();
+
+    let history = sut.get_history();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].player, "p1");
+    assert_eq!(history[0].serialized_move, "first");
+    assert_eq!(history[0].result_kind, "ok");
+
+    p1.send_move("second");
+    game.expect_move("p1", "second", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(None);
+
+    let history = sut.get_history();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[1].serialized_move, "second");
+    assert_eq!(history[1].result_kind, "win");
+    assert!(history[0].timestamp <= history[1].timestamp);
+}
+
+#[test]
+fn status_follows_players_and_score() {
+    let (mut sut, mut game) = Sut::start();
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(None);
+
+    let status = sut.get_status();
+    assert_eq!(status.connected_users[0].name, "p1");
+    assert_eq!(status.score["p1"], 1);
+    assert_eq!(status.game_mode, controller::GameMode::Practice);
+    assert!(!status.paused);
+}
+
+#[test]
+fn status_counts_moves() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+    p2.send_move("mv");
+    game.expect_move("p2", "mv", ok_move("p1", ""));
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(None);
+
+    let status = sut.get_status();
+    assert_eq!(status.total_moves, 3);
+    let names: Vec<&str> = status
+        .connected_users
+        .iter()
+        .map(|u| u.name.as_str())
+        .collect();
+    assert_eq!(names, ["p1", "p2"]);
+    assert_eq!(status.score, HashMap::from([("p1".to_string(), 1)]));
+}
+
+#[test]
+fn status_follows_the_turns_of_a_game() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+
+    let status = sut.get_status();
+    assert_eq!(status.turn_number, 0);
+    assert_eq!(status.current_player.as_deref(), Some("p1"));
+    assert!(status.game_start_time.is_some());
+
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+    let status = sut.get_status();
+    assert_eq!(status.turn_number, 1);
+    assert_eq!(status.current_player.as_deref(), Some("p2"));
+
+    p2.send_move("mv");
+    game.expect_move("p2", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(None);
+    let status = sut.get_status();
+    assert_eq!(status.turn_number, 0);
+    assert_eq!(status.current_player, None);
+    assert_eq!(status.game_start_time, None);
+}
+
+#[test]
+fn metrics_count_a_whole_game() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+
+    p1.expect_my_turn();
+    p1.send_move("bad");
+    game.expect_move(
+        "p1",
+        "bad",
+        PlayerMoveResult::InvalidMove(Some(player_turn("p2"))),
+    );
+    p2.expect_my_turn();
+    p2.send_move("mv");
+    game.expect_move("p2", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+    p2.send_move("mv");
+    game.expect_move("p2", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(None);
+    // A round trip through the controller, so the gauge is updated
+    sut.get_status();
+
+    let scraped = sut.metrics.gather();
+    assert!(scraped.contains("\nmoves_total 3\n"), "{scraped}");
+    assert!(scraped.contains("\ninvalid_moves_total 1\n"), "{scraped}");
+    assert!(scraped.contains("\ngames_completed 1\n"), "{scraped}");
+    assert!(
+        scraped.contains("\nturn_latency_seconds_count 3\n"),
+        "{scraped}"
+    );
+    // p1 was thrown out for the invalid move
+    assert!(scraped.contains("\nactive_connections 1\n"), "{scraped}");
+    assert!(
+        scraped.contains("\nplayer_moves{player=\"p2\"} 2\n"),
+        "{scraped}"
+    );
+    assert!(!scraped.contains("player=\"p1\""), "{scraped}");
+}
+
+#[test]
+fn move_count_starts_over_on_reconnect() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    assert_eq!(sut.get_status().player_stats["p1"].move_count, 0);
+
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+    let status = sut.get_status();
+    assert_eq!(status.player_stats["p1"].move_count, 1);
+    assert_eq!(status.player_stats["p2"].move_count, 0);
+
+    disconnect(&mut sut, "p1");
+    let _p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    assert_eq!(sut.get_status().player_stats["p1"].move_count, 0);
+}
+
+#[test]
+fn player_count_leaves_out_spectators() {
+    let (mut sut, mut game) = Sut::start();
+    assert_eq!(sut.get_player_count(), 0);
+
+    let _spectator = sut.connect_spectator("watcher");
+    let (_p1, _p2) = start_two_player_game(&mut sut, &mut game);
+    assert_eq!(sut.get_player_count(), 2);
+}
+
+#[test]
+fn spectator_sees_every_state() {
+    let (mut sut, mut game) = Sut::start();
+
+    // The game doesn't know about spectators
+    let mut spectator = sut.connect_spectator("watcher");
+    spectator.expect_nothing();
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+    spectator.expect_state("");
+
+    p1.send_move("mv1");
+    game.expect_move("p1", "mv1", ok_move("p1", "state after mv1"));
+    p1.expect_my_turn();
+    spectator.expect_state("state after mv1");
+
+    p1.send_move("mv2");
+    game.expect_move("p1", "mv2", ok_move("p1", "state after mv2"));
+    p1.expect_my_turn();
+    spectator.expect_state("state after mv2");
+
+    p1.send_move("mv3");
+    game.expect_move("p1", "mv3", PlayerMoveResult::Win);
+    spectator.expect_game_over();
+    spectator.expect_nothing();
+}
+
+fn start_two_player_game(sut: &mut Sut, game: &mut TestGame) -> (Player, Player) {
+    let p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+
+    let p2 = sut.connect_player("p2");
+    game.expect_player_connected("p2");
+    game.expect_try_start_game(Some("p1"));
+    (p1, p2)
+}
+
+#[test]
+fn score_after_every_game() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(Some("p2"));
+    for p in [&mut p1, &mut p2] {
+        p.expect_game_over();
+        p.expect_score(&[("p1", 1)]);
+    }
+
+    p2.expect_my_turn();
+    p2.send_move("mv");
+    game.expect_move("p2", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(Some("p1"));
+    for p in [&mut p1, &mut p2] {
+        p.expect_game_over();
+        p.expect_score(&[("p1", 1), ("p2", 1)]);
+    }
+
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(Some("p2"));
+    for p in [&mut p1, &mut p2] {
+        p.expect_game_over();
+        p.expect_score(&[("p1", 2), ("p2", 1)]);
+    }
+}
+
+#[test]
+fn accepted_draw_ends_the_game() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+
+    sut.send_msg(controller::ControllerMsg::OfferDraw("p1".to_string()));
+    p2.expect_draw_offer("p1");
+    p1.expect_nothing();
+
+    sut.send_msg(controller::ControllerMsg::AcceptDraw("p2".to_string()));
+    game.expect_reset();
+    game.expect_try_start_game(Some("p2"));
+    p1.expect_draw();
+    p2.expect_draw();
+    p2.expect_my_turn();
+}
+
+#[test]
+fn declined_draw_keeps_playing() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+
+    sut.send_msg(controller::ControllerMsg::OfferDraw("p1".to_string()));
+    p2.expect_draw_offer("p1");
+    sut.send_msg(controller::ControllerMsg::DeclineDraw("p2".to_string()));
+    // Too late, the offer is gone
+    sut.send_msg(controller::ControllerMsg::AcceptDraw("p2".to_string()));
+    p1.expect_nothing();
+    p2.expect_nothing();
+
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+}
+
+#[test]
+fn draw_offer_expires() {
+    let (mut sut, mut game) = Sut::start();
+    sut.send_msg(controller::ControllerMsg::SetDrawOfferTimeout(
+        Duration::ZERO,
+    ));
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+
+    sut.send_msg(controller::ControllerMsg::OfferDraw("p1".to_string()));
+    p2.expect_draw_offer("p1");
+    sut.send_msg(controller::ControllerMsg::AcceptDraw("p2".to_string()));
+    p1.expect_nothing();
+    p2.expect_nothing();
+
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+}
+
+#[test]
+fn draw_offered_outside_own_turn_needs_everyone() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    let mut p3 = sut.connect_player("p3");
+    game.expect_player_connected("p3");
+    p1.expect_my_turn();
+
+    // p1 is the one thinking, p2 offers
+    sut.send_msg(controller::ControllerMsg::OfferDraw("p2".to_string()));
+    p1.expect_draw_offer("p2");
+    p3.expect_draw_offer("p2");
+    p2.expect_nothing();
+
+    sut.send_msg(controller::ControllerMsg::AcceptDraw("p3".to_string()));
+    p1.expect_nothing();
+
+    sut.send_msg(controller::ControllerMsg::AcceptDraw("p1".to_string()));
+    game.expect_reset();
+    game.expect_try_start_game(None);
+    p1.expect_draw();
+    p2.expect_draw();
+    p3.expect_draw();
+}
+
+fn request_undo(sut: &mut Sut, player: &str) {
+    sut.send_msg(controller::ControllerMsg::RequestUndo {
+        player: player.to_string(),
+    });
+}
+
+#[test]
+fn undo_gives_the_turn_back() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+
+    request_undo(&mut sut, "p2");
+    game.expect_undo(Some("p1"));
+    p1.expect_undo_granted();
+    p2.expect_undo_granted();
+    p1.expect_my_turn();
+    p2.expect_nothing();
+    assert!(sut.get_history().is_empty());
+
+    p1.send_move("mv2");
+    game.expect_move("p1", "mv2", ok_move("p2", ""));
+    p2.expect_my_turn();
+}
+
+#[test]
+fn undo_after_a_win_is_denied() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(Some("p2"));
+    p1.expect_game_over();
+    p2.expect_game_over();
+    p2.expect_my_turn();
+
+    // The game that was won is gone, the new one has nothing to take back
+    request_undo(&mut sut, "p1");
+    game.expect_undo(None);
+    p1.expect_undo_denied("not supported");
+    p2.expect_nothing();
+}
+
+#[test]
+fn undo_before_any_move_is_denied() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+
+    request_undo(&mut sut, "p1");
+    game.expect_undo(None);
+    p1.expect_undo_denied("not supported");
+    p2.expect_nothing();
+
+    // Still p1's turn
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p2.expect_my_turn();
+}
+
+#[test]
+fn undo_on_the_first_turn_keeps_the_turn_count() {
+    let (mut sut, mut game) = Sut::start();
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(Some("p2"));
+    p1.expect_game_over();
+    p2.expect_game_over();
+    p2.expect_my_turn();
+
+    // The move from the last game is still in the history, there's none in this one
+    request_undo(&mut sut, "p2");
+    game.expect_undo(Some("p2"));
+    p1.expect_undo_granted();
+    p2.expect_undo_granted();
+    p2.expect_my_turn();
+    let status = sut.get_status();
+    assert_eq!(status.turn_number, 0);
+    assert_eq!(status.total_moves, 0);
+    assert!(sut.get_history().is_empty());
+}
+
+#[test]
+fn undo_without_a_game_is_denied() {
+    let (mut sut, mut game) = Sut::start();
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+
+    request_undo(&mut sut, "p1");
+    p1.expect_undo_denied("no game running");
+}
+
+#[test]
+fn lobby_holds_players_until_there_are_enough() {
+    let (mut sut, mut game) = Sut::start_with_player_limits(3, usize::MAX);
+
+    // The game hears nothing about them yet
+    let mut p1 = sut.connect_player("p1");
+    p1.expect_waiting(1);
+    let mut p2 = sut.connect_player("p2");
+    p1.expect_waiting(1);
+    p2.expect_waiting(2);
+
+    // p1 gives up, p2 is first in line now
+    sut.send_msg(controller::ControllerMsg::Kick("p1".to_string()));
+    p1.expect_kicked();
+    p2.expect_waiting(1);
+
+    let mut p3 = sut.connect_player("p3");
+    p2.expect_waiting(1);
+    p3.expect_waiting(2);
+
+    // Enough for a game, everyone is seated in the order they came
+    let mut p4 = sut.connect_player("p4");
+    game.expect_player_connected("p2");
+    game.expect_player_connected("p3");
+    game.expect_player_connected("p4");
+    game.expect_try_start_game(Some("p2"));
+    p2.expect_my_turn();
+    p3.expect_nothing();
+    p4.expect_nothing();
+}
+
+#[test]
+fn too_few_players_left_go_back_to_the_lobby() {
+    let (mut sut, mut game) = Sut::start_with_player_limits(2, usize::MAX);
+
+    let mut p1 = sut.connect_player("p1");
+    p1.expect_waiting(1);
+    let mut p2 = sut.connect_player("p2");
+    game.expect_player_connected("p1");
+    game.expect_player_connected("p2");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+
+    sut.send_msg(controller::ControllerMsg::Kick("p2".to_string()));
+    p2.expect_kicked();
+
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    p1.expect_game_over();
+    p1.expect_waiting(1);
+
+    // The next one to show up starts a new game with p1
+    let mut p3 = sut.connect_player("p3");
+    game.expect_player_connected("p1");
+    game.expect_player_connected("p3");
+    game.expect_try_start_game(Some("p3"));
+    p3.expect_my_turn();
+}
+
+#[test]
+fn four_player_tournament_is_three_games() {
+    let (mut sut, mut game) = Sut::start();
+    let mut players = connect_n_players(&mut sut, &mut game, 4);
+
+    // Same rating for everyone, so they're seeded in the order they came
+    sut.send_msg(controller::ControllerMsg::StartTournament);
+    game.expect_reset();
+    game.expect_try_start_game(Some("Player0"));
+    assert_eq!(players[0].next_game_started(), ["Player0", "Player3"]);
+    players[0].expect_my_turn();
+    assert_eq!(players[1].next_game_started(), ["Player0", "Player3"]);
+    assert_eq!(players[2].next_game_started(), ["Player0", "Player3"]);
+    assert_eq!(players[3].next_game_started(), ["Player0", "Player3"]);
+
+    players[0].send_move("mv");
+    game.expect_move("Player0", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(Some("Player1"));
+    players[3].expect_eliminated();
+    assert_eq!(players[1].next_game_started(), ["Player1", "Player2"]);
+    players[1].expect_my_turn();
+    assert_eq!(players[2].next_game_started(), ["Player1", "Player2"]);
+
+    players[1].send_move("mv");
+    game.expect_move("Player1", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    game.expect_try_start_game(Some("Player0"));
+    players[2].expect_eliminated();
+    assert_eq!(players[0].next_game_started(), ["Player1", "Player2"]);
+    assert_eq!(players[0].next_game_started(), ["Player0", "Player1"]);
+    players[0].expect_my_turn();
+    assert_eq!(players[1].next_game_started(), ["Player0", "Player1"]);
+
+    // The final, no game after it
+    players[0].send_move("mv");
+    game.expect_move("Player0", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    players[1].expect_eliminated();
+
+    let status = sut.get_status();
+    assert_eq!(status.game_mode, controller::GameMode::Tournament);
+    assert_eq!(
+        status.bracket.unwrap().rounds()[2][0],
+        BracketSlot::Player("Player0".to_string())
+    );
+}
+
+#[test]
+fn min_players_override_lets_one_player_start() {
+    let (mut sut, mut game) = Sut::start_with_player_limits(2, usize::MAX);
+    sut.send_msg(controller::ControllerMsg::SetMinPlayers(1));
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+
+    // Only counts from the next game
+    sut.send_msg(controller::ControllerMsg::SetMinPlayers(2));
+    p1.send_move("mv");
+    game.expect_move("p1", "mv", PlayerMoveResult::Win);
+    game.expect_reset();
+    p1.expect_game_over();
+    p1.expect_waiting(1);
+
+    let mut p2 = sut.connect_player("p2");
+    game.expect_player_connected("p1");
+    game.expect_player_connected("p2");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+    p2.expect_nothing();
+}
+
+#[test]
+fn max_players_override_turns_newcomers_away() {
+    let (mut sut, mut game) = Sut::start_with_player_limits(1, usize::MAX);
+    sut.send_msg(controller::ControllerMsg::SetMaxPlayers(1));
+
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(Some("p1"));
+    p1.expect_my_turn();
+
+    let mut p2 = sut.connect_player("p2");
+    p2.expect_game_full();
+    p1.expect_nothing();
+}
+
+#[test]
+fn spectate_mode_asks_nobody_to_move() {
+    let (mut sut, mut game) = Sut::start();
+    let mut spectator = sut.connect_spectator("watcher");
+    let (mut p1, mut p2) = start_two_player_game(&mut sut, &mut game);
+    p1.expect_my_turn();
+    spectator.expect_state("");
+
+    // The turn p1 was asked about is shown to everyone instead, spectators see it once
+    sut.send_msg(controller::ControllerMsg::GoToMode(
+        controller::GameMode::Spectate,
+    ));
+    p1.expect_game_state();
+    p2.expect_game_state();
+    spectator.expect_state("");
+    spectator.expect_nothing();
+
+    sut.send_msg(controller::ControllerMsg::InjectMove("mv".to_string()));
+    game.expect_move("p1", "mv", ok_move("p2", ""));
+    p1.expect_game_state();
+    p2.expect_game_state();
+    spectator.expect_state("");
+    p1.expect_nothing();
+    p2.expect_nothing();
+    spectator.expect_nothing();
+
+    // Back to playing, the turn the game is on is asked for again
+    sut.send_msg(controller::ControllerMsg::GoToMode(
+        controller::GameMode::Practice,
+    ));
+    p2.expect_my_turn();
+    p1.expect_nothing();
+}
+
+#[test]
+fn injected_move_without_a_game_is_ignored() {
+    let (mut sut, mut game) = Sut::start();
+    let mut p1 = sut.connect_player("p1");
+    game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+
+    sut.send_msg(controller::ControllerMsg::InjectMove("mv".to_string()));
+    game.expect_no_move();
+    p1.expect_nothing();
+}
+
+#[test]
+fn full_game_turns_players_away() {
+    let (mut sut, mut game) = Sut::start_with_player_limits(1, 2);
+
+    let mut p1 = sut.connect_player("p1");
     game.expect_player_connected("p1");
+    game.expect_try_start_game(None);
+    let mut p2 = sut.connect_player("p2");
+    game.expect_player_connected("p2");
     game.expect_try_start_game(Some("p1"));
     p1.expect_my_turn();
+
+    // The game never hears of p3
+    let mut p3 = sut.connect_player("p3");
+    p3.expect_game_full();
+    p2.expect_nothing();
+
+    // A seat is free again
+    sut.send_msg(controller::ControllerMsg::Kick("p2".to_string()));
+    p2.expect_kicked();
+    let mut p4 = sut.connect_player("p4");
+    game.expect_player_connected("p4");
+    p4.expect_nothing();
 }